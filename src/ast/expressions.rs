@@ -1,5 +1,43 @@
+use std::fmt;
+
 use serde::{Serialize, Deserialize};
 
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum BinaryOperator {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Equal,
+    NotEqual,
+    LessThan,
+    LessThanOrEqual,
+    GreaterThan,
+    GreaterThanOrEqual,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum UnaryOperator {
+    Negate,
+    Not,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Pattern {
+    Wildcard,
+    Binding { name: String },
+    IntLiteral { val: i64 },
+    BooleanLiteral { val: bool },
+    Variant { enum_name: String, variant: String, fields: Vec<Pattern> },
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MatchArm {
+    pub pattern: Pattern,
+    pub body: Expression,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Expression {
     StringLiteral { val: String },
@@ -8,4 +46,124 @@ pub enum Expression {
     BooleanLiteral { val: bool },
     FloatLiteral { val: f64 },
     FunctionCall { name: String, params: Vec<Expression> },
+    MethodCall { receiver: Box<Expression>, method: String, params: Vec<Expression> },
+    ArrayLiteral { elements: Vec<Expression> },
+    Index { array: Box<Expression>, index: Box<Expression> },
+    StructLiteral { name: String, fields: Vec<(String, Expression)> },
+    FieldAccess { expr: Box<Expression>, field: String },
+    EnumVariant { enum_name: String, variant: String, values: Vec<Expression> },
+    Match { expr: Box<Expression>, arms: Vec<MatchArm> },
+    BinaryOperation { op: BinaryOperator, lhs: Box<Expression>, rhs: Box<Expression> },
+    UnaryOperation { op: UnaryOperator, expr: Box<Expression> },
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Wildcard => write!(f, "_"),
+            Pattern::Binding { name } => write!(f, "{}", name),
+            Pattern::IntLiteral { val } => write!(f, "{}", val),
+            Pattern::BooleanLiteral { val } => write!(f, "{}", val),
+            Pattern::Variant {
+                enum_name,
+                variant,
+                fields,
+            } => {
+                write!(f, "{}::{}", enum_name, variant)?;
+                if !fields.is_empty() {
+                    let fields: Vec<String> = fields.iter().map(|p| p.to_string()).collect();
+                    write!(f, "({})", fields.join(", "))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl fmt::Display for BinaryOperator {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let op = match self {
+            BinaryOperator::Add => "+",
+            BinaryOperator::Subtract => "-",
+            BinaryOperator::Multiply => "*",
+            BinaryOperator::Divide => "/",
+            BinaryOperator::Modulo => "%",
+            BinaryOperator::Equal => "==",
+            BinaryOperator::NotEqual => "!=",
+            BinaryOperator::LessThan => "<",
+            BinaryOperator::LessThanOrEqual => "<=",
+            BinaryOperator::GreaterThan => ">",
+            BinaryOperator::GreaterThanOrEqual => ">=",
+        };
+        write!(f, "{}", op)
+    }
+}
+
+fn join_expressions(exprs: &[Expression]) -> String {
+    exprs
+        .iter()
+        .map(|e| e.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Formats expressions the way they are written in source, for error
+/// messages. Nested binary operations are parenthesised.
+impl fmt::Display for Expression {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expression::StringLiteral { val } => write!(f, "{:?}", val),
+            Expression::Identifier { val } => write!(f, "{}", val),
+            Expression::IntLiteral { val } => write!(f, "{}", val),
+            Expression::BooleanLiteral { val } => write!(f, "{}", val),
+            Expression::FloatLiteral { val } => write!(f, "{:?}", val),
+            Expression::FunctionCall { name, params } => {
+                write!(f, "{}({})", name, join_expressions(params))
+            }
+            Expression::MethodCall {
+                receiver,
+                method,
+                params,
+            } => write!(f, "{}.{}({})", receiver, method, join_expressions(params)),
+            Expression::ArrayLiteral { elements } => write!(f, "[{}]", join_expressions(elements)),
+            Expression::Index { array, index } => write!(f, "{}[{}]", array, index),
+            Expression::StructLiteral { name, fields } => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(name, value)| format!("{}: {}", name, value))
+                    .collect();
+                write!(f, "{} {{ {} }}", name, fields.join(", "))
+            }
+            Expression::FieldAccess { expr, field } => write!(f, "{}.{}", expr, field),
+            Expression::EnumVariant {
+                enum_name,
+                variant,
+                values,
+            } => {
+                write!(f, "{}::{}", enum_name, variant)?;
+                if !values.is_empty() {
+                    write!(f, "({})", join_expressions(values))?;
+                }
+                Ok(())
+            }
+            Expression::Match { expr, .. } => write!(f, "match {} ... end", expr),
+            Expression::BinaryOperation { op, lhs, rhs } => {
+                let operand = |expr: &Expression| match expr {
+                    Expression::BinaryOperation { .. } => format!("({})", expr),
+                    expr => expr.to_string(),
+                };
+                write!(f, "{} {} {}", operand(lhs), op, operand(rhs))
+            }
+            Expression::UnaryOperation { op, expr } => {
+                let op = match op {
+                    UnaryOperator::Negate => "-",
+                    UnaryOperator::Not => "!",
+                };
+                match expr.as_ref() {
+                    Expression::BinaryOperation { .. } => write!(f, "{}({})", op, expr),
+                    expr => write!(f, "{}{}", op, expr),
+                }
+            }
+        }
+    }
 }
@@ -1,15 +1,51 @@
 pub mod expressions;
 pub mod statements;
-use regex::Regex;
+use std::{
+    collections::{HashMap, HashSet},
+    fmt,
+};
+
 use serde::{Deserialize, Serialize};
 
+use expressions::Expression;
+use statements::Statements;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FuncParam {
     pub name: String,
     pub r#type: Type,
 }
 
+/// A type parameter of a generic function or struct, with the traits its
+/// type arguments must implement.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TypeParam {
+    pub name: String,
+    pub bounds: Vec<String>,
+}
+
+/// A method signature in a `trait` declaration.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunctionSignature {
+    pub name: String,
+    pub params: Vec<FuncParam>,
+    pub return_type: Type,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StructField {
+    pub name: String,
+    pub r#type: Type,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnumVariant {
+    pub name: String,
+    pub fields: Vec<Type>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[allow(clippy::enum_variant_names)]
 pub enum Type {
     Int8,
     Int16,
@@ -24,6 +60,17 @@ pub enum Type {
     Boolean,
     Void,
     Array(Box<Type>),
+    FixedArray(Box<Type>, usize),
+    Tuple(Vec<Type>),
+    Function {
+        params: Vec<Type>,
+        return_type: Box<Type>,
+    },
+    Generic { name: String, params: Vec<Type> },
+    /// `*T`, a raw pointer for passing data to and from C. `*void` points
+    /// to anything.
+    Pointer(Box<Type>),
+    TypeParameter { name: String },
     UserDefinedType { name: String },
 }
 
@@ -45,7 +92,11 @@ impl Program {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ExportType {
     Const,
+    Global,
     Function,
+    Struct,
+    Enum,
+    Trait,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -71,43 +122,243 @@ impl Type {
             "str" => Type::String,
             "bool" => Type::Boolean,
             "void" => Type::Void,
-            name => {
-                let regexp = Regex::new(r"array\[[a-zA-Z0-9_]+\]").unwrap();
-
-                if regexp.is_match(name) {
-                    let name = name.replace("[", " ");
-                    let name = name.replace("]", "");
-                    let xs = name.split(" ");
-                    let mut str_arr: Vec<&str> = vec![];
-                    for x in xs {
-                        str_arr.push(x);
-                    }
-                    return Type::Array(Box::new(Type::parse_type(str_arr[1].to_owned())));
-                }
+            name => Type::UserDefinedType {
+                name: name.to_string(),
+            },
+        }
+    }
 
-                Type::UserDefinedType {
-                    name: name.to_string(),
-                }
+    pub fn has_type_params(&self) -> bool {
+        match self {
+            Type::TypeParameter { .. } => true,
+            Type::Array(r#type) | Type::FixedArray(r#type, _) | Type::Pointer(r#type) => {
+                r#type.has_type_params()
+            }
+            Type::Tuple(types) | Type::Generic { params: types, .. } => {
+                types.iter().any(|t| t.has_type_params())
+            }
+            Type::Function {
+                params,
+                return_type,
+            } => params.iter().any(|t| t.has_type_params()) || return_type.has_type_params(),
+            _ => false,
+        }
+    }
+
+    /// Replaces the type parameters in `self` with their bound types.
+    /// Parameters without a binding are left as they are.
+    pub fn substitute(&self, bindings: &HashMap<String, Type>) -> Type {
+        let substitute_all = |types: &[Type]| -> Vec<Type> {
+            types.iter().map(|t| t.substitute(bindings)).collect()
+        };
+
+        match self {
+            Type::TypeParameter { name } => bindings.get(name).cloned().unwrap_or(self.clone()),
+            Type::Array(r#type) => Type::Array(Box::new(r#type.substitute(bindings))),
+            Type::FixedArray(r#type, size) => {
+                Type::FixedArray(Box::new(r#type.substitute(bindings)), *size)
+            }
+            Type::Tuple(types) => Type::Tuple(substitute_all(types)),
+            Type::Pointer(r#type) => Type::Pointer(Box::new(r#type.substitute(bindings))),
+            Type::Function {
+                params,
+                return_type,
+            } => Type::Function {
+                params: substitute_all(params),
+                return_type: Box::new(return_type.substitute(bindings)),
+            },
+            Type::Generic { name, params } => Type::Generic {
+                name: name.clone(),
+                params: substitute_all(params),
+            },
+            r#type => r#type.clone(),
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(
+            self,
+            Type::Int8 | Type::Int16 | Type::Int32 | Type::Int64 | Type::Int128
+        )
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(
+            self,
+            Type::Float16 | Type::Float32 | Type::Float64 | Type::Float128
+        )
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Type::Int8 => write!(f, "i8"),
+            Type::Int16 => write!(f, "i16"),
+            Type::Int32 => write!(f, "i32"),
+            Type::Int64 => write!(f, "i64"),
+            Type::Int128 => write!(f, "i128"),
+            Type::Float16 => write!(f, "f16"),
+            Type::Float32 => write!(f, "f32"),
+            Type::Float64 => write!(f, "f64"),
+            Type::Float128 => write!(f, "f128"),
+            Type::String => write!(f, "str"),
+            Type::Boolean => write!(f, "bool"),
+            Type::Void => write!(f, "void"),
+            Type::Array(r#type) => write!(f, "array[{}]", r#type),
+            Type::FixedArray(r#type, size) => write!(f, "[{}; {}]", r#type, size),
+            Type::Tuple(types) => write!(f, "({})", join_types(types)),
+            Type::Function {
+                params,
+                return_type,
+            } => match return_type.as_ref() {
+                Type::Void => write!(f, "fn({})", join_types(params)),
+                return_type => write!(f, "fn({}) -> {}", join_types(params), return_type),
+            },
+            Type::Generic { name, params } => write!(f, "{}[{}]", name, join_types(params)),
+            Type::Pointer(r#type) => write!(f, "*{}", r#type),
+            Type::TypeParameter { name } | Type::UserDefinedType { name } => write!(f, "{}", name),
+        }
+    }
+}
+
+fn join_types(types: &[Type]) -> String {
+    types
+        .iter()
+        .map(|t| t.to_string())
+        .collect::<Vec<String>>()
+        .join(", ")
+}
+
+/// Binds the type parameters in `pattern` so that it matches `actual`.
+/// Mismatches between concrete types are left for the conversion of the
+/// value to report.
+pub fn unify(pattern: &Type, actual: &Type, bindings: &mut HashMap<String, Type>) -> Result<(), String> {
+    match (pattern, actual) {
+        (Type::TypeParameter { name }, actual) => match bindings.get(name) {
+            Some(bound) if bound != actual => Err(format!(
+                "type parameter `{}` can not be both {} and {}",
+                name, bound, actual
+            )),
+            Some(_) => Ok(()),
+            None => {
+                bindings.insert(name.clone(), actual.clone());
+                Ok(())
             }
+        },
+        (Type::Array(pattern), Type::Array(actual))
+        | (Type::Array(pattern), Type::FixedArray(actual, _)) => unify(pattern, actual, bindings),
+        (Type::FixedArray(pattern, n), Type::FixedArray(actual, m)) if n == m => {
+            unify(pattern, actual, bindings)
+        }
+        (Type::Pointer(pattern), Type::Pointer(actual)) => unify(pattern, actual, bindings),
+        (Type::Tuple(patterns), Type::Tuple(actuals)) if patterns.len() == actuals.len() => patterns
+            .iter()
+            .zip(actuals)
+            .try_for_each(|(p, a)| unify(p, a, bindings)),
+        (
+            Type::Function {
+                params: patterns,
+                return_type: pattern,
+            },
+            Type::Function {
+                params: actuals,
+                return_type: actual,
+            },
+        ) if patterns.len() == actuals.len() => {
+            patterns
+                .iter()
+                .zip(actuals)
+                .try_for_each(|(p, a)| unify(p, a, bindings))?;
+            unify(pattern, actual, bindings)
         }
+        (
+            Type::Generic {
+                name,
+                params: patterns,
+            },
+            Type::Generic {
+                name: actual_name,
+                params: actuals,
+            },
+        ) if name == actual_name && patterns.len() == actuals.len() => patterns
+            .iter()
+            .zip(actuals)
+            .try_for_each(|(p, a)| unify(p, a, bindings)),
+        _ => Ok(()),
     }
+}
 
-    pub fn to(self) -> &'static str {
-        match &self {
-            Type::Int8 => "i8",
-            Type::Int16 => "i16",
-            Type::Int32 => "i32",
-            Type::Int64 => "i64",
-            Type::Int128 => "i128",
-            Type::Float16 => "f16",
-            Type::Float32 => "f32",
-            Type::Float64 => "f64",
-            Type::Float128 => "f128",
-            Type::String => "str",
-            Type::Boolean => "bool",
-            Type::Void => "void",
-            Type::Array(_) => todo!(),
-            Type::UserDefinedType { name } => todo!(),
+/// Symbol name of `method` on `r#type`, so that methods of different types
+/// can share a name.
+pub fn mangle_method(r#type: &Type, method: &str) -> String {
+    format!("{}::{}", r#type, method)
+}
+
+/// Returns the methods of an `impl` block as free functions under their
+/// mangled names, with `self` as the first parameter.
+pub fn impl_functions(r#impl: &Statements) -> Result<Vec<Statements>, String> {
+    match r#impl {
+        Statements::ImplDeclaration {
+            r#type, methods, ..
+        } => methods
+            .iter()
+            .map(|method| match method {
+                Statements::FunctionDeclaration {
+                    name, type_params, ..
+                } if !type_params.is_empty() => {
+                    Err(format!("Method `{}` can not have type parameters", name))
+                }
+                Statements::FunctionDeclaration {
+                    name,
+                    params,
+                    body,
+                    return_type,
+                    line,
+                    ..
+                } => Ok(Statements::FunctionDeclaration {
+                    name: mangle_method(r#type, name),
+                    type_params: vec![],
+                    params: params.clone(),
+                    body: body.clone(),
+                    return_type: return_type.clone(),
+                    is_const: false,
+                    is_extern: false,
+                    line: *line,
+                }),
+                _ => Err("Expected FunctionDecl in impl block".to_string()),
+            })
+            .collect(),
+        _ => Err("Expected ImplDecl as input".to_string()),
+    }
+}
+
+/// Names of the variables assigned to in `body`, including those only a
+/// field or element of is assigned to. Shadowing is not taken into account.
+pub fn assigned_variables(body: &[Statements]) -> HashSet<String> {
+    let mut assigned = HashSet::new();
+    for statement in body {
+        match statement {
+            Statements::Assignment { target, .. } => {
+                let mut root = target;
+                while let Expression::FieldAccess { expr: inner, .. }
+                | Expression::Index { array: inner, .. } = root
+                {
+                    root = inner.as_ref();
+                }
+                if let Expression::Identifier { val } = root {
+                    assigned.insert(val.clone());
+                }
+            }
+            Statements::If {
+                body, else_body, ..
+            } => {
+                assigned.extend(assigned_variables(body));
+                assigned.extend(assigned_variables(else_body));
+            }
+            Statements::While { body, .. } => assigned.extend(assigned_variables(body)),
+            _ => {}
         }
     }
+    assigned
 }
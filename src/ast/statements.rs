@@ -1,25 +1,108 @@
 use serde::{Serialize, Deserialize};
 
-use super::{expressions::Expression, Type, FuncParam};
+use super::{expressions::Expression, Type, FuncParam, StructField, EnumVariant, TypeParam, FunctionSignature};
 
 
+/// `line` is the line of the source a statement starts on, for debug
+/// information. Statements made up by the compiler are on line 0.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Statements {
     VariableDeclaration {
         name: String,
         value: Expression,
-        r#type: Type
+        r#type: Type,
+        line: usize
+    },
+    /// `const NAME: T = value`, evaluated at compile time
+    ConstDeclaration {
+        name: String,
+        r#type: Type,
+        value: Expression,
+        line: usize
     },
     ModuleDeclaration {
         name: String
     },
+    /// `import a.b` or `import a.b.{x, y}`
+    Import {
+        path: Vec<String>,
+        items: Option<Vec<String>>
+    },
     FunctionDeclaration {
         name: String,
+        type_params: Vec<TypeParam>,
         params: Vec<FuncParam>,
         body: Vec<Statements>,
+        return_type: Type,
+        /// `const fn`, which may also be called in constant expressions
+        is_const: bool,
+        /// `public extern "C" fn`, which C can call by its plain name
+        is_extern: bool,
+        line: usize
+    },
+    /// `extern "C" fn name(params) -> type`, a function defined in C
+    ExternFunctionDeclaration {
+        name: String,
+        params: Vec<FuncParam>,
         return_type: Type
     },
+    StructDeclaration {
+        name: String,
+        type_params: Vec<TypeParam>,
+        fields: Vec<StructField>
+    },
+    EnumDeclaration {
+        name: String,
+        variants: Vec<EnumVariant>
+    },
+    TraitDeclaration {
+        name: String,
+        methods: Vec<FunctionSignature>
+    },
+    ImplDeclaration {
+        r#trait: Option<String>,
+        r#type: Type,
+        methods: Vec<Statements>
+    },
     ExpressionStatement {
-        expr: Expression
+        expr: Expression,
+        line: usize
+    },
+    Assignment {
+        target: Expression,
+        value: Expression,
+        line: usize
+    },
+    Return {
+        value: Option<Expression>,
+        line: usize
+    },
+    If {
+        condition: Expression,
+        body: Vec<Statements>,
+        else_body: Vec<Statements>,
+        line: usize
+    },
+    While {
+        condition: Expression,
+        body: Vec<Statements>,
+        line: usize
+    }
+}
+
+impl Statements {
+    /// The line the statement starts on, for statements that record it.
+    pub fn line(&self) -> Option<usize> {
+        match self {
+            Statements::VariableDeclaration { line, .. }
+            | Statements::ConstDeclaration { line, .. }
+            | Statements::FunctionDeclaration { line, .. }
+            | Statements::ExpressionStatement { line, .. }
+            | Statements::Assignment { line, .. }
+            | Statements::Return { line, .. }
+            | Statements::If { line, .. }
+            | Statements::While { line, .. } => Some(*line),
+            _ => None,
+        }
     }
-}
\ No newline at end of file
+}
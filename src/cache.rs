@@ -0,0 +1,187 @@
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    ast::Program,
+    error::{CompilerError, CompilerErrorKind},
+};
+
+/// Version of what the cache holds. Entries written with another version
+/// are treated as missing, so it must be bumped whenever the AST, the
+/// interfaces or the code generated for them change. The package version
+/// can not be used for this, since it does not change between builds.
+const FORMAT_VERSION: u32 = 2;
+
+/// On-disk cache of the work done for each module by `aurorac build`. For
+/// every module it keeps the parsed AST, keyed on the hash of the source,
+/// and the interface and object file, keyed on the hash of the source, the
+/// interface hashes of every module it depends on and the code generation
+/// options. Entries that can not be read are treated as missing.
+///
+/// Token streams are not cached: a module whose source changed has to be
+/// lexed again anyway, and one whose source did not change is not lexed
+/// at all since its AST is reused.
+pub struct Cache {
+    dir: PathBuf,
+    /// Whether cached results may be used. With `--no-cache` everything is
+    /// rebuilt, but the results are still written for the next build.
+    reuse: bool,
+    /// Options object files are compiled with, like the optimisation level
+    codegen: String,
+    pub stats: Stats,
+}
+
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub modules: usize,
+    pub parsed: usize,
+    pub compiled: usize,
+}
+
+impl fmt::Display for Stats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} modules: {} parsed, {} compiled, {} up to date",
+            self.modules,
+            self.parsed,
+            self.compiled,
+            self.modules - self.compiled
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct ParsedEntry {
+    format: u32,
+    source_hash: u64,
+    program: Program,
+}
+
+#[derive(Serialize, Deserialize, PartialEq)]
+struct CompiledEntry {
+    format: u32,
+    source_hash: u64,
+    /// Interface hashes of the modules the module was compiled against
+    dependencies: BTreeMap<String, u64>,
+    codegen: String,
+}
+
+fn cache_error(message: String) -> CompilerError {
+    CompilerError {
+        code: 401,
+        kind: CompilerErrorKind::BuildError,
+        message,
+    }
+}
+
+impl Cache {
+    pub fn open(dir: PathBuf, reuse: bool, codegen: String) -> Result<Self, CompilerError> {
+        fs::create_dir_all(&dir).map_err(|e| {
+            cache_error(format!("can not create cache directory {}: {}", dir.display(), e))
+        })?;
+
+        Ok(Self {
+            dir,
+            reuse,
+            codegen,
+            stats: Stats::default(),
+        })
+    }
+
+    /// Directory interfaces are written to and read from during a build.
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Path of a cached file of module `key`, see `Module::cache_key`.
+    /// Interfaces of imported modules are cached as `<name>.auri`.
+    pub fn path(&self, key: &str, extension: &str) -> PathBuf {
+        self.dir.join(format!("{}.{}", key, extension))
+    }
+
+    fn read<T: DeserializeOwned>(&self, path: &Path) -> Option<T> {
+        if !self.reuse {
+            return None;
+        }
+
+        let contents = fs::read_to_string(path).ok()?;
+        serde_json::from_str(&contents).ok()
+    }
+
+    fn write<T: Serialize>(&self, path: &Path, value: &T) -> Result<(), CompilerError> {
+        let contents = serde_json::to_string(value).unwrap();
+        fs::write(path, contents)
+            .map_err(|e| cache_error(format!("can not write {}: {}", path.display(), e)))
+    }
+
+    /// The AST of module `key` if it was parsed from the same source before.
+    pub fn parsed(&self, key: &str, source_hash: u64) -> Option<Program> {
+        let entry: ParsedEntry = self.read(&self.path(key, "ast"))?;
+        if entry.format == FORMAT_VERSION && entry.source_hash == source_hash {
+            Some(entry.program)
+        } else {
+            None
+        }
+    }
+
+    pub fn store_parsed(
+        &mut self,
+        key: &str,
+        source_hash: u64,
+        program: &Program,
+    ) -> Result<(), CompilerError> {
+        self.stats.parsed += 1;
+
+        let entry = ParsedEntry {
+            format: FORMAT_VERSION,
+            source_hash,
+            program: program.clone(),
+        };
+        self.write(&self.path(key, "ast"), &entry)
+    }
+
+    /// Whether the object file of module `key`, and its interface if it has
+    /// one, were compiled from the same source against the same interfaces
+    /// of its dependencies.
+    pub fn is_compiled(
+        &self,
+        key: &str,
+        source_hash: u64,
+        dependencies: &BTreeMap<String, u64>,
+        has_interface: bool,
+    ) -> bool {
+        let expected = CompiledEntry {
+            format: FORMAT_VERSION,
+            source_hash,
+            dependencies: dependencies.clone(),
+            codegen: self.codegen.clone(),
+        };
+
+        self.read(&self.path(key, "json")) == Some(expected)
+            && self.path(key, "o").is_file()
+            && (!has_interface || self.path(key, "auri").is_file())
+    }
+
+    pub fn store_compiled(
+        &mut self,
+        key: &str,
+        source_hash: u64,
+        dependencies: BTreeMap<String, u64>,
+    ) -> Result<(), CompilerError> {
+        self.stats.compiled += 1;
+
+        let entry = CompiledEntry {
+            format: FORMAT_VERSION,
+            source_hash,
+            dependencies,
+            codegen: self.codegen.clone(),
+        };
+        self.write(&self.path(key, "json"), &entry)
+    }
+}
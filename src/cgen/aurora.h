@@ -0,0 +1,269 @@
+/* Runtime support for C code generated by aurorac. */
+#ifndef AURORA_H
+#define AURORA_H
+
+#include <inttypes.h>
+#include <math.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+#if defined(__GNUC__)
+#define AURORA_NORETURN __attribute__((noreturn))
+#else
+#define AURORA_NORETURN
+#endif
+
+#define aurora_unreachable() abort()
+
+#define AURORA_STRINGIFY(x) #x
+#define AURORA_EXPAND_STRINGIFY(x) AURORA_STRINGIFY(x)
+
+/* Gives a function declared under its mangled name the symbol `name`, for
+ * functions of and for C. */
+#define AURORA_SYMBOL(name) __asm__(AURORA_EXPAND_STRINGIFY(__USER_LABEL_PREFIX__) name)
+
+static inline AURORA_NORETURN void aurora_panic_bounds(int64_t index, int64_t len)
+{
+    fprintf(stderr, "panic: index out of bounds: the len is %" PRId64 " but the index is %" PRId64 "\n", len, index);
+    exit(101);
+}
+
+/* Returns `index` if it is within an array of `len` elements, and panics
+ * like the LLVM backend otherwise. */
+static inline int64_t aurora_check_index(int64_t index, int64_t len)
+{
+    if ((uint64_t)index >= (uint64_t)len) {
+        aurora_panic_bounds(index, len);
+    }
+    return index;
+}
+
+AURORA_NORETURN void aurora_panic(const char *message);
+
+static inline void aurora_check_division(bool by_zero, bool overflows)
+{
+    if (by_zero) {
+        aurora_panic("attempt to divide by zero");
+    }
+    if (overflows) {
+        aurora_panic("attempt to divide with overflow");
+    }
+}
+
+/* Integer division and remainder, which panic like the LLVM backend where C
+ * leaves them undefined: for a zero divisor, and for the smallest value
+ * divided by -1. */
+#define AURORA_DIVISION(name, type, min)                                \
+    static inline type aurora_div_##name(type lhs, type rhs)            \
+    {                                                                   \
+        aurora_check_division(rhs == 0, lhs == (min) && rhs == -1);     \
+        return lhs / rhs;                                               \
+    }                                                                   \
+    static inline type aurora_rem_##name(type lhs, type rhs)            \
+    {                                                                   \
+        aurora_check_division(rhs == 0, lhs == (min) && rhs == -1);     \
+        return lhs % rhs;                                               \
+    }
+
+AURORA_DIVISION(i8, int8_t, INT8_MIN)
+AURORA_DIVISION(i16, int16_t, INT16_MIN)
+AURORA_DIVISION(i32, int32_t, INT32_MIN)
+AURORA_DIVISION(i64, int64_t, INT64_MIN)
+AURORA_DIVISION(i128, __int128, (__int128)((unsigned __int128)1 << 127))
+
+/* Strings and the elements of dynamic arrays live in blocks that start with
+ * a header counting the references to them. Generated code retains a value
+ * when it copies it and releases it when the copy goes out of scope, and the
+ * block is freed along with the values in it once the last reference is
+ * released. String literals have a header too, with a negative count that
+ * keeps them alive forever.
+ *
+ * Blocks are allocated with `AURORA_MALLOC` and freed with `AURORA_FREE`,
+ * which can be defined when compiling the generated code to use another
+ * allocator than that of libc. */
+#ifndef AURORA_MALLOC
+#define AURORA_MALLOC malloc
+#endif
+#ifndef AURORA_FREE
+#define AURORA_FREE free
+#endif
+
+typedef struct {
+    int64_t refs;
+    /* Keeps the data after the header aligned like memory from malloc */
+    int64_t padding;
+} aurora_header;
+
+/* Defines `name`, a string literal with a header, whose `chars` are the
+ * string. */
+#define AURORA_STRING(name, text) \
+    static const struct { aurora_header header; char chars[sizeof(text)]; } name = { { -1, 0 }, text }
+
+/* Allocates a block of `size` bytes with a single reference, and returns
+ * its data. */
+static inline void *aurora_alloc(size_t size)
+{
+    aurora_header *header = AURORA_MALLOC(sizeof(aurora_header) + size);
+    if (header == NULL) {
+        abort();
+    }
+    header->refs = 1;
+    header->padding = 0;
+    return header + 1;
+}
+
+/* Adds a reference to the block of `data`. NULL, which C functions may
+ * return for strings, has no block. */
+static inline void aurora_retain(const void *data)
+{
+    if (data != NULL) {
+        aurora_header *header = (aurora_header *)data - 1;
+        if (header->refs >= 0) {
+            header->refs++;
+        }
+    }
+}
+
+/* Drops a reference to the block of `data` and returns whether it was the
+ * last one, in which case the caller releases the values in the block and
+ * frees it. */
+static inline bool aurora_release(const void *data)
+{
+    if (data == NULL) {
+        return false;
+    }
+    aurora_header *header = (aurora_header *)data - 1;
+    return header->refs >= 0 && --header->refs == 0;
+}
+
+static inline void aurora_free(const void *data)
+{
+    AURORA_FREE((aurora_header *)data - 1);
+}
+
+static inline const char *aurora_retain_str(const char *text)
+{
+    aurora_retain(text);
+    return text;
+}
+
+static inline void aurora_release_str(const char *text)
+{
+    if (aurora_release(text)) {
+        aurora_free(text);
+    }
+}
+
+/* Copies `size` bytes to a new block, for arrays made from literals and
+ * fixed arrays. */
+static inline void *aurora_copy(const void *data, size_t size)
+{
+    void *copy = aurora_alloc(size);
+    memcpy(copy, data, size);
+    return copy;
+}
+
+/* Copies a string returned by C to a new block, since the strings generated
+ * code holds are released. */
+static inline const char *aurora_string_copy(const char *text)
+{
+    return text == NULL ? NULL : aurora_copy(text, strlen(text) + 1);
+}
+
+/* Concatenates two strings into a new one. */
+static inline const char *aurora_concat(const char *lhs, const char *rhs)
+{
+    size_t lhs_len = strlen(lhs);
+    size_t rhs_len = strlen(rhs);
+    char *result = aurora_alloc(lhs_len + rhs_len + 1);
+    memcpy(result, lhs, lhs_len);
+    memcpy(result + lhs_len, rhs, rhs_len + 1);
+    return result;
+}
+
+/* Writes `value` in decimal to a new string, for `format`. */
+static inline const char *aurora_format_int(__int128 value)
+{
+    char buffer[41];
+    char *start = buffer + 40;
+    *start = '\0';
+
+    unsigned __int128 rest = value < 0 ? -(unsigned __int128)value : (unsigned __int128)value;
+    do {
+        *--start = (char)('0' + rest % 10);
+        rest /= 10;
+    } while (rest != 0);
+    if (value < 0) {
+        *--start = '-';
+    }
+    return aurora_copy(start, (size_t)(buffer + 41 - start));
+}
+
+/* Writes `value` with `digits` significant digits to a new string, for
+ * `format`. */
+static inline const char *aurora_format_float(double value, int digits)
+{
+    char *buffer = aurora_alloc(32);
+    snprintf(buffer, 32, "%.*g", digits, value);
+    return buffer;
+}
+
+/* The standard library declares the functions below `extern "C"`, so
+ * unlike the rest of the runtime they are not static. */
+
+/* Writes `len` bytes of `data` to stdout or, for `fd` 2, to stderr. */
+void aurora_write(int32_t fd, const char *data, int32_t len)
+{
+    fwrite(data, 1, len > 0 ? (size_t)len : 0, fd == 2 ? stderr : stdout);
+}
+
+/* Stops the program with `message`, like a failed bounds check. */
+AURORA_NORETURN void aurora_panic(const char *message)
+{
+    fprintf(stderr, "panic: %s\n", message);
+    exit(101);
+}
+
+/* The byte at `index`, which has to be within `text`. */
+int32_t aurora_byte(const char *text, int64_t index)
+{
+    return (unsigned char)text[index];
+}
+
+/* The math functions `std.math` wraps, which the LLVM backend compiles to
+ * intrinsics. */
+double aurora_sqrt(double x)
+{
+    return sqrt(x);
+}
+
+double aurora_pow(double x, double y)
+{
+    return pow(x, y);
+}
+
+double aurora_floor(double x)
+{
+    return floor(x);
+}
+
+double aurora_ceil(double x)
+{
+    return ceil(x);
+}
+
+/* Copies the bytes of `text` from `start` up to `end`, which have to be
+ * within it, to a new string. */
+const char *aurora_substring(const char *text, int64_t start, int64_t end)
+{
+    size_t len = (size_t)(end - start);
+    char *result = aurora_alloc(len + 1);
+    memcpy(result, text + start, len);
+    result[len] = '\0';
+    return result;
+}
+
+#endif
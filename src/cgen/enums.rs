@@ -0,0 +1,268 @@
+use std::collections::HashMap;
+
+use crate::ast::{
+    expressions::{Expression, MatchArm, Pattern},
+    statements::Statements,
+    Type,
+};
+
+use super::{c_identifier, int_literal, last_impure, CGen};
+
+/// Enum values are C structs of a tag and a union of the variant fields, see
+/// `type_definition`. A `match` becomes a chain of `if`s testing the arms in
+/// order, which C compilers turn into a switch where it pays off.
+impl CGen {
+    fn variant_index(&self, enum_name: &str, variant: &str) -> Result<usize, String> {
+        self.enums
+            .get(enum_name)
+            .ok_or(format!("Unknown enum `{}`", enum_name))?
+            .iter()
+            .position(|v| v.name == variant)
+            .ok_or(format!("`{}` has no variant `{}`", enum_name, variant))
+    }
+
+    pub(super) fn compile_enum_variant(
+        &mut self,
+        enum_name: &str,
+        variant: &str,
+        values: &[Expression],
+    ) -> Result<(String, Type), String> {
+        let index = self.variant_index(enum_name, variant)?;
+        let fields = self.enums[enum_name][index].fields.clone();
+        if fields.len() != values.len() {
+            return Err(format!(
+                "`{}::{}` has {} fields but {} were supplied",
+                enum_name,
+                variant,
+                fields.len(),
+                values.len()
+            ));
+        }
+
+        let r#type = Type::UserDefinedType {
+            name: enum_name.to_string(),
+        };
+        let c_type = self.c_type(&r#type)?;
+
+        let last_impure = last_impure(values.iter());
+        let mut field_values = vec![];
+        for (i, (value, field_type)) in values.iter().zip(fields.iter()).enumerate() {
+            let compiled = self.compile_expression_as(value, field_type)?;
+            let compiled = self.sequence(i, last_impure, value, compiled, field_type)?;
+            field_values.push(self.take(compiled, field_type)?);
+        }
+
+        let value = match field_values.is_empty() {
+            true => format!("({}){{ .tag = {} }}", c_type, index),
+            false => format!(
+                "({}){{ .tag = {}, .payload.{} = {{ {} }} }}",
+                c_type,
+                index,
+                c_identifier(variant),
+                field_values.join(", ")
+            ),
+        };
+
+        Ok((self.owned_temporary(&r#type, value)?, r#type))
+    }
+
+    /// Translates a `match`. The arm bodies are translated first, since the
+    /// first arm decides the type of the variable holding the result, which
+    /// has to be declared before the chain of tests. A `void` hint makes the
+    /// match a statement without a value. Each arm releases its bindings and
+    /// new values before the chain continues.
+    pub(super) fn compile_match(
+        &mut self,
+        expr: &Expression,
+        arms: &[MatchArm],
+        hint: Option<&Type>,
+    ) -> Result<(Option<String>, Type), String> {
+        let (value, r#type) = self.compile_expression(expr, None)?;
+        let scrutinee = self.temporary(&r#type, value)?;
+        let result = self.fresh_name("tmp");
+
+        let lines = std::mem::take(&mut self.lines);
+        self.indent += 1;
+
+        let mut result_type: Option<Type> = None;
+        let mut chain: Vec<(Option<String>, Vec<String>)> = vec![];
+        for arm in arms {
+            let test = self.pattern_test(&arm.pattern, &scrutinee, &r#type)?;
+
+            let mark = self.temporaries.len();
+            self.scopes.push(HashMap::new());
+            self.owned.push(vec![]);
+            self.bind_pattern(&arm.pattern, &scrutinee, &r#type)?;
+
+            match (&result_type, hint) {
+                (_, Some(Type::Void)) | (Some(Type::Void), _) => {
+                    self.compile_statement(&Statements::ExpressionStatement {
+                        expr: arm.body.clone(),
+                        line: 0,
+                    })?;
+                }
+                (Some(expected), _) => {
+                    let expected = expected.clone();
+                    let value = self.compile_expression_as(&arm.body, &expected)?;
+                    let value = self.take(value, &expected)?;
+                    self.emit(format!("{} = {};", result, value));
+                }
+                (None, hint) => match self.compile_value_or_void(&arm.body, hint)? {
+                    (Some(value), found) if found != Type::Void => {
+                        let value = self.take(value, &found)?;
+                        self.emit(format!("{} = {};", result, value));
+                        result_type = Some(found);
+                    }
+                    (value, _) => {
+                        if let Some(value) = value {
+                            if !self.is_temporary(&value) {
+                                self.emit(format!("{};", value));
+                            }
+                        }
+                        result_type = Some(Type::Void);
+                    }
+                },
+            }
+
+            self.release_temporaries(mark)?;
+            self.release_scopes(self.owned.len() - 1)?;
+            self.owned.pop();
+            self.scopes.pop();
+            let catch_all = test.is_none();
+            chain.push((test, std::mem::take(&mut self.lines)));
+            // Later arms can never be reached
+            if catch_all {
+                break;
+            }
+        }
+
+        self.indent -= 1;
+        self.lines = lines;
+
+        let result_type = match (hint, result_type) {
+            (Some(Type::Void), _) | (_, None) | (_, Some(Type::Void)) => None,
+            (_, Some(r#type)) => Some(r#type),
+        };
+        if let Some(r#type) = &result_type {
+            let declaration = self.c_declaration(r#type, &result)?;
+            self.emit(format!("{};", declaration));
+        }
+
+        let exhaustive = matches!(chain.last(), Some((None, _)));
+        for (i, (test, body)) in chain.into_iter().enumerate() {
+            let opening = match (i, test) {
+                (0, Some(test)) => format!("if ({}) {{", test),
+                (0, None) => "{".to_string(),
+                (_, Some(test)) => format!("}} else if ({}) {{", test),
+                (_, None) => "} else {".to_string(),
+            };
+            self.emit(opening);
+            self.lines.extend(body);
+        }
+        if !exhaustive {
+            // Sema makes sure the arms cover every value
+            if !arms.is_empty() {
+                self.emit("} else {".to_string());
+            } else {
+                self.emit("{".to_string());
+            }
+            self.indent += 1;
+            self.emit("aurora_unreachable();".to_string());
+            self.indent -= 1;
+        }
+        self.emit("}".to_string());
+
+        match result_type {
+            Some(r#type) => {
+                if self.is_managed(&r#type) {
+                    self.temporaries.push((result.clone(), r#type.clone()));
+                }
+                Ok((Some(result), r#type))
+            }
+            None => Ok((None, Type::Void)),
+        }
+    }
+
+    fn check_pattern_type(&self, pattern: &Pattern, r#type: &Type) -> Result<(), String> {
+        let matches = match pattern {
+            Pattern::Wildcard | Pattern::Binding { .. } => true,
+            Pattern::IntLiteral { .. } => r#type.is_integer(),
+            Pattern::BooleanLiteral { .. } => r#type == &Type::Boolean,
+            Pattern::Variant { enum_name, .. } => {
+                r#type
+                    == &Type::UserDefinedType {
+                        name: enum_name.clone(),
+                    }
+            }
+        };
+
+        if matches {
+            Ok(())
+        } else {
+            Err(format!("Pattern `{}` can not match a value of type {}", pattern, r#type))
+        }
+    }
+
+    /// The C condition under which `value` matches `pattern`, or `None` if
+    /// it always does.
+    fn pattern_test(&self, pattern: &Pattern, value: &str, r#type: &Type) -> Result<Option<String>, String> {
+        self.check_pattern_type(pattern, r#type)?;
+
+        match pattern {
+            Pattern::Wildcard | Pattern::Binding { .. } => Ok(None),
+            Pattern::IntLiteral { val } => Ok(Some(format!(
+                "{} == {}",
+                value,
+                int_literal(*val as i128, r#type)
+            ))),
+            Pattern::BooleanLiteral { val: true } => Ok(Some(value.to_string())),
+            Pattern::BooleanLiteral { val: false } => Ok(Some(format!("!{}", value))),
+            Pattern::Variant {
+                enum_name,
+                variant,
+                fields,
+            } => {
+                let index = self.variant_index(enum_name, variant)?;
+                let mut tests = vec![format!("{}.tag == {}", value, index)];
+
+                let field_types = &self.enums[enum_name][index].fields;
+                for (j, (field, field_type)) in fields.iter().zip(field_types).enumerate() {
+                    let field_value = format!("{}.payload.{}._{}", value, c_identifier(variant), j);
+                    tests.extend(self.pattern_test(field, &field_value, field_type)?);
+                }
+
+                Ok(Some(tests.join(" && ")))
+            }
+        }
+    }
+
+    /// Declares the variables bound by `pattern`, copying their values out of
+    /// the matched value and retaining them.
+    fn bind_pattern(&mut self, pattern: &Pattern, value: &str, r#type: &Type) -> Result<(), String> {
+        match pattern {
+            Pattern::Binding { name } => {
+                let c_name = self.fresh_name(name);
+                let declaration = self.c_declaration(r#type, &c_name)?;
+                let value = self.take(value.to_string(), r#type)?;
+                self.emit(format!("{} = {};", declaration, value));
+                self.own(&c_name, r#type);
+                self.declare_variable(name, c_name, r#type.clone());
+            }
+            Pattern::Variant {
+                enum_name,
+                variant,
+                fields,
+            } => {
+                let index = self.variant_index(enum_name, variant)?;
+                let field_types = self.enums[enum_name][index].fields.clone();
+                for (j, (field, field_type)) in fields.iter().zip(&field_types).enumerate() {
+                    let field_value = format!("{}.payload.{}._{}", value, c_identifier(variant), j);
+                    self.bind_pattern(field, &field_value, field_type)?;
+                }
+            }
+            Pattern::Wildcard | Pattern::IntLiteral { .. } | Pattern::BooleanLiteral { .. } => {}
+        }
+
+        Ok(())
+    }
+}
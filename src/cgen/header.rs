@@ -0,0 +1,292 @@
+use crate::{
+    ast::{statements::Statements, ExportType, Program, StructField, Type},
+    consteval::ConstEvaluator,
+};
+
+use super::{c_identifier, constant_literal};
+
+/// A C header for the public items of a module.
+pub struct Header {
+    pub source: String,
+    /// Why each public item C can not use was left out
+    pub skipped: Vec<String>,
+}
+
+/// The name C code knows an item `name` by. Items of the entry module keep
+/// their names, those of other modules are only ever pointed to and get
+/// the dots of their path replaced.
+fn c_name(name: &str) -> String {
+    name.replace('.', "_")
+}
+
+/// `FOO_H` for a header written to `foo.h`.
+fn include_guard(file_name: &str) -> String {
+    file_name
+        .chars()
+        .map(|c| match c.is_ascii_alphanumeric() {
+            true => c.to_ascii_uppercase(),
+            false => '_',
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct HeaderGen {
+    /// Structs the header defines so far, which can be used by value
+    defined: Vec<String>,
+    /// Structs the header declares, including those only pointed to
+    declared: Vec<String>,
+    defines: Vec<String>,
+    definitions: Vec<String>,
+    prototypes: Vec<String>,
+    skipped: Vec<String>,
+}
+
+impl HeaderGen {
+    fn declare(&mut self, name: &str) {
+        if !self.declared.iter().any(|declared| declared == name) {
+            self.declared.push(name.to_string());
+        }
+    }
+
+    /// The C declaration of `inner`, a name or an abstract declarator, as
+    /// having `r#type`. `None` if C has no equivalent of the type, or can
+    /// only use it once the header defines it.
+    fn declarator(&mut self, r#type: &Type, inner: &str) -> Option<String> {
+        let base = |c_type: &str| match inner.is_empty() {
+            true => c_type.to_string(),
+            false => format!("{} {}", c_type, inner),
+        };
+
+        match r#type {
+            Type::Int8 => Some(base("int8_t")),
+            Type::Int16 => Some(base("int16_t")),
+            Type::Int32 => Some(base("int32_t")),
+            Type::Int64 => Some(base("int64_t")),
+            Type::Float32 => Some(base("float")),
+            Type::Float64 => Some(base("double")),
+            Type::Boolean => Some(base("bool")),
+            Type::Void => Some(base("void")),
+            Type::String => Some(format!("const char *{}", inner)),
+            Type::Pointer(pointee) => match pointee.as_ref() {
+                Type::Void => Some(format!("void *{}", inner)),
+                // Structs only pointed to can stay incomplete
+                Type::UserDefinedType { name } => {
+                    self.declare(name);
+                    Some(format!("{} *{}", c_name(name), inner))
+                }
+                pointee => self.declarator(pointee, &format!("*{}", inner)),
+            },
+            Type::FixedArray(element, size) => match inner.starts_with('*') {
+                true => self.declarator(element, &format!("({})[{}]", inner, size)),
+                false => self.declarator(element, &format!("{}[{}]", inner, size)),
+            },
+            Type::Function {
+                params,
+                return_type,
+            } => {
+                let params = self.params(params.iter().map(|r#type| (r#type, "")))?;
+                self.declarator(return_type, &format!("(*{})({})", inner, params))
+            }
+            Type::UserDefinedType { name } if self.defined.contains(name) => {
+                Some(base(&c_name(name)))
+            }
+            _ => None,
+        }
+    }
+
+    fn params<'p>(&mut self, params: impl Iterator<Item = (&'p Type, &'p str)>) -> Option<String> {
+        let params = params
+            .map(|(r#type, name)| self.declarator(r#type, &c_identifier(name)))
+            .collect::<Option<Vec<String>>>()?;
+
+        match params.is_empty() {
+            true => Some("void".to_string()),
+            false => Some(params.join(", ")),
+        }
+    }
+
+    fn function(&mut self, function: &Statements) {
+        let (name, params, return_type, is_extern) = match function {
+            Statements::FunctionDeclaration {
+                name,
+                params,
+                return_type,
+                is_extern,
+                ..
+            } => (name, params, return_type, *is_extern),
+            _ => return,
+        };
+        if !is_extern {
+            self.skipped
+                .push(format!("function `{}` is not `extern \"C\"`", name));
+            return;
+        }
+
+        let params = self.params(params.iter().map(|p| (&p.r#type, p.name.as_str())));
+        match params
+            .and_then(|params| self.declarator(return_type, &format!("{}({})", name, params)))
+        {
+            Some(prototype) => self.prototypes.push(format!("{};", prototype)),
+            None => self.skipped.push(format!(
+                "function `{}` points to types C has no equivalent of",
+                name
+            )),
+        }
+    }
+
+    fn r#struct(&mut self, name: &str, generic: bool, fields: &[StructField]) {
+        if generic {
+            self.skipped.push(format!("struct `{}` is generic", name));
+            return;
+        }
+
+        let mut lines = vec![];
+        for field in fields {
+            match self.declarator(&field.r#type, &c_identifier(&field.name)) {
+                Some(declaration) => lines.push(format!("    {};", declaration)),
+                None => {
+                    self.skipped.push(format!(
+                        "field `{}` of struct `{}` has type {}, which C has no equivalent of",
+                        field.name, name, field.r#type
+                    ));
+                    return;
+                }
+            }
+        }
+        // C structs can not be empty
+        if lines.is_empty() {
+            self.skipped
+                .push(format!("struct `{}` has no fields", name));
+            return;
+        }
+
+        self.declare(name);
+        self.defined.push(name.to_string());
+        self.definitions.push(format!(
+            "struct {} {{\n{}\n}};",
+            c_name(name),
+            lines.join("\n")
+        ));
+    }
+
+    fn constant(
+        &mut self,
+        constants: &mut ConstEvaluator,
+        name: &str,
+        r#type: &Type,
+    ) -> Result<(), String> {
+        if !matches!(
+            r#type,
+            Type::Int8
+                | Type::Int16
+                | Type::Int32
+                | Type::Int64
+                | Type::Float32
+                | Type::Float64
+                | Type::Boolean
+                | Type::String
+        ) {
+            self.skipped.push(format!(
+                "constant `{}` has type {}, which C has no equivalent of",
+                name, r#type
+            ));
+            return Ok(());
+        }
+
+        let value = constants.value(name).map_err(|e| e.to_string())?;
+        let literal = constant_literal(&value, r#type);
+        let literal = match literal.starts_with('-') {
+            true => format!("({})", literal),
+            false => literal,
+        };
+        self.defines
+            .push(format!("#define {} {}", c_name(name), literal));
+        Ok(())
+    }
+}
+
+/// Generates the C header `file_name` for the exports of a checked
+/// `program`, so C code linked with its object file can use them.
+/// Functions must be `public extern "C"`, since others are only known by
+/// backend specific symbols, and only take and return types C has as well.
+/// Structs are defined with the same layout as the compiled code gives them
+/// and constants become macros. Anything else is skipped, with the reason
+/// in `Header::skipped`.
+pub fn header(program: &Program, file_name: &str) -> Result<Header, String> {
+    let mut gen = HeaderGen::default();
+
+    let mut constants = ConstEvaluator::default();
+    for statement in &program.statements {
+        match statement {
+            Statements::ConstDeclaration { .. } => constants.declare(statement),
+            Statements::FunctionDeclaration { is_const: true, .. } => {
+                constants.declare_function(statement)
+            }
+            _ => Ok(()),
+        }
+        .map_err(|e| e.to_string())?;
+    }
+
+    for export in &program.exports {
+        match (&export.r#type, &export.statement) {
+            (ExportType::Function, function) => gen.function(function),
+            (
+                ExportType::Struct,
+                Statements::StructDeclaration {
+                    name,
+                    type_params,
+                    fields,
+                },
+            ) => gen.r#struct(name, !type_params.is_empty(), fields),
+            (ExportType::Const, Statements::ConstDeclaration { name, r#type, .. }) => {
+                gen.constant(&mut constants, name, r#type)?
+            }
+            (ExportType::Global, Statements::VariableDeclaration { name, .. }) => {
+                gen.skipped.push(format!(
+                    "global `{}` is only known to the compiled code by a backend specific symbol",
+                    name
+                ))
+            }
+            (ExportType::Enum, Statements::EnumDeclaration { name, .. }) => gen
+                .skipped
+                .push(format!("enum `{}` has no C equivalent", name)),
+            (ExportType::Trait, Statements::TraitDeclaration { name, .. }) => gen
+                .skipped
+                .push(format!("trait `{}` has no C equivalent", name)),
+            _ => {}
+        }
+    }
+
+    let guard = include_guard(file_name);
+    let typedefs: Vec<String> = gen
+        .declared
+        .iter()
+        .map(|name| format!("typedef struct {} {};", c_name(name), c_name(name)))
+        .collect();
+    let sections: Vec<String> = [
+        (&gen.defines, "\n"),
+        (&typedefs, "\n"),
+        (&gen.definitions, "\n\n"),
+        (&gen.prototypes, "\n"),
+    ]
+    .into_iter()
+    .filter(|(section, _)| !section.is_empty())
+    .map(|(section, separator)| section.join(separator))
+    .collect();
+
+    let mut source = format!(
+        "/* Generated by aurorac, do not edit. */\n#ifndef {}\n#define {}\n\n#include <stdbool.h>\n#include <stdint.h>\n\n#ifdef __cplusplus\nextern \"C\" {{\n#endif\n\n",
+        guard, guard
+    );
+    for section in sections {
+        source.push_str(&section);
+        source.push_str("\n\n");
+    }
+    source.push_str("#ifdef __cplusplus\n}\n#endif\n\n#endif\n");
+
+    Ok(Header {
+        source,
+        skipped: gen.skipped,
+    })
+}
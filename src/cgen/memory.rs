@@ -0,0 +1,250 @@
+use crate::{
+    ast::{EnumVariant, StructField, Type},
+    sema::memory::{self, Declarations},
+};
+
+use super::{c_identifier, string_literal, CGen};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Glue {
+    Retain,
+    Release,
+}
+
+impl Declarations for CGen {
+    fn struct_fields(&self, r#type: &Type) -> Option<Vec<StructField>> {
+        CGen::struct_fields(self, r#type)
+    }
+
+    fn enum_variants(&self, name: &str) -> Option<&[EnumVariant]> {
+        self.enums.get(name).map(Vec::as_slice)
+    }
+}
+
+/// Reference counting of the values `sema::memory` says hold references,
+/// with the functions of `aurora.h`.
+impl CGen {
+    /// Whether values of `r#type` hold references.
+    pub(super) fn is_managed(&self, r#type: &Type) -> bool {
+        memory::is_managed(self, r#type)
+    }
+
+    /// The name of the function retaining or releasing values of `r#type`,
+    /// which `finish` defines.
+    fn glue_function(&mut self, glue: Glue, r#type: &Type) -> Result<String, String> {
+        let action = match glue {
+            Glue::Retain => "retain",
+            Glue::Release => "release",
+        };
+        if *r#type == Type::String {
+            return Ok(format!("aurora_{}_str", action));
+        }
+
+        if !self.glue.contains(&(glue, r#type.clone())) {
+            self.glue.push((glue, r#type.clone()));
+        }
+        let c_type = self.c_type(r#type)?;
+        Ok(format!("aurora_{}{}", action, &c_type["aur".len()..]))
+    }
+
+    /// An expression adding a reference to `value`, which it evaluates to.
+    pub(super) fn retain(&mut self, value: &str, r#type: &Type) -> Result<String, String> {
+        match self.is_managed(r#type) {
+            true => Ok(format!("{}({})", self.glue_function(Glue::Retain, r#type)?, value)),
+            false => Ok(value.to_string()),
+        }
+    }
+
+    pub(super) fn release(&mut self, value: &str, r#type: &Type) -> Result<(), String> {
+        if self.is_managed(r#type) {
+            let release = self.glue_function(Glue::Release, r#type)?;
+            self.emit(format!("{}({});", release, value));
+        }
+        Ok(())
+    }
+
+    /// Stores `value`, a new value, in a temporary released at the end of
+    /// the statement.
+    pub(super) fn owned_temporary(&mut self, r#type: &Type, value: String) -> Result<String, String> {
+        if !self.is_managed(r#type) {
+            return Ok(value);
+        }
+
+        let name = self.temporary(r#type, value)?;
+        self.temporaries.push((name.clone(), r#type.clone()));
+        Ok(name)
+    }
+
+    pub(super) fn is_temporary(&self, value: &str) -> bool {
+        self.temporaries.iter().any(|(name, _)| name == value)
+    }
+
+    /// A reference to `value` for the caller to keep, moving it out of its
+    /// temporary if it is new.
+    pub(super) fn take(&mut self, value: String, r#type: &Type) -> Result<String, String> {
+        if let Some(i) = self.temporaries.iter().position(|(name, _)| *name == value) {
+            self.temporaries.remove(i);
+            return Ok(value);
+        }
+        if value.starts_with("aurora_string_") {
+            return Ok(value);
+        }
+        self.retain(&value, r#type)
+    }
+
+    /// Releases the temporaries created since there were `mark` of them.
+    pub(super) fn release_temporaries(&mut self, mark: usize) -> Result<(), String> {
+        for (name, r#type) in self.temporaries.split_off(mark).into_iter().rev() {
+            self.release(&name, &r#type)?;
+        }
+        Ok(())
+    }
+
+    /// Makes the innermost scope release `c_name` when it ends.
+    pub(super) fn own(&mut self, c_name: &str, r#type: &Type) {
+        if self.is_managed(r#type) {
+            self.owned
+                .last_mut()
+                .unwrap()
+                .push((c_name.to_string(), r#type.clone()));
+        }
+    }
+
+    /// Releases the variables of the scopes from the `depth`th one inwards,
+    /// without ending them.
+    pub(super) fn release_scopes(&mut self, depth: usize) -> Result<(), String> {
+        let variables: Vec<(String, Type)> = self.owned[depth..]
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev().cloned())
+            .collect();
+        for (name, r#type) in variables {
+            self.release(&name, &r#type)?;
+        }
+        Ok(())
+    }
+
+    /// A string literal, which is defined with a header like the strings on
+    /// the heap but never freed.
+    pub(super) fn compile_string(&mut self, value: &str) -> String {
+        let index = match self.strings.iter().position(|s| s == value) {
+            Some(index) => index,
+            None => {
+                self.strings.push(value.to_string());
+                self.strings.len() - 1
+            }
+        };
+        format!("aurora_string_{}.chars", index)
+    }
+
+    pub(super) fn string_definitions(&self) -> Vec<String> {
+        self.strings
+            .iter()
+            .enumerate()
+            .map(|(i, value)| format!("AURORA_STRING(aurora_string_{}, {});", i, string_literal(value)))
+            .collect()
+    }
+
+    /// Prototypes and definitions of the glue functions used so far,
+    /// including those they use in turn.
+    pub(super) fn glue_definitions(&mut self) -> Result<(Vec<String>, Vec<String>), String> {
+        let mut prototypes = vec![];
+        let mut definitions = vec![];
+
+        let mut i = 0;
+        while i < self.glue.len() {
+            let (glue, r#type) = self.glue[i].clone();
+            let c_type = self.c_type(&r#type)?;
+            let name = self.glue_function(glue, &r#type)?;
+            let signature = match glue {
+                Glue::Retain => format!("static {} {}({} value)", c_type, name, c_type),
+                Glue::Release => format!("static void {}({} value)", name, c_type),
+            };
+
+            let mut body = self.glue_body(glue, &r#type)?;
+            if glue == Glue::Retain {
+                body.push("return value;".to_string());
+            }
+            prototypes.push(format!("{};", signature));
+            definitions.push(format!(
+                "{}\n{{\n{}\n}}\n",
+                signature,
+                body.iter()
+                    .map(|line| format!("    {}", line))
+                    .collect::<Vec<String>>()
+                    .join("\n")
+            ));
+            i += 1;
+        }
+
+        Ok((prototypes, definitions))
+    }
+
+    fn glue_body(&mut self, glue: Glue, r#type: &Type) -> Result<Vec<String>, String> {
+        let mut body = vec![];
+        match r#type {
+            Type::Array(element) => match glue {
+                Glue::Retain => body.push("aurora_retain(value.data);".to_string()),
+                Glue::Release => {
+                    body.push("if (aurora_release(value.data)) {".to_string());
+                    if self.is_managed(element) {
+                        body.push("    for (int64_t i = 0; i < value.len; i++) {".to_string());
+                        body.push(format!("        {}", self.glue_call(glue, "value.data[i]", element)?));
+                        body.push("    }".to_string());
+                    }
+                    body.push("    aurora_free(value.data);".to_string());
+                    body.push("}".to_string());
+                }
+            },
+            Type::FixedArray(element, size) => {
+                body.push(format!("for (int64_t i = 0; i < {}; i++) {{", size));
+                body.push(format!("    {}", self.glue_call(glue, "value.items[i]", element)?));
+                body.push("}".to_string());
+            }
+            Type::Tuple(types) => {
+                for (i, field_type) in types.iter().enumerate() {
+                    if self.is_managed(field_type) {
+                        body.push(self.glue_call(glue, &format!("value._{}", i), field_type)?);
+                    }
+                }
+            }
+            r#type => match self.struct_fields(r#type) {
+                Some(fields) => {
+                    for field in fields {
+                        if self.is_managed(&field.r#type) {
+                            let value = format!("value.{}", c_identifier(&field.name));
+                            body.push(self.glue_call(glue, &value, &field.r#type)?);
+                        }
+                    }
+                }
+                None => {
+                    let variants = self.enums[&r#type.to_string()].clone();
+                    body.push("switch (value.tag) {".to_string());
+                    for (index, variant) in variants.iter().enumerate() {
+                        if !variant.fields.iter().any(|t| self.is_managed(t)) {
+                            continue;
+                        }
+                        body.push(format!("case {}:", index));
+                        for (j, field_type) in variant.fields.iter().enumerate() {
+                            if self.is_managed(field_type) {
+                                let value = format!("value.payload.{}._{}", c_identifier(&variant.name), j);
+                                body.push(format!("    {}", self.glue_call(glue, &value, field_type)?));
+                            }
+                        }
+                        body.push("    break;".to_string());
+                    }
+                    body.push("}".to_string());
+                }
+            },
+        }
+        Ok(body)
+    }
+
+    fn glue_call(&mut self, glue: Glue, value: &str, r#type: &Type) -> Result<String, String> {
+        let function = self.glue_function(glue, r#type)?;
+        match glue {
+            Glue::Retain => Ok(format!("(void){}({});", function, value)),
+            Glue::Release => Ok(format!("{}({});", function, value)),
+        }
+    }
+}
@@ -0,0 +1,1653 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ast::{
+        expressions::{BinaryOperator, Expression, UnaryOperator},
+        assigned_variables, impl_functions, mangle_method,
+        statements::Statements,
+        EnumVariant, Program, StructField, Type, TypeParam,
+    },
+    consteval::{ConstEvaluator, Value},
+    sema::typing::{self, Inference},
+    utils::{float_digits, format_template, is_runtime_function},
+};
+
+mod enums;
+mod header;
+mod memory;
+mod types;
+
+pub use header::header;
+use memory::Glue;
+use types::unsigned;
+
+/// The runtime header the generated code includes as `aurora.h`.
+pub const RUNTIME_HEADER: &str = include_str!("aurora.h");
+
+/// C keywords and names the runtime header uses, which locals can not be
+/// called in the generated code.
+const RESERVED: &[&str] = &[
+    "auto", "break", "case", "char", "const", "continue", "default", "do", "double", "else",
+    "enum", "extern", "float", "for", "goto", "if", "inline", "int", "long", "register",
+    "restrict", "return", "short", "signed", "sizeof", "static", "struct", "switch", "typedef",
+    "union", "unsigned", "void", "volatile", "while", "_Bool", "_Complex", "_Imaginary", "bool",
+    "true", "false", "main", "abort", "exit", "fmod", "fmodf", "fmodl", "malloc", "memcpy",
+    "strlen", "strcmp", "stderr", "fprintf", "size_t", "NULL", "INFINITY", "NAN",
+];
+
+/// Escapes `name` into a C identifier. Letters and digits are kept, every
+/// other character becomes `_` and a letter, so distinct Aurora names never
+/// map to the same C name: `math.max` becomes `aur_math_Dmax` and
+/// `Pair[i32, f64]` becomes `aur_Pair_Li32_Cf64_R`.
+pub fn mangle(name: &str) -> String {
+    let mut mangled = "aur_".to_string();
+    let mut chars = name.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_ascii_alphanumeric() => mangled.push(c),
+            '_' => mangled.push_str("__"),
+            '.' => mangled.push_str("_D"),
+            ':' if chars.peek() == Some(&':') => {
+                chars.next();
+                mangled.push_str("_M");
+            }
+            '[' => mangled.push_str("_L"),
+            ']' => mangled.push_str("_R"),
+            ',' => mangled.push_str("_C"),
+            ';' => mangled.push_str("_S"),
+            '(' => mangled.push_str("_P"),
+            ')' => mangled.push_str("_Q"),
+            '-' => mangled.push_str("_H"),
+            '>' => mangled.push_str("_G"),
+            ' ' => {}
+            c => mangled.push_str(&format!("_X{:x}_", c as u32)),
+        }
+    }
+
+    mangled
+}
+
+/// Field and variant names, which only clash with C keywords.
+fn c_identifier(name: &str) -> String {
+    if RESERVED.contains(&name) {
+        format!("{}_", name)
+    } else {
+        name.to_string()
+    }
+}
+
+/// Whether evaluating `expr` may have side effects, or is affected by
+/// those of other expressions.
+fn is_pure(expr: &Expression) -> bool {
+    match expr {
+        Expression::FunctionCall { name, params } if name == "len" => params.iter().all(is_pure),
+        Expression::FunctionCall { .. } | Expression::MethodCall { .. } | Expression::Match { .. } => false,
+        Expression::StringLiteral { .. }
+        | Expression::Identifier { .. }
+        | Expression::IntLiteral { .. }
+        | Expression::BooleanLiteral { .. }
+        | Expression::FloatLiteral { .. } => true,
+        Expression::ArrayLiteral { elements } => elements.iter().all(is_pure),
+        Expression::EnumVariant { values, .. } => values.iter().all(is_pure),
+        Expression::StructLiteral { fields, .. } => fields.iter().all(|(_, value)| is_pure(value)),
+        Expression::Index { array, index } => is_pure(array) && is_pure(index),
+        Expression::FieldAccess { expr, .. } | Expression::UnaryOperation { expr, .. } => is_pure(expr),
+        Expression::BinaryOperation { lhs, rhs, .. } => is_pure(lhs) && is_pure(rhs),
+    }
+}
+
+/// Whether `value` is a C variable rather than an expression.
+fn is_name(value: &str) -> bool {
+    value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_')
+}
+
+fn is_literal(expr: &Expression) -> bool {
+    matches!(
+        expr,
+        Expression::StringLiteral { .. }
+            | Expression::IntLiteral { .. }
+            | Expression::BooleanLiteral { .. }
+            | Expression::FloatLiteral { .. }
+    )
+}
+
+/// The position of the last of `exprs` with side effects. C leaves the order
+/// operands and arguments are evaluated in unspecified, so every operand
+/// before it is stored in a temporary to evaluate them left to right like
+/// the LLVM backend does.
+fn last_impure<'e>(exprs: impl Iterator<Item = &'e Expression>) -> Option<usize> {
+    exprs
+        .enumerate()
+        .filter(|(_, expr)| !is_pure(expr))
+        .map(|(i, _)| i)
+        .last()
+}
+
+fn int_literal(value: i128, r#type: &Type) -> String {
+    let value = typing::wrap(value, r#type);
+
+    match r#type {
+        Type::Int8 => format!("((int8_t){})", value),
+        Type::Int16 => format!("((int16_t){})", value),
+        Type::Int32 if value == i32::MIN as i128 => "INT32_MIN".to_string(),
+        Type::Int32 => format!("{}", value),
+        Type::Int64 if value == i64::MIN as i128 => "INT64_MIN".to_string(),
+        Type::Int64 => format!("INT64_C({})", value),
+        _ if value == i64::MIN as i128 => "((__int128)INT64_MIN)".to_string(),
+        _ if i64::try_from(value).is_ok() => format!("((__int128)INT64_C({}))", value),
+        _ => format!(
+            "((__int128)(((unsigned __int128)UINT64_C({}) << 64) | UINT64_C({})))",
+            (value >> 64) as u64,
+            value as u64
+        ),
+    }
+}
+
+fn float_literal(value: f64, r#type: &Type) -> String {
+    let (cast, suffix) = match r#type {
+        Type::Float16 | Type::Float32 => ("float", "f"),
+        Type::Float128 => ("long double", "L"),
+        _ => ("double", ""),
+    };
+
+    if value.is_nan() {
+        format!("(({})NAN)", cast)
+    } else if value.is_infinite() {
+        let sign = if value < 0.0 { "-" } else { "" };
+        format!("(({}){}INFINITY)", cast, sign)
+    } else {
+        format!("{:?}{}", value, suffix)
+    }
+}
+
+/// A C string literal. Anything but printable ASCII is written as an octal
+/// escape, which unlike hex escapes ends after three digits.
+fn string_literal(value: &str) -> String {
+    let mut literal = "\"".to_string();
+    for byte in value.bytes() {
+        match byte {
+            b'"' => literal.push_str("\\\""),
+            b'\\' => literal.push_str("\\\\"),
+            b'\n' => literal.push_str("\\n"),
+            b'\t' => literal.push_str("\\t"),
+            // `??` starts a trigraph
+            b'?' => literal.push_str("\\?"),
+            0x20..=0x7e => literal.push(byte as char),
+            byte => literal.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    literal.push('"');
+    literal
+}
+
+/// Translates a checked program, with the modules it imports, into a single
+/// C99 source file. Like the LLVM backend it monomorphises generic code and
+/// evaluates constants at compile time, and integer arithmetic wraps around
+/// instead of being undefined on overflow. The generated code needs
+/// `__int128` for `i128`, which GCC and Clang provide.
+#[derive(Default)]
+pub struct CGen {
+    /// Parameter and return types of the functions, by Aurora name
+    functions: HashMap<String, (Vec<Type>, Type)>,
+    /// Functions defined in C
+    c_functions: HashSet<String>,
+    structs: HashMap<String, Vec<StructField>>,
+    enums: HashMap<String, Vec<EnumVariant>>,
+    generic_functions: HashMap<String, Statements>,
+    generic_structs: HashMap<String, (Vec<TypeParam>, Vec<StructField>)>,
+    instances: HashSet<String>,
+    pending_instances: Vec<(Statements, HashMap<String, Type>)>,
+    /// Type arguments of the generic function instance being translated
+    type_bindings: HashMap<String, Type>,
+    constants: ConstEvaluator,
+    globals: HashMap<String, Type>,
+    /// Aggregate types used so far, see `type_definitions`
+    types: Vec<Type>,
+    /// Functions retaining or releasing values used so far, see `memory`
+    glue: Vec<(Glue, Type)>,
+    /// String literals used so far, see `compile_string`
+    strings: Vec<String>,
+    global_definitions: Vec<String>,
+    prototypes: Vec<String>,
+    function_definitions: Vec<String>,
+    /// Local variables of the function being translated, with their C names
+    scopes: Vec<HashMap<String, (String, Type)>>,
+    /// Variables holding references, which each scope releases when it ends
+    owned: Vec<Vec<(String, Type)>>,
+    /// New values of the statement being translated, see `memory`
+    temporaries: Vec<(String, Type)>,
+    /// C names used in the function being translated
+    names: HashSet<String>,
+    /// Body of the function being translated
+    lines: Vec<String>,
+    indent: usize,
+    /// Return type of the function being translated
+    return_type: Option<Type>,
+}
+
+pub fn translate(program: &Program) -> Result<String, String> {
+    let mut cgen = CGen::default();
+
+    for statement in &program.statements {
+        match statement {
+            Statements::StructDeclaration { .. } => cgen.declare_struct(statement)?,
+            Statements::EnumDeclaration { .. } => cgen.declare_enum(statement)?,
+            Statements::ConstDeclaration { .. } => cgen
+                .constants
+                .declare(statement)
+                .map_err(|e| e.to_string())?,
+            _ => {}
+        }
+    }
+    for statement in &program.statements {
+        match statement {
+            Statements::FunctionDeclaration { .. } => cgen.declare_function(statement)?,
+            Statements::ExternFunctionDeclaration { .. } => cgen.declare_extern_function(statement)?,
+            Statements::ImplDeclaration { .. } => {
+                for method in impl_functions(statement)? {
+                    cgen.declare_function(&method)?;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    for statement in &program.statements {
+        match statement {
+            Statements::FunctionDeclaration { type_params, .. } if !type_params.is_empty() => {}
+            Statements::FunctionDeclaration { .. } => cgen.compile_function(statement)?,
+            Statements::ImplDeclaration { .. } => {
+                for method in impl_functions(statement)? {
+                    cgen.compile_function(&method)?;
+                }
+            }
+            Statements::VariableDeclaration { .. } => cgen.define_global(statement)?,
+            _ => {}
+        }
+    }
+    cgen.compile_instances()?;
+
+    cgen.finish()
+}
+
+impl CGen {
+    fn declare_struct(&mut self, r#struct: &Statements) -> Result<(), String> {
+        match r#struct {
+            Statements::StructDeclaration {
+                name,
+                type_params,
+                fields,
+            } => {
+                self.check_type_name(name)?;
+                if type_params.is_empty() {
+                    self.structs.insert(name.clone(), fields.clone());
+                } else {
+                    self.generic_structs
+                        .insert(name.clone(), (type_params.clone(), fields.clone()));
+                }
+                Ok(())
+            }
+            _ => Err("Expected StructDecl as input".to_string()),
+        }
+    }
+
+    fn declare_enum(&mut self, r#enum: &Statements) -> Result<(), String> {
+        match r#enum {
+            Statements::EnumDeclaration { name, variants } => {
+                self.check_type_name(name)?;
+                self.enums.insert(name.clone(), variants.clone());
+                Ok(())
+            }
+            _ => Err("Expected EnumDecl as input".to_string()),
+        }
+    }
+
+    fn check_type_name(&self, name: &str) -> Result<(), String> {
+        if self.structs.contains_key(name)
+            || self.enums.contains_key(name)
+            || self.generic_structs.contains_key(name)
+        {
+            return Err(format!("Type `{}` is declared more than once", name));
+        }
+        Ok(())
+    }
+
+    fn declare_function(&mut self, function: &Statements) -> Result<(), String> {
+        match function {
+            Statements::FunctionDeclaration {
+                name,
+                type_params,
+                params,
+                return_type,
+                is_const,
+                ..
+            } => {
+                if self.functions.contains_key(name) || self.generic_functions.contains_key(name) {
+                    return Err(format!("Function `{}` is declared more than once", name));
+                }
+
+                if *is_const {
+                    self.constants
+                        .declare_function(function)
+                        .map_err(|e| e.to_string())?;
+                }
+
+                if type_params.is_empty() {
+                    self.functions.insert(
+                        name.clone(),
+                        (
+                            params.iter().map(|p| p.r#type.clone()).collect(),
+                            return_type.clone(),
+                        ),
+                    );
+                } else {
+                    self.generic_functions.insert(name.clone(), function.clone());
+                }
+                Ok(())
+            }
+            _ => Err("Expected FunctionDecl as input".to_string()),
+        }
+    }
+
+    /// C functions are declared under their mangled name like any other, and
+    /// given their actual symbol with `AURORA_SYMBOL`. The declarations of
+    /// the standard headers `aurora.h` includes may not match the Aurora
+    /// signature in C's eyes, e.g. `puts` taking `int8_t *` instead of
+    /// `const char *`, which the same name would make an error.
+    fn declare_extern_function(&mut self, function: &Statements) -> Result<(), String> {
+        match function {
+            Statements::ExternFunctionDeclaration {
+                name,
+                params,
+                return_type,
+            } => {
+                let signature = (
+                    params.iter().map(|p| p.r#type.clone()).collect(),
+                    return_type.clone(),
+                );
+                // Every module using a C function declares it
+                match self.functions.get(name) {
+                    Some(declared) if *declared == signature => return Ok(()),
+                    Some(_) => {
+                        return Err(format!("Function `{}` is declared more than once", name))
+                    }
+                    None => {}
+                }
+
+                let c_params = match params.is_empty() {
+                    true => "void".to_string(),
+                    false => params
+                        .iter()
+                        .map(|p| self.c_type(&p.r#type))
+                        .collect::<Result<Vec<String>, String>>()?
+                        .join(", "),
+                };
+                let declaration = self.c_declaration(return_type, &mangle(name))?;
+                self.prototypes.push(format!(
+                    "{}({}) AURORA_SYMBOL(\"{}\");",
+                    declaration, c_params, name
+                ));
+                self.functions.insert(name.clone(), signature);
+                self.c_functions.insert(name.clone());
+                Ok(())
+            }
+            _ => Err("Expected ExternFunctionDecl as input".to_string()),
+        }
+    }
+
+    /// Module-level `let`s become C globals with a constant initialiser.
+    fn define_global(&mut self, global: &Statements) -> Result<(), String> {
+        match global {
+            Statements::VariableDeclaration {
+                name,
+                r#type,
+                value,
+                ..
+            } => {
+                if !(r#type.is_integer() || r#type.is_float() || matches!(r#type, Type::Boolean | Type::String)) {
+                    return Err(format!(
+                        "`{}` has type {}, but globals and constants can only be numbers, bools or strings",
+                        name, r#type
+                    ));
+                }
+
+                let value = self.constants.evaluate(value, r#type).map_err(|e| {
+                    format!("Initialiser of global `{}` is not a constant: {}", name, e)
+                })?;
+                let declaration = self.c_declaration(r#type, &mangle(name))?;
+                let value = self.constant_value(&value, r#type);
+                self.global_definitions
+                    .push(format!("{} = {};", declaration, value));
+                self.globals.insert(name.clone(), r#type.clone());
+                Ok(())
+            }
+            _ => Err("Expected VariableDecl as input".to_string()),
+        }
+    }
+
+    /// Assembles the C file from the definitions translated so far. The
+    /// Aurora `main` is called by a C `main`, which turns its result into the
+    /// exit status.
+    fn finish(mut self) -> Result<String, String> {
+        let main = match self.functions.get("main") {
+            Some((_, Type::Int32)) => Some(format!("int main(void)\n{{\n    return {}();\n}}", mangle("main"))),
+            Some((_, Type::Void)) => Some(format!(
+                "int main(void)\n{{\n    {}();\n    return 0;\n}}",
+                mangle("main")
+            )),
+            Some((_, r#type)) => return Err(format!("`main` must return i32 or void, not {}", r#type)),
+            None => None,
+        };
+
+        let mut sections = vec![
+            "/* Generated by aurorac. */".to_string(),
+            "#include \"aurora.h\"".to_string(),
+            String::new(),
+        ];
+        // Glue uses types, but no new glue
+        let (glue_prototypes, glue_definitions) = self.glue_definitions()?;
+        sections.extend(self.type_definitions()?);
+        let strings = self.string_definitions();
+        for part in [&strings, &self.global_definitions, &glue_prototypes, &self.prototypes] {
+            if !part.is_empty() {
+                sections.extend(part.iter().cloned());
+                sections.push(String::new());
+            }
+        }
+        sections.extend(glue_definitions);
+        sections.extend(self.function_definitions.iter().cloned());
+        sections.extend(main);
+
+        Ok(sections.join("\n") + "\n")
+    }
+
+    fn compile_function(&mut self, function: &Statements) -> Result<(), String> {
+        let (name, params, body, return_type, is_extern) = match function {
+            Statements::FunctionDeclaration {
+                name,
+                params,
+                body,
+                return_type,
+                is_extern,
+                ..
+            } => (name, params, body, return_type, is_extern),
+            _ => return Err("Expected FunctionDecl as input".to_string()),
+        };
+
+        self.names.clear();
+        self.scopes = vec![HashMap::new()];
+        self.owned = vec![vec![]];
+        self.return_type = Some(return_type.clone());
+
+        let mut c_params = vec![];
+        for param in params {
+            let c_name = self.fresh_name(&param.name);
+            c_params.push(self.c_declaration(&param.r#type, &c_name)?);
+            self.declare_variable(&param.name, c_name, param.r#type.clone());
+        }
+        let c_params = match c_params.is_empty() {
+            true => "void".to_string(),
+            false => c_params.join(", "),
+        };
+        let signature = format!(
+            "{}({})",
+            self.c_declaration(return_type, &mangle(name))?,
+            c_params
+        );
+
+        self.indent = 1;
+        // Arguments are borrowed, but a parameter that is assigned to
+        // releases its old value. Strings from C have no header, and are
+        // copied to one that does.
+        let assigned = assigned_variables(body);
+        for param in params {
+            let (c_name, r#type) = self.lookup_variable(&param.name)?;
+            if *is_extern && r#type == Type::String {
+                self.emit(format!("{} = aurora_string_copy({});", c_name, c_name));
+            } else if assigned.contains(&param.name) && self.is_managed(&r#type) {
+                let retained = self.retain(&c_name, &r#type)?;
+                self.emit(format!("(void){};", retained));
+            } else {
+                continue;
+            }
+            self.own(&c_name, &r#type);
+        }
+
+        self.compile_block(body)?;
+        if !matches!(body.last(), Some(Statements::Return { .. })) {
+            if *return_type == Type::Void {
+                self.release_scopes(0)?;
+            } else {
+                // Only reachable when every branch of a trailing `if`
+                // returned
+                self.emit("aurora_unreachable();".to_string());
+            }
+        }
+
+        // C calls `public extern "C"` functions by their plain name, see
+        // `declare_extern_function`
+        match is_extern {
+            true => self
+                .prototypes
+                .push(format!("{} AURORA_SYMBOL(\"{}\");", signature, name)),
+            false => self.prototypes.push(format!("{};", signature)),
+        }
+        self.function_definitions.push(format!(
+            "{}\n{{\n{}\n}}\n",
+            signature,
+            std::mem::take(&mut self.lines).join("\n")
+        ));
+        self.scopes.clear();
+        self.owned.clear();
+        Ok(())
+    }
+
+    /// Translates the instances of generic functions requested so far,
+    /// including any instances those request in turn.
+    fn compile_instances(&mut self) -> Result<(), String> {
+        while let Some((instance, bindings)) = self.pending_instances.pop() {
+            self.type_bindings = bindings;
+            self.compile_function(&instance)?;
+        }
+
+        self.type_bindings.clear();
+        Ok(())
+    }
+
+    fn emit(&mut self, line: String) {
+        self.lines.push(format!("{}{}", "    ".repeat(self.indent), line));
+    }
+
+    /// A C name for a new local based on `name`, which no other local of the
+    /// function has. Aurora allows shadowing where C does not.
+    fn fresh_name(&mut self, name: &str) -> String {
+        let mut c_name = name.to_string();
+        let mut i = 1;
+        while self.names.contains(&c_name)
+            || RESERVED.contains(&c_name.as_str())
+            || c_name.starts_with("aur")
+            || c_name.starts_with('_')
+        {
+            c_name = format!("{}_{}", name.trim_start_matches('_'), i);
+            i += 1;
+        }
+
+        self.names.insert(c_name.clone());
+        c_name
+    }
+
+    /// Stores `value` in a new local, so that it is evaluated at this point.
+    fn temporary(&mut self, r#type: &Type, value: String) -> Result<String, String> {
+        let name = self.fresh_name("tmp");
+        let declaration = self.c_declaration(r#type, &name)?;
+        self.emit(format!("{} = {};", declaration, value));
+        Ok(name)
+    }
+
+    /// Stores `value`, the `index`th of a list of operands, in a temporary if
+    /// operands after it have side effects.
+    fn sequence(
+        &mut self,
+        index: usize,
+        last_impure: Option<usize>,
+        expr: &Expression,
+        value: String,
+        r#type: &Type,
+    ) -> Result<String, String> {
+        match last_impure {
+            Some(last) if index < last && !is_literal(expr) && !self.is_temporary(&value) => {
+                self.temporary(r#type, value)
+            }
+            _ => Ok(value),
+        }
+    }
+
+    fn declare_variable(&mut self, name: &str, c_name: String, r#type: Type) {
+        self.scopes
+            .last_mut()
+            .unwrap()
+            .insert(name.to_string(), (c_name, r#type));
+    }
+
+    fn variable(&self, name: &str) -> Option<(String, Type)> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).cloned())
+            .or_else(|| self.globals.get(name).map(|r#type| (mangle(name), r#type.clone())))
+    }
+
+    fn lookup_variable(&self, name: &str) -> Result<(String, Type), String> {
+        self.variable(name).ok_or(format!("Unknown variable `{}`", name))
+    }
+
+    /// Whether `name` is a function used as a value, which variables of the
+    /// same name hide.
+    fn is_function_value(&self, name: &str) -> bool {
+        self.variable(name).is_none() && self.functions.contains_key(name)
+    }
+
+    /// A pointer to the function `name`, which C converts its name to.
+    fn compile_function_value(&self, name: &str) -> (String, Type) {
+        let (params, return_type) = self.functions[name].clone();
+        (
+            mangle(name),
+            Type::Function {
+                params,
+                return_type: Box::new(return_type),
+            },
+        )
+    }
+
+    fn is_constant(&self, name: &str) -> bool {
+        self.constants.is_constant(name) && !self.scopes.iter().any(|scope| scope.contains_key(name))
+    }
+
+    fn compile_constant(&mut self, name: &str) -> Result<(String, Type), String> {
+        let r#type = self.constants.type_of(name).unwrap().clone();
+        let value = self.constants.value(name).map_err(|e| e.to_string())?;
+        Ok((self.constant_value(&value, &r#type), r#type))
+    }
+
+    /// The C expression for a constant, unlike `constant_literal` with
+    /// strings that have a header.
+    fn constant_value(&mut self, value: &Value, r#type: &Type) -> String {
+        match value {
+            Value::String(value) => self.compile_string(value),
+            value => constant_literal(value, r#type),
+        }
+    }
+
+    fn resolve_type(&self, r#type: &Type) -> Type {
+        r#type.substitute(&self.type_bindings)
+    }
+
+    fn compile_block(&mut self, block: &[Statements]) -> Result<(), String> {
+        self.scopes.push(HashMap::new());
+        self.owned.push(vec![]);
+
+        let mut returned = false;
+        for statement in block {
+            self.compile_statement(statement)?;
+            // Anything after a `return` can never run
+            if matches!(statement, Statements::Return { .. }) {
+                returned = true;
+                break;
+            }
+        }
+
+        // A `return` released the variables already
+        if !returned {
+            self.release_scopes(self.owned.len() - 1)?;
+        }
+        self.owned.pop();
+        self.scopes.pop();
+        Ok(())
+    }
+
+    /// Translates `block` into a braced C block.
+    fn compile_nested_block(&mut self, block: &[Statements]) -> Result<(), String> {
+        self.indent += 1;
+        let result = self.compile_block(block);
+        self.indent -= 1;
+        result
+    }
+
+    fn compile_statement(&mut self, statement: &Statements) -> Result<(), String> {
+        let mark = self.temporaries.len();
+
+        match statement {
+            Statements::VariableDeclaration {
+                name,
+                value,
+                r#type,
+                ..
+            } => {
+                let r#type = self.resolve_type(r#type);
+                let value = self.compile_expression_as(value, &r#type)?;
+                let value = self.take(value, &r#type)?;
+                let c_name = self.fresh_name(name);
+                let declaration = self.c_declaration(&r#type, &c_name)?;
+                self.emit(format!("{} = {};", declaration, value));
+                self.own(&c_name, &r#type);
+                self.declare_variable(name, c_name, r#type);
+            }
+            Statements::Assignment { target, value, .. } => {
+                let (place, r#type) = self.compile_place(target)?;
+                let value = self.compile_expression_as(value, &r#type)?;
+
+                if self.is_managed(&r#type) {
+                    // The new value is retained before the old one is
+                    // released, which may be the same
+                    let value = self.take(value, &r#type)?;
+                    let value = match is_name(&value) {
+                        true => value,
+                        false => self.temporary(&r#type, value)?,
+                    };
+                    let place = match is_name(&place) {
+                        true => place,
+                        false => {
+                            let slot = self.fresh_name("slot");
+                            let declaration =
+                                self.c_declaration(&Type::Pointer(Box::new(r#type.clone())), &slot)?;
+                            self.emit(format!("{} = &{};", declaration, place));
+                            format!("*{}", slot)
+                        }
+                    };
+                    self.release(&place, &r#type)?;
+                    self.emit(format!("{} = {};", place, value));
+                } else {
+                    self.emit(format!("{} = {};", place, value));
+                }
+            }
+            Statements::Return { value, .. } => {
+                let return_type = self.return_type.clone().unwrap_or(Type::Void);
+
+                match value {
+                    Some(value) => {
+                        let value = self.compile_expression_as(value, &return_type)?;
+                        let value = self.take(value, &return_type)?;
+                        // The value may read from what is released
+                        let releases = self.temporaries.len() > mark || self.owned.iter().any(|scope| !scope.is_empty());
+                        let value = match releases && !is_name(&value) {
+                            true => self.temporary(&return_type, value)?,
+                            false => value,
+                        };
+                        self.release_temporaries(mark)?;
+                        self.release_scopes(0)?;
+                        self.emit(format!("return {};", value));
+                    }
+                    None if return_type == Type::Void => {
+                        self.release_temporaries(mark)?;
+                        self.release_scopes(0)?;
+                        self.emit("return;".to_string());
+                    }
+                    None => return Err(format!("Expected a return value of type {}", return_type)),
+                }
+            }
+            Statements::If {
+                condition,
+                body,
+                else_body,
+                ..
+            } => {
+                let condition = self.compile_condition(condition)?;
+                self.emit(format!("if ({}) {{", condition));
+                self.compile_nested_block(body)?;
+                if !else_body.is_empty() {
+                    self.emit("} else {".to_string());
+                    self.compile_nested_block(else_body)?;
+                }
+                self.emit("}".to_string());
+            }
+            Statements::While { condition, body, .. } => {
+                // A condition that needs statements of its own is evaluated
+                // at the top of the loop body
+                let lines = std::mem::take(&mut self.lines);
+                self.indent += 1;
+                let condition = self.compile_condition(condition);
+                self.indent -= 1;
+                let condition_lines = std::mem::replace(&mut self.lines, lines);
+                let condition = condition?;
+
+                if condition_lines.is_empty() {
+                    self.emit(format!("while ({}) {{", condition));
+                } else {
+                    self.emit("while (1) {".to_string());
+                    self.lines.extend(condition_lines);
+                    self.indent += 1;
+                    self.emit(format!("if (!{}) break;", condition));
+                    self.indent -= 1;
+                }
+                self.compile_nested_block(body)?;
+                self.emit("}".to_string());
+            }
+            Statements::ExpressionStatement { expr, .. } => match expr {
+                Expression::Match { expr, arms } => {
+                    self.compile_match(expr, arms, Some(&Type::Void))?;
+                }
+                // A new value is already stored in a temporary
+                Expression::FunctionCall { .. } | Expression::MethodCall { .. } => {
+                    if let (Some(value), _) = self.compile_value_or_void(expr, None)? {
+                        if !self.is_temporary(&value) {
+                            self.emit(format!("{};", value));
+                        }
+                    }
+                }
+                expr => {
+                    let (value, _) = self.compile_expression(expr, None)?;
+                    if !self.is_temporary(&value) {
+                        self.emit(format!("(void){};", value));
+                    }
+                }
+            },
+            Statements::FunctionDeclaration { .. } => {
+                return Err("Nested functions are not supported".to_string())
+            }
+            Statements::ExternFunctionDeclaration { .. } => {
+                return Err("Extern functions must be declared at the top level".to_string())
+            }
+            Statements::StructDeclaration { .. } => {
+                return Err("Structs must be declared at the top level".to_string())
+            }
+            Statements::EnumDeclaration { .. } => {
+                return Err("Enums must be declared at the top level".to_string())
+            }
+            Statements::ImplDeclaration { .. } => {
+                return Err("Impl blocks must be at the top level".to_string())
+            }
+            Statements::TraitDeclaration { .. } => {
+                return Err("Traits must be declared at the top level".to_string())
+            }
+            Statements::ConstDeclaration { .. } => {
+                return Err("Constants must be declared at the top level".to_string())
+            }
+            Statements::ModuleDeclaration { .. } => {
+                return Err("Module declarations must be at the top of the file".to_string())
+            }
+            Statements::Import { .. } => {
+                return Err("Imports must be at the top of the file".to_string())
+            }
+        }
+
+        self.release_temporaries(mark)
+    }
+
+    /// Translates a condition, releasing the new values it needed before
+    /// the branch.
+    fn compile_condition(&mut self, condition: &Expression) -> Result<String, String> {
+        let mark = self.temporaries.len();
+        let value = match self.compile_expression(condition, Some(&Type::Boolean))? {
+            (value, Type::Boolean) => value,
+            (_, r#type) => return Err(format!("Expected a condition of type bool, found {}", r#type)),
+        };
+
+        if self.temporaries.len() == mark {
+            return Ok(value);
+        }
+        let value = self.temporary(&Type::Boolean, value)?;
+        self.release_temporaries(mark)?;
+        Ok(value)
+    }
+
+    /// Translates `expr` and converts the result to `r#type`.
+    fn compile_expression_as(&mut self, expr: &Expression, r#type: &Type) -> Result<String, String> {
+        let (value, found) = self.compile_expression(expr, Some(r#type))?;
+        self.coerce(value, &found, r#type)
+    }
+
+    fn coerce(&mut self, value: String, from: &Type, to: &Type) -> Result<String, String> {
+        match (from, to) {
+            (from, to) if from == to => Ok(value),
+            (from, to) if from.is_integer() && to.is_integer() => {
+                Ok(format!("(({}){})", self.c_type(to)?, value))
+            }
+            // C takes strings as `char *`
+            (Type::String, Type::Pointer(pointee)) if **pointee == Type::Int8 => {
+                Ok(format!("((int8_t *){})", value))
+            }
+            (Type::Pointer(_), Type::Pointer(pointee)) if **pointee == Type::Void => {
+                Ok(format!("((void *){})", value))
+            }
+            (Type::FixedArray(element, size), Type::Array(expected)) if element == expected => {
+                // The elements are copied to the heap so the dynamic array
+                // can outlive the fixed one, and keep their references
+                let array = self.take(value, from)?;
+                let array = match is_name(&array) {
+                    true => array,
+                    false => self.temporary(from, array)?,
+                };
+                let value = format!(
+                    "({}){{ aurora_copy({}.items, sizeof({}.items)), INT64_C({}) }}",
+                    self.c_type(to)?,
+                    array,
+                    array,
+                    size
+                );
+                self.owned_temporary(to, value)
+            }
+            (from, to) => Err(format!("Mismatched types: expected {}, found {}", to, from)),
+        }
+    }
+
+    fn compile_expression(&mut self, expr: &Expression, hint: Option<&Type>) -> Result<(String, Type), String> {
+        match expr {
+            Expression::StringLiteral { val } => Ok((self.compile_string(val), Type::String)),
+            Expression::IntLiteral { val } => {
+                let r#type = typing::int_literal_type(hint);
+                match r#type.is_float() {
+                    true => Ok((float_literal(*val as f64, &r#type), r#type)),
+                    false => Ok((int_literal(*val as i128, &r#type), r#type)),
+                }
+            }
+            Expression::FloatLiteral { val } => {
+                let r#type = typing::float_literal_type(hint);
+                Ok((float_literal(*val, &r#type), r#type))
+            }
+            Expression::BooleanLiteral { val } => Ok((val.to_string(), Type::Boolean)),
+            Expression::Identifier { val } if self.is_constant(val) => self.compile_constant(val),
+            Expression::Identifier { val } if self.is_function_value(val) => Ok(self.compile_function_value(val)),
+            Expression::Identifier { .. } | Expression::FieldAccess { .. } | Expression::Index { .. } => {
+                let (value, r#type) = self.compile_place(expr)?;
+                // A function could otherwise release a value it was passed
+                // by assigning to where it came from
+                if self.is_shared_place(expr) && self.is_managed(&r#type) {
+                    let value = self.retain(&value, &r#type)?;
+                    return Ok((self.owned_temporary(&r#type, value)?, r#type));
+                }
+                Ok((value, r#type))
+            }
+            Expression::FunctionCall { name, .. } | Expression::MethodCall { method: name, .. } => {
+                match self.compile_value_or_void(expr, hint)? {
+                    (Some(value), r#type) if r#type != Type::Void => Ok((value, r#type)),
+                    _ => Err(format!("`{}` does not return a value", name)),
+                }
+            }
+            Expression::ArrayLiteral { elements } => self.compile_array_literal(elements, hint),
+            Expression::StructLiteral { name, fields } => self.compile_struct_literal(name, fields, hint),
+            Expression::EnumVariant {
+                enum_name,
+                variant,
+                values,
+            } => self.compile_enum_variant(enum_name, variant, values),
+            Expression::Match { expr, arms } => match self.compile_match(expr, arms, hint)? {
+                (Some(value), r#type) => Ok((value, r#type)),
+                (None, _) => Err("`match` does not produce a value".to_string()),
+            },
+            Expression::BinaryOperation { op, lhs, rhs } => self.compile_binary_operation(*op, lhs, rhs, hint),
+            Expression::UnaryOperation { op, expr } => {
+                let (value, r#type) = self.compile_expression(expr, hint)?;
+                let r#type = typing::unary_type(*op, &r#type)?;
+
+                let value = match op {
+                    UnaryOperator::Negate if r#type.is_integer() => {
+                        let c_type = self.c_type(&r#type)?;
+                        format!("(({})-({}){})", c_type, unsigned(&c_type), value)
+                    }
+                    UnaryOperator::Negate => format!("(-{})", value),
+                    UnaryOperator::Not => format!("(!{})", value),
+                };
+
+                Ok((value, r#type))
+            }
+        }
+    }
+
+    /// Translates an expression that is allowed to have no value, such as a
+    /// call to a void function. A `match` used as a statement has no C
+    /// expression at all.
+    fn compile_value_or_void(
+        &mut self,
+        expr: &Expression,
+        hint: Option<&Type>,
+    ) -> Result<(Option<String>, Type), String> {
+        match expr {
+            Expression::FunctionCall { name, params } => {
+                self.compile_call(name, params, hint).map(|(v, t)| (Some(v), t))
+            }
+            Expression::MethodCall {
+                receiver,
+                method,
+                params,
+            } => self
+                .compile_method_call(receiver, method, params)
+                .map(|(v, t)| (Some(v), t)),
+            Expression::Match { expr, arms } => self.compile_match(expr, arms, hint),
+            expr => {
+                let (value, r#type) = self.compile_expression(expr, hint)?;
+                Ok((Some(value), r#type))
+            }
+        }
+    }
+
+    /// Translates an assignable expression, or a field or element of any
+    /// value, to a C lvalue.
+    fn compile_place(&mut self, expr: &Expression) -> Result<(String, Type), String> {
+        match expr {
+            Expression::Identifier { val } if self.is_constant(val) => {
+                Err(format!("Can not assign to constant `{}`", val))
+            }
+            Expression::Identifier { val } => self.lookup_variable(val),
+            Expression::FieldAccess { expr, field } => {
+                let (value, struct_type) = self.compile_place_or_value(expr)?;
+
+                let field = self
+                    .struct_fields(&struct_type)
+                    .and_then(|fields| fields.into_iter().find(|f| &f.name == field))
+                    .ok_or(format!("{} has no field `{}`", struct_type, field))?;
+
+                Ok((format!("{}.{}", value, c_identifier(&field.name)), field.r#type))
+            }
+            Expression::Index { array, index } => {
+                let (mut value, array_type) = self.compile_place_or_value(array)?;
+
+                let index = match self.compile_expression(index, Some(&Type::Int64))? {
+                    (index, Type::Int64) => index,
+                    (index, r#type) if r#type.is_integer() => format!("((int64_t){})", index),
+                    (_, r#type) => return Err(format!("Can not index with a value of type {}", r#type)),
+                };
+
+                match &array_type {
+                    Type::FixedArray(element, size) => Ok((
+                        format!("{}.items[aurora_check_index({}, INT64_C({}))]", value, index, size),
+                        *element.clone(),
+                    )),
+                    Type::Array(element) => {
+                        // The array is used twice, for its data and length
+                        if !is_pure(array) {
+                            value = self.temporary(&array_type, value)?;
+                        }
+                        Ok((
+                            format!("{}.data[aurora_check_index({}, {}.len)]", value, index, value),
+                            *element.clone(),
+                        ))
+                    }
+                    r#type => Err(format!("Can not index into a value of type {}", r#type)),
+                }
+            }
+            _ => Err("Can not assign to this expression".to_string()),
+        }
+    }
+
+    /// Whether the place `expr` is reachable from other functions, through a
+    /// global or the elements of an array.
+    fn is_shared_place(&self, expr: &Expression) -> bool {
+        match expr {
+            Expression::Identifier { val } => {
+                self.globals.contains_key(val) && !self.scopes.iter().any(|scope| scope.contains_key(val))
+            }
+            Expression::FieldAccess { expr, .. } => self.is_shared_place(expr),
+            Expression::Index { .. } => true,
+            _ => false,
+        }
+    }
+
+    fn compile_place_or_value(&mut self, expr: &Expression) -> Result<(String, Type), String> {
+        match expr {
+            Expression::Identifier { val } if !self.is_constant(val) => self.compile_place(expr),
+            Expression::Index { .. } | Expression::FieldAccess { .. } => self.compile_place(expr),
+            expr => self.compile_expression(expr, None),
+        }
+    }
+
+    fn compile_array_literal(
+        &mut self,
+        elements: &[Expression],
+        hint: Option<&Type>,
+    ) -> Result<(String, Type), String> {
+        let mut element_type = match hint {
+            Some(Type::Array(r#type)) | Some(Type::FixedArray(r#type, _)) => Some(*r#type.clone()),
+            _ => None,
+        };
+
+        let last_impure = last_impure(elements.iter());
+        let mut values: Vec<String> = vec![];
+        for (i, element) in elements.iter().enumerate() {
+            let (value, r#type) = match element_type.clone() {
+                Some(r#type) => (self.compile_expression_as(element, &r#type)?, r#type),
+                None => {
+                    let (value, r#type) = self.compile_expression(element, None)?;
+                    element_type = Some(r#type.clone());
+                    (value, r#type)
+                }
+            };
+            let value = self.sequence(i, last_impure, element, value, &r#type)?;
+            values.push(self.take(value, &r#type)?);
+        }
+
+        let element_type = match element_type {
+            Some(r#type) => r#type,
+            None => return Err("Can not infer the element type of an empty array".to_string()),
+        };
+        let c_element = self.c_type(&element_type)?;
+
+        if let Some(Type::Array(_)) = hint {
+            let r#type = Type::Array(Box::new(element_type));
+            let value = format!(
+                "({}){{ aurora_copy(({}[]){{ {} }}, sizeof({}[{}])), INT64_C({}) }}",
+                self.c_type(&r#type)?,
+                c_element,
+                values.join(", "),
+                c_element,
+                values.len(),
+                values.len()
+            );
+            return Ok((self.owned_temporary(&r#type, value)?, r#type));
+        }
+
+        let r#type = Type::FixedArray(Box::new(element_type), values.len());
+        let value = format!("({}){{ {{ {} }} }}", self.c_type(&r#type)?, values.join(", "));
+        Ok((self.owned_temporary(&r#type, value)?, r#type))
+    }
+
+    /// Translates a struct literal to a C compound literal. The type
+    /// arguments of a generic struct are taken from `hint` or inferred from
+    /// the field values.
+    fn compile_struct_literal(
+        &mut self,
+        name: &str,
+        values: &[(String, Expression)],
+        hint: Option<&Type>,
+    ) -> Result<(String, Type), String> {
+        let (type_params, fields) = match (self.structs.get(name), self.generic_structs.get(name)) {
+            (Some(fields), _) => (vec![], fields.clone()),
+            (None, Some((type_params, fields))) => (type_params.clone(), fields.clone()),
+            (None, None) => return Err(format!("Unknown struct `{}`", name)),
+        };
+
+        for (field, _) in values {
+            if !fields.iter().any(|f| &f.name == field) {
+                return Err(format!("{} has no field `{}`", name, field));
+            }
+            if values.iter().filter(|(f, _)| f == field).count() > 1 {
+                return Err(format!("Field `{}` is specified more than once", field));
+            }
+        }
+
+        let mut inference = Inference::with_hint(name, &type_params, hint);
+
+        // Fields are evaluated in declaration order, like in the LLVM backend
+        let mut exprs = vec![];
+        for field in &fields {
+            let expr = values
+                .iter()
+                .find(|(f, _)| f == &field.name)
+                .map(|(_, expr)| expr)
+                .ok_or(format!("Missing field `{}` in {} literal", field.name, name))?;
+            exprs.push(expr);
+        }
+
+        let last_impure = last_impure(exprs.iter().copied());
+        let mut field_values: Vec<String> = vec![];
+        for (i, (field, expr)) in fields.iter().zip(exprs).enumerate() {
+            let (value, r#type) = match inference.expected(&field.r#type) {
+                Some(field_type) => (self.compile_expression_as(expr, &field_type)?, field_type),
+                None => {
+                    let (value, found) = self.compile_expression(expr, None)?;
+                    inference.unify(&field.r#type, &found)?;
+                    (value, found)
+                }
+            };
+            let value = self.sequence(i, last_impure, expr, value, &r#type)?;
+            let value = self.take(value, &r#type)?;
+            field_values.push(format!(".{} = {}", c_identifier(&field.name), value));
+        }
+
+        let r#type = if type_params.is_empty() {
+            Type::UserDefinedType {
+                name: name.to_string(),
+            }
+        } else {
+            let params = inference.type_arguments()?;
+            Type::Generic {
+                name: name.to_string(),
+                params,
+            }
+        };
+
+        if field_values.is_empty() {
+            field_values.push("0".to_string());
+        }
+        let value = format!("({}){{ {} }}", self.c_type(&r#type)?, field_values.join(", "));
+        Ok((self.owned_temporary(&r#type, value)?, r#type))
+    }
+
+    fn compile_binary_operation(
+        &mut self,
+        op: BinaryOperator,
+        lhs: &Expression,
+        rhs: &Expression,
+        hint: Option<&Type>,
+    ) -> Result<(String, Type), String> {
+        use BinaryOperator::*;
+
+        let last_impure = last_impure([lhs, rhs].into_iter());
+        let (l, lhs_type) = self.compile_expression(lhs, typing::operand_hint(op, hint))?;
+        let l = self.sequence(0, last_impure, lhs, l, &lhs_type)?;
+        let (r, rhs_type) = self.compile_expression(rhs, Some(&lhs_type))?;
+
+        let r#type = typing::operand_type(op, &lhs_type, &rhs_type)?;
+        let l = self.coerce(l, &lhs_type, &r#type)?;
+        let r = self.coerce(r, &rhs_type, &r#type)?;
+        let result_type = typing::binary_type(op, &r#type)?;
+
+        let c_op = match op {
+            Add => "+",
+            Subtract => "-",
+            Multiply => "*",
+            Divide => "/",
+            Modulo => "%",
+            Equal => "==",
+            NotEqual => "!=",
+            LessThan => "<",
+            LessThanOrEqual => "<=",
+            GreaterThan => ">",
+            GreaterThanOrEqual => ">=",
+        };
+
+        // Like in the interpreter, strings can be concatenated and compared
+        if r#type == Type::String {
+            let value = match op {
+                Add => {
+                    let value = format!("aurora_concat({}, {})", l, r);
+                    self.owned_temporary(&Type::String, value)?
+                }
+                Equal => format!("(strcmp({}, {}) == 0)", l, r),
+                _ => format!("(strcmp({}, {}) != 0)", l, r),
+            };
+            return Ok((value, result_type));
+        }
+
+        if typing::is_comparison(op) {
+            return Ok((format!("({} {} {})", l, c_op, r), result_type));
+        }
+
+        let c_type = self.c_type(&r#type)?;
+        let value = match op {
+            // Signed overflow is undefined in C, unsigned arithmetic wraps
+            // around
+            Add | Subtract | Multiply if r#type.is_integer() => {
+                let u = unsigned(&c_type);
+                format!("(({})(({}){} {} ({}){}))", c_type, u, l, c_op, u, r)
+            }
+            // Checked like in the other backends, see `aurora.h`
+            Divide if r#type.is_integer() => format!("aurora_div_{}({}, {})", r#type, l, r),
+            Modulo if r#type.is_integer() => format!("aurora_rem_{}({}, {})", r#type, l, r),
+            Modulo => match c_type.as_str() {
+                "float" => format!("fmodf({}, {})", l, r),
+                "double" => format!("fmod({}, {})", l, r),
+                _ => format!("fmodl({}, {})", l, r),
+            },
+            // Rounded to the type, since C may compute in a wider one
+            _ => format!("(({})({} {} {}))", c_type, l, c_op, r),
+        };
+        Ok((value, result_type))
+    }
+
+    fn compile_call(
+        &mut self,
+        name: &str,
+        params: &[Expression],
+        hint: Option<&Type>,
+    ) -> Result<(String, Type), String> {
+        // Variables holding functions hide the functions of the same name
+        if let Some((variable, r#type @ Type::Function { .. })) = self.variable(name) {
+            return self.compile_indirect_call(name, &variable, &r#type, params);
+        }
+        if name == "len" {
+            return self.compile_len(params);
+        }
+        if name == "format" {
+            return self.compile_format(params);
+        }
+        if name == "resize" {
+            return self.compile_resize(params);
+        }
+        if let Some(r#type) = typing::conversion_type(name) {
+            return self.compile_conversion(name, &r#type, params);
+        }
+
+        if let Some(function) = self.generic_functions.get(name).cloned() {
+            return self.compile_generic_call(&function, params, hint);
+        }
+
+        let (param_types, return_type) = self
+            .functions
+            .get(name)
+            .cloned()
+            .ok_or(format!("Unknown function `{}`", name))?;
+
+        if param_types.len() != params.len() {
+            return Err(format!(
+                "`{}` takes {} arguments but {} were supplied",
+                name,
+                param_types.len(),
+                params.len()
+            ));
+        }
+
+        let last_impure = last_impure(params.iter());
+        let mut args = vec![];
+        for (i, (param, r#type)) in params.iter().zip(param_types.iter()).enumerate() {
+            let value = self.compile_expression_as(param, r#type)?;
+            args.push(self.sequence(i, last_impure, param, value, r#type)?);
+        }
+
+        let value = format!("{}({})", mangle(name), args.join(", "));
+        // Strings C returns are its own to free
+        let value = match self.c_functions.contains(name) && !is_runtime_function(name) {
+            true if return_type == Type::String => format!("aurora_string_copy({})", value),
+            _ => value,
+        };
+        Ok((self.owned_temporary(&return_type, value)?, return_type))
+    }
+
+    /// Calls the function whose pointer the variable `name`, `variable` in C,
+    /// of the function type `r#type` holds.
+    fn compile_indirect_call(
+        &mut self,
+        name: &str,
+        variable: &str,
+        r#type: &Type,
+        params: &[Expression],
+    ) -> Result<(String, Type), String> {
+        let (param_types, return_type) = match r#type {
+            Type::Function {
+                params,
+                return_type,
+            } => (params, return_type.as_ref().clone()),
+            r#type => return Err(format!("`{}` of type {} is not a function", name, r#type)),
+        };
+
+        if param_types.len() != params.len() {
+            return Err(format!(
+                "`{}` takes {} arguments but {} were supplied",
+                name,
+                param_types.len(),
+                params.len()
+            ));
+        }
+
+        let last_impure = last_impure(params.iter());
+        let mut args = vec![];
+        for (i, (param, r#type)) in params.iter().zip(param_types.iter()).enumerate() {
+            let value = self.compile_expression_as(param, r#type)?;
+            args.push(self.sequence(i, last_impure, param, value, r#type)?);
+        }
+
+        let value = format!("{}({})", variable, args.join(", "));
+        Ok((self.owned_temporary(&return_type, value)?, return_type))
+    }
+
+    /// `receiver.method(params)` calls the method of the receiver's type with
+    /// the receiver as its `self` argument.
+    fn compile_method_call(
+        &mut self,
+        receiver: &Expression,
+        method: &str,
+        params: &[Expression],
+    ) -> Result<(String, Type), String> {
+        let last_impure = last_impure([receiver].into_iter().chain(params));
+        let (value, receiver_type) = self.compile_expression(receiver, None)?;
+        let value = self.sequence(0, last_impure, receiver, value, &receiver_type)?;
+        let name = mangle_method(&receiver_type, method);
+
+        let (param_types, return_type) = self
+            .functions
+            .get(&name)
+            .cloned()
+            .ok_or(format!("{} has no method `{}`", receiver_type, method))?;
+
+        if param_types.first() != Some(&receiver_type) {
+            return Err(format!(
+                "`{}` of {} can not be called as a method, it takes no `self`",
+                method, receiver_type
+            ));
+        }
+
+        if param_types.len() != params.len() + 1 {
+            return Err(format!(
+                "`{}` takes {} arguments but {} were supplied",
+                method,
+                param_types.len() - 1,
+                params.len()
+            ));
+        }
+
+        let mut args = vec![value];
+        for (i, (param, r#type)) in params.iter().zip(param_types[1..].iter()).enumerate() {
+            let value = self.compile_expression_as(param, r#type)?;
+            args.push(self.sequence(i + 1, last_impure, param, value, r#type)?);
+        }
+
+        let value = format!("{}({})", mangle(&name), args.join(", "));
+        Ok((self.owned_temporary(&return_type, value)?, return_type))
+    }
+
+    /// Calls a generic function, inferring its type arguments from the
+    /// arguments in order and then from `hint`, see
+    /// `CodeGen::compile_generic_call`.
+    fn compile_generic_call(
+        &mut self,
+        function: &Statements,
+        params: &[Expression],
+        hint: Option<&Type>,
+    ) -> Result<(String, Type), String> {
+        let (name, type_params, fn_params, return_type) = match function {
+            Statements::FunctionDeclaration {
+                name,
+                type_params,
+                params,
+                return_type,
+                ..
+            } => (name, type_params, params, return_type),
+            _ => return Err("Expected FunctionDecl as input".to_string()),
+        };
+
+        if fn_params.len() != params.len() {
+            return Err(format!(
+                "`{}` takes {} arguments but {} were supplied",
+                name,
+                fn_params.len(),
+                params.len()
+            ));
+        }
+
+        let last_impure = last_impure(params.iter());
+        let mut inference = Inference::new(name, type_params);
+        let mut values: Vec<(String, Type)> = vec![];
+        for (i, (param, fn_param)) in params.iter().zip(fn_params.iter()).enumerate() {
+            let (value, found) = match inference.expected(&fn_param.r#type) {
+                Some(param_type) => (self.compile_expression_as(param, &param_type)?, param_type),
+                None => {
+                    let (value, found) = self.compile_expression(param, None)?;
+                    inference.unify(&fn_param.r#type, &found)?;
+                    (value, found)
+                }
+            };
+            let value = self.sequence(i, last_impure, param, value, &found)?;
+            values.push((value, found));
+        }
+        inference.expect_result(return_type, hint);
+
+        let type_args = inference.type_arguments()?;
+        let symbol = self.instantiate_function(function, &type_args)?;
+
+        let mut args = vec![];
+        for ((value, found), fn_param) in values.into_iter().zip(fn_params.iter()) {
+            let param_type = inference.substitute(&fn_param.r#type);
+            args.push(self.coerce(value, &found, &param_type)?);
+        }
+
+        let return_type = inference.substitute(return_type);
+        let value = format!("{}({})", mangle(&symbol), args.join(", "));
+        Ok((self.owned_temporary(&return_type, value)?, return_type))
+    }
+
+    /// Queues the instance of a generic function for `type_args`, unless it
+    /// was already. Returns the name of the instance.
+    fn instantiate_function(&mut self, function: &Statements, type_args: &[Type]) -> Result<String, String> {
+        let (name, type_params, params, body, return_type, line) = match function {
+            Statements::FunctionDeclaration {
+                name,
+                type_params,
+                params,
+                body,
+                return_type,
+                line,
+                ..
+            } => (name, type_params, params, body, return_type, line),
+            _ => return Err("Expected FunctionDecl as input".to_string()),
+        };
+
+        let symbol = Type::Generic {
+            name: name.clone(),
+            params: type_args.to_vec(),
+        }
+        .to_string();
+        if !self.instances.insert(symbol.clone()) {
+            return Ok(symbol);
+        }
+
+        let bindings: HashMap<String, Type> = type_params
+            .iter()
+            .map(|p| p.name.clone())
+            .zip(type_args.iter().cloned())
+            .collect();
+
+        let mut instance_params = params.clone();
+        for param in instance_params.iter_mut() {
+            param.r#type = param.r#type.substitute(&bindings);
+        }
+
+        let instance = Statements::FunctionDeclaration {
+            name: symbol.clone(),
+            type_params: vec![],
+            params: instance_params,
+            body: body.clone(),
+            return_type: return_type.substitute(&bindings),
+            is_const: false,
+            is_extern: false,
+            line: *line,
+        };
+        self.pending_instances.push((instance, bindings));
+
+        Ok(symbol)
+    }
+
+    /// `i32(x)` and the like, see [`typing::conversion_type`].
+    fn compile_conversion(
+        &mut self,
+        name: &str,
+        r#type: &Type,
+        params: &[Expression],
+    ) -> Result<(String, Type), String> {
+        if params.len() != 1 {
+            return Err(format!("`{}` takes 1 argument but {} were supplied", name, params.len()));
+        }
+
+        let (value, from) = self.compile_expression(&params[0], Some(r#type))?;
+        if !from.is_integer() {
+            return Err(format!("`{}` converts integers, not {}", name, from));
+        }
+        Ok((self.coerce(value, &from, r#type)?, r#type.clone()))
+    }
+
+    /// `len(x)` is the number of elements of an array or bytes of a string.
+    fn compile_len(&mut self, params: &[Expression]) -> Result<(String, Type), String> {
+        if params.len() != 1 {
+            return Err(format!("`len` takes 1 argument but {} were supplied", params.len()));
+        }
+
+        let (value, r#type) = self.compile_expression(&params[0], None)?;
+        let len = match r#type {
+            Type::FixedArray(_, size) if is_pure(&params[0]) => format!("INT64_C({})", size),
+            Type::FixedArray(_, size) => format!("((void){}, INT64_C({}))", value, size),
+            Type::Array(_) => format!("{}.len", value),
+            Type::String => format!("((int64_t)strlen({}))", value),
+            r#type => return Err(format!("`len` is not defined for {}", r#type)),
+        };
+
+        Ok((len, Type::Int64))
+    }
+
+    /// `resize(values, len, fill)`, see `CodeGen::compile_resize`. The new
+    /// array is filled in by a loop ahead of the expression it is used in.
+    fn compile_resize(&mut self, params: &[Expression]) -> Result<(String, Type), String> {
+        if params.len() != 3 {
+            return Err(format!("`resize` takes 3 arguments but {} were supplied", params.len()));
+        }
+
+        let (array, array_type) = self.compile_expression(&params[0], None)?;
+        let element = match &array_type {
+            Type::Array(element) => *element.clone(),
+            r#type => return Err(format!("`resize` takes an array, not {}", r#type)),
+        };
+        let array = self.temporary(&array_type, array)?;
+        let len = self.compile_expression_as(&params[1], &Type::Int64)?;
+        let len = self.temporary(&Type::Int64, len)?;
+        let fill = self.compile_expression_as(&params[2], &element)?;
+        let fill = self.temporary(&element, fill)?;
+
+        self.emit(format!("if ({} < 0) {} = 0;", len, len));
+        let resized = format!(
+            "({}){{ aurora_alloc(sizeof({}) * (size_t){}), {} }}",
+            self.c_type(&array_type)?,
+            self.c_type(&element)?,
+            len,
+            len
+        );
+        let resized = self.owned_temporary(&array_type, resized)?;
+        let index = self.fresh_name("i");
+        self.emit(format!(
+            "for (int64_t {} = 0; {} < {}; {}++) {{",
+            index, index, len, index
+        ));
+        self.indent += 1;
+        let kept = self.retain(&format!("{}.data[{}]", array, index), &element)?;
+        let fill = self.retain(&fill, &element)?;
+        self.emit(format!(
+            "{}.data[{}] = {} < {}.len ? {} : {};",
+            resized, index, index, array, kept, fill
+        ));
+        self.indent -= 1;
+        self.emit("}".to_string());
+
+        Ok((resized, array_type))
+    }
+
+    /// `format(template, values...)` concatenates the pieces of the template
+    /// and the values, which the runtime writes to new strings.
+    fn compile_format(&mut self, params: &[Expression]) -> Result<(String, Type), String> {
+        let pieces = format_template(params)?;
+        let values = &params[1..];
+        let last_impure = last_impure(values.iter());
+
+        let mut text = self.compile_string(&pieces[0]);
+        for (i, (param, piece)) in values.iter().zip(&pieces[1..]).enumerate() {
+            let (value, r#type) = self.compile_expression(param, None)?;
+            let value = self.sequence(i, last_impure, param, value, &r#type)?;
+            let value = match &r#type {
+                r#type if r#type.is_integer() => {
+                    let value = format!("aurora_format_int((__int128){})", value);
+                    self.owned_temporary(&Type::String, value)?
+                }
+                r#type if r#type.is_float() => {
+                    let value = format!(
+                        "aurora_format_float((double){}, {})",
+                        value,
+                        float_digits(r#type)
+                    );
+                    self.owned_temporary(&Type::String, value)?
+                }
+                Type::Boolean => format!(
+                    "({} ? {} : {})",
+                    value,
+                    self.compile_string("true"),
+                    self.compile_string("false")
+                ),
+                Type::String => value,
+                r#type => return Err(format!("`format` can not write values of type {}", r#type)),
+            };
+
+            let concat = format!("aurora_concat({}, {})", text, value);
+            text = self.owned_temporary(&Type::String, concat)?;
+            if !piece.is_empty() {
+                let concat = format!("aurora_concat({}, {})", text, self.compile_string(piece));
+                text = self.owned_temporary(&Type::String, concat)?;
+            }
+        }
+
+        Ok((text, Type::String))
+    }
+}
+
+fn constant_literal(value: &Value, r#type: &Type) -> String {
+    match value {
+        Value::Int(value) => int_literal(*value, r#type),
+        Value::Float(value) => float_literal(*value, r#type),
+        Value::Bool(value) => value.to_string(),
+        Value::String(value) => string_literal(value),
+    }
+}
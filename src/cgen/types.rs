@@ -0,0 +1,286 @@
+use std::collections::HashSet;
+
+use crate::ast::{StructField, Type};
+
+use super::{c_identifier, mangle, CGen};
+
+/// Every aggregate Aurora type becomes a C struct, named after the mangled
+/// Aurora type, so that it can be passed and returned by value:
+///
+/// - `array[T]` is a `{ T *data; int64_t len; }` pair, like in the LLVM
+///   backend
+/// - `[T; n]` wraps a C array, `{ T items[n]; }`
+/// - tuples have the fields `_0`, `_1`, ...
+/// - enums are a tag followed by a union with a struct per variant that
+///   has fields
+///
+/// Types are declared when first used and defined at the end, in an order
+/// where every type is complete before it is embedded in another.
+impl CGen {
+    /// The C name of `r#type`.
+    pub(super) fn c_type(&mut self, r#type: &Type) -> Result<String, String> {
+        let name = match r#type {
+            Type::Int8 => "int8_t".to_string(),
+            Type::Int16 => "int16_t".to_string(),
+            Type::Int32 => "int32_t".to_string(),
+            Type::Int64 => "int64_t".to_string(),
+            Type::Int128 => "__int128".to_string(),
+            // C99 has no half or quadruple precision floats
+            Type::Float16 | Type::Float32 => "float".to_string(),
+            Type::Float64 => "double".to_string(),
+            Type::Float128 => "long double".to_string(),
+            Type::String => "const char *".to_string(),
+            Type::Boolean => "bool".to_string(),
+            Type::Void => "void".to_string(),
+            Type::Pointer(pointee) => match pointee.as_ref() {
+                Type::Void => "void *".to_string(),
+                pointee => format!("{} *", self.c_type(pointee)?),
+            },
+            Type::TypeParameter { name } => return Err(format!("Unresolved type parameter `{}`", name)),
+            Type::UserDefinedType { name }
+                if !self.structs.contains_key(name) && !self.enums.contains_key(name) =>
+            {
+                return Err(format!("Unknown type `{}`", name))
+            }
+            Type::Generic { name, params } => {
+                let (type_params, _) = self
+                    .generic_structs
+                    .get(name)
+                    .ok_or(format!("Unknown generic type `{}`", name))?;
+                if type_params.len() != params.len() {
+                    return Err(format!(
+                        "`{}` takes {} type arguments but {} were supplied",
+                        name,
+                        type_params.len(),
+                        params.len()
+                    ));
+                }
+                mangle(&r#type.to_string())
+            }
+            r#type => mangle(&r#type.to_string()),
+        };
+
+        if !is_primitive(r#type) && !self.types.contains(r#type) {
+            self.types.push(r#type.clone());
+        }
+
+        Ok(name)
+    }
+
+    /// `type name`, which for function pointers is not the same as the type
+    /// followed by the name.
+    pub(super) fn c_declaration(&mut self, r#type: &Type, name: &str) -> Result<String, String> {
+        let c_type = self.c_type(r#type)?;
+        if c_type.ends_with('*') {
+            Ok(format!("{}{}", c_type, name))
+        } else {
+            Ok(format!("{} {}", c_type, name))
+        }
+    }
+
+    /// The fields of a struct or generic struct instance, with the type
+    /// arguments substituted.
+    pub(super) fn struct_fields(&self, r#type: &Type) -> Option<Vec<StructField>> {
+        match r#type {
+            Type::UserDefinedType { name } => self.structs.get(name).cloned(),
+            Type::Generic { name, params } => {
+                let (type_params, fields) = self.generic_structs.get(name)?;
+                let bindings = type_params
+                    .iter()
+                    .map(|p| p.name.clone())
+                    .zip(params.iter().cloned())
+                    .collect();
+
+                Some(
+                    fields
+                        .iter()
+                        .map(|f| StructField {
+                            name: f.name.clone(),
+                            r#type: f.r#type.substitute(&bindings),
+                        })
+                        .collect(),
+                )
+            }
+            _ => None,
+        }
+    }
+
+    /// Forward declarations and definitions of the types used so far.
+    pub(super) fn type_definitions(&mut self) -> Result<Vec<String>, String> {
+        let mut declarations = vec![];
+        let mut definitions = vec![];
+        let mut defined = HashSet::new();
+        let mut defining = HashSet::new();
+
+        // Defining a type may use new ones, which are appended to the list
+        let mut i = 0;
+        while i < self.types.len() {
+            let r#type = self.types[i].clone();
+            if !matches!(r#type, Type::Function { .. }) {
+                let name = self.c_type(&r#type)?;
+                declarations.push(format!("typedef struct {} {};", name, name));
+            }
+            self.define_type(&r#type, &mut definitions, &mut defined, &mut defining)?;
+            i += 1;
+        }
+
+        if !declarations.is_empty() {
+            declarations.push(String::new());
+        }
+        declarations.extend(definitions);
+        Ok(declarations)
+    }
+
+    fn define_type(
+        &mut self,
+        r#type: &Type,
+        definitions: &mut Vec<String>,
+        defined: &mut HashSet<Type>,
+        defining: &mut HashSet<Type>,
+    ) -> Result<(), String> {
+        if is_primitive(r#type) || defined.contains(r#type) {
+            return Ok(());
+        }
+        if !defining.insert(r#type.clone()) {
+            return Err(format!("{} contains itself", r#type));
+        }
+
+        // Types embedded by value have to be defined first. Array elements
+        // are behind a pointer, but function pointer types are typedefs,
+        // which have to come before any use.
+        let embedded: Vec<Type> = match r#type {
+            Type::Array(element) if matches!(element.as_ref(), Type::Function { .. }) => {
+                vec![*element.clone()]
+            }
+            Type::Array(_) => vec![],
+            Type::FixedArray(element, _) => vec![*element.clone()],
+            Type::Tuple(types) => types.clone(),
+            Type::Function {
+                params,
+                return_type,
+            } => params
+                .iter()
+                .chain([return_type.as_ref()])
+                .filter(|t| matches!(t, Type::Function { .. }))
+                .cloned()
+                .collect(),
+            r#type => match self.struct_fields(r#type) {
+                Some(fields) => fields.into_iter().map(|f| f.r#type).collect(),
+                None => self.enums[&r#type.to_string()]
+                    .iter()
+                    .flat_map(|v| v.fields.clone())
+                    .collect(),
+            },
+        };
+        for embedded in &embedded {
+            self.c_type(embedded)?;
+            self.define_type(embedded, definitions, defined, defining)?;
+        }
+
+        let definition = self.type_definition(r#type)?;
+        definitions.push(definition);
+        definitions.push(String::new());
+
+        defining.remove(r#type);
+        defined.insert(r#type.clone());
+        Ok(())
+    }
+
+    fn type_definition(&mut self, r#type: &Type) -> Result<String, String> {
+        let name = self.c_type(r#type)?;
+
+        let fields: Vec<String> = match r#type {
+            Type::Function {
+                params,
+                return_type,
+            } => {
+                let params = match params.is_empty() {
+                    true => "void".to_string(),
+                    false => params
+                        .iter()
+                        .map(|t| self.c_type(t))
+                        .collect::<Result<Vec<String>, String>>()?
+                        .join(", "),
+                };
+                let return_type = self.c_type(return_type)?;
+                return Ok(format!("typedef {} (*{})({});", return_type, name, params));
+            }
+            Type::Array(element) => vec![
+                self.c_declaration(element, "*data")?,
+                "int64_t len".to_string(),
+            ],
+            // C arrays can not be empty
+            Type::FixedArray(element, size) => {
+                vec![self.c_declaration(element, &format!("items[{}]", (*size).max(1)))?]
+            }
+            Type::Tuple(types) => types
+                .iter()
+                .enumerate()
+                .map(|(i, t)| self.c_declaration(t, &format!("_{}", i)))
+                .collect::<Result<_, String>>()?,
+            r#type => match self.struct_fields(r#type) {
+                Some(fields) => fields
+                    .iter()
+                    .map(|f| self.c_declaration(&f.r#type, &c_identifier(&f.name)))
+                    .collect::<Result<_, String>>()?,
+                None => {
+                    let variants = self.enums[&r#type.to_string()].clone();
+                    let mut fields = vec!["int32_t tag".to_string()];
+
+                    let mut payload = vec![];
+                    for variant in variants.iter().filter(|v| !v.fields.is_empty()) {
+                        let variant_fields = variant
+                            .fields
+                            .iter()
+                            .enumerate()
+                            .map(|(i, t)| self.c_declaration(t, &format!("_{}", i)))
+                            .collect::<Result<Vec<String>, String>>()?;
+                        payload.push(format!(
+                            "struct {{ {}; }} {}",
+                            variant_fields.join("; "),
+                            c_identifier(&variant.name)
+                        ));
+                    }
+                    if !payload.is_empty() {
+                        fields.push(format!(
+                            "union {{\n        {};\n    }} payload",
+                            payload.join(";\n        ")
+                        ));
+                    }
+                    fields
+                }
+            },
+        };
+
+        // C structs can not be empty either
+        let fields = match fields.is_empty() {
+            true => vec!["char unused".to_string()],
+            false => fields,
+        };
+
+        Ok(format!(
+            "struct {} {{\n{}\n}};",
+            name,
+            fields
+                .iter()
+                .map(|f| format!("    {};", f))
+                .collect::<Vec<String>>()
+                .join("\n")
+        ))
+    }
+}
+
+fn is_primitive(r#type: &Type) -> bool {
+    r#type.is_integer()
+        || r#type.is_float()
+        || matches!(r#type, Type::String | Type::Boolean | Type::Void | Type::Pointer(_))
+}
+
+/// The unsigned type of the same width as the integer type `c_type`, used
+/// for arithmetic that wraps around.
+pub(super) fn unsigned(c_type: &str) -> String {
+    match c_type {
+        "__int128" => "unsigned __int128".to_string(),
+        c_type => format!("u{}", c_type),
+    }
+}
@@ -0,0 +1,492 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use inkwell::{
+    debug_info::{
+        debug_metadata_version, AsDIScope, DICompileUnit, DIFile, DIFlags, DIFlagsConstants,
+        DISubprogram, DIType, DWARFEmissionKind, DWARFSourceLanguage, DebugInfoBuilder,
+    },
+    module::{FlagBehavior, Linkage},
+    targets::TargetData,
+    types::{AnyType, BasicTypeEnum, StructType},
+    values::{FunctionValue, PointerValue},
+    AddressSpace,
+};
+
+use crate::ast::{statements::Statements, FuncParam, Program, Type};
+
+use super::{get_array_struct_type, get_llvm_type, CodeGen};
+
+/// Type encodings of DWARF base types, from the DWARF 5 standard.
+const DW_ATE_BOOLEAN: u32 = 0x02;
+const DW_ATE_FLOAT: u32 = 0x04;
+const DW_ATE_SIGNED: u32 = 0x05;
+const DW_ATE_UNSIGNED_CHAR: u32 = 0x08;
+
+/// DWARF debug information for the module being compiled, emitted with
+/// `-g`. Every module is a compile unit of its source file and every
+/// function compiled from source a subprogram, whose instructions are
+/// located at the line of the statement they were compiled from.
+pub(super) struct DebugInfo<'ctx> {
+    builder: DebugInfoBuilder<'ctx>,
+    compile_unit: DICompileUnit<'ctx>,
+    file: DIFile<'ctx>,
+    /// Sizes and offsets of the types of the target
+    layout: TargetData,
+    optimized: bool,
+    /// Generic functions declared in the module. Instances of imported ones
+    /// have no source in its file, so they are compiled without locations.
+    generic_functions: HashSet<String>,
+    /// Subprogram of the function being compiled, if it has one
+    function: Option<DISubprogram<'ctx>>,
+    /// Line of the statement being compiled
+    line: u32,
+    types: HashMap<Type, DIType<'ctx>>,
+}
+
+impl<'a, 'ctx> CodeGen<'a, 'ctx> {
+    /// Starts emitting debug information for `program`, read from `source`.
+    /// The triple and data layout of the module must already be set, see
+    /// `target::configure_module`.
+    pub fn enable_debug_info(&mut self, source: &Path, program: &Program, optimized: bool) {
+        let file_name = source.file_name().unwrap_or_default().to_string_lossy();
+        let directory = match source.parent().map(|dir| dir.canonicalize()) {
+            Some(Ok(dir)) => dir,
+            _ => Default::default(),
+        };
+
+        // DWARF has no language code for Aurora, and C is what debuggers
+        // know how to show its values as
+        let (builder, compile_unit) = self.module.create_debug_info_builder(
+            true,
+            DWARFSourceLanguage::C,
+            &file_name,
+            &directory.to_string_lossy(),
+            concat!("aurorac ", env!("CARGO_PKG_VERSION")),
+            optimized,
+            "",
+            0,
+            "",
+            DWARFEmissionKind::Full,
+            0,
+            false,
+            false,
+            "",
+            "",
+        );
+        self.module.add_basic_value_flag(
+            "Debug Info Version",
+            FlagBehavior::Warning,
+            self.context
+                .i32_type()
+                .const_int(debug_metadata_version() as u64, false),
+        );
+
+        let layout = self.module.get_data_layout();
+        self.debug = Some(DebugInfo {
+            builder,
+            compile_unit,
+            file: compile_unit.get_file(),
+            layout: TargetData::create(layout.as_str().to_str().unwrap_or_default()),
+            optimized,
+            generic_functions: program
+                .statements
+                .iter()
+                .filter_map(|s| match s {
+                    Statements::FunctionDeclaration {
+                        name, type_params, ..
+                    } if !type_params.is_empty() => Some(name.clone()),
+                    _ => None,
+                })
+                .collect(),
+            function: None,
+            line: 0,
+            types: HashMap::new(),
+        });
+    }
+
+    /// Resolves the debug information, which has to happen before the
+    /// module is verified or written.
+    pub fn finalize_debug_info(&self) {
+        if let Some(debug) = &self.debug {
+            debug.builder.finalize();
+        }
+    }
+
+    /// The line of an instance of the generic function `name` declared on
+    /// `line`, which is 0 unless the function was declared in this module.
+    pub(super) fn instance_line(&self, name: &str, line: usize) -> usize {
+        match &self.debug {
+            Some(debug) if debug.generic_functions.contains(name) => line,
+            _ => 0,
+        }
+    }
+
+    /// Makes `function` a subprogram and locates the code following at
+    /// `line`. Functions on line 0 are compiled without debug information.
+    pub(super) fn debug_function(
+        &mut self,
+        function: FunctionValue<'ctx>,
+        params: &[FuncParam],
+        return_type: &Type,
+        line: usize,
+    ) {
+        if self.debug.is_none() || line == 0 {
+            return;
+        }
+
+        let return_type = match return_type {
+            Type::Void => None,
+            r#type => Some(self.debug_type(r#type)),
+        };
+        let param_types: Vec<DIType> = params.iter().map(|p| self.debug_type(&p.r#type)).collect();
+
+        let debug = self.debug.as_mut().unwrap();
+        let subroutine_type = debug.builder.create_subroutine_type(
+            debug.file,
+            return_type,
+            &param_types,
+            DIFlags::ZERO,
+        );
+        let name = function.get_name().to_string_lossy();
+        let subprogram = debug.builder.create_function(
+            debug.compile_unit.as_debug_info_scope(),
+            &name,
+            None,
+            debug.file,
+            line as u32,
+            subroutine_type,
+            function.get_linkage() == Linkage::Internal,
+            true,
+            line as u32,
+            DIFlags::PROTOTYPED,
+            debug.optimized,
+        );
+        function.set_subprogram(subprogram);
+        debug.function = Some(subprogram);
+        self.debug_line(line);
+    }
+
+    /// Stops locating code, once the function being compiled is done.
+    pub(super) fn end_debug_function(&mut self) {
+        if let Some(debug) = &mut self.debug {
+            debug.function = None;
+            self.builder.unset_current_debug_location();
+        }
+    }
+
+    /// Locates the code compiled from `statement` at its line.
+    pub(super) fn debug_statement(&mut self, statement: &Statements) {
+        if let Some(line) = statement.line() {
+            self.debug_line(line);
+        }
+    }
+
+    fn debug_line(&mut self, line: usize) {
+        let Some(debug) = &mut self.debug else {
+            return;
+        };
+        let Some(function) = debug.function else {
+            return;
+        };
+
+        debug.line = line as u32;
+        let location = debug.builder.create_debug_location(
+            self.context,
+            debug.line,
+            0,
+            function.as_debug_info_scope(),
+            None,
+        );
+        self.builder.set_current_debug_location(location);
+    }
+
+    /// Describes the variable `name` of `r#type` stored at `ptr`, declared
+    /// by the statement being compiled. `arg` is the position of parameters,
+    /// counting from 1.
+    pub(super) fn debug_variable(
+        &mut self,
+        name: &str,
+        ptr: PointerValue<'ctx>,
+        r#type: &Type,
+        arg: Option<u32>,
+    ) {
+        match &self.debug {
+            Some(debug) if debug.function.is_some() => {}
+            _ => return,
+        }
+
+        let r#type = self.debug_type(r#type);
+        let debug = self.debug.as_ref().unwrap();
+        let scope = debug.function.unwrap().as_debug_info_scope();
+        let variable = match arg {
+            Some(arg) => debug.builder.create_parameter_variable(
+                scope,
+                name,
+                arg,
+                debug.file,
+                debug.line,
+                r#type,
+                true,
+                DIFlags::ZERO,
+            ),
+            None => debug.builder.create_auto_variable(
+                scope,
+                name,
+                debug.file,
+                debug.line,
+                r#type,
+                true,
+                DIFlags::ZERO,
+                0,
+            ),
+        };
+
+        let location =
+            debug
+                .builder
+                .create_debug_location(self.context, debug.line, 0, scope, None);
+        let block = self.builder.get_insert_block().unwrap();
+        debug
+            .builder
+            .insert_declare_at_end(ptr, Some(variable), None, location, block);
+    }
+
+    /// The DWARF description of `r#type`. Structs, enums and tuples are
+    /// described with the layout of their LLVM type; enums as their tag and
+    /// the bytes of the payload.
+    fn debug_type(&mut self, r#type: &Type) -> DIType<'ctx> {
+        let r#type = r#type.substitute(&self.type_bindings);
+        if let Some(debug_type) = self.debug.as_ref().unwrap().types.get(&r#type) {
+            return *debug_type;
+        }
+
+        let llvm_type = get_llvm_type(&r#type, self.context);
+        let (size, align) = self.type_size(&llvm_type);
+        let name = r#type.to_string();
+
+        let debug_type = match &r#type {
+            Type::Int8 | Type::Int16 | Type::Int32 | Type::Int64 | Type::Int128 => {
+                self.debug_basic_type(&name, size, DW_ATE_SIGNED)
+            }
+            Type::Float16 | Type::Float32 | Type::Float64 | Type::Float128 => {
+                self.debug_basic_type(&name, size, DW_ATE_FLOAT)
+            }
+            Type::Boolean => self.debug_basic_type(&name, size, DW_ATE_BOOLEAN),
+            Type::String => {
+                let byte = self.debug_basic_type("u8", 8, DW_ATE_UNSIGNED_CHAR);
+                self.debug_pointer_type(&name, byte, size, align)
+            }
+            Type::Array(element) => {
+                let struct_type = get_array_struct_type(element, self.context);
+                let (pointer_size, pointer_align) =
+                    self.type_size(&struct_type.get_field_type_at_index(0).unwrap());
+                let element = self.debug_type(element);
+                let data = self.debug_pointer_type("", element, pointer_size, pointer_align);
+                let len = self.debug_type(&Type::Int64);
+                self.debug_struct_type(
+                    &name,
+                    struct_type,
+                    vec![("data".to_string(), data), ("len".to_string(), len)],
+                )
+            }
+            Type::FixedArray(element, len) => {
+                let element = self.debug_type(element);
+                let debug = self.debug.as_ref().unwrap();
+                debug
+                    .builder
+                    .create_array_type(
+                        element,
+                        size,
+                        align,
+                        std::slice::from_ref(&(0..*len as i64)),
+                    )
+                    .as_type()
+            }
+            Type::Tuple(types) => {
+                let fields = types
+                    .iter()
+                    .enumerate()
+                    .map(|(i, r#type)| (i.to_string(), self.debug_type(r#type)))
+                    .collect();
+                self.debug_struct_type(&name, llvm_type.into_struct_type(), fields)
+            }
+            Type::Function { .. } => {
+                // inkwell can not point to a subroutine type, so a function
+                // is an address under the name of its type
+                let byte = self.debug_basic_type("u8", 8, DW_ATE_UNSIGNED_CHAR);
+                self.debug_pointer_type(&name, byte, size, align)
+            }
+            Type::Pointer(pointee) => {
+                let pointee = match pointee.as_ref() {
+                    Type::Void => self.debug_basic_type("u8", 8, DW_ATE_UNSIGNED_CHAR),
+                    pointee => self.debug_type(pointee),
+                };
+                self.debug_pointer_type(&name, pointee, size, align)
+            }
+            Type::UserDefinedType { name } if self.enums.contains_key(name) => {
+                let struct_type = llvm_type.into_struct_type();
+                let tag = self.debug_type(&Type::Int32);
+                let payload_type = struct_type.get_field_type_at_index(1).unwrap();
+                let (payload_size, payload_align) = self.type_size(&payload_type);
+                let byte = self.debug_basic_type("u8", 8, DW_ATE_UNSIGNED_CHAR);
+                let debug = self.debug.as_ref().unwrap();
+                let payload = debug
+                    .builder
+                    .create_array_type(
+                        byte,
+                        payload_size,
+                        payload_align,
+                        std::slice::from_ref(&(0..(payload_size / 8) as i64)),
+                    )
+                    .as_type();
+                self.debug_struct_type(
+                    name,
+                    struct_type,
+                    vec![("tag".to_string(), tag), ("payload".to_string(), payload)],
+                )
+            }
+            Type::UserDefinedType { .. } | Type::Generic { .. } => {
+                // Stands in for the struct in its own fields, which can
+                // point back to it through arrays
+                let placeholder = self.debug_struct_placeholder(&name);
+                self.debug
+                    .as_mut()
+                    .unwrap()
+                    .types
+                    .insert(r#type.clone(), placeholder);
+
+                let fields = self
+                    .struct_fields(&r#type)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .map(|field| (field.name, self.debug_type(&field.r#type)))
+                    .collect();
+                self.debug_struct_type(&name, llvm_type.into_struct_type(), fields)
+            }
+            Type::Void | Type::TypeParameter { .. } => {
+                unreachable!("{} is not the type of a value", r#type)
+            }
+        };
+
+        self.debug
+            .as_mut()
+            .unwrap()
+            .types
+            .insert(r#type, debug_type);
+        debug_type
+    }
+
+    /// Size and alignment of `r#type` in bits.
+    fn type_size(&self, r#type: &BasicTypeEnum<'ctx>) -> (u64, u32) {
+        let layout = &self.debug.as_ref().unwrap().layout;
+        let r#type: &dyn AnyType = r#type;
+        (
+            layout.get_bit_size(r#type),
+            layout.get_abi_alignment(r#type) * 8,
+        )
+    }
+
+    fn debug_basic_type(&self, name: &str, size: u64, encoding: u32) -> DIType<'ctx> {
+        let debug = self.debug.as_ref().unwrap();
+        debug
+            .builder
+            .create_basic_type(name, size, encoding, DIFlags::ZERO)
+            .unwrap()
+            .as_type()
+    }
+
+    fn debug_pointer_type(
+        &self,
+        name: &str,
+        pointee: DIType<'ctx>,
+        size: u64,
+        align: u32,
+    ) -> DIType<'ctx> {
+        let debug = self.debug.as_ref().unwrap();
+        debug
+            .builder
+            .create_pointer_type(name, pointee, size, align, AddressSpace::default())
+            .as_type()
+    }
+
+    fn debug_struct_placeholder(&self, name: &str) -> DIType<'ctx> {
+        let debug = self.debug.as_ref().unwrap();
+        debug
+            .builder
+            .create_struct_type(
+                debug.compile_unit.as_debug_info_scope(),
+                name,
+                debug.file,
+                0,
+                0,
+                0,
+                DIFlags::FWD_DECL,
+                None,
+                &[],
+                0,
+                None,
+                "",
+            )
+            .as_type()
+    }
+
+    /// A struct of `fields`, laid out like `struct_type`.
+    fn debug_struct_type(
+        &self,
+        name: &str,
+        struct_type: StructType<'ctx>,
+        fields: Vec<(String, DIType<'ctx>)>,
+    ) -> DIType<'ctx> {
+        let debug = self.debug.as_ref().unwrap();
+        let scope = debug.compile_unit.as_debug_info_scope();
+
+        let members: Vec<DIType> = fields
+            .into_iter()
+            .enumerate()
+            .map(|(i, (field_name, field_type))| {
+                let llvm_type = struct_type.get_field_type_at_index(i as u32).unwrap();
+                let (size, align) = self.type_size(&llvm_type);
+                let offset = debug
+                    .layout
+                    .offset_of_element(&struct_type, i as u32)
+                    .unwrap_or(0);
+                debug
+                    .builder
+                    .create_member_type(
+                        scope,
+                        &field_name,
+                        debug.file,
+                        0,
+                        size,
+                        align,
+                        offset * 8,
+                        DIFlags::PUBLIC,
+                        field_type,
+                    )
+                    .as_type()
+            })
+            .collect();
+
+        let (size, align) = self.type_size(&struct_type.into());
+        debug
+            .builder
+            .create_struct_type(
+                scope,
+                name,
+                debug.file,
+                0,
+                size,
+                align,
+                DIFlags::PUBLIC,
+                None,
+                &members,
+                0,
+                None,
+                "",
+            )
+            .as_type()
+    }
+}
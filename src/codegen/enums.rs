@@ -0,0 +1,528 @@
+use std::collections::HashMap;
+
+use inkwell::{
+    basic_block::BasicBlock,
+    types::{BasicTypeEnum, StructType},
+    values::{BasicValueEnum, IntValue, PointerValue},
+    AddressSpace, IntPredicate,
+};
+
+use crate::ast::{
+    expressions::{Expression, MatchArm, Pattern},
+    statements::Statements,
+    EnumVariant, Type,
+};
+
+use super::{get_llvm_type, CodeGen};
+
+/// Enums are laid out as `{ i32 tag, [n x i64] payload }`, where the payload
+/// is large enough for the fields of any variant. The fields of a variant
+/// are read by casting the payload pointer to a struct of those fields.
+impl<'a, 'ctx> CodeGen<'a, 'ctx> {
+    pub fn declare_enum(&mut self, r#enum: &Statements) -> Result<(), String> {
+        match r#enum {
+            Statements::EnumDeclaration { name, variants } => {
+                if self.structs.contains_key(name) || self.enums.contains_key(name) {
+                    return Err(format!("Type `{}` is declared more than once", name));
+                }
+
+                // See `declare_struct`
+                if self.context.get_struct_type(name).is_none() {
+                    self.context.opaque_struct_type(name);
+                }
+                self.enums.insert(name.clone(), variants.clone());
+                Ok(())
+            }
+            _ => Err("Expected EnumDecl as input".to_string()),
+        }
+    }
+
+    pub fn define_enum(&mut self, r#enum: &Statements) -> Result<(), String> {
+        match r#enum {
+            Statements::EnumDeclaration { name, variants } => {
+                for variant in variants {
+                    for field in &variant.fields {
+                        self.instantiate_type(field)?;
+                    }
+                }
+
+                let (words, word_size) = self.payload_layout(variants);
+                let word = self.context.custom_width_int_type(word_size as u32 * 8);
+
+                let enum_type = self.context.get_struct_type(name).unwrap();
+                if enum_type.is_opaque() {
+                    enum_type.set_body(
+                        &[
+                            self.context.i32_type().into(),
+                            word.array_type(words as u32).into(),
+                        ],
+                        false,
+                    );
+                }
+                Ok(())
+            }
+            _ => Err("Expected EnumDecl as input".to_string()),
+        }
+    }
+
+    /// Size and alignment of a value of `r#type` under the C layout rules of
+    /// a 64-bit target. Only used to size enum payloads, so overestimating on
+    /// smaller targets is harmless.
+    fn type_layout(&self, r#type: &Type) -> (u64, u64) {
+        match r#type {
+            Type::Int8 | Type::Boolean => (1, 1),
+            Type::Int16 | Type::Float16 => (2, 2),
+            Type::Int32 | Type::Float32 => (4, 4),
+            Type::Int64 | Type::Float64 | Type::String | Type::Function { .. } | Type::Pointer(_) => {
+                (8, 8)
+            }
+            Type::Int128 | Type::Float128 => (16, 16),
+            Type::Void => (0, 1),
+            Type::Array(_) => (16, 8),
+            Type::FixedArray(r#type, size) => {
+                let (element_size, align) = self.type_layout(r#type);
+                (element_size * *size as u64, align)
+            }
+            Type::Tuple(types) => self.fields_layout(types),
+            Type::TypeParameter { name } => panic!("Unresolved type parameter `{}`", name),
+            Type::Generic { .. } | Type::UserDefinedType { .. } => {
+                if let Some(fields) = self.struct_fields(r#type) {
+                    let types: Vec<Type> = fields.iter().map(|f| f.r#type.clone()).collect();
+                    return self.fields_layout(&types);
+                }
+
+                let name = r#type.to_string();
+                let (words, word_size) = self.payload_layout(&self.enums[&name]);
+                let size = 4_u64.div_ceil(word_size) * word_size + words * word_size;
+                (size, word_size)
+            }
+        }
+    }
+
+    /// Number and size in bytes of the words making up the payload of an
+    /// enum. Words are `i64`s unless a variant needs a larger alignment.
+    fn payload_layout(&self, variants: &[EnumVariant]) -> (u64, u64) {
+        let (mut size, mut align) = (0, 8);
+        for variant in variants {
+            let (variant_size, variant_align) = self.fields_layout(&variant.fields);
+            size = size.max(variant_size);
+            align = align.max(variant_align);
+        }
+
+        (size.div_ceil(align), align)
+    }
+
+    fn fields_layout(&self, types: &[Type]) -> (u64, u64) {
+        let (mut size, mut align): (u64, u64) = (0, 1);
+        for r#type in types {
+            let (field_size, field_align) = self.type_layout(r#type);
+            size = size.div_ceil(field_align) * field_align + field_size;
+            align = align.max(field_align);
+        }
+        (size.div_ceil(align) * align, align)
+    }
+
+    fn variant_struct_type(&self, enum_name: &str, index: usize) -> StructType<'ctx> {
+        let fields: Vec<BasicTypeEnum> = self.enums[enum_name][index]
+            .fields
+            .iter()
+            .map(|t| get_llvm_type(t, self.context))
+            .collect();
+        self.context.struct_type(&fields, false)
+    }
+
+    fn variant_index(&self, enum_name: &str, variant: &str) -> usize {
+        self.enums[enum_name]
+            .iter()
+            .position(|v| v.name == variant)
+            .unwrap()
+    }
+
+    pub(super) fn enum_tag_pointer(&self, ptr: PointerValue<'ctx>, enum_name: &str) -> PointerValue<'ctx> {
+        let enum_type = self.context.get_struct_type(enum_name).unwrap();
+        self.builder
+            .build_struct_gep(enum_type, ptr, 0, "tag")
+            .unwrap()
+    }
+
+    /// Pointer to field `field` of variant `index` of the enum stored at `ptr`.
+    pub(super) fn variant_field_pointer(
+        &self,
+        ptr: PointerValue<'ctx>,
+        enum_name: &str,
+        index: usize,
+        field: usize,
+    ) -> PointerValue<'ctx> {
+        let enum_type = self.context.get_struct_type(enum_name).unwrap();
+        let variant_type = self.variant_struct_type(enum_name, index);
+
+        let payload = self
+            .builder
+            .build_struct_gep(enum_type, ptr, 1, "payload")
+            .unwrap();
+        let payload = self.builder.build_pointer_cast(
+            payload,
+            variant_type.ptr_type(AddressSpace::default()),
+            "variant",
+        );
+
+        self.builder
+            .build_struct_gep(variant_type, payload, field as u32, "field")
+            .unwrap()
+    }
+
+    pub(super) fn compile_enum_variant(
+        &mut self,
+        enum_name: &str,
+        variant: &str,
+        values: &[Expression],
+    ) -> Result<(BasicValueEnum<'ctx>, Type), String> {
+        let variants = self
+            .enums
+            .get(enum_name)
+            .cloned()
+            .ok_or(format!("Unknown enum `{}`", enum_name))?;
+        let (index, fields) = variants
+            .iter()
+            .enumerate()
+            .find(|(_, v)| v.name == variant)
+            .map(|(i, v)| (i, v.fields.clone()))
+            .ok_or(format!("`{}` has no variant `{}`", enum_name, variant))?;
+
+        let r#type = Type::UserDefinedType {
+            name: enum_name.to_string(),
+        };
+        let enum_type = get_llvm_type(&r#type, self.context);
+        let ptr = self.create_entry_block_alloca(enum_type, enum_name);
+
+        let tag = self.context.i32_type().const_int(index as u64, false);
+        self.builder
+            .build_store(self.enum_tag_pointer(ptr, enum_name), tag);
+
+        for (i, (value, field_type)) in values.iter().zip(fields.iter()).enumerate() {
+            let value = self.compile_expression_as(value, field_type)?;
+            let value = self.take(value, field_type);
+            let field_ptr = self.variant_field_pointer(ptr, enum_name, index, i);
+            self.builder.build_store(field_ptr, value);
+        }
+
+        let value = self.builder.build_load(enum_type, ptr, enum_name);
+        Ok((self.owned_temporary(value, &r#type), r#type))
+    }
+
+    /// Lowers a `match` to a `switch` on the enum tag (or on the value for
+    /// integers and booleans). Each case then tries, in order, the arms
+    /// whose top-level pattern can match that case, testing nested patterns
+    /// with branches and falling through to the next arm on failure. Each arm
+    /// releases its bindings and new values before jumping to the end.
+    pub(super) fn compile_match(
+        &mut self,
+        expr: &Expression,
+        arms: &[MatchArm],
+        hint: Option<&Type>,
+    ) -> Result<(Option<BasicValueEnum<'ctx>>, Type), String> {
+        let (ptr, r#type) = self.compile_place_or_temporary(expr)?;
+        let function = self.current_function();
+
+        let unreachable_block = self.context.append_basic_block(function, "match.unreachable");
+        let arm_blocks: Vec<BasicBlock> = arms
+            .iter()
+            .map(|_| self.context.append_basic_block(function, "match.arm"))
+            .collect();
+        let end_block = self.context.append_basic_block(function, "match.end");
+
+        // The value the top-level pattern of each arm switches on, `None`
+        // for wildcards and bindings which match every case.
+        let mut keys: Vec<Option<IntValue>> = vec![];
+        for arm in arms {
+            keys.push(self.switch_key(&arm.pattern, &r#type)?);
+        }
+
+        let scrutinee = match &r#type {
+            Type::UserDefinedType { name } if self.enums.contains_key(name) => Some(
+                self.builder
+                    .build_load(
+                        self.context.i32_type(),
+                        self.enum_tag_pointer(ptr, name),
+                        "tag",
+                    )
+                    .into_int_value(),
+            ),
+            r#type if r#type.is_integer() || r#type == &Type::Boolean => Some(
+                self.builder
+                    .build_load(get_llvm_type(r#type, self.context), ptr, "scrutinee")
+                    .into_int_value(),
+            ),
+            _ => None,
+        };
+
+        let switch_block = self.builder.get_insert_block().unwrap();
+
+        match scrutinee {
+            Some(scrutinee) => {
+                let default_arms: Vec<usize> = (0..arms.len()).filter(|i| keys[*i].is_none()).collect();
+                let default_block = self.build_arm_chain(&default_arms, arms, &arm_blocks, ptr, unreachable_block)?;
+
+                let mut cases: Vec<(IntValue, BasicBlock)> = vec![];
+                for (i, key) in keys.iter().enumerate() {
+                    let key = match key {
+                        Some(key) if !keys[..i].contains(&Some(*key)) => *key,
+                        _ => continue,
+                    };
+                    let case_arms: Vec<usize> = (0..arms.len())
+                        .filter(|j| keys[*j].is_none() || keys[*j] == Some(key))
+                        .collect();
+                    let case_block = self.build_arm_chain(&case_arms, arms, &arm_blocks, ptr, unreachable_block)?;
+                    cases.push((key, case_block));
+                }
+
+                // `build_arm_chain` moves the builder
+                self.builder.position_at_end(switch_block);
+                self.builder
+                    .build_switch(scrutinee, default_block, &cases);
+            }
+            None => {
+                let all_arms: Vec<usize> = (0..arms.len()).collect();
+                let chain = self.build_arm_chain(&all_arms, arms, &arm_blocks, ptr, unreachable_block)?;
+                self.builder.position_at_end(switch_block);
+                self.builder.build_unconditional_branch(chain);
+            }
+        }
+
+        self.builder.position_at_end(unreachable_block);
+        self.builder.build_unreachable();
+
+        // Compile the arm bodies, storing their value in a shared slot. The
+        // first arm decides the type of the match unless it is a statement.
+        let mut result: Option<(PointerValue<'ctx>, Type)> = None;
+        let mut result_type: Option<Type> = None;
+
+        for (arm, block) in arms.iter().zip(arm_blocks.iter()) {
+            self.builder.position_at_end(*block);
+            let mark = self.temporaries.len();
+            self.scopes.push(HashMap::new());
+            self.owned.push(vec![]);
+            self.bind_pattern(&arm.pattern, ptr, &r#type)?;
+
+            let value = match (&result_type, hint) {
+                (_, Some(Type::Void)) => {
+                    self.compile_value_or_void(&arm.body, None)?;
+                    None
+                }
+                (Some(expected), _) => {
+                    let expected = expected.clone();
+                    let value = self.compile_expression_as(&arm.body, &expected)?;
+                    Some(self.take(value, &expected))
+                }
+                (None, hint) => {
+                    let (value, found) = self.compile_value_or_void(&arm.body, hint)?;
+                    result_type = Some(found.clone());
+                    value.map(|value| self.take(value, &found))
+                }
+            };
+
+            if let Some(value) = value {
+                let slot = match &result {
+                    Some((slot, _)) => *slot,
+                    None => {
+                        let slot = self.create_entry_block_alloca(value.get_type(), "match.result");
+                        result = Some((slot, result_type.clone().unwrap()));
+                        slot
+                    }
+                };
+                self.builder.build_store(slot, value);
+            }
+
+            self.release_temporaries(mark);
+            self.release_scopes(self.owned.len() - 1);
+            self.owned.pop();
+            self.scopes.pop();
+            self.builder.build_unconditional_branch(end_block);
+        }
+
+        self.builder.position_at_end(end_block);
+
+        match result {
+            Some((slot, r#type)) => {
+                let value = self
+                    .builder
+                    .build_load(get_llvm_type(&r#type, self.context), slot, "match");
+                Ok((Some(self.owned_temporary(value, &r#type)), r#type))
+            }
+            None => Ok((None, Type::Void)),
+        }
+    }
+
+    fn switch_key(&self, pattern: &Pattern, r#type: &Type) -> Result<Option<IntValue<'ctx>>, String> {
+        self.check_pattern_type(pattern, r#type)?;
+
+        Ok(match pattern {
+            Pattern::Wildcard | Pattern::Binding { .. } => None,
+            Pattern::IntLiteral { val } => Some(
+                get_llvm_type(r#type, self.context)
+                    .into_int_type()
+                    .const_int(*val as u64, true),
+            ),
+            Pattern::BooleanLiteral { val } => {
+                Some(self.context.bool_type().const_int(*val as u64, false))
+            }
+            Pattern::Variant {
+                enum_name, variant, ..
+            } => {
+                let index = self.variant_index(enum_name, variant);
+                Some(self.context.i32_type().const_int(index as u64, false))
+            }
+        })
+    }
+
+    fn check_pattern_type(&self, pattern: &Pattern, r#type: &Type) -> Result<(), String> {
+        let matches = match pattern {
+            Pattern::Wildcard | Pattern::Binding { .. } => true,
+            Pattern::IntLiteral { .. } => r#type.is_integer(),
+            Pattern::BooleanLiteral { .. } => r#type == &Type::Boolean,
+            Pattern::Variant { enum_name, .. } => {
+                r#type
+                    == &Type::UserDefinedType {
+                        name: enum_name.clone(),
+                    }
+            }
+        };
+
+        if matches {
+            Ok(())
+        } else {
+            Err(format!("Pattern `{}` can not match a value of type {}", pattern, r#type))
+        }
+    }
+
+    /// Builds a chain of blocks testing `candidates` in order, each jumping
+    /// to its arm block on success and to the next candidate on failure.
+    /// The top-level constructor is assumed to have been matched by the
+    /// switch already. Returns the first block of the chain.
+    fn build_arm_chain(
+        &mut self,
+        candidates: &[usize],
+        arms: &[MatchArm],
+        arm_blocks: &[BasicBlock<'ctx>],
+        ptr: PointerValue<'ctx>,
+        unreachable_block: BasicBlock<'ctx>,
+    ) -> Result<BasicBlock<'ctx>, String> {
+        let function = self.current_function();
+        let mut next = unreachable_block;
+
+        for i in candidates.iter().rev() {
+            let test_block = self.context.append_basic_block(function, "match.test");
+            self.builder.position_at_end(test_block);
+
+            // Literals were fully matched by the switch, only the fields of
+            // variants are left to test
+            if let Pattern::Variant {
+                enum_name,
+                variant,
+                fields,
+            } = &arms[*i].pattern
+            {
+                let index = self.variant_index(enum_name, variant);
+                for (j, field) in fields.iter().enumerate() {
+                    let field_type = self.enums[enum_name][index].fields[j].clone();
+                    let field_ptr = self.variant_field_pointer(ptr, enum_name, index, j);
+                    self.build_pattern_test(field, field_ptr, &field_type, next)?;
+                }
+            }
+
+            self.builder.build_unconditional_branch(arm_blocks[*i]);
+            next = test_block;
+        }
+
+        Ok(next)
+    }
+
+    /// Emits the checks for `pattern` against the value at `ptr`, branching
+    /// to `fail` if it does not match and leaving the builder in the block
+    /// reached when it does.
+    fn build_pattern_test(
+        &mut self,
+        pattern: &Pattern,
+        ptr: PointerValue<'ctx>,
+        r#type: &Type,
+        fail: BasicBlock<'ctx>,
+    ) -> Result<(), String> {
+        self.check_pattern_type(pattern, r#type)?;
+
+        let (value, expected) = match pattern {
+            Pattern::Wildcard | Pattern::Binding { .. } => return Ok(()),
+            Pattern::IntLiteral { .. } | Pattern::BooleanLiteral { .. } => (
+                self.builder
+                    .build_load(get_llvm_type(r#type, self.context), ptr, "value")
+                    .into_int_value(),
+                self.switch_key(pattern, r#type)?.unwrap(),
+            ),
+            Pattern::Variant { enum_name, .. } => (
+                self.builder
+                    .build_load(
+                        self.context.i32_type(),
+                        self.enum_tag_pointer(ptr, enum_name),
+                        "tag",
+                    )
+                    .into_int_value(),
+                self.switch_key(pattern, r#type)?.unwrap(),
+            ),
+        };
+
+        let function = self.current_function();
+        let matched = self.context.append_basic_block(function, "match.matched");
+        let is_match = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, value, expected, "is_match");
+        self.builder.build_conditional_branch(is_match, matched, fail);
+        self.builder.position_at_end(matched);
+
+        if let Pattern::Variant {
+            enum_name,
+            variant,
+            fields,
+        } = pattern
+        {
+            let index = self.variant_index(enum_name, variant);
+            for (j, field) in fields.iter().enumerate() {
+                let field_type = self.enums[enum_name][index].fields[j].clone();
+                let field_ptr = self.variant_field_pointer(ptr, enum_name, index, j);
+                self.build_pattern_test(field, field_ptr, &field_type, fail)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Declares the variables bound by `pattern`, copying their values out of
+    /// the matched value and retaining them.
+    fn bind_pattern(&mut self, pattern: &Pattern, ptr: PointerValue<'ctx>, r#type: &Type) -> Result<(), String> {
+        match pattern {
+            Pattern::Binding { name } => {
+                let llvm_type = get_llvm_type(r#type, self.context);
+                let value = self.builder.build_load(llvm_type, ptr, name);
+                let value = self.take(value, r#type);
+                let slot = self.create_entry_block_alloca(llvm_type, name);
+                self.builder.build_store(slot, value);
+                self.own(slot, r#type);
+                self.debug_variable(name, slot, r#type, None);
+                self.declare_variable(name.clone(), slot, r#type.clone());
+            }
+            Pattern::Variant {
+                enum_name,
+                variant,
+                fields,
+            } => {
+                let index = self.variant_index(enum_name, variant);
+                for (j, field) in fields.iter().enumerate() {
+                    let field_type = self.enums[enum_name][index].fields[j].clone();
+                    let field_ptr = self.variant_field_pointer(ptr, enum_name, index, j);
+                    self.bind_pattern(field, field_ptr, &field_type)?;
+                }
+            }
+            Pattern::Wildcard | Pattern::IntLiteral { .. } | Pattern::BooleanLiteral { .. } => {}
+        }
+
+        Ok(())
+    }
+}
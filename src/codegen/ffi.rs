@@ -0,0 +1,116 @@
+use inkwell::{
+    attributes::{Attribute, AttributeLoc},
+    module::Linkage,
+    values::FunctionValue,
+};
+
+use crate::ast::{statements::Statements, FuncParam, Type};
+
+use super::{get_function_params, get_function_type, CodeGen};
+
+/// LLVM's number for the C calling convention, `ccc`.
+const C_CALLING_CONVENTION: u32 = 0;
+
+/// The math functions of the runtime, which `std.math` wraps, and the LLVM
+/// intrinsics calls to them are compiled to. LLVM can fold those and lower
+/// them to instructions.
+const INTRINSICS: &[(&str, &str)] = &[
+    ("aurora_sqrt", "llvm.sqrt.f64"),
+    ("aurora_pow", "llvm.pow.f64"),
+    ("aurora_floor", "llvm.floor.f64"),
+    ("aurora_ceil", "llvm.ceil.f64"),
+];
+
+/// The LLVM intrinsic the C function `name` is compiled to, if it is a math
+/// function of the runtime declared with `f64`s only.
+fn intrinsic_name(name: &str, params: &[FuncParam], return_type: &Type) -> Option<&'static str> {
+    let (_, intrinsic) = INTRINSICS.iter().find(|(function, _)| *function == name)?;
+    match *return_type == Type::Float64 && params.iter().all(|p| p.r#type == Type::Float64) {
+        true => Some(intrinsic),
+        false => None,
+    }
+}
+
+/// The attribute C compilers put on a parameter or return value of `r#type`
+/// to say how it is widened to a whole register, which the callee may rely
+/// on.
+fn extension(r#type: &Type) -> Option<&'static str> {
+    match r#type {
+        Type::Int8 | Type::Int16 => Some("signext"),
+        Type::Boolean => Some("zeroext"),
+        _ => None,
+    }
+}
+
+/// Functions declared with `extern "C"` are called by or call into C. They
+/// keep their plain names as symbols, see `modules::resolve`, and follow the
+/// C calling convention. Their signatures only have types C has as well,
+/// which `sema` checks.
+impl<'a, 'ctx> CodeGen<'a, 'ctx> {
+    /// Declares a C function that is linked in from elsewhere.
+    pub fn declare_extern_function(&mut self, function: &Statements) -> Result<FunctionValue<'ctx>, String> {
+        match function {
+            Statements::ExternFunctionDeclaration {
+                name,
+                params,
+                return_type,
+            } => {
+                if let Some(fn_val) = self.module.get_function(name) {
+                    return Ok(fn_val);
+                }
+                if let Some(fn_val) = self.intrinsics.get(name) {
+                    return Ok(*fn_val);
+                }
+
+                for param in params {
+                    self.instantiate_type(&param.r#type)?;
+                }
+                self.instantiate_type(return_type)?;
+
+                let fn_params = get_function_params(params.clone(), self.context);
+                let fn_type = get_function_type(return_type.clone(), fn_params, self.context);
+                let fn_val = match intrinsic_name(name, params, return_type) {
+                    Some(intrinsic) => {
+                        let fn_val = self.module.add_function(intrinsic, fn_type, None);
+                        self.intrinsics.insert(name.clone(), fn_val);
+                        fn_val
+                    }
+                    None => {
+                        let fn_val = self.module.add_function(name, fn_type, Some(Linkage::External));
+                        self.set_c_abi(fn_val, params, return_type);
+                        fn_val
+                    }
+                };
+
+                self.functions.insert(
+                    name.clone(),
+                    (
+                        params.iter().map(|p| p.r#type.clone()).collect(),
+                        return_type.clone(),
+                    ),
+                );
+                self.c_functions.insert(name.clone());
+
+                Ok(fn_val)
+            }
+            _ => Err("Expected ExternFunctionDecl as input".to_string()),
+        }
+    }
+
+    /// Makes `fn_val` callable like a C function with the same signature.
+    pub(super) fn set_c_abi(&self, fn_val: FunctionValue<'ctx>, params: &[FuncParam], return_type: &Type) {
+        fn_val.set_call_conventions(C_CALLING_CONVENTION);
+
+        let locations = params
+            .iter()
+            .enumerate()
+            .map(|(i, param)| (AttributeLoc::Param(i as u32), &param.r#type))
+            .chain([(AttributeLoc::Return, return_type)]);
+        for (location, r#type) in locations {
+            if let Some(name) = extension(r#type) {
+                let kind = Attribute::get_named_enum_kind_id(name);
+                fn_val.add_attribute(location, self.context.create_enum_attribute(kind, 0));
+            }
+        }
+    }
+}
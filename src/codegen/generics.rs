@@ -0,0 +1,253 @@
+use std::collections::HashMap;
+
+use inkwell::{
+    module::Linkage,
+    types::BasicTypeEnum,
+    values::{BasicMetadataValueEnum, BasicValueEnum},
+};
+
+use crate::{
+    ast::{expressions::Expression, statements::Statements, StructField, Type, TypeParam},
+    sema::typing::Inference,
+};
+
+use super::{get_llvm_type, CodeGen};
+
+fn bind_type_params(type_params: &[TypeParam], type_args: &[Type]) -> HashMap<String, Type> {
+    type_params
+        .iter()
+        .map(|p| p.name.clone())
+        .zip(type_args.iter().cloned())
+        .collect()
+}
+
+/// Generic functions and structs are monomorphised: every distinct list of
+/// type arguments gets its own copy, named after the type arguments (e.g.
+/// `max[i32]` or `Pair[i32, f64]`).
+impl<'a, 'ctx> CodeGen<'a, 'ctx> {
+    pub fn declare_generic_function(&mut self, function: &Statements) -> Result<(), String> {
+        match function {
+            Statements::FunctionDeclaration { name, .. } => {
+                if self.generic_functions.contains_key(name) || self.functions.contains_key(name) {
+                    return Err(format!("Function `{}` is declared more than once", name));
+                }
+
+                self.generic_functions.insert(name.clone(), function.clone());
+                Ok(())
+            }
+            _ => Err("Expected FunctionDecl as input".to_string()),
+        }
+    }
+
+    /// Compiles the bodies of the instances of generic functions requested
+    /// so far, including any instances those bodies request in turn.
+    pub fn compile_instances(&mut self) -> Result<(), String> {
+        while let Some((instance, bindings)) = self.pending_instances.pop() {
+            self.type_bindings = bindings;
+            self.compile_function_statement(instance)?;
+        }
+
+        self.type_bindings.clear();
+        Ok(())
+    }
+
+    /// Substitutes the type arguments of the instance being compiled into a
+    /// type from the source, creating any generic struct instances it uses.
+    pub(super) fn resolve_type(&mut self, r#type: &Type) -> Result<Type, String> {
+        let r#type = r#type.substitute(&self.type_bindings);
+        self.instantiate_type(&r#type)?;
+        Ok(r#type)
+    }
+
+    /// Creates the LLVM struct types for the generic struct instances used in
+    /// `r#type`.
+    pub(super) fn instantiate_type(&mut self, r#type: &Type) -> Result<(), String> {
+        match r#type {
+            Type::Array(r#type) | Type::FixedArray(r#type, _) | Type::Pointer(r#type) => {
+                self.instantiate_type(r#type)
+            }
+            Type::Tuple(types) => types.iter().try_for_each(|t| self.instantiate_type(t)),
+            Type::Function {
+                params,
+                return_type,
+            } => {
+                params.iter().try_for_each(|t| self.instantiate_type(t))?;
+                self.instantiate_type(return_type)
+            }
+            Type::Generic { name, params } => {
+                let symbol = r#type.to_string();
+                if self.context.get_struct_type(&symbol).is_some() {
+                    return Ok(());
+                }
+
+                params.iter().try_for_each(|t| self.instantiate_type(t))?;
+
+                let (type_params, _) = self
+                    .generic_structs
+                    .get(name)
+                    .cloned()
+                    .ok_or(format!("Unknown generic type `{}`", name))?;
+                if type_params.len() != params.len() {
+                    return Err(format!(
+                        "`{}` takes {} type arguments but {} were supplied",
+                        name,
+                        type_params.len(),
+                        params.len()
+                    ));
+                }
+
+                // Named before the fields are instantiated so that they may
+                // point back to it
+                let struct_type = self.context.opaque_struct_type(&symbol);
+                let fields = self.struct_fields(r#type).unwrap();
+                fields
+                    .iter()
+                    .try_for_each(|f| self.instantiate_type(&f.r#type))?;
+
+                let field_types: Vec<BasicTypeEnum> = fields
+                    .iter()
+                    .map(|f| get_llvm_type(&f.r#type, self.context))
+                    .collect();
+                struct_type.set_body(&field_types, false);
+                Ok(())
+            }
+            Type::TypeParameter { name } => Err(format!("Unresolved type parameter `{}`", name)),
+            _ => Ok(()),
+        }
+    }
+
+    /// The fields of a struct or generic struct instance, with the type
+    /// arguments substituted.
+    pub(super) fn struct_fields(&self, r#type: &Type) -> Option<Vec<StructField>> {
+        match r#type {
+            Type::UserDefinedType { name } => self.structs.get(name).cloned(),
+            Type::Generic { name, params } => {
+                let (type_params, fields) = self.generic_structs.get(name)?;
+                let bindings = bind_type_params(type_params, params);
+
+                Some(
+                    fields
+                        .iter()
+                        .map(|f| StructField {
+                            name: f.name.clone(),
+                            r#type: f.r#type.substitute(&bindings),
+                        })
+                        .collect(),
+                )
+            }
+            _ => None,
+        }
+    }
+
+    /// Calls a generic function, inferring its type arguments from the
+    /// arguments in order. Once a parameter is bound later arguments are
+    /// converted to it, so `max(x, 1)` with `x: i64` calls `max[i64]`.
+    /// Parameters no argument binds are taken from `hint`, the type the call
+    /// is expected to have, so `let l: List[i32] = list()` calls `list[i32]`.
+    pub(super) fn compile_generic_call(
+        &mut self,
+        function: &Statements,
+        params: &[Expression],
+        hint: Option<&Type>,
+    ) -> Result<(Option<BasicValueEnum<'ctx>>, Type), String> {
+        let (name, type_params, fn_params, return_type) = match function {
+            Statements::FunctionDeclaration {
+                name,
+                type_params,
+                params,
+                return_type,
+                ..
+            } => (name, type_params, params, return_type),
+            _ => return Err("Expected FunctionDecl as input".to_string()),
+        };
+
+        if fn_params.len() != params.len() {
+            return Err(format!(
+                "`{}` takes {} arguments but {} were supplied",
+                name,
+                fn_params.len(),
+                params.len()
+            ));
+        }
+
+        let mut inference = Inference::new(name, type_params);
+        let mut values: Vec<(BasicValueEnum, Type)> = vec![];
+        for (param, fn_param) in params.iter().zip(fn_params.iter()) {
+            match inference.expected(&fn_param.r#type) {
+                Some(param_type) => values.push((self.compile_expression_as(param, &param_type)?, param_type)),
+                None => {
+                    let (value, found) = self.compile_expression(param, None)?;
+                    inference.unify(&fn_param.r#type, &found)?;
+                    values.push((value, found));
+                }
+            }
+        }
+        inference.expect_result(return_type, hint);
+
+        let type_args = inference.type_arguments()?;
+        let symbol = self.instantiate_function(function, &type_args)?;
+
+        let mut args: Vec<BasicMetadataValueEnum> = vec![];
+        for ((value, found), fn_param) in values.into_iter().zip(fn_params.iter()) {
+            let param_type = inference.substitute(&fn_param.r#type);
+            args.push(self.coerce(value, &found, &param_type)?.into());
+        }
+
+        let return_type = inference.substitute(return_type);
+        let value = self.build_call(&symbol, &args);
+        Ok((value.map(|value| self.owned_temporary(value, &return_type)), return_type))
+    }
+
+    /// Declares the instance of a generic function for `type_args` and
+    /// queues its body for compilation. Returns the symbol of the instance.
+    fn instantiate_function(&mut self, function: &Statements, type_args: &[Type]) -> Result<String, String> {
+        let (name, type_params, params, body, return_type, line) = match function {
+            Statements::FunctionDeclaration {
+                name,
+                type_params,
+                params,
+                body,
+                return_type,
+                line,
+                ..
+            } => (name, type_params, params, body, return_type, line),
+            _ => return Err("Expected FunctionDecl as input".to_string()),
+        };
+
+        let key = (name.clone(), type_args.to_vec());
+        if let Some(symbol) = self.instances.get(&key) {
+            return Ok(symbol.clone());
+        }
+
+        let symbol = Type::Generic {
+            name: name.clone(),
+            params: type_args.to_vec(),
+        }
+        .to_string();
+        let bindings = bind_type_params(type_params, type_args);
+
+        let mut instance_params = params.clone();
+        for param in instance_params.iter_mut() {
+            param.r#type = param.r#type.substitute(&bindings);
+        }
+
+        let instance = Statements::FunctionDeclaration {
+            name: symbol.clone(),
+            type_params: vec![],
+            params: instance_params,
+            body: body.clone(),
+            return_type: return_type.substitute(&bindings),
+            is_const: false,
+            is_extern: false,
+            // Imported generic functions have no source in this module
+            line: self.instance_line(name, *line),
+        };
+
+        // Every module using an instance compiles its own copy
+        self.declare_function(&instance)?.set_linkage(Linkage::Internal);
+        self.instances.insert(key, symbol.clone());
+        self.pending_instances.push((instance, bindings));
+
+        Ok(symbol)
+    }
+}
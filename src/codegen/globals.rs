@@ -0,0 +1,151 @@
+use inkwell::{
+    module::Linkage,
+    values::{BasicValueEnum, PointerValue},
+};
+
+use crate::{
+    ast::{statements::Statements, Type},
+    consteval::Value,
+};
+
+use super::{get_llvm_type, CodeGen};
+
+fn check_global_type(name: &str, r#type: &Type) -> Result<(), String> {
+    if r#type.is_integer() || r#type.is_float() || matches!(r#type, Type::Boolean | Type::String) {
+        Ok(())
+    } else {
+        Err(format!(
+            "`{}` has type {}, but globals and constants can only be numbers, bools or strings",
+            name, r#type
+        ))
+    }
+}
+
+/// Constants are evaluated at compile time and used as LLVM constants
+/// wherever they are named, so they never get a symbol of their own. Module
+/// level `let`s become globals with a constant initialiser, which modules
+/// importing them refer to as external globals.
+impl<'a, 'ctx> CodeGen<'a, 'ctx> {
+    pub fn declare_constant(&mut self, constant: &Statements) -> Result<(), String> {
+        match constant {
+            Statements::ConstDeclaration { name, r#type, .. } => {
+                check_global_type(name, r#type)?;
+                self.constants
+                    .declare(constant)
+                    .map_err(|e| e.to_string())
+            }
+            _ => Err("Expected ConstDecl as input".to_string()),
+        }
+    }
+
+    /// Evaluates a constant of the module being compiled, so that errors are
+    /// reported even if it is never used.
+    pub fn define_constant(&mut self, constant: &Statements) -> Result<(), String> {
+        match constant {
+            Statements::ConstDeclaration { name, .. } => self
+                .constants
+                .value(name)
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+            _ => Err("Expected ConstDecl as input".to_string()),
+        }
+    }
+
+    /// Adds the global of a module-level `let` to the module. Unless it is
+    /// given an initialiser by `define_global`, it refers to the global of
+    /// the module that declared it.
+    pub fn declare_global(&mut self, global: &Statements) -> Result<(), String> {
+        match global {
+            Statements::VariableDeclaration { name, r#type, .. } => {
+                check_global_type(name, r#type)?;
+                if self.globals.contains_key(name) {
+                    return Err(format!("Global `{}` is declared more than once", name));
+                }
+
+                let ptr = self
+                    .module
+                    .add_global(get_llvm_type(r#type, self.context), None, name)
+                    .as_pointer_value();
+                self.globals.insert(name.clone(), (ptr, r#type.clone()));
+                Ok(())
+            }
+            _ => Err("Expected VariableDecl as input".to_string()),
+        }
+    }
+
+    pub fn define_global(&mut self, global: &Statements) -> Result<(), String> {
+        match global {
+            Statements::VariableDeclaration {
+                name,
+                r#type,
+                value,
+                ..
+            } => {
+                let value = self.constants.evaluate(value, r#type).map_err(|e| {
+                    format!("Initialiser of global `{}` is not a constant: {}", name, e)
+                })?;
+                let value = self.const_value(&value, r#type);
+                self.module
+                    .get_global(name)
+                    .unwrap()
+                    .set_initializer(&value);
+                Ok(())
+            }
+            _ => Err("Expected VariableDecl as input".to_string()),
+        }
+    }
+
+    /// Whether `name` refers to a constant rather than to a variable.
+    pub(super) fn is_constant(&self, name: &str) -> bool {
+        self.constants.is_constant(name) && !self.scopes.iter().any(|scope| scope.contains_key(name))
+    }
+
+    pub(super) fn compile_constant(&mut self, name: &str) -> Result<(BasicValueEnum<'ctx>, Type), String> {
+        let r#type = self.constants.type_of(name).unwrap().clone();
+        let value = self.constants.value(name).map_err(|e| e.to_string())?;
+        Ok((self.const_value(&value, &r#type), r#type))
+    }
+
+    fn const_value(&self, value: &Value, r#type: &Type) -> BasicValueEnum<'ctx> {
+        let llvm_type = get_llvm_type(r#type, self.context);
+
+        match value {
+            Value::Int(value) => llvm_type
+                .into_int_type()
+                .const_int_arbitrary_precision(&[*value as u64, (*value >> 64) as u64])
+                .into(),
+            Value::Float(value) => llvm_type.into_float_type().const_float(*value).into(),
+            Value::Bool(value) => self.context.bool_type().const_int(*value as u64, false).into(),
+            Value::String(value) => self.const_string(value).into(),
+        }
+    }
+
+    /// A string literal, which is stored with a header like the strings on
+    /// the heap, see `runtime::ALLOC`, but never freed. Unlike
+    /// `build_global_string_ptr`, usable outside of functions too.
+    pub(super) fn const_string(&self, value: &str) -> PointerValue<'ctx> {
+        let i32_type = self.context.i32_type();
+        let i64_type = self.context.i64_type();
+        let chars = self.context.const_string(value.as_bytes(), true);
+        let string = self.context.const_struct(
+            &[
+                i64_type.const_all_ones().into(),
+                i64_type.const_zero().into(),
+                chars.into(),
+            ],
+            false,
+        );
+
+        let global = self.module.add_global(string.get_type(), None, "str");
+        global.set_initializer(&string);
+        global.set_constant(true);
+        global.set_linkage(Linkage::Private);
+
+        let indexes = [i32_type.const_zero(), i32_type.const_int(2, false), i32_type.const_zero()];
+        unsafe {
+            global
+                .as_pointer_value()
+                .const_in_bounds_gep(string.get_type(), &indexes)
+        }
+    }
+}
@@ -0,0 +1,353 @@
+use inkwell::{
+    module::Linkage,
+    values::{BasicValueEnum, FunctionValue, IntValue, PointerValue},
+    AddressSpace, IntPredicate,
+};
+
+use crate::{
+    ast::{EnumVariant, StructField, Type},
+    sema::memory::{self, Declarations},
+};
+
+use super::{get_llvm_type, runtime, CodeGen};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum Glue {
+    Retain,
+    Release,
+}
+
+impl<'a, 'ctx> Declarations for CodeGen<'a, 'ctx> {
+    fn struct_fields(&self, r#type: &Type) -> Option<Vec<StructField>> {
+        CodeGen::struct_fields(self, r#type)
+    }
+
+    fn enum_variants(&self, name: &str) -> Option<&[EnumVariant]> {
+        self.enums.get(name).map(Vec::as_slice)
+    }
+}
+
+/// Reference counting of the values `sema::memory` says hold references.
+/// The counts are kept in front of the data, see `runtime::ALLOC`.
+impl<'a, 'ctx> CodeGen<'a, 'ctx> {
+    /// Whether values of `r#type` hold references.
+    pub(super) fn is_managed(&self, r#type: &Type) -> bool {
+        memory::is_managed(self, r#type)
+    }
+
+    pub(super) fn retain(&mut self, value: BasicValueEnum<'ctx>, r#type: &Type) {
+        if self.is_managed(r#type) {
+            self.build_glue_call(Glue::Retain, value, r#type);
+        }
+    }
+
+    pub(super) fn release(&mut self, value: BasicValueEnum<'ctx>, r#type: &Type) {
+        if self.is_managed(r#type) {
+            self.build_glue_call(Glue::Release, value, r#type);
+        }
+    }
+
+    /// Registers `value`, a new value, to be released at the end of the
+    /// statement.
+    pub(super) fn owned_temporary(
+        &mut self,
+        value: BasicValueEnum<'ctx>,
+        r#type: &Type,
+    ) -> BasicValueEnum<'ctx> {
+        if self.is_managed(r#type) {
+            self.temporaries.push((value, r#type.clone()));
+        }
+        value
+    }
+
+    /// A reference to `value` for the caller to keep, moved out of the
+    /// temporaries if it is new.
+    pub(super) fn take(
+        &mut self,
+        value: BasicValueEnum<'ctx>,
+        r#type: &Type,
+    ) -> BasicValueEnum<'ctx> {
+        if let Some(i) = self.temporaries.iter().position(|(v, _)| *v == value) {
+            self.temporaries.remove(i);
+            return value;
+        }
+        // String literals live forever
+        if *r#type == Type::String && value.into_pointer_value().is_const() {
+            return value;
+        }
+        self.retain(value, r#type);
+        value
+    }
+
+    /// Releases the temporaries created since there were `mark` of them.
+    pub(super) fn release_temporaries(&mut self, mark: usize) {
+        for (value, r#type) in self.temporaries.split_off(mark).into_iter().rev() {
+            self.release(value, &r#type);
+        }
+    }
+
+    /// Makes the innermost scope release the variable at `ptr` when it ends.
+    pub(super) fn own(&mut self, ptr: PointerValue<'ctx>, r#type: &Type) {
+        if self.is_managed(r#type) {
+            self.owned.last_mut().unwrap().push((ptr, r#type.clone()));
+        }
+    }
+
+    /// Releases the variables of the scopes from the `depth`th one inwards,
+    /// without ending them.
+    pub(super) fn release_scopes(&mut self, depth: usize) {
+        let variables: Vec<(PointerValue, Type)> = self.owned[depth..]
+            .iter()
+            .rev()
+            .flat_map(|scope| scope.iter().rev().cloned())
+            .collect();
+        for (ptr, r#type) in variables {
+            let value = self
+                .builder
+                .build_load(get_llvm_type(&r#type, self.context), ptr, "owned");
+            self.release(value, &r#type);
+        }
+    }
+
+    fn build_glue_call(&mut self, glue: Glue, value: BasicValueEnum<'ctx>, r#type: &Type) {
+        let function = self.glue_function(glue, r#type);
+        self.builder.build_call(function, &[value.into()], "");
+    }
+
+    /// The function retaining or releasing values of `r#type`. Every module
+    /// defines its own, privately, when it first needs them.
+    fn glue_function(&mut self, glue: Glue, r#type: &Type) -> FunctionValue<'ctx> {
+        let name = match glue {
+            Glue::Retain => format!("aurora.retain.{}", r#type),
+            Glue::Release => format!("aurora.release.{}", r#type),
+        };
+        if let Some(function) = self.module.get_function(&name) {
+            return function;
+        }
+
+        let fn_type = self
+            .context
+            .void_type()
+            .fn_type(&[get_llvm_type(r#type, self.context).into()], false);
+        let function = self
+            .module
+            .add_function(&name, fn_type, Some(Linkage::Private));
+
+        // Built with a builder of its own, which has no position or debug
+        // location in the function being compiled
+        let builder = std::mem::replace(&mut self.builder, self.context.create_builder());
+        self.builder
+            .position_at_end(self.context.append_basic_block(function, "entry"));
+        self.build_glue_body(glue, r#type, function);
+        self.builder.build_return(None);
+        self.builder = builder;
+
+        function
+    }
+
+    fn build_glue_body(&mut self, glue: Glue, r#type: &Type, function: FunctionValue<'ctx>) {
+        let value = function.get_nth_param(0).unwrap();
+
+        match r#type {
+            Type::String => self.build_block_glue(glue, function, value.into_pointer_value(), None),
+            Type::Array(element) => {
+                let array = value.into_struct_value();
+                let data = self
+                    .builder
+                    .build_extract_value(array, 0, "data")
+                    .unwrap()
+                    .into_pointer_value();
+                let len = self
+                    .builder
+                    .build_extract_value(array, 1, "len")
+                    .unwrap()
+                    .into_int_value();
+                self.build_block_glue(glue, function, data, Some((len, element)));
+            }
+            Type::FixedArray(element, size) => {
+                let ptr = self.builder.build_alloca(value.get_type(), "array");
+                self.builder.build_store(ptr, value);
+                let zero = self.context.i64_type().const_zero();
+                let data = unsafe {
+                    self.builder
+                        .build_in_bounds_gep(value.get_type(), ptr, &[zero, zero], "data")
+                };
+                let len = self.context.i64_type().const_int(*size as u64, false);
+                self.build_elements_glue(glue, function, data, len, element);
+            }
+            Type::Tuple(types) => {
+                for (i, field_type) in types.iter().enumerate() {
+                    if self.is_managed(field_type) {
+                        let field = self
+                            .builder
+                            .build_extract_value(value.into_struct_value(), i as u32, "field")
+                            .unwrap();
+                        self.build_glue_call(glue, field, field_type);
+                    }
+                }
+            }
+            r#type => match self.struct_fields(r#type) {
+                Some(fields) => {
+                    for (i, field) in fields.iter().enumerate() {
+                        if self.is_managed(&field.r#type) {
+                            let field_value = self
+                                .builder
+                                .build_extract_value(
+                                    value.into_struct_value(),
+                                    i as u32,
+                                    &field.name,
+                                )
+                                .unwrap();
+                            self.build_glue_call(glue, field_value, &field.r#type);
+                        }
+                    }
+                }
+                None => self.build_enum_glue(glue, r#type, function),
+            },
+        }
+    }
+
+    /// Retains or releases the block at `data`. The last release frees it,
+    /// after releasing the `len` values of type `element` in it.
+    fn build_block_glue(
+        &mut self,
+        glue: Glue,
+        function: FunctionValue<'ctx>,
+        data: PointerValue<'ctx>,
+        elements: Option<(IntValue<'ctx>, &Type)>,
+    ) {
+        let block = self.builder.build_pointer_cast(
+            data,
+            self.context.i8_type().ptr_type(AddressSpace::default()),
+            "block",
+        );
+
+        if glue == Glue::Retain {
+            let retain = self.get_runtime_function(runtime::RETAIN);
+            self.builder.build_call(retain, &[block.into()], "");
+            return;
+        }
+
+        let free_block = self.context.append_basic_block(function, "free");
+        let done_block = self.context.append_basic_block(function, "done");
+        let release = self.get_runtime_function(runtime::RELEASE);
+        let is_last = self
+            .builder
+            .build_call(release, &[block.into()], "is_last")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_int_value();
+        self.builder
+            .build_conditional_branch(is_last, free_block, done_block);
+
+        self.builder.position_at_end(free_block);
+        if let Some((len, element)) = elements {
+            if self.is_managed(element) {
+                self.build_elements_glue(glue, function, data, len, element);
+            }
+        }
+        let free = self.get_runtime_function(runtime::FREE);
+        self.builder.build_call(free, &[block.into()], "");
+        self.builder.build_unconditional_branch(done_block);
+
+        self.builder.position_at_end(done_block);
+    }
+
+    /// Retains or releases the `len` values of type `element` at `data`.
+    fn build_elements_glue(
+        &mut self,
+        glue: Glue,
+        function: FunctionValue<'ctx>,
+        data: PointerValue<'ctx>,
+        len: IntValue<'ctx>,
+        element: &Type,
+    ) {
+        let i64_type = self.context.i64_type();
+        let element_type = get_llvm_type(element, self.context);
+
+        let check_block = self.context.append_basic_block(function, "elements.check");
+        let body_block = self.context.append_basic_block(function, "elements.body");
+        let end_block = self.context.append_basic_block(function, "elements.end");
+
+        let index_slot = self.builder.build_alloca(i64_type, "index");
+        self.builder.build_store(index_slot, i64_type.const_zero());
+        self.builder.build_unconditional_branch(check_block);
+
+        self.builder.position_at_end(check_block);
+        let index = self
+            .builder
+            .build_load(i64_type, index_slot, "index")
+            .into_int_value();
+        let more = self
+            .builder
+            .build_int_compare(IntPredicate::SLT, index, len, "more");
+        self.builder
+            .build_conditional_branch(more, body_block, end_block);
+
+        self.builder.position_at_end(body_block);
+        let element_ptr = unsafe {
+            self.builder
+                .build_in_bounds_gep(element_type, data, &[index], "element")
+        };
+        let value = self
+            .builder
+            .build_load(element_type, element_ptr, "element");
+        self.build_glue_call(glue, value, element);
+        let index = self
+            .builder
+            .build_int_add(index, i64_type.const_int(1, false), "index");
+        self.builder.build_store(index_slot, index);
+        self.builder.build_unconditional_branch(check_block);
+
+        self.builder.position_at_end(end_block);
+    }
+
+    /// Retains or releases the fields of the variant an enum value holds.
+    fn build_enum_glue(&mut self, glue: Glue, r#type: &Type, function: FunctionValue<'ctx>) {
+        let name = r#type.to_string();
+        let value = function.get_nth_param(0).unwrap();
+        let ptr = self.builder.build_alloca(value.get_type(), &name);
+        self.builder.build_store(ptr, value);
+        let tag = self
+            .builder
+            .build_load(
+                self.context.i32_type(),
+                self.enum_tag_pointer(ptr, &name),
+                "tag",
+            )
+            .into_int_value();
+
+        let switch_block = self.builder.get_insert_block().unwrap();
+        let done_block = self.context.append_basic_block(function, "done");
+        let mut cases = vec![];
+        for (index, variant) in self.enums[&name].clone().iter().enumerate() {
+            if !variant.fields.iter().any(|t| self.is_managed(t)) {
+                continue;
+            }
+
+            let case_block = self.context.append_basic_block(function, &variant.name);
+            self.builder.position_at_end(case_block);
+            for (j, field_type) in variant.fields.iter().enumerate() {
+                if self.is_managed(field_type) {
+                    let field_ptr = self.variant_field_pointer(ptr, &name, index, j);
+                    let field = self.builder.build_load(
+                        get_llvm_type(field_type, self.context),
+                        field_ptr,
+                        "field",
+                    );
+                    self.build_glue_call(glue, field, field_type);
+                }
+            }
+            self.builder.build_unconditional_branch(done_block);
+            cases.push((
+                self.context.i32_type().const_int(index as u64, false),
+                case_block,
+            ));
+        }
+
+        self.builder.position_at_end(switch_block);
+        self.builder.build_switch(tag, done_block, &cases);
+        self.builder.position_at_end(done_block);
+    }
+}
@@ -1,67 +1,124 @@
-use aurorac_macros::generate_params;
+use std::collections::{HashMap, HashSet};
+
 use inkwell::{
-    context::ContextRef,
+    attributes::{Attribute, AttributeLoc},
+    basic_block::BasicBlock,
+    builder::Builder,
+    context::Context,
     module::Module,
-    types::{BasicMetadataTypeEnum, FunctionType},
+    types::{BasicMetadataTypeEnum, BasicType, BasicTypeEnum, FunctionType, StructType},
+    values::{BasicMetadataValueEnum, BasicValueEnum, FunctionValue, IntValue, PointerValue},
+    AddressSpace, FloatPredicate, IntPredicate,
+};
+
+use crate::{
+    ast::{
+        assigned_variables,
+        expressions::{BinaryOperator, Expression, UnaryOperator},
+        mangle_method,
+        statements::Statements,
+        EnumVariant, FuncParam, StructField, Type, TypeParam,
+    },
+    consteval::ConstEvaluator,
+    sema::typing::{self, Inference},
+    utils::{float_digits, format_template, is_runtime_function},
 };
 
-use crate::ast::{statements::Statements, FuncParam, Type};
+mod debug;
+mod enums;
+mod ffi;
+mod generics;
+mod globals;
+mod memory;
+pub mod passes;
+pub mod runtime;
+pub mod target;
+pub mod verify;
 
-pub fn get_param_type(r#type: Type) -> String {
+pub fn get_llvm_type<'a>(r#type: &Type, ctx: &'a Context) -> BasicTypeEnum<'a> {
     match r#type {
-        Type::Int8 => "into_int_value",
-        Type::Int16 => "into_int_value",
-        Type::Int32 => "into_int_value",
-        Type::Int64 => "into_int_value",
-        Type::Int128 => "into_int_value",
-        Type::Float16 => "into_float_value",
-        Type::Float32 => "into_float_value",
-        Type::Float64 => "into_float_value",
-        Type::Float128 => "into_float_value",
-        Type::String => todo!(),
-        Type::Boolean => "into_int_value",
-        Type::Void => unreachable!(),
-        Type::Array(_) => "into_array_value",
-        Type::UserDefinedType { name } => todo!(),
-    }
-    .to_string()
+        Type::Int8 => ctx.i8_type().into(),
+        Type::Int16 => ctx.i16_type().into(),
+        Type::Int32 => ctx.i32_type().into(),
+        Type::Int64 => ctx.i64_type().into(),
+        Type::Int128 => ctx.i128_type().into(),
+        Type::Float16 => ctx.f16_type().into(),
+        Type::Float32 => ctx.f32_type().into(),
+        Type::Float64 => ctx.f64_type().into(),
+        Type::Float128 => ctx.f128_type().into(),
+        Type::String => ctx.i8_type().ptr_type(AddressSpace::default()).into(),
+        Type::Boolean => ctx.bool_type().into(),
+        Type::Void => panic!("void is not a value type"),
+        Type::Array(r#type) => get_array_struct_type(r#type, ctx).into(),
+        Type::FixedArray(r#type, size) => get_llvm_type(r#type, ctx)
+            .array_type(*size as u32)
+            .into(),
+        Type::Tuple(types) => {
+            let fields: Vec<BasicTypeEnum> = types.iter().map(|t| get_llvm_type(t, ctx)).collect();
+            ctx.struct_type(&fields, false).into()
+        }
+        Type::Function {
+            params,
+            return_type,
+        } => get_function_type(
+            *return_type.clone(),
+            get_function_params(
+                params
+                    .iter()
+                    .map(|r#type| FuncParam {
+                        name: String::new(),
+                        r#type: r#type.clone(),
+                    })
+                    .collect(),
+                ctx,
+            ),
+            ctx,
+        )
+        .ptr_type(AddressSpace::default())
+        .into(),
+        // Instances of generic structs are named after their type arguments
+        Type::Pointer(pointee) => match pointee.as_ref() {
+            Type::Void => ctx.i8_type().ptr_type(AddressSpace::default()).into(),
+            pointee => get_llvm_type(pointee, ctx)
+                .ptr_type(AddressSpace::default())
+                .into(),
+        },
+        Type::Generic { .. } => ctx
+            .get_struct_type(&r#type.to_string())
+            .unwrap_or_else(|| panic!("Unknown type `{}`", r#type))
+            .into(),
+        Type::TypeParameter { name } => panic!("Unresolved type parameter `{}`", name),
+        Type::UserDefinedType { name } => ctx
+            .get_struct_type(name)
+            .unwrap_or_else(|| panic!("Unknown type `{}`", name))
+            .into(),
+    }
+}
+
+/// Dynamic arrays are passed around as a `{ T*, i64 }` pair of data pointer
+/// and length.
+pub fn get_array_struct_type<'a>(element: &Type, ctx: &'a Context) -> StructType<'a> {
+    ctx.struct_type(
+        &[
+            get_llvm_type(element, ctx)
+                .ptr_type(AddressSpace::default())
+                .into(),
+            ctx.i64_type().into(),
+        ],
+        false,
+    )
 }
 
 pub fn get_function_params<'a>(
     params: Vec<FuncParam>,
-    ctx: &ContextRef<'a>,
+    ctx: &'a Context,
 ) -> Vec<BasicMetadataTypeEnum<'a>> {
     let mut llvm_params: Vec<BasicMetadataTypeEnum> = vec![];
 
-    let i8_type = ctx.i8_type().clone();
-    let i16_type = ctx.i16_type().clone();
-    let i32_type = ctx.i32_type().clone();
-    let i64_type = ctx.i64_type().clone();
-    let i128_type = ctx.i128_type().clone();
-
-    let f16_type = ctx.f16_type().clone();
-    let f32_type = ctx.f32_type().clone();
-    let f64_type = ctx.f64_type().clone();
-    let f128_type = ctx.f128_type().clone();
-
-    let bool_type = ctx.bool_type().clone();
-
     for param in params {
         match param.r#type {
-            Type::Int8 => llvm_params.push(i8_type.into()),
-            Type::Int16 => llvm_params.push(i16_type.into()),
-            Type::Int32 => llvm_params.push(i32_type.into()),
-            Type::Int64 => llvm_params.push(i64_type.into()),
-            Type::Int128 => llvm_params.push(i128_type.into()),
-            Type::Float16 => llvm_params.push(f16_type.into()),
-            Type::Float32 => llvm_params.push(f32_type.into()),
-            Type::Float64 => llvm_params.push(f64_type.into()),
-            Type::Float128 => llvm_params.push(f128_type.into()),
-            Type::String => todo!(),
-            Type::Boolean => llvm_params.push(bool_type.into()),
             Type::Void => panic!("Function param type can not be void"),
-            Type::Array(_) => todo!(),
-            Type::UserDefinedType { .. } => todo!(),
+            r#type => llvm_params.push(get_llvm_type(&r#type, ctx).into()),
         }
     }
 
@@ -71,81 +128,1592 @@ pub fn get_function_params<'a>(
 pub fn get_function_type<'a>(
     ret_type: Type,
     params: Vec<BasicMetadataTypeEnum<'a>>,
-    ctx: &ContextRef<'a>,
+    ctx: &'a Context,
 ) -> FunctionType<'a> {
-    let fn_type = match ret_type {
-        Type::Int8 => ctx.i8_type().fn_type(&params, false).clone(),
-        Type::Int16 => ctx.i16_type().fn_type(&params, false).clone(),
-        Type::Int32 => ctx.i32_type().fn_type(&params, false).clone(),
-        Type::Int64 => ctx.i64_type().fn_type(&params, false).clone(),
-        Type::Int128 => ctx.i128_type().fn_type(&params, false).clone(),
-        Type::Float16 => ctx.f16_type().fn_type(&params, false).clone(),
-        Type::Float32 => ctx.i32_type().fn_type(&params, false).clone(),
-        Type::Float64 => ctx.i64_type().fn_type(&params, false).clone(),
-        Type::Float128 => ctx.i128_type().fn_type(&params, false).clone(),
-        Type::String => {
-            todo!()
-        }
-        Type::Boolean => ctx.bool_type().fn_type(&params, false).clone(),
-        Type::Void => ctx.void_type().fn_type(&params, false).clone(),
-        Type::Array(_) => {
-            todo!()
-        }
-        Type::UserDefinedType { .. } => {
-            todo!()
-        }
-    };
-
-    fn_type
+    match ret_type {
+        Type::Void => ctx.void_type().fn_type(&params, false),
+        r#type => get_llvm_type(&r#type, ctx).fn_type(&params, false),
+    }
 }
 
-pub struct CodeGen;
+pub struct CodeGen<'a, 'ctx> {
+    context: &'ctx Context,
+    module: &'a Module<'ctx>,
+    builder: Builder<'ctx>,
+    functions: HashMap<String, (Vec<Type>, Type)>,
+    /// Functions defined in C
+    c_functions: HashSet<String>,
+    structs: HashMap<String, Vec<StructField>>,
+    enums: HashMap<String, Vec<EnumVariant>>,
+    generic_functions: HashMap<String, Statements>,
+    generic_structs: HashMap<String, (Vec<TypeParam>, Vec<StructField>)>,
+    /// Symbols of the generic function instances, by function name and type
+    /// arguments
+    instances: HashMap<(String, Vec<Type>), String>,
+    pending_instances: Vec<(Statements, HashMap<String, Type>)>,
+    /// Type arguments of the generic function instance being compiled
+    type_bindings: HashMap<String, Type>,
+    constants: ConstEvaluator,
+    /// Module-level variables, which are looked up after the local scopes
+    globals: HashMap<String, (PointerValue<'ctx>, Type)>,
+    scopes: Vec<HashMap<String, (PointerValue<'ctx>, Type)>>,
+    /// Variables holding references, which each scope releases when it ends
+    owned: Vec<Vec<(PointerValue<'ctx>, Type)>>,
+    /// New values of the statement being compiled, see `memory`
+    temporaries: Vec<(BasicValueEnum<'ctx>, Type)>,
+    current_function: Option<(FunctionValue<'ctx>, Type)>,
+    /// Only emitted with `-g`, see `enable_debug_info`
+    debug: Option<debug::DebugInfo<'ctx>>,
+    /// C functions that are compiled to LLVM intrinsics, see `ffi`
+    intrinsics: HashMap<String, FunctionValue<'ctx>>,
+}
 
-impl CodeGen {
-    pub fn new() -> Self {
-        Self
+impl<'a, 'ctx> CodeGen<'a, 'ctx> {
+    pub fn new(context: &'ctx Context, module: &'a Module<'ctx>) -> Self {
+        Self {
+            context,
+            module,
+            builder: context.create_builder(),
+            functions: HashMap::new(),
+            c_functions: HashSet::new(),
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            generic_functions: HashMap::new(),
+            generic_structs: HashMap::new(),
+            instances: HashMap::new(),
+            pending_instances: vec![],
+            type_bindings: HashMap::new(),
+            constants: ConstEvaluator::default(),
+            globals: HashMap::new(),
+            scopes: vec![],
+            owned: vec![],
+            temporaries: vec![],
+            current_function: None,
+            debug: None,
+            intrinsics: HashMap::new(),
+        }
     }
 
-    pub fn compile_function_statement(
-        &self,
-        function: Statements,
-        r#mod: &Module,
-    ) -> Result<(), String> {
+    /// Creates the named LLVM struct type for a struct declaration. Bodies are
+    /// filled in by `define_struct` once every struct has a name, so fields
+    /// may refer to structs declared later in the file.
+    pub fn declare_struct(&mut self, r#struct: &Statements) -> Result<(), String> {
+        match r#struct {
+            Statements::StructDeclaration {
+                name,
+                type_params,
+                fields,
+            } => {
+                if self.structs.contains_key(name)
+                    || self.enums.contains_key(name)
+                    || self.generic_structs.contains_key(name)
+                {
+                    return Err(format!("Type `{}` is declared more than once", name));
+                }
+
+                // Generic structs only get LLVM types once instantiated
+                if !type_params.is_empty() {
+                    self.generic_structs
+                        .insert(name.clone(), (type_params.clone(), fields.clone()));
+                    return Ok(());
+                }
+
+                // Every module compiled in the context shares its named
+                // types, so the type may exist already if it was imported
+                if self.context.get_struct_type(name).is_none() {
+                    self.context.opaque_struct_type(name);
+                }
+                self.structs.insert(name.clone(), fields.clone());
+                Ok(())
+            }
+            _ => Err("Expected StructDecl as input".to_string()),
+        }
+    }
+
+    /// Sets the fields of a struct declared with `declare_struct`. Fields are
+    /// laid out in declaration order without packing, which matches the
+    /// layout a C compiler would pick for the same struct.
+    pub fn define_struct(&mut self, r#struct: &Statements) -> Result<(), String> {
+        match r#struct {
+            Statements::StructDeclaration {
+                type_params, ..
+            } if !type_params.is_empty() => Ok(()),
+            Statements::StructDeclaration { name, fields, .. } => {
+                for field in fields {
+                    self.instantiate_type(&field.r#type)?;
+                }
+
+                let field_types: Vec<BasicTypeEnum> = fields
+                    .iter()
+                    .map(|field| get_llvm_type(&field.r#type, self.context))
+                    .collect();
+
+                let struct_type = self.context.get_struct_type(name).unwrap();
+                if struct_type.is_opaque() {
+                    struct_type.set_body(&field_types, false);
+                }
+                Ok(())
+            }
+            _ => Err("Expected StructDecl as input".to_string()),
+        }
+    }
+
+    /// Adds the LLVM declaration for a function so that it can be called
+    /// before its body has been compiled.
+    pub fn declare_function(&mut self, function: &Statements) -> Result<FunctionValue<'ctx>, String> {
         match function {
             Statements::FunctionDeclaration {
                 name,
                 params,
-                body,
                 return_type,
+                is_const,
+                is_extern,
+                ..
             } => {
-                let ctx = r#mod.get_context();
-
-                let fn_params = get_function_params(params.clone(), &ctx);
-                let fn_type = get_function_type(return_type, fn_params, &ctx);
-                let fn_val = r#mod.add_function(&name, fn_type, None);
-                let entry_basic_block = ctx.append_basic_block(fn_val, "entry");
+                if let Some(fn_val) = self.module.get_function(name) {
+                    return Ok(fn_val);
+                }
 
-                let builder = ctx.create_builder();
-                builder.position_at_end(entry_basic_block);
+                // Constants and globals may call `const fn`s
+                if *is_const {
+                    self.constants
+                        .declare_function(function)
+                        .map_err(|e| e.to_string())?;
+                }
 
-                let mut curr_param = 0;
                 for param in params {
+                    self.instantiate_type(&param.r#type)?;
+                }
+                self.instantiate_type(return_type)?;
+
+                let fn_params = get_function_params(params.clone(), self.context);
+                let fn_type = get_function_type(return_type.clone(), fn_params, self.context);
+                let fn_val = self.module.add_function(name, fn_type, None);
+                if *is_extern {
+                    self.set_c_abi(fn_val, params, return_type);
+                }
+
+                self.functions.insert(
+                    name.clone(),
+                    (
+                        params.iter().map(|p| p.r#type.clone()).collect(),
+                        return_type.clone(),
+                    ),
+                );
+
+                Ok(fn_val)
+            }
+            _ => Err("Expected FunctionDecl as input".to_string()),
+        }
+    }
+
+    pub fn compile_function_statement(&mut self, function: Statements) -> Result<(), String> {
+        let fn_val = self.declare_function(&function)?;
+
+        match function {
+            Statements::FunctionDeclaration {
+                params,
+                body,
+                return_type,
+                is_extern,
+                line,
+                ..
+            } => {
+                let entry_basic_block = self.context.append_basic_block(fn_val, "entry");
+                self.builder.position_at_end(entry_basic_block);
+
+                self.current_function = Some((fn_val, return_type.clone()));
+                self.scopes.push(HashMap::new());
+                self.owned = vec![vec![]];
+                self.debug_function(fn_val, &params, &return_type, line);
+
+                // Arguments are borrowed, but a parameter that is assigned to
+                // releases its old value. Strings from C have no header, and
+                // are copied to one that does.
+                let assigned = assigned_variables(&body);
+                for (curr_param, param) in params.into_iter().enumerate() {
                     let FuncParam { name, r#type } = param;
-                    let param_type = r#type.to();
-                    let param_type = get_param_type(Type::parse_type(param_type.to_string()));
-                    generate_params!(name curr_param param_type);
-                    curr_param += 1;
+                    let mut value = fn_val.get_nth_param(curr_param as u32).unwrap();
+                    let ptr = self.create_entry_block_alloca(get_llvm_type(&r#type, self.context), &name);
+                    if is_extern && r#type == Type::String {
+                        value = self.call_runtime(runtime::STRING_COPY, &[value.into()]).into();
+                        self.own(ptr, &r#type);
+                    } else if assigned.contains(&name) {
+                        self.retain(value, &r#type);
+                        self.own(ptr, &r#type);
+                    }
+                    self.builder.build_store(ptr, value);
+                    self.debug_variable(&name, ptr, &r#type, Some(curr_param as u32 + 1));
+                    self.declare_variable(name, ptr, r#type);
+                }
+
+                self.compile_block(&body)?;
+
+                if !self.is_terminated() {
+                    if return_type == Type::Void {
+                        self.release_scopes(0);
+                        self.builder.build_return(None);
+                    } else {
+                        // Only reachable when every branch of a trailing
+                        // `if` returned, leaving an empty merge block.
+                        self.builder.build_unreachable();
+                    }
+                }
+
+                self.scopes.pop();
+                self.owned.clear();
+                self.current_function = None;
+                self.end_debug_function();
+
+                Ok(())
+            }
+            _ => Err("Expected FunctionDecl as input".to_string()),
+        }
+    }
+
+    fn current_function(&self) -> FunctionValue<'ctx> {
+        self.current_function.as_ref().unwrap().0
+    }
+
+    fn is_terminated(&self) -> bool {
+        self.builder
+            .get_insert_block()
+            .and_then(|block| block.get_terminator())
+            .is_some()
+    }
+
+    fn create_entry_block_alloca(&self, r#type: BasicTypeEnum<'ctx>, name: &str) -> PointerValue<'ctx> {
+        let builder = self.context.create_builder();
+        let entry = self.current_function().get_first_basic_block().unwrap();
+
+        match entry.get_first_instruction() {
+            Some(instruction) => builder.position_before(&instruction),
+            None => builder.position_at_end(entry),
+        }
+
+        builder.build_alloca(r#type, name)
+    }
+
+    fn declare_variable(&mut self, name: String, ptr: PointerValue<'ctx>, r#type: Type) {
+        self.scopes.last_mut().unwrap().insert(name, (ptr, r#type));
+    }
+
+    fn variable(&self, name: &str) -> Option<(PointerValue<'ctx>, Type)> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name).cloned())
+            .or_else(|| self.globals.get(name).cloned())
+    }
+
+    fn lookup_variable(&self, name: &str) -> Result<(PointerValue<'ctx>, Type), String> {
+        self.variable(name).ok_or(format!("Unknown variable `{}`", name))
+    }
+
+    /// Whether `name` is a function used as a value, which variables of the
+    /// same name hide.
+    fn is_function_value(&self, name: &str) -> bool {
+        self.variable(name).is_none() && self.functions.contains_key(name)
+    }
+
+    /// A pointer to the function `name`.
+    fn compile_function_value(&self, name: &str) -> Result<(BasicValueEnum<'ctx>, Type), String> {
+        let (params, return_type) = self.functions[name].clone();
+        let function = self
+            .module
+            .get_function(name)
+            .ok_or(format!("Unknown function `{}`", name))?;
+        Ok((
+            function.as_global_value().as_pointer_value().into(),
+            Type::Function {
+                params,
+                return_type: Box::new(return_type),
+            },
+        ))
+    }
+
+    fn compile_block(&mut self, block: &[Statements]) -> Result<(), String> {
+        self.scopes.push(HashMap::new());
+        self.owned.push(vec![]);
+
+        for statement in block {
+            // Anything after a `return` can never run
+            if self.is_terminated() {
+                break;
+            }
+            self.compile_statement(statement)?;
+        }
+
+        // A `return` released the variables already
+        if !self.is_terminated() {
+            self.release_scopes(self.owned.len() - 1);
+        }
+        self.owned.pop();
+        self.scopes.pop();
+        Ok(())
+    }
+
+    fn compile_statement(&mut self, statement: &Statements) -> Result<(), String> {
+        self.debug_statement(statement);
+        let mark = self.temporaries.len();
+
+        match statement {
+            Statements::VariableDeclaration {
+                name,
+                value,
+                r#type,
+                ..
+            } => {
+                let r#type = self.resolve_type(r#type)?;
+                let value = self.compile_expression_as(value, &r#type)?;
+                let value = self.take(value, &r#type);
+                let ptr = self.create_entry_block_alloca(get_llvm_type(&r#type, self.context), name);
+                self.builder.build_store(ptr, value);
+                self.own(ptr, &r#type);
+                self.debug_variable(name, ptr, &r#type, None);
+                self.declare_variable(name.clone(), ptr, r#type);
+            }
+            Statements::Assignment { target, value, .. } => {
+                let (ptr, r#type) = self.compile_place(target)?;
+                let value = self.compile_expression_as(value, &r#type)?;
+
+                // The new value is retained before the old one is released,
+                // which may be the same
+                let value = self.take(value, &r#type);
+                let old = match self.is_managed(&r#type) {
+                    true => {
+                        let llvm_type = get_llvm_type(&r#type, self.context);
+                        Some(self.builder.build_load(llvm_type, ptr, "old"))
+                    }
+                    false => None,
+                };
+                self.builder.build_store(ptr, value);
+                if let Some(old) = old {
+                    self.release(old, &r#type);
+                }
+            }
+            Statements::Return { value, .. } => {
+                let return_type = self.current_function.as_ref().unwrap().1.clone();
+
+                match value {
+                    Some(value) => {
+                        let value = self.compile_expression_as(value, &return_type)?;
+                        let value = self.take(value, &return_type);
+                        self.release_temporaries(mark);
+                        self.release_scopes(0);
+                        self.builder.build_return(Some(&value));
+                    }
+                    None if return_type == Type::Void => {
+                        self.release_temporaries(mark);
+                        self.release_scopes(0);
+                        self.builder.build_return(None);
+                    }
+                    None => return Err(format!("Expected a return value of type {}", return_type)),
+                }
+            }
+            Statements::If {
+                condition,
+                body,
+                else_body,
+                ..
+            } => {
+                let condition = self.compile_condition(condition)?;
+                let function = self.current_function();
+
+                let then_block = self.context.append_basic_block(function, "if.then");
+                let else_block = self.context.append_basic_block(function, "if.else");
+                let merge_block = self.context.append_basic_block(function, "if.end");
+
+                self.builder
+                    .build_conditional_branch(condition, then_block, else_block);
+
+                self.compile_branch(then_block, body, merge_block)?;
+                self.compile_branch(else_block, else_body, merge_block)?;
+
+                self.builder.position_at_end(merge_block);
+            }
+            Statements::While { condition, body, .. } => {
+                let function = self.current_function();
+
+                let cond_block = self.context.append_basic_block(function, "while.cond");
+                let body_block = self.context.append_basic_block(function, "while.body");
+                let end_block = self.context.append_basic_block(function, "while.end");
+
+                self.builder.build_unconditional_branch(cond_block);
+                self.builder.position_at_end(cond_block);
+                let condition = self.compile_condition(condition)?;
+                self.builder
+                    .build_conditional_branch(condition, body_block, end_block);
+
+                self.compile_branch(body_block, body, cond_block)?;
+
+                self.builder.position_at_end(end_block);
+            }
+            Statements::ExpressionStatement { expr, .. } => match expr {
+                Expression::FunctionCall { name, params } => {
+                    self.compile_call(name, params, None)?;
+                }
+                Expression::MethodCall {
+                    receiver,
+                    method,
+                    params,
+                } => {
+                    self.compile_method_call(receiver, method, params)?;
+                }
+                Expression::Match { expr, arms } => {
+                    self.compile_match(expr, arms, Some(&Type::Void))?;
+                }
+                expr => {
+                    self.compile_expression(expr, None)?;
+                }
+            },
+            Statements::FunctionDeclaration { .. } => {
+                return Err("Nested functions are not supported".to_string())
+            }
+            Statements::ExternFunctionDeclaration { .. } => {
+                return Err("Extern functions must be declared at the top level".to_string())
+            }
+            Statements::StructDeclaration { .. } => {
+                return Err("Structs must be declared at the top level".to_string())
+            }
+            Statements::EnumDeclaration { .. } => {
+                return Err("Enums must be declared at the top level".to_string())
+            }
+            Statements::ImplDeclaration { .. } => {
+                return Err("Impl blocks must be at the top level".to_string())
+            }
+            Statements::TraitDeclaration { .. } => {
+                return Err("Traits must be declared at the top level".to_string())
+            }
+            Statements::ConstDeclaration { .. } => {
+                return Err("Constants must be declared at the top level".to_string())
+            }
+            Statements::ModuleDeclaration { .. } => {
+                return Err("Module declarations must be at the top of the file".to_string())
+            }
+            Statements::Import { .. } => {
+                return Err("Imports must be at the top of the file".to_string())
+            }
+        }
+
+        self.release_temporaries(mark);
+        Ok(())
+    }
+
+    /// Compiles `body` into `block`, falling through to `next` unless the
+    /// body already ended in a terminator.
+    fn compile_branch(
+        &mut self,
+        block: BasicBlock<'ctx>,
+        body: &[Statements],
+        next: BasicBlock<'ctx>,
+    ) -> Result<(), String> {
+        self.builder.position_at_end(block);
+        self.compile_block(body)?;
+
+        if !self.is_terminated() {
+            self.builder.build_unconditional_branch(next);
+        }
+
+        Ok(())
+    }
+
+    /// Compiles a condition, releasing the new values it needed before the
+    /// branch.
+    fn compile_condition(&mut self, condition: &Expression) -> Result<IntValue<'ctx>, String> {
+        let mark = self.temporaries.len();
+        let value = match self.compile_expression(condition, Some(&Type::Boolean))? {
+            (value, Type::Boolean) => value.into_int_value(),
+            (_, r#type) => return Err(format!("Expected a condition of type bool, found {}", r#type)),
+        };
+        self.release_temporaries(mark);
+        Ok(value)
+    }
+
+    /// Compiles `expr` and converts the result to `r#type`.
+    fn compile_expression_as(
+        &mut self,
+        expr: &Expression,
+        r#type: &Type,
+    ) -> Result<BasicValueEnum<'ctx>, String> {
+        let (value, found) = self.compile_expression(expr, Some(r#type))?;
+        self.coerce(value, &found, r#type)
+    }
+
+    fn coerce(
+        &mut self,
+        value: BasicValueEnum<'ctx>,
+        from: &Type,
+        to: &Type,
+    ) -> Result<BasicValueEnum<'ctx>, String> {
+        match (from, to) {
+            (from, to) if from == to => Ok(value),
+            // C takes strings as `char *`
+            (Type::String, Type::Pointer(pointee)) if **pointee == Type::Int8 => Ok(value),
+            (Type::Pointer(_), Type::Pointer(pointee)) if **pointee == Type::Void => Ok(self
+                .builder
+                .build_pointer_cast(
+                    value.into_pointer_value(),
+                    self.context.i8_type().ptr_type(AddressSpace::default()),
+                    "cast",
+                )
+                .into()),
+            (from, to) if from.is_integer() && to.is_integer() => Ok(self
+                .builder
+                .build_int_cast(
+                    value.into_int_value(),
+                    get_llvm_type(to, self.context).into_int_type(),
+                    "cast",
+                )
+                .into()),
+            (Type::FixedArray(element, size), Type::Array(expected)) if element == expected => {
+                // Copy the elements to the heap so the dynamic array can
+                // outlive the stack slot of the fixed one. It takes over
+                // their references.
+                let value = self.take(value, from);
+                let ptr = self.create_entry_block_alloca(value.get_type(), "tmp");
+                self.builder.build_store(ptr, value);
+                let elements: Vec<BasicValueEnum> = (0..*size)
+                    .map(|i| {
+                        let index = self.context.i64_type().const_int(i as u64, false);
+                        let element_ptr = self.fixed_element_pointer(ptr, from, index);
+                        self.builder.build_load(
+                            get_llvm_type(element, self.context),
+                            element_ptr,
+                            "element",
+                        )
+                    })
+                    .collect();
+                let array = self.build_dynamic_array(element, elements);
+                Ok(self.owned_temporary(array, to))
+            }
+            (from, to) => Err(format!("Mismatched types: expected {}, found {}", to, from)),
+        }
+    }
+
+    fn compile_expression(
+        &mut self,
+        expr: &Expression,
+        hint: Option<&Type>,
+    ) -> Result<(BasicValueEnum<'ctx>, Type), String> {
+        match expr {
+            Expression::StringLiteral { val } => Ok((self.const_string(val).into(), Type::String)),
+            Expression::IntLiteral { val } => match hint {
+                Some(r#type) if r#type.is_integer() => Ok((
+                    get_llvm_type(r#type, self.context)
+                        .into_int_type()
+                        .const_int(*val as u64, true)
+                        .into(),
+                    r#type.clone(),
+                )),
+                Some(r#type) if r#type.is_float() => Ok((
+                    get_llvm_type(r#type, self.context)
+                        .into_float_type()
+                        .const_float(*val as f64)
+                        .into(),
+                    r#type.clone(),
+                )),
+                _ => Ok((
+                    self.context.i32_type().const_int(*val as u64, true).into(),
+                    Type::Int32,
+                )),
+            },
+            Expression::FloatLiteral { val } => {
+                let r#type = match hint {
+                    Some(r#type) if r#type.is_float() => r#type.clone(),
+                    _ => Type::Float64,
+                };
+                Ok((
+                    get_llvm_type(&r#type, self.context)
+                        .into_float_type()
+                        .const_float(*val)
+                        .into(),
+                    r#type,
+                ))
+            }
+            Expression::BooleanLiteral { val } => Ok((
+                self.context.bool_type().const_int(*val as u64, false).into(),
+                Type::Boolean,
+            )),
+            Expression::Identifier { val } if self.is_constant(val) => self.compile_constant(val),
+            Expression::Identifier { val } if self.is_function_value(val) => self.compile_function_value(val),
+            Expression::Identifier { .. } | Expression::FieldAccess { .. } | Expression::Index { .. } => {
+                let (ptr, r#type) = self.compile_place(expr)?;
+                let value = self
+                    .builder
+                    .build_load(get_llvm_type(&r#type, self.context), ptr, "value");
+                // A function could otherwise release a value it was passed
+                // by assigning to where it came from
+                if self.is_shared_place(expr) && self.is_managed(&r#type) {
+                    self.retain(value, &r#type);
+                    return Ok((self.owned_temporary(value, &r#type), r#type));
+                }
+                Ok((value, r#type))
+            }
+            Expression::FunctionCall { name, params } => match self.compile_call(name, params, hint)? {
+                (Some(value), r#type) => Ok((value, r#type)),
+                (None, _) => Err(format!("`{}` does not return a value", name)),
+            },
+            Expression::MethodCall {
+                receiver,
+                method,
+                params,
+            } => match self.compile_method_call(receiver, method, params)? {
+                (Some(value), r#type) => Ok((value, r#type)),
+                (None, _) => Err(format!("`{}` does not return a value", method)),
+            },
+            Expression::ArrayLiteral { elements } => self.compile_array_literal(elements, hint),
+            Expression::StructLiteral { name, fields } => {
+                self.compile_struct_literal(name, fields, hint)
+            }
+            Expression::EnumVariant {
+                enum_name,
+                variant,
+                values,
+            } => self.compile_enum_variant(enum_name, variant, values),
+            Expression::Match { expr, arms } => match self.compile_match(expr, arms, hint)? {
+                (Some(value), r#type) => Ok((value, r#type)),
+                (None, _) => Err("`match` does not produce a value".to_string()),
+            },
+            Expression::BinaryOperation { op, lhs, rhs } => {
+                self.compile_binary_operation(*op, lhs, rhs, hint)
+            }
+            Expression::UnaryOperation { op, expr } => {
+                let (value, r#type) = self.compile_expression(expr, hint)?;
+
+                let value: BasicValueEnum = match op {
+                    UnaryOperator::Negate if r#type.is_integer() => {
+                        self.builder.build_int_neg(value.into_int_value(), "neg").into()
+                    }
+                    UnaryOperator::Negate if r#type.is_float() => {
+                        self.builder.build_float_neg(value.into_float_value(), "neg").into()
+                    }
+                    UnaryOperator::Not if r#type == Type::Boolean => {
+                        self.builder.build_not(value.into_int_value(), "not").into()
+                    }
+                    op => return Err(format!("Can not apply {:?} to {}", op, r#type)),
+                };
+
+                Ok((value, r#type))
+            }
+        }
+    }
+
+    /// Compiles an expression that is allowed to have no value, such as a
+    /// call to a void function.
+    fn compile_value_or_void(
+        &mut self,
+        expr: &Expression,
+        hint: Option<&Type>,
+    ) -> Result<(Option<BasicValueEnum<'ctx>>, Type), String> {
+        match expr {
+            Expression::FunctionCall { name, params } => self.compile_call(name, params, hint),
+            Expression::MethodCall {
+                receiver,
+                method,
+                params,
+            } => self.compile_method_call(receiver, method, params),
+            Expression::Match { expr, arms } => self.compile_match(expr, arms, hint),
+            expr => {
+                let (value, r#type) = self.compile_expression(expr, hint)?;
+                Ok((Some(value), r#type))
+            }
+        }
+    }
+
+    /// Returns a pointer to the storage an assignable expression refers to.
+    fn compile_place(&mut self, expr: &Expression) -> Result<(PointerValue<'ctx>, Type), String> {
+        match expr {
+            Expression::Identifier { val } if self.is_constant(val) => {
+                Err(format!("Can not assign to constant `{}`", val))
+            }
+            Expression::Identifier { val } => self.lookup_variable(val),
+            Expression::FieldAccess { expr, field } => {
+                let (struct_ptr, struct_type) = self.compile_place_or_temporary(expr)?;
+
+                let fields = self
+                    .struct_fields(&struct_type)
+                    .ok_or(format!("{} has no field `{}`", struct_type, field))?;
+                let (index, field) = fields
+                    .iter()
+                    .enumerate()
+                    .find(|(_, f)| &f.name == field)
+                    .ok_or(format!("{} has no field `{}`", struct_type, field))?;
+
+                let field_ptr = self
+                    .builder
+                    .build_struct_gep(
+                        get_llvm_type(&struct_type, self.context),
+                        struct_ptr,
+                        index as u32,
+                        &field.name,
+                    )
+                    .unwrap();
+                Ok((field_ptr, field.r#type.clone()))
+            }
+            Expression::Index { array, index } => {
+                let (array_ptr, array_type) = self.compile_place_or_temporary(array)?;
+
+                let index = match self.compile_expression(index, Some(&Type::Int64))? {
+                    (value, r#type) if r#type.is_integer() => self.builder.build_int_cast(
+                        value.into_int_value(),
+                        self.context.i64_type(),
+                        "index",
+                    ),
+                    (_, r#type) => return Err(format!("Can not index with a value of type {}", r#type)),
+                };
+
+                match &array_type {
+                    Type::FixedArray(element, size) => {
+                        let len = self.context.i64_type().const_int(*size as u64, false);
+                        self.build_bounds_check(index, len);
+                        Ok((
+                            self.fixed_element_pointer(array_ptr, &array_type, index),
+                            *element.clone(),
+                        ))
+                    }
+                    Type::Array(element) => {
+                        let array = self
+                            .builder
+                            .build_load(get_llvm_type(&array_type, self.context), array_ptr, "array")
+                            .into_struct_value();
+                        let data = self
+                            .builder
+                            .build_extract_value(array, 0, "data")
+                            .unwrap()
+                            .into_pointer_value();
+                        let len = self
+                            .builder
+                            .build_extract_value(array, 1, "len")
+                            .unwrap()
+                            .into_int_value();
+
+                        self.build_bounds_check(index, len);
+
+                        let element_ptr = unsafe {
+                            self.builder.build_in_bounds_gep(
+                                get_llvm_type(element, self.context),
+                                data,
+                                &[index],
+                                "element",
+                            )
+                        };
+                        Ok((element_ptr, *element.clone()))
+                    }
+                    r#type => Err(format!("Can not index into a value of type {}", r#type)),
+                }
+            }
+            _ => Err("Can not assign to this expression".to_string()),
+        }
+    }
+
+    /// Whether the place `expr` is reachable from other functions, through a
+    /// global or the elements of an array.
+    fn is_shared_place(&self, expr: &Expression) -> bool {
+        match expr {
+            Expression::Identifier { val } => {
+                self.globals.contains_key(val) && !self.scopes.iter().any(|scope| scope.contains_key(val))
+            }
+            Expression::FieldAccess { expr, .. } => self.is_shared_place(expr),
+            Expression::Index { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Like `compile_place`, but values that are not stored anywhere are
+    /// first spilled to a stack slot.
+    fn compile_place_or_temporary(
+        &mut self,
+        expr: &Expression,
+    ) -> Result<(PointerValue<'ctx>, Type), String> {
+        match expr {
+            Expression::Identifier { .. } | Expression::Index { .. } | Expression::FieldAccess { .. } => {
+                self.compile_place(expr)
+            }
+            expr => {
+                let (value, r#type) = self.compile_expression(expr, None)?;
+                let ptr = self.create_entry_block_alloca(value.get_type(), "tmp");
+                self.builder.build_store(ptr, value);
+                Ok((ptr, r#type))
+            }
+        }
+    }
+
+    fn fixed_element_pointer(
+        &self,
+        array_ptr: PointerValue<'ctx>,
+        array_type: &Type,
+        index: IntValue<'ctx>,
+    ) -> PointerValue<'ctx> {
+        let zero = self.context.i64_type().const_zero();
+        unsafe {
+            self.builder.build_in_bounds_gep(
+                get_llvm_type(array_type, self.context),
+                array_ptr,
+                &[zero, index],
+                "element",
+            )
+        }
+    }
+
+    /// Branches to `aurora_panic_bounds` unless `0 <= index < len`.
+    fn build_bounds_check(&mut self, index: IntValue<'ctx>, len: IntValue<'ctx>) {
+        let function = self.current_function();
+        let ok_block = self.context.append_basic_block(function, "bounds.ok");
+        let fail_block = self.context.append_basic_block(function, "bounds.fail");
+
+        // A negative index wraps around to a huge unsigned value
+        let in_bounds = self
+            .builder
+            .build_int_compare(IntPredicate::ULT, index, len, "inbounds");
+        self.builder
+            .build_conditional_branch(in_bounds, ok_block, fail_block);
+
+        self.builder.position_at_end(fail_block);
+        let panic = self.get_runtime_function(runtime::PANIC_BOUNDS);
+        self.builder
+            .build_call(panic, &[index.into(), len.into()], "");
+        self.builder.build_unreachable();
+
+        self.builder.position_at_end(ok_block);
+    }
+
+    /// Branches to `aurora_panic` when `lhs / rhs` has no result, because
+    /// `rhs` is zero or the quotient of the smallest value and -1 does not
+    /// fit. `sdiv` and `srem` leave both undefined.
+    fn build_division_check(&mut self, lhs: IntValue<'ctx>, rhs: IntValue<'ctx>, r#type: &Type) {
+        let int_type = get_llvm_type(r#type, self.context).into_int_type();
+
+        let is_zero = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, rhs, int_type.const_zero(), "iszero");
+        self.build_panic_if(is_zero, "attempt to divide by zero");
+
+        let min = i128::MIN >> (128 - typing::bit_width(r#type));
+        let min = int_type.const_int_arbitrary_precision(&[min as u64, (min >> 64) as u64]);
+        let is_min = self.builder.build_int_compare(IntPredicate::EQ, lhs, min, "ismin");
+        let is_minus_one = self
+            .builder
+            .build_int_compare(IntPredicate::EQ, rhs, int_type.const_all_ones(), "isminusone");
+        let overflows = self.builder.build_and(is_min, is_minus_one, "overflows");
+        self.build_panic_if(overflows, "attempt to divide with overflow");
+    }
+
+    /// Branches to `aurora_panic` with `message` if `condition` holds.
+    fn build_panic_if(&mut self, condition: IntValue<'ctx>, message: &str) {
+        let function = self.current_function();
+        let ok_block = self.context.append_basic_block(function, "check.ok");
+        let fail_block = self.context.append_basic_block(function, "check.fail");
+        self.builder
+            .build_conditional_branch(condition, fail_block, ok_block);
+
+        self.builder.position_at_end(fail_block);
+        let message = self.builder.build_global_string_ptr(message, "message");
+        let panic = self.get_runtime_function(runtime::PANIC);
+        self.builder
+            .build_call(panic, &[message.as_pointer_value().into()], "");
+        self.builder.build_unreachable();
+
+        self.builder.position_at_end(ok_block);
+    }
+
+    fn get_runtime_function(&self, name: &str) -> FunctionValue<'ctx> {
+        if let Some(function) = self.module.get_function(name) {
+            return function;
+        }
+
+        let i64_type = self.context.i64_type();
+        let str_type = self.context.i8_type().ptr_type(AddressSpace::default());
+        let (fn_type, noreturn) = match name {
+            runtime::PANIC_BOUNDS => (
+                self.context
+                    .void_type()
+                    .fn_type(&[i64_type.into(), i64_type.into()], false),
+                true,
+            ),
+            runtime::PANIC => (
+                self.context.void_type().fn_type(&[str_type.into()], false),
+                true,
+            ),
+            runtime::STRLEN => (i64_type.fn_type(&[str_type.into()], false), false),
+            runtime::STRCMP => (
+                self.context
+                    .i32_type()
+                    .fn_type(&[str_type.into(), str_type.into()], false),
+                false,
+            ),
+            runtime::CONCAT => (
+                str_type.fn_type(&[str_type.into(), str_type.into()], false),
+                false,
+            ),
+            runtime::FORMAT_INT => (
+                str_type.fn_type(&[self.context.i128_type().into()], false),
+                false,
+            ),
+            runtime::FORMAT_FLOAT => (
+                str_type.fn_type(
+                    &[self.context.f64_type().into(), self.context.i32_type().into()],
+                    false,
+                ),
+                false,
+            ),
+            runtime::ALLOC => (str_type.fn_type(&[i64_type.into()], false), false),
+            runtime::RETAIN | runtime::FREE => (
+                self.context.void_type().fn_type(&[str_type.into()], false),
+                false,
+            ),
+            runtime::RELEASE => (
+                self.context.bool_type().fn_type(&[str_type.into()], false),
+                false,
+            ),
+            runtime::STRING_COPY => (str_type.fn_type(&[str_type.into()], false), false),
+            name => panic!("unknown runtime function {}", name),
+        };
+
+        let function = self.module.add_function(name, fn_type, None);
+        if noreturn {
+            let kind = Attribute::get_named_enum_kind_id("noreturn");
+            function.add_attribute(
+                AttributeLoc::Function,
+                self.context.create_enum_attribute(kind, 0),
+            );
+        }
+
+        function
+    }
+
+    fn build_dynamic_array(
+        &mut self,
+        element: &Type,
+        elements: Vec<BasicValueEnum<'ctx>>,
+    ) -> BasicValueEnum<'ctx> {
+        let i64_type = self.context.i64_type();
+        let element_type = get_llvm_type(element, self.context);
+        let len = i64_type.const_int(elements.len() as u64, false);
+
+        let data = self.build_array_alloc(element_type, len);
+
+        for (i, value) in elements.into_iter().enumerate() {
+            let index = i64_type.const_int(i as u64, false);
+            let element_ptr =
+                unsafe { self.builder.build_in_bounds_gep(element_type, data, &[index], "element") };
+            self.builder.build_store(element_ptr, value);
+        }
+
+        let array_type = get_array_struct_type(element, self.context);
+        let array = self
+            .builder
+            .build_insert_value(array_type.get_undef(), data, 0, "array")
+            .unwrap()
+            .into_struct_value();
+        let array = self
+            .builder
+            .build_insert_value(array, len, 1, "array")
+            .unwrap()
+            .into_struct_value();
+
+        array.into()
+    }
+
+    /// Allocates a block for `len` elements of `element_type`.
+    fn build_array_alloc(
+        &self,
+        element_type: BasicTypeEnum<'ctx>,
+        len: IntValue<'ctx>,
+    ) -> PointerValue<'ctx> {
+        let size = self
+            .builder
+            .build_int_mul(len, element_type.size_of().unwrap(), "size");
+        let data = self.call_runtime(runtime::ALLOC, &[size.into()]);
+        self.builder
+            .build_pointer_cast(data, element_type.ptr_type(AddressSpace::default()), "data")
+    }
+
+    fn compile_array_literal(
+        &mut self,
+        elements: &[Expression],
+        hint: Option<&Type>,
+    ) -> Result<(BasicValueEnum<'ctx>, Type), String> {
+        let mut element_type = match hint {
+            Some(Type::Array(r#type)) | Some(Type::FixedArray(r#type, _)) => Some(*r#type.clone()),
+            _ => None,
+        };
+
+        let mut values: Vec<BasicValueEnum> = vec![];
+        for element in elements {
+            let value = if let Some(r#type) = element_type.clone() {
+                self.compile_expression_as(element, &r#type)?
+            } else {
+                let (value, r#type) = self.compile_expression(element, None)?;
+                element_type = Some(r#type);
+                value
+            };
+            let value = self.take(value, element_type.as_ref().unwrap());
+            values.push(value);
+        }
+
+        let element_type = match element_type {
+            Some(r#type) => r#type,
+            None => return Err("Can not infer the element type of an empty array".to_string()),
+        };
+
+        if let Some(Type::Array(_)) = hint {
+            let array = self.build_dynamic_array(&element_type, values);
+            let r#type = Type::Array(Box::new(element_type));
+            return Ok((self.owned_temporary(array, &r#type), r#type));
+        }
+
+        let array_type = Type::FixedArray(Box::new(element_type), values.len());
+        let ptr = self.create_entry_block_alloca(get_llvm_type(&array_type, self.context), "array");
+        for (i, value) in values.into_iter().enumerate() {
+            let index = self.context.i64_type().const_int(i as u64, false);
+            let element_ptr = self.fixed_element_pointer(ptr, &array_type, index);
+            self.builder.build_store(element_ptr, value);
+        }
+
+        let array = self
+            .builder
+            .build_load(get_llvm_type(&array_type, self.context), ptr, "array");
+        Ok((self.owned_temporary(array, &array_type), array_type))
+    }
+
+    /// Compiles a struct literal. The type arguments of a generic struct are
+    /// taken from `hint` or inferred from the field values.
+    fn compile_struct_literal(
+        &mut self,
+        name: &str,
+        values: &[(String, Expression)],
+        hint: Option<&Type>,
+    ) -> Result<(BasicValueEnum<'ctx>, Type), String> {
+        let (type_params, fields) = match (self.structs.get(name), self.generic_structs.get(name)) {
+            (Some(fields), _) => (vec![], fields.clone()),
+            (None, Some((type_params, fields))) => (type_params.clone(), fields.clone()),
+            (None, None) => return Err(format!("Unknown struct `{}`", name)),
+        };
+
+        for (field, _) in values {
+            if !fields.iter().any(|f| &f.name == field) {
+                return Err(format!("{} has no field `{}`", name, field));
+            }
+            if values.iter().filter(|(f, _)| f == field).count() > 1 {
+                return Err(format!("Field `{}` is specified more than once", field));
+            }
+        }
+
+        let mut inference = Inference::with_hint(name, &type_params, hint);
+
+        let mut field_values: Vec<BasicValueEnum> = vec![];
+        for field in &fields {
+            let expr = values
+                .iter()
+                .find(|(f, _)| f == &field.name)
+                .map(|(_, expr)| expr)
+                .ok_or(format!("Missing field `{}` in {} literal", field.name, name))?;
+
+            let (value, field_type) = match inference.expected(&field.r#type) {
+                Some(field_type) => (self.compile_expression_as(expr, &field_type)?, field_type),
+                None => {
+                    let (value, found) = self.compile_expression(expr, None)?;
+                    inference.unify(&field.r#type, &found)?;
+                    (value, found)
+                }
+            };
+            field_values.push(self.take(value, &field_type));
+        }
+
+        let r#type = if type_params.is_empty() {
+            Type::UserDefinedType {
+                name: name.to_string(),
+            }
+        } else {
+            let params = inference.type_arguments()?;
+            Type::Generic {
+                name: name.to_string(),
+                params,
+            }
+        };
+        self.instantiate_type(&r#type)?;
+
+        let mut value = get_llvm_type(&r#type, self.context)
+            .into_struct_type()
+            .get_undef();
+
+        for (index, (field, field_value)) in fields.iter().zip(field_values).enumerate() {
+            value = self
+                .builder
+                .build_insert_value(value, field_value, index as u32, &field.name)
+                .unwrap()
+                .into_struct_value();
+        }
+
+        Ok((self.owned_temporary(value.into(), &r#type), r#type))
+    }
+
+    fn compile_binary_operation(
+        &mut self,
+        op: BinaryOperator,
+        lhs: &Expression,
+        rhs: &Expression,
+        hint: Option<&Type>,
+    ) -> Result<(BasicValueEnum<'ctx>, Type), String> {
+        let is_comparison = typing::is_comparison(op);
+
+        let (lhs, lhs_type) = self.compile_expression(lhs, typing::operand_hint(op, hint))?;
+        let (rhs, rhs_type) = self.compile_expression(rhs, Some(&lhs_type))?;
+
+        let r#type = typing::operand_type(op, &lhs_type, &rhs_type)?;
+        let lhs = self.coerce(lhs, &lhs_type, &r#type)?;
+        let rhs = self.coerce(rhs, &rhs_type, &r#type)?;
+
+        // Like in the interpreter and the C backend, strings can be
+        // concatenated and compared
+        if r#type == Type::String && op == BinaryOperator::Add {
+            let value = self.call_runtime(runtime::CONCAT, &[lhs.into(), rhs.into()]);
+            return Ok((self.owned_temporary(value.into(), &r#type), r#type));
+        }
+        if r#type == Type::String && matches!(op, BinaryOperator::Equal | BinaryOperator::NotEqual) {
+            let strcmp = self.get_runtime_function(runtime::STRCMP);
+            let order = self
+                .builder
+                .build_call(strcmp, &[lhs.into(), rhs.into()], "order")
+                .try_as_basic_value()
+                .left()
+                .unwrap()
+                .into_int_value();
+            let predicate = match op {
+                BinaryOperator::Equal => IntPredicate::EQ,
+                _ => IntPredicate::NE,
+            };
+            let zero = self.context.i32_type().const_zero();
+            return Ok((
+                self.builder.build_int_compare(predicate, order, zero, "cmp").into(),
+                Type::Boolean,
+            ));
+        }
+
+        if r#type.is_integer() || (r#type == Type::Boolean && is_comparison) {
+            let (lhs, rhs) = (lhs.into_int_value(), rhs.into_int_value());
+            let predicate = match op {
+                BinaryOperator::Add => return Ok((self.builder.build_int_add(lhs, rhs, "add").into(), r#type)),
+                BinaryOperator::Subtract => return Ok((self.builder.build_int_sub(lhs, rhs, "sub").into(), r#type)),
+                BinaryOperator::Multiply => return Ok((self.builder.build_int_mul(lhs, rhs, "mul").into(), r#type)),
+                BinaryOperator::Divide => {
+                    self.build_division_check(lhs, rhs, &r#type);
+                    return Ok((self.builder.build_int_signed_div(lhs, rhs, "div").into(), r#type));
+                }
+                BinaryOperator::Modulo => {
+                    self.build_division_check(lhs, rhs, &r#type);
+                    return Ok((self.builder.build_int_signed_rem(lhs, rhs, "rem").into(), r#type));
                 }
+                BinaryOperator::Equal => IntPredicate::EQ,
+                BinaryOperator::NotEqual => IntPredicate::NE,
+                BinaryOperator::LessThan => IntPredicate::SLT,
+                BinaryOperator::LessThanOrEqual => IntPredicate::SLE,
+                BinaryOperator::GreaterThan => IntPredicate::SGT,
+                BinaryOperator::GreaterThanOrEqual => IntPredicate::SGE,
+            };
+
+            return Ok((
+                self.builder.build_int_compare(predicate, lhs, rhs, "cmp").into(),
+                Type::Boolean,
+            ));
+        }
+
+        if r#type.is_float() {
+            let (lhs, rhs) = (lhs.into_float_value(), rhs.into_float_value());
+            let predicate = match op {
+                BinaryOperator::Add => return Ok((self.builder.build_float_add(lhs, rhs, "add").into(), r#type)),
+                BinaryOperator::Subtract => return Ok((self.builder.build_float_sub(lhs, rhs, "sub").into(), r#type)),
+                BinaryOperator::Multiply => return Ok((self.builder.build_float_mul(lhs, rhs, "mul").into(), r#type)),
+                BinaryOperator::Divide => return Ok((self.builder.build_float_div(lhs, rhs, "div").into(), r#type)),
+                BinaryOperator::Modulo => return Ok((self.builder.build_float_rem(lhs, rhs, "rem").into(), r#type)),
+                BinaryOperator::Equal => FloatPredicate::OEQ,
+                BinaryOperator::NotEqual => FloatPredicate::UNE,
+                BinaryOperator::LessThan => FloatPredicate::OLT,
+                BinaryOperator::LessThanOrEqual => FloatPredicate::OLE,
+                BinaryOperator::GreaterThan => FloatPredicate::OGT,
+                BinaryOperator::GreaterThanOrEqual => FloatPredicate::OGE,
+            };
+
+            return Ok((
+                self.builder.build_float_compare(predicate, lhs, rhs, "cmp").into(),
+                Type::Boolean,
+            ));
+        }
+
+        Err(format!("Can not apply {:?} to {}", op, r#type))
+    }
+
+    fn compile_call(
+        &mut self,
+        name: &str,
+        params: &[Expression],
+        hint: Option<&Type>,
+    ) -> Result<(Option<BasicValueEnum<'ctx>>, Type), String> {
+        // Variables holding functions hide the functions of the same name
+        if let Some((ptr, r#type @ Type::Function { .. })) = self.variable(name) {
+            return self.compile_indirect_call(name, ptr, &r#type, params);
+        }
+        if name == "len" {
+            return self.compile_len(params).map(|(value, r#type)| (Some(value), r#type));
+        }
+        if name == "format" {
+            return self.compile_format(params).map(|(value, r#type)| (Some(value), r#type));
+        }
+        if name == "resize" {
+            return self.compile_resize(params).map(|(value, r#type)| (Some(value), r#type));
+        }
+        if let Some(r#type) = typing::conversion_type(name) {
+            return self
+                .compile_conversion(name, &r#type, params)
+                .map(|(value, r#type)| (Some(value), r#type));
+        }
+
+        if let Some(function) = self.generic_functions.get(name).cloned() {
+            return self.compile_generic_call(&function, params, hint);
+        }
 
-                let x = fn_val.get_nth_param(0).unwrap().into_int_value();
-                let y = fn_val.get_nth_param(1).unwrap().into_int_value();
+        let (param_types, return_type) = self
+            .functions
+            .get(name)
+            .cloned()
+            .ok_or(format!("Unknown function `{}`", name))?;
 
-                let ret = builder.build_int_add(x, y, "add");
-                let _ = builder.build_return(Some(&ret));
+        if param_types.len() != params.len() {
+            return Err(format!(
+                "`{}` takes {} arguments but {} were supplied",
+                name,
+                param_types.len(),
+                params.len()
+            ));
+        }
+
+        let mut args: Vec<BasicMetadataValueEnum> = vec![];
+        for (param, r#type) in params.iter().zip(param_types.iter()) {
+            args.push(self.compile_expression_as(param, r#type)?.into());
+        }
+
+        let mut value = self.build_call(name, &args);
+        // Strings C returns are its own to free
+        if self.c_functions.contains(name) && !is_runtime_function(name) && return_type == Type::String {
+            value = value.map(|text| self.call_runtime(runtime::STRING_COPY, &[text.into()]).into());
+        }
+        Ok((value.map(|value| self.owned_temporary(value, &return_type)), return_type))
+    }
+
+    /// Calls the function whose pointer the variable `name` at `ptr` of the
+    /// function type `r#type` holds.
+    fn compile_indirect_call(
+        &mut self,
+        name: &str,
+        ptr: PointerValue<'ctx>,
+        r#type: &Type,
+        params: &[Expression],
+    ) -> Result<(Option<BasicValueEnum<'ctx>>, Type), String> {
+        let (param_types, return_type) = match r#type {
+            Type::Function {
+                params,
+                return_type,
+            } => (params, return_type.as_ref().clone()),
+            r#type => return Err(format!("`{}` of type {} is not a function", name, r#type)),
+        };
+
+        if param_types.len() != params.len() {
+            return Err(format!(
+                "`{}` takes {} arguments but {} were supplied",
+                name,
+                param_types.len(),
+                params.len()
+            ));
+        }
+
+        let function = self
+            .builder
+            .build_load(get_llvm_type(r#type, self.context), ptr, name)
+            .into_pointer_value();
+        let mut args: Vec<BasicMetadataValueEnum> = vec![];
+        for (param, r#type) in params.iter().zip(param_types.iter()) {
+            args.push(self.compile_expression_as(param, r#type)?.into());
+        }
+
+        let fn_type = get_function_type(
+            return_type.clone(),
+            param_types.iter().map(|t| get_llvm_type(t, self.context).into()).collect(),
+            self.context,
+        );
+        let value = self
+            .builder
+            .build_indirect_call(fn_type, function, &args, "call")
+            .try_as_basic_value()
+            .left();
+        Ok((value.map(|value| self.owned_temporary(value, &return_type)), return_type))
+    }
+
+    /// `receiver.method(params)` calls the method of the receiver's type with
+    /// the receiver as its `self` argument.
+    fn compile_method_call(
+        &mut self,
+        receiver: &Expression,
+        method: &str,
+        params: &[Expression],
+    ) -> Result<(Option<BasicValueEnum<'ctx>>, Type), String> {
+        let (receiver, receiver_type) = self.compile_expression(receiver, None)?;
+        let name = mangle_method(&receiver_type, method);
+
+        let (param_types, return_type) = self
+            .functions
+            .get(&name)
+            .cloned()
+            .ok_or(format!("{} has no method `{}`", receiver_type, method))?;
+
+        if param_types.first() != Some(&receiver_type) {
+            return Err(format!(
+                "`{}` of {} can not be called as a method, it takes no `self`",
+                method, receiver_type
+            ));
+        }
 
-                return Ok(());
+        if param_types.len() != params.len() + 1 {
+            return Err(format!(
+                "`{}` takes {} arguments but {} were supplied",
+                method,
+                param_types.len() - 1,
+                params.len()
+            ));
+        }
+
+        let mut args: Vec<BasicMetadataValueEnum> = vec![receiver.into()];
+        for (param, r#type) in params.iter().zip(param_types[1..].iter()) {
+            args.push(self.compile_expression_as(param, r#type)?.into());
+        }
+
+        let value = self.build_call(&name, &args);
+        Ok((value.map(|value| self.owned_temporary(value, &return_type)), return_type))
+    }
+
+    fn build_call(
+        &mut self,
+        name: &str,
+        args: &[BasicMetadataValueEnum<'ctx>],
+    ) -> Option<BasicValueEnum<'ctx>> {
+        let function = match self.intrinsics.get(name) {
+            Some(intrinsic) => *intrinsic,
+            None => self.module.get_function(name).unwrap(),
+        };
+        self.builder
+            .build_call(function, args, "call")
+            .try_as_basic_value()
+            .left()
+    }
+
+    /// Calls the runtime function `name`, which returns a string.
+    fn call_runtime(&self, name: &str, args: &[BasicMetadataValueEnum<'ctx>]) -> PointerValue<'ctx> {
+        let function = self.get_runtime_function(name);
+        self.builder
+            .build_call(function, args, "str")
+            .try_as_basic_value()
+            .left()
+            .unwrap()
+            .into_pointer_value()
+    }
+
+    /// `format(template, values...)` concatenates the pieces of the template
+    /// and the values, which the runtime writes to new strings.
+    fn compile_format(&mut self, params: &[Expression]) -> Result<(BasicValueEnum<'ctx>, Type), String> {
+        let pieces = format_template(params)?;
+
+        let mut text = self.const_string(&pieces[0]);
+        for (param, piece) in params[1..].iter().zip(&pieces[1..]) {
+            let (value, r#type) = self.compile_expression(param, None)?;
+            let value = match &r#type {
+                r#type if r#type.is_integer() => {
+                    let value = self
+                        .builder
+                        .build_int_cast(value.into_int_value(), self.context.i128_type(), "value");
+                    let value = self.call_runtime(runtime::FORMAT_INT, &[value.into()]);
+                    self.owned_temporary(value.into(), &Type::String).into_pointer_value()
+                }
+                r#type if r#type.is_float() => {
+                    let value = self
+                        .builder
+                        .build_float_cast(value.into_float_value(), self.context.f64_type(), "value");
+                    let digits = self.context.i32_type().const_int(float_digits(r#type) as u64, false);
+                    let value = self.call_runtime(runtime::FORMAT_FLOAT, &[value.into(), digits.into()]);
+                    self.owned_temporary(value.into(), &Type::String).into_pointer_value()
+                }
+                Type::Boolean => self
+                    .builder
+                    .build_select(
+                        value.into_int_value(),
+                        self.const_string("true"),
+                        self.const_string("false"),
+                        "value",
+                    )
+                    .into_pointer_value(),
+                Type::String => value.into_pointer_value(),
+                r#type => return Err(format!("`format` can not write values of type {}", r#type)),
+            };
+
+            text = self.call_runtime(runtime::CONCAT, &[text.into(), value.into()]);
+            self.owned_temporary(text.into(), &Type::String);
+            if !piece.is_empty() {
+                let piece = self.const_string(piece);
+                text = self.call_runtime(runtime::CONCAT, &[text.into(), piece.into()]);
+                self.owned_temporary(text.into(), &Type::String);
             }
-            _ => return Err(format!("Expected FunctionDecl as input")),
         }
+
+        Ok((text.into(), Type::String))
+    }
+
+    /// `len(x)` is the number of elements of an array or bytes of a string.
+    /// `i32(x)` and the like, see [`typing::conversion_type`].
+    fn compile_conversion(
+        &mut self,
+        name: &str,
+        r#type: &Type,
+        params: &[Expression],
+    ) -> Result<(BasicValueEnum<'ctx>, Type), String> {
+        if params.len() != 1 {
+            return Err(format!("`{}` takes 1 argument but {} were supplied", name, params.len()));
+        }
+
+        let (value, from) = self.compile_expression(&params[0], Some(r#type))?;
+        if !from.is_integer() {
+            return Err(format!("`{}` converts integers, not {}", name, from));
+        }
+        Ok((self.coerce(value, &from, r#type)?, r#type.clone()))
+    }
+
+    fn compile_len(&mut self, params: &[Expression]) -> Result<(BasicValueEnum<'ctx>, Type), String> {
+        if params.len() != 1 {
+            return Err(format!("`len` takes 1 argument but {} were supplied", params.len()));
+        }
+
+        let (value, r#type) = self.compile_expression(&params[0], None)?;
+        let len: IntValue = match r#type {
+            Type::FixedArray(_, size) => self.context.i64_type().const_int(size as u64, false),
+            Type::Array(_) => self
+                .builder
+                .build_extract_value(value.into_struct_value(), 1, "len")
+                .unwrap()
+                .into_int_value(),
+            Type::String => {
+                let strlen = self.get_runtime_function(runtime::STRLEN);
+                self.builder
+                    .build_call(strlen, &[value.into()], "len")
+                    .try_as_basic_value()
+                    .left()
+                    .unwrap()
+                    .into_int_value()
+            }
+            r#type => return Err(format!("`len` is not defined for {}", r#type)),
+        };
+
+        Ok((len.into(), Type::Int64))
+    }
+
+    /// `resize(values, len, fill)` is a new array of `len` elements, the
+    /// first of which are copied from `values` and the others set to `fill`.
+    /// A negative `len` gives an empty array.
+    fn compile_resize(&mut self, params: &[Expression]) -> Result<(BasicValueEnum<'ctx>, Type), String> {
+        if params.len() != 3 {
+            return Err(format!("`resize` takes 3 arguments but {} were supplied", params.len()));
+        }
+
+        let (array, array_type) = self.compile_expression(&params[0], None)?;
+        let element = match &array_type {
+            Type::Array(element) => *element.clone(),
+            r#type => return Err(format!("`resize` takes an array, not {}", r#type)),
+        };
+        let len = self.compile_expression_as(&params[1], &Type::Int64)?.into_int_value();
+        let fill = self.compile_expression_as(&params[2], &element)?;
+
+        let i64_type = self.context.i64_type();
+        let element_type = get_llvm_type(&element, self.context);
+        let zero = i64_type.const_zero();
+        let is_negative = self.builder.build_int_compare(IntPredicate::SLT, len, zero, "is_negative");
+        let len = self
+            .builder
+            .build_select(is_negative, zero, len, "len")
+            .into_int_value();
+
+        let array = array.into_struct_value();
+        let old_data = self
+            .builder
+            .build_extract_value(array, 0, "old_data")
+            .unwrap()
+            .into_pointer_value();
+        let old_len = self
+            .builder
+            .build_extract_value(array, 1, "old_len")
+            .unwrap()
+            .into_int_value();
+        let data = self.build_array_alloc(element_type, len);
+
+        let function = self.current_function();
+        let check_block = self.context.append_basic_block(function, "resize.check");
+        let pick_block = self.context.append_basic_block(function, "resize.pick");
+        let copy_block = self.context.append_basic_block(function, "resize.copy");
+        let fill_block = self.context.append_basic_block(function, "resize.fill");
+        let next_block = self.context.append_basic_block(function, "resize.next");
+        let end_block = self.context.append_basic_block(function, "resize.end");
+
+        let index_slot = self.create_entry_block_alloca(i64_type.into(), "index");
+        self.builder.build_store(index_slot, zero);
+        self.builder.build_unconditional_branch(check_block);
+
+        self.builder.position_at_end(check_block);
+        let index = self
+            .builder
+            .build_load(i64_type, index_slot, "index")
+            .into_int_value();
+        let element_ptr = unsafe {
+            self.builder
+                .build_in_bounds_gep(element_type, data, &[index], "element")
+        };
+        let more = self.builder.build_int_compare(IntPredicate::SLT, index, len, "more");
+        self.builder.build_conditional_branch(more, pick_block, end_block);
+
+        self.builder.position_at_end(pick_block);
+        let is_kept = self.builder.build_int_compare(IntPredicate::SLT, index, old_len, "is_kept");
+        self.builder.build_conditional_branch(is_kept, copy_block, fill_block);
+
+        self.builder.position_at_end(copy_block);
+        let old_ptr = unsafe {
+            self.builder
+                .build_in_bounds_gep(element_type, old_data, &[index], "old_element")
+        };
+        let old = self.builder.build_load(element_type, old_ptr, "old_element");
+        self.retain(old, &element);
+        self.builder.build_store(element_ptr, old);
+        self.builder.build_unconditional_branch(next_block);
+
+        self.builder.position_at_end(fill_block);
+        self.retain(fill, &element);
+        self.builder.build_store(element_ptr, fill);
+        self.builder.build_unconditional_branch(next_block);
+
+        self.builder.position_at_end(next_block);
+        let index = self
+            .builder
+            .build_int_add(index, i64_type.const_int(1, false), "index");
+        self.builder.build_store(index_slot, index);
+        self.builder.build_unconditional_branch(check_block);
+
+        self.builder.position_at_end(end_block);
+        let array_struct = get_array_struct_type(&element, self.context);
+        let resized = self
+            .builder
+            .build_insert_value(array_struct.get_undef(), data, 0, "resized")
+            .unwrap()
+            .into_struct_value();
+        let resized = self
+            .builder
+            .build_insert_value(resized, len, 1, "resized")
+            .unwrap()
+            .into_struct_value();
+
+        Ok((self.owned_temporary(resized.into(), &array_type), array_type))
     }
 }
@@ -0,0 +1,64 @@
+use inkwell::{
+    module::Module,
+    passes::{PassBuilderOptions, PassManager, PassManagerBuilder},
+    targets::TargetMachine,
+};
+
+use crate::package::OptLevel;
+
+/// Inlining thresholds of clang for each level. `O1` only inlines functions
+/// that must be.
+fn inline_threshold(level: OptLevel) -> Option<u32> {
+    match level {
+        OptLevel::O0 | OptLevel::O1 => None,
+        OptLevel::O2 => Some(225),
+        OptLevel::O3 => Some(275),
+        OptLevel::Os => Some(75),
+    }
+}
+
+/// Optimises `module` for `level`, before it is written or run by the JIT.
+/// Every function first gets its variables promoted from the stack to
+/// registers, then the standard LLVM pipeline of the level runs over the
+/// functions (instcombine, GVN, CFG simplification, ...) and over the
+/// module (inlining, global DCE, ...). `O0` leaves the module alone.
+///
+/// `passes`, given by `--llvm-passes`, is a pipeline like
+/// `instcombine,gvn` in the syntax of `opt -passes`, run after the others.
+pub fn optimize(
+    module: &Module,
+    machine: &TargetMachine,
+    level: OptLevel,
+    passes: Option<&str>,
+) -> Result<(), String> {
+    if level != OptLevel::O0 {
+        let builder = PassManagerBuilder::create();
+        builder.set_optimization_level(level.optimization_level());
+        builder.set_size_level(if level == OptLevel::Os { 1 } else { 0 });
+        if let Some(threshold) = inline_threshold(level) {
+            builder.set_inliner_with_threshold(threshold);
+        }
+
+        let function_passes = PassManager::create(module);
+        // The code generator keeps every variable in an alloca
+        function_passes.add_promote_memory_to_register_pass();
+        builder.populate_function_pass_manager(&function_passes);
+        function_passes.initialize();
+        for function in module.get_functions() {
+            function_passes.run_on(&function);
+        }
+        function_passes.finalize();
+
+        let module_passes = PassManager::create(());
+        builder.populate_module_pass_manager(&module_passes);
+        module_passes.run_on(module);
+    }
+
+    if let Some(passes) = passes {
+        module
+            .run_passes(passes, machine, PassBuilderOptions::create())
+            .map_err(|e| format!("Invalid `--llvm-passes` pipeline `{}`: {}", passes, e))?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,1212 @@
+use std::{
+    ffi::{c_char, CStr},
+    io::{self, Write},
+    process, slice,
+};
+
+use inkwell::{
+    attributes::AttributeLoc,
+    builder::Builder,
+    context::Context,
+    execution_engine::ExecutionEngine,
+    module::{Linkage, Module},
+    types::{ArrayType, FunctionType},
+    values::{BasicMetadataValueEnum, BasicValueEnum, FunctionValue, IntValue, PointerValue},
+    AddressSpace, IntPredicate,
+};
+
+use crate::{ast::Type, utils::format_float};
+
+pub const PANIC_BOUNDS: &str = "aurora_panic_bounds";
+pub const STRLEN: &str = "strlen";
+/// `strcmp` of libc, which compares strings
+pub const STRCMP: &str = "strcmp";
+pub const MALLOC: &str = "malloc";
+/// `aurora_alloc(size: i64) -> i8*` allocates a block with a single
+/// reference and returns its data, which follows a header counting the
+/// references. Strings and the elements of dynamic arrays live in such
+/// blocks, see `codegen::memory`.
+pub const ALLOC: &str = "aurora_alloc";
+/// `aurora_retain(data: i8*)` adds a reference to the block of `data`
+pub const RETAIN: &str = "aurora_retain";
+/// `aurora_release(data: i8*) -> bool` drops a reference to the block of
+/// `data` and returns whether it was the last one
+pub const RELEASE: &str = "aurora_release";
+/// `aurora_free(data: i8*)` frees the block of `data`, which WebAssembly
+/// never does
+pub const FREE: &str = "aurora_free";
+/// `aurora_string_copy(text: str) -> str` copies a string returned by C to a
+/// new block
+pub const STRING_COPY: &str = "aurora_string_copy";
+/// `aurora_write(fd: i32, data: str, len: i32)`, which `std.io` writes
+/// with. Without WASI, WebAssembly has no way to write.
+pub const WRITE: &str = "aurora_write";
+/// `aurora_concat(lhs: str, rhs: str) -> str`
+pub const CONCAT: &str = "aurora_concat";
+/// `aurora_format_int(value: i128) -> str` writes `value` in decimal
+pub const FORMAT_INT: &str = "aurora_format_int";
+/// `aurora_format_float(value: f64, digits: i32) -> str` writes `value` like
+/// `%.{digits}g`, not on WebAssembly
+pub const FORMAT_FLOAT: &str = "aurora_format_float";
+/// `aurora_write_int(fd: i32, value: i64)` writes `value` in decimal, WASI
+/// only
+pub const WRITE_INT: &str = "aurora_write_int";
+/// `aurora_panic(message: str)` stops the program with `message`, which
+/// generated code also calls when a division has no result
+pub const PANIC: &str = "aurora_panic";
+/// `aurora_byte(text: str, index: i64) -> i32` is the byte at `index`, which
+/// has to be within the string
+pub const BYTE: &str = "aurora_byte";
+/// `aurora_substring(text: str, start: i64, end: i64) -> str` copies the
+/// bytes from `start` up to `end`, which have to be within the string
+pub const SUBSTRING: &str = "aurora_substring";
+
+/// The module WASI functions are imported from.
+const WASI_MODULE: &str = "wasi_snapshot_preview1";
+/// Size of a WebAssembly memory page.
+const WASM_PAGE_SIZE: u64 = 65536;
+/// Size of the header in front of the data of a block, an `i64` reference
+/// count and padding that keeps the data aligned like memory from `malloc`.
+/// String literals have one too, whose negative count keeps them alive
+/// forever.
+const HEADER_SIZE: u64 = 16;
+/// The runtime functions WebAssembly has none of, with what calls them.
+/// Formatting floats needs the `snprintf` of libc.
+const NOT_ON_WASM: &[(&str, &str)] = &[(FORMAT_FLOAT, "`format` of a float")];
+
+/// Called by generated code when an array index is out of range.
+#[no_mangle]
+pub extern "C" fn aurora_panic_bounds(index: i64, len: i64) {
+    eprintln!(
+        "panic: index out of bounds: the len is {} but the index is {}",
+        len, index
+    );
+    process::exit(101);
+}
+
+/// `aurora_write` of JIT compiled code, writing to stdout or, for `fd` 2,
+/// to stderr.
+unsafe extern "C" fn write(fd: i32, data: *const u8, len: i32) {
+    let data = slice::from_raw_parts(data, len.max(0) as usize);
+    // Errors are ignored, like by the runtime of linked programs
+    let _ = match fd {
+        2 => io::stderr().write_all(data),
+        _ => io::stdout().write_all(data),
+    };
+}
+
+extern "C" {
+    fn malloc(size: usize) -> *mut u8;
+    fn free(ptr: *mut u8);
+}
+
+/// `aurora_alloc` of JIT compiled code, on top of the `malloc` of the
+/// compiler.
+unsafe extern "C" fn alloc(size: i64) -> *mut u8 {
+    let header = malloc(HEADER_SIZE as usize + size.max(0) as usize) as *mut i64;
+    if header.is_null() {
+        process::abort();
+    }
+    *header = 1;
+    *header.add(1) = 0;
+    header.add(2) as *mut u8
+}
+
+unsafe extern "C" fn retain(data: *const u8) {
+    if !data.is_null() {
+        let refs = (data as *mut i64).sub(2);
+        if *refs >= 0 {
+            *refs += 1;
+        }
+    }
+}
+
+unsafe extern "C" fn release(data: *const u8) -> bool {
+    if data.is_null() {
+        return false;
+    }
+    let refs = (data as *mut i64).sub(2);
+    if *refs < 0 {
+        return false;
+    }
+    *refs -= 1;
+    *refs == 0
+}
+
+unsafe extern "C" fn free_block(data: *const u8) {
+    free((data as *mut u8).sub(HEADER_SIZE as usize));
+}
+
+/// Copies `text` to a new block, with a terminating 0.
+fn new_string(text: &[u8]) -> *const c_char {
+    unsafe {
+        let data = alloc(text.len() as i64 + 1);
+        data.copy_from_nonoverlapping(text.as_ptr(), text.len());
+        *data.add(text.len()) = 0;
+        data as *const c_char
+    }
+}
+
+unsafe extern "C" fn string_copy(text: *const c_char) -> *const c_char {
+    match text.is_null() {
+        true => text,
+        false => new_string(CStr::from_ptr(text).to_bytes()),
+    }
+}
+
+unsafe extern "C" fn concat(lhs: *const c_char, rhs: *const c_char) -> *const c_char {
+    let mut text = CStr::from_ptr(lhs).to_bytes().to_vec();
+    text.extend_from_slice(CStr::from_ptr(rhs).to_bytes());
+    new_string(&text)
+}
+
+extern "C" fn format_int(value: i128) -> *const c_char {
+    new_string(value.to_string().as_bytes())
+}
+
+extern "C" fn format_float_value(value: f64, digits: i32) -> *const c_char {
+    new_string(format_float(value, digits.max(0) as usize).as_bytes())
+}
+
+unsafe extern "C" fn panic_with(message: *const c_char) {
+    eprintln!("panic: {}", CStr::from_ptr(message).to_string_lossy());
+    process::exit(101);
+}
+
+unsafe extern "C" fn byte(text: *const u8, index: i64) -> i32 {
+    *text.offset(index as isize) as i32
+}
+
+unsafe extern "C" fn substring(text: *const u8, start: i64, end: i64) -> *const c_char {
+    let len = (end - start).max(0) as usize;
+    new_string(slice::from_raw_parts(text.offset(start as isize), len))
+}
+
+/// Points the runtime functions declared in `module` at their definitions in
+/// the compiler so JIT compiled code can call them.
+pub fn map_runtime_functions(module: &Module, execution_engine: &ExecutionEngine) {
+    let functions = [
+        (PANIC_BOUNDS, aurora_panic_bounds as *const () as usize),
+        (WRITE, write as *const () as usize),
+        (ALLOC, alloc as *const () as usize),
+        (RETAIN, retain as *const () as usize),
+        (RELEASE, release as *const () as usize),
+        (FREE, free_block as *const () as usize),
+        (STRING_COPY, string_copy as *const () as usize),
+        (CONCAT, concat as *const () as usize),
+        (FORMAT_INT, format_int as *const () as usize),
+        (FORMAT_FLOAT, format_float_value as *const () as usize),
+        (PANIC, panic_with as *const () as usize),
+        (BYTE, byte as *const () as usize),
+        (SUBSTRING, substring as *const () as usize),
+    ];
+    for (name, address) in functions {
+        if let Some(function) = module.get_function(name) {
+            execution_engine.add_global_mapping(&function, address);
+        }
+    }
+}
+
+/// Defines the runtime functions in `module`, for programs that are linked
+/// into a binary instead of run by the JIT. They only depend on libc.
+pub fn define_runtime_functions<'ctx>(context: &'ctx Context, module: &Module<'ctx>) {
+    let builder = context.create_builder();
+    let i32_type = context.i32_type();
+    let i64_type = context.i64_type();
+    let void_type = context.void_type();
+    let str_type = context.i8_type().ptr_type(AddressSpace::default());
+
+    let dprintf = module.add_function(
+        "dprintf",
+        i32_type.fn_type(&[i32_type.into(), str_type.into()], true),
+        None,
+    );
+    let exit = module.add_function("exit", void_type.fn_type(&[i32_type.into()], false), None);
+
+    let panic_bounds = module.add_function(
+        PANIC_BOUNDS,
+        void_type.fn_type(&[i64_type.into(), i64_type.into()], false),
+        None,
+    );
+    builder.position_at_end(context.append_basic_block(panic_bounds, "entry"));
+    let message = builder.build_global_string_ptr(
+        "panic: index out of bounds: the len is %lld but the index is %lld\n",
+        "message",
+    );
+    builder.build_call(
+        dprintf,
+        &[
+            i32_type.const_int(2, false).into(),
+            message.as_pointer_value().into(),
+            panic_bounds.get_nth_param(1).unwrap().into(),
+            panic_bounds.get_nth_param(0).unwrap().into(),
+        ],
+        "",
+    );
+    builder.build_call(exit, &[i32_type.const_int(101, false).into()], "");
+    builder.build_unreachable();
+
+    let malloc = module.add_function(MALLOC, str_type.fn_type(&[i64_type.into()], false), None);
+    let free = module.add_function("free", void_type.fn_type(&[str_type.into()], false), None);
+    let strlen = module.add_function(STRLEN, i64_type.fn_type(&[str_type.into()], false), None);
+    let alloc = define_memory_functions(context, module, &builder, malloc, Some(free));
+    define_string_copy(context, module, &builder, alloc, strlen);
+    define_libc_write(context, module, &builder);
+    define_concat(context, module, &builder, alloc, strlen);
+    define_format_int(context, module, &builder, alloc);
+    define_format_float(context, module, &builder, alloc);
+    define_panic(context, module, &builder, dprintf, exit);
+    define_byte(context, module, &builder);
+    define_substring(context, module, &builder, alloc);
+}
+
+/// Calls `function`, which returns a value.
+fn call<'ctx>(
+    builder: &Builder<'ctx>,
+    function: FunctionValue<'ctx>,
+    args: &[BasicMetadataValueEnum<'ctx>],
+    name: &str,
+) -> BasicValueEnum<'ctx> {
+    builder
+        .build_call(function, args, name)
+        .try_as_basic_value()
+        .left()
+        .unwrap()
+}
+
+/// Defines `aurora_alloc`, `aurora_retain`, `aurora_release` and
+/// `aurora_free` on top of `malloc` and `free`, like those of `aurora.h`.
+/// Without `free`, blocks are never freed. Returns `aurora_alloc`.
+fn define_memory_functions<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    builder: &Builder<'ctx>,
+    malloc: FunctionValue<'ctx>,
+    free: Option<FunctionValue<'ctx>>,
+) -> FunctionValue<'ctx> {
+    let i8_type = context.i8_type();
+    let i64_type = context.i64_type();
+    let bool_type = context.bool_type();
+    let void_type = context.void_type();
+    let str_type = i8_type.ptr_type(AddressSpace::default());
+    let header_size = i64_type.const_int(HEADER_SIZE, false);
+
+    // The reference count of the block of `data`, at the start of the header
+    let refs_pointer = |data: PointerValue<'ctx>| {
+        let minus_header = i64_type.const_int(HEADER_SIZE.wrapping_neg(), true);
+        let header = unsafe { builder.build_in_bounds_gep(i8_type, data, &[minus_header], "header") };
+        builder.build_pointer_cast(header, i64_type.ptr_type(AddressSpace::default()), "refs")
+    };
+
+    let alloc = module.add_function(ALLOC, str_type.fn_type(&[i64_type.into()], false), None);
+    let entry = context.append_basic_block(alloc, "entry");
+    let out_of_memory = context.append_basic_block(alloc, "out_of_memory");
+    let done = context.append_basic_block(alloc, "done");
+    builder.position_at_end(entry);
+    let size = alloc.get_nth_param(0).unwrap().into_int_value();
+    let size = builder.build_int_add(size, header_size, "size");
+    let size_type = malloc.get_nth_param(0).unwrap().into_int_value().get_type();
+    let size = builder.build_int_cast(size, size_type, "size");
+    let header = call(builder, malloc, &[size.into()], "header").into_pointer_value();
+    let failed = builder.build_is_null(header, "failed");
+    builder.build_conditional_branch(failed, out_of_memory, done);
+    builder.position_at_end(out_of_memory);
+    let trap = module
+        .get_function("llvm.trap")
+        .unwrap_or_else(|| module.add_function("llvm.trap", void_type.fn_type(&[], false), None));
+    builder.build_call(trap, &[], "");
+    builder.build_unreachable();
+    builder.position_at_end(done);
+    let fields = builder.build_pointer_cast(header, i64_type.ptr_type(AddressSpace::default()), "fields");
+    for (i, value) in [1, 0].into_iter().enumerate() {
+        let index = i64_type.const_int(i as u64, false);
+        let field = unsafe { builder.build_in_bounds_gep(i64_type, fields, &[index], "field") };
+        builder.build_store(field, i64_type.const_int(value, false));
+    }
+    let data = unsafe { builder.build_in_bounds_gep(i8_type, header, &[header_size], "data") };
+    builder.build_return(Some(&data));
+
+    // NULL, which C functions may return for strings, has no block
+    let retain = module.add_function(RETAIN, void_type.fn_type(&[str_type.into()], false), None);
+    let entry = context.append_basic_block(retain, "entry");
+    let check = context.append_basic_block(retain, "check");
+    let increment = context.append_basic_block(retain, "increment");
+    let done = context.append_basic_block(retain, "done");
+    builder.position_at_end(entry);
+    let data = retain.get_nth_param(0).unwrap().into_pointer_value();
+    let is_null = builder.build_is_null(data, "is_null");
+    builder.build_conditional_branch(is_null, done, check);
+    builder.position_at_end(check);
+    let refs_ptr = refs_pointer(data);
+    let refs = builder.build_load(i64_type, refs_ptr, "refs").into_int_value();
+    let is_counted = builder.build_int_compare(IntPredicate::SGE, refs, i64_type.const_zero(), "is_counted");
+    builder.build_conditional_branch(is_counted, increment, done);
+    builder.position_at_end(increment);
+    let refs = builder.build_int_add(refs, i64_type.const_int(1, false), "refs");
+    builder.build_store(refs_ptr, refs);
+    builder.build_unconditional_branch(done);
+    builder.position_at_end(done);
+    builder.build_return(None);
+
+    let release = module.add_function(RELEASE, bool_type.fn_type(&[str_type.into()], false), None);
+    let entry = context.append_basic_block(release, "entry");
+    let check = context.append_basic_block(release, "check");
+    let decrement = context.append_basic_block(release, "decrement");
+    let kept = context.append_basic_block(release, "kept");
+    builder.position_at_end(entry);
+    let data = release.get_nth_param(0).unwrap().into_pointer_value();
+    let is_null = builder.build_is_null(data, "is_null");
+    builder.build_conditional_branch(is_null, kept, check);
+    builder.position_at_end(check);
+    let refs_ptr = refs_pointer(data);
+    let refs = builder.build_load(i64_type, refs_ptr, "refs").into_int_value();
+    let is_counted = builder.build_int_compare(IntPredicate::SGE, refs, i64_type.const_zero(), "is_counted");
+    builder.build_conditional_branch(is_counted, decrement, kept);
+    builder.position_at_end(decrement);
+    let refs = builder.build_int_sub(refs, i64_type.const_int(1, false), "refs");
+    builder.build_store(refs_ptr, refs);
+    let is_last = builder.build_int_compare(IntPredicate::EQ, refs, i64_type.const_zero(), "is_last");
+    builder.build_return(Some(&is_last));
+    builder.position_at_end(kept);
+    builder.build_return(Some(&bool_type.const_zero()));
+
+    let free_block = module.add_function(FREE, void_type.fn_type(&[str_type.into()], false), None);
+    builder.position_at_end(context.append_basic_block(free_block, "entry"));
+    if let Some(free) = free {
+        let data = free_block.get_nth_param(0).unwrap().into_pointer_value();
+        let header = builder.build_pointer_cast(refs_pointer(data), str_type, "header");
+        builder.build_call(free, &[header.into()], "");
+    }
+    builder.build_return(None);
+
+    alloc
+}
+
+/// `aurora_string_copy(text: i8*) -> i8*`, copying `text` to a new block
+/// unless it is NULL.
+fn define_string_copy<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    builder: &Builder<'ctx>,
+    alloc: FunctionValue<'ctx>,
+    strlen: FunctionValue<'ctx>,
+) {
+    let i64_type = context.i64_type();
+    let str_type = context.i8_type().ptr_type(AddressSpace::default());
+
+    let string_copy = module.add_function(STRING_COPY, str_type.fn_type(&[str_type.into()], false), None);
+    let entry = context.append_basic_block(string_copy, "entry");
+    let copy = context.append_basic_block(string_copy, "copy");
+    let null = context.append_basic_block(string_copy, "null");
+    builder.position_at_end(entry);
+    let text = string_copy.get_nth_param(0).unwrap().into_pointer_value();
+    let is_null = builder.build_is_null(text, "is_null");
+    builder.build_conditional_branch(is_null, null, copy);
+
+    builder.position_at_end(copy);
+    let len = call(builder, strlen, &[text.into()], "len").into_int_value();
+    let size = builder.build_int_add(len, i64_type.const_int(1, false), "size");
+    let result = call(builder, alloc, &[size.into()], "result").into_pointer_value();
+    builder.build_memcpy(result, 1, text, 1, size).unwrap();
+    builder.build_return(Some(&result));
+
+    builder.position_at_end(null);
+    builder.build_return(Some(&text));
+}
+
+/// `aurora_write(fd: i32, data: i8*, len: i32)` on top of the `write` of
+/// libc. Errors and short writes are ignored, like with WASI.
+fn define_libc_write<'ctx>(context: &'ctx Context, module: &Module<'ctx>, builder: &Builder<'ctx>) {
+    let i32_type = context.i32_type();
+    let i64_type = context.i64_type();
+    let str_type = context.i8_type().ptr_type(AddressSpace::default());
+
+    let libc_write = module.add_function(
+        "write",
+        i64_type.fn_type(&[i32_type.into(), str_type.into(), i64_type.into()], false),
+        None,
+    );
+    let write = module.add_function(
+        WRITE,
+        context
+            .void_type()
+            .fn_type(&[i32_type.into(), str_type.into(), i32_type.into()], false),
+        None,
+    );
+    builder.position_at_end(context.append_basic_block(write, "entry"));
+
+    let len = write.get_nth_param(2).unwrap().into_int_value();
+    let len = builder.build_int_s_extend(len, i64_type, "len");
+    builder.build_call(
+        libc_write,
+        &[
+            write.get_nth_param(0).unwrap().into(),
+            write.get_nth_param(1).unwrap().into(),
+            len.into(),
+        ],
+        "",
+    );
+    builder.build_return(None);
+}
+
+/// `aurora_concat(lhs: i8*, rhs: i8*) -> i8*`, copying both strings into a
+/// new one.
+fn define_concat<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    builder: &Builder<'ctx>,
+    alloc: FunctionValue<'ctx>,
+    strlen: FunctionValue<'ctx>,
+) {
+    let i8_type = context.i8_type();
+    let i64_type = context.i64_type();
+    let str_type = i8_type.ptr_type(AddressSpace::default());
+
+    let concat = module.add_function(
+        CONCAT,
+        str_type.fn_type(&[str_type.into(), str_type.into()], false),
+        None,
+    );
+    builder.position_at_end(context.append_basic_block(concat, "entry"));
+
+    let lhs = concat.get_nth_param(0).unwrap().into_pointer_value();
+    let rhs = concat.get_nth_param(1).unwrap().into_pointer_value();
+    let lhs_len = call(builder, strlen, &[lhs.into()], "lhs_len").into_int_value();
+    let rhs_len = call(builder, strlen, &[rhs.into()], "rhs_len").into_int_value();
+    // The terminating 0 of `rhs` is copied as well
+    let rhs_size = builder.build_int_add(rhs_len, i64_type.const_int(1, false), "rhs_size");
+    let size = builder.build_int_add(lhs_len, rhs_size, "size");
+
+    let result = call(builder, alloc, &[size.into()], "result").into_pointer_value();
+    builder.build_memcpy(result, 1, lhs, 1, lhs_len).unwrap();
+    let end = unsafe { builder.build_in_bounds_gep(i8_type, result, &[lhs_len], "end") };
+    builder.build_memcpy(end, 1, rhs, 1, rhs_size).unwrap();
+    builder.build_return(Some(&result));
+}
+
+/// `aurora_format_int(value: i128) -> i8*`, writing `value` in decimal to a
+/// new string. Like in `aurora_write_int`, the digits are produced backwards
+/// into a buffer large enough for `i128::MIN`, and then copied.
+fn define_format_int<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    builder: &Builder<'ctx>,
+    alloc: FunctionValue<'ctx>,
+) {
+    const BUFFER_SIZE: u64 = 41;
+
+    let i8_type = context.i8_type();
+    let i64_type = context.i64_type();
+    let i128_type = context.i128_type();
+    let str_type = i8_type.ptr_type(AddressSpace::default());
+
+    let format_int = module.add_function(FORMAT_INT, str_type.fn_type(&[i128_type.into()], false), None);
+    let entry = context.append_basic_block(format_int, "entry");
+    let digit = context.append_basic_block(format_int, "digit");
+    let sign = context.append_basic_block(format_int, "sign");
+    let minus = context.append_basic_block(format_int, "minus");
+    let done = context.append_basic_block(format_int, "done");
+
+    builder.position_at_end(entry);
+    let buffer_type = i8_type.array_type(BUFFER_SIZE as u32);
+    let buffer = builder.build_alloca(buffer_type, "buffer");
+    let pos_slot = builder.build_alloca(i64_type, "pos");
+    builder.build_store(pos_slot, i64_type.const_int(BUFFER_SIZE, false));
+    push_byte(builder, context, buffer_type, buffer, pos_slot, i8_type.const_zero());
+
+    // The magnitude is treated as unsigned, which also holds `-i128::MIN`
+    let value = format_int.get_nth_param(0).unwrap().into_int_value();
+    let is_negative = builder.build_int_compare(IntPredicate::SLT, value, i128_type.const_zero(), "is_negative");
+    let negated = builder.build_int_neg(value, "negated");
+    let magnitude = builder.build_select(is_negative, negated, value, "magnitude");
+    let magnitude_slot = builder.build_alloca(i128_type, "magnitude");
+    builder.build_store(magnitude_slot, magnitude);
+    builder.build_unconditional_branch(digit);
+
+    builder.position_at_end(digit);
+    let ten = i128_type.const_int(10, false);
+    let magnitude = builder.build_load(i128_type, magnitude_slot, "magnitude").into_int_value();
+    let remainder = builder.build_int_unsigned_rem(magnitude, ten, "remainder");
+    let remainder = builder.build_int_truncate(remainder, i8_type, "remainder");
+    let character = builder.build_int_add(remainder, i8_type.const_int(b'0' as u64, false), "character");
+    push_byte(builder, context, buffer_type, buffer, pos_slot, character);
+    let magnitude = builder.build_int_unsigned_div(magnitude, ten, "magnitude");
+    builder.build_store(magnitude_slot, magnitude);
+    let more = builder.build_int_compare(IntPredicate::NE, magnitude, i128_type.const_zero(), "more");
+    builder.build_conditional_branch(more, digit, sign);
+
+    builder.position_at_end(sign);
+    builder.build_conditional_branch(is_negative, minus, done);
+    builder.position_at_end(minus);
+    push_byte(builder, context, buffer_type, buffer, pos_slot, i8_type.const_int(b'-' as u64, false));
+    builder.build_unconditional_branch(done);
+
+    builder.position_at_end(done);
+    let pos = builder.build_load(i64_type, pos_slot, "pos").into_int_value();
+    let start = unsafe {
+        builder.build_in_bounds_gep(buffer_type, buffer, &[i64_type.const_zero(), pos], "start")
+    };
+    let size = builder.build_int_sub(i64_type.const_int(BUFFER_SIZE, false), pos, "size");
+    let result = call(builder, alloc, &[size.into()], "result").into_pointer_value();
+    builder.build_memcpy(result, 1, start, 1, size).unwrap();
+    builder.build_return(Some(&result));
+}
+
+/// `aurora_format_float(value: f64, digits: i32) -> i8*`, writing `value`
+/// to a new string with the `snprintf` of libc.
+fn define_format_float<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    builder: &Builder<'ctx>,
+    alloc: FunctionValue<'ctx>,
+) {
+    const BUFFER_SIZE: u64 = 32;
+
+    let i32_type = context.i32_type();
+    let i64_type = context.i64_type();
+    let f64_type = context.f64_type();
+    let str_type = context.i8_type().ptr_type(AddressSpace::default());
+
+    let snprintf = module.add_function(
+        "snprintf",
+        i32_type.fn_type(&[str_type.into(), i64_type.into(), str_type.into()], true),
+        None,
+    );
+    let format_float = module.add_function(
+        FORMAT_FLOAT,
+        str_type.fn_type(&[f64_type.into(), i32_type.into()], false),
+        None,
+    );
+    builder.position_at_end(context.append_basic_block(format_float, "entry"));
+
+    let size = i64_type.const_int(BUFFER_SIZE, false);
+    let buffer = call(builder, alloc, &[size.into()], "buffer");
+    let pattern = builder.build_global_string_ptr("%.*g", "pattern");
+    builder.build_call(
+        snprintf,
+        &[
+            buffer.into(),
+            size.into(),
+            pattern.as_pointer_value().into(),
+            format_float.get_nth_param(1).unwrap().into(),
+            format_float.get_nth_param(0).unwrap().into(),
+        ],
+        "",
+    );
+    builder.build_return(Some(&buffer));
+}
+
+/// `aurora_panic(message: i8*)`, which writes `message` to stderr and exits
+/// like `aurora_panic_bounds`.
+fn define_panic<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    builder: &Builder<'ctx>,
+    dprintf: FunctionValue<'ctx>,
+    exit: FunctionValue<'ctx>,
+) {
+    let i32_type = context.i32_type();
+    let str_type = context.i8_type().ptr_type(AddressSpace::default());
+
+    let panic = module.add_function(PANIC, context.void_type().fn_type(&[str_type.into()], false), None);
+    builder.position_at_end(context.append_basic_block(panic, "entry"));
+
+    let pattern = builder.build_global_string_ptr("panic: %s\n", "pattern");
+    builder.build_call(
+        dprintf,
+        &[
+            i32_type.const_int(2, false).into(),
+            pattern.as_pointer_value().into(),
+            panic.get_nth_param(0).unwrap().into(),
+        ],
+        "",
+    );
+    builder.build_call(exit, &[i32_type.const_int(101, false).into()], "");
+    builder.build_unreachable();
+}
+
+/// `aurora_byte(text: i8*, index: i64) -> i32`, the byte at `index` as an
+/// unsigned value.
+fn define_byte<'ctx>(context: &'ctx Context, module: &Module<'ctx>, builder: &Builder<'ctx>) {
+    let i8_type = context.i8_type();
+    let i32_type = context.i32_type();
+    let i64_type = context.i64_type();
+    let str_type = i8_type.ptr_type(AddressSpace::default());
+
+    let byte = module.add_function(
+        BYTE,
+        i32_type.fn_type(&[str_type.into(), i64_type.into()], false),
+        None,
+    );
+    builder.position_at_end(context.append_basic_block(byte, "entry"));
+
+    let text = byte.get_nth_param(0).unwrap().into_pointer_value();
+    let index = byte.get_nth_param(1).unwrap().into_int_value();
+    let byte_ptr = unsafe { builder.build_in_bounds_gep(i8_type, text, &[index], "byte") };
+    let value = builder.build_load(i8_type, byte_ptr, "byte").into_int_value();
+    let value = builder.build_int_z_extend(value, i32_type, "byte");
+    builder.build_return(Some(&value));
+}
+
+/// `aurora_substring(text: i8*, start: i64, end: i64) -> i8*`, copying the
+/// bytes from `start` up to `end` into a new string.
+fn define_substring<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    builder: &Builder<'ctx>,
+    alloc: FunctionValue<'ctx>,
+) {
+    let i8_type = context.i8_type();
+    let i64_type = context.i64_type();
+    let str_type = i8_type.ptr_type(AddressSpace::default());
+
+    let substring = module.add_function(
+        SUBSTRING,
+        str_type.fn_type(&[str_type.into(), i64_type.into(), i64_type.into()], false),
+        None,
+    );
+    builder.position_at_end(context.append_basic_block(substring, "entry"));
+
+    let text = substring.get_nth_param(0).unwrap().into_pointer_value();
+    let start = substring.get_nth_param(1).unwrap().into_int_value();
+    let end = substring.get_nth_param(2).unwrap().into_int_value();
+    let len = builder.build_int_sub(end, start, "len");
+    let size = builder.build_int_add(len, i64_type.const_int(1, false), "size");
+
+    let result = call(builder, alloc, &[size.into()], "result").into_pointer_value();
+    let source = unsafe { builder.build_in_bounds_gep(i8_type, text, &[start], "source") };
+    builder.build_memcpy(result, 1, source, 1, len).unwrap();
+    let end_ptr = unsafe { builder.build_in_bounds_gep(i8_type, result, &[len], "end") };
+    builder.build_store(end_ptr, i8_type.const_zero());
+    builder.build_return(Some(&result));
+}
+
+/// Defines the runtime for the WebAssembly targets, which have no libc to
+/// build on: `strlen`, `strcmp`, `memcpy`, a `malloc` that bumps a pointer
+/// and never frees, the reference counting on top of it, the string
+/// functions other than `aurora_format_float` and the panic handlers. With
+/// WASI, panics print their message to stderr and `_start` runs `main`, if
+/// there is one, exiting with its result. Without WASI there is no way to
+/// print, so panics trap.
+pub fn define_wasm_runtime_functions<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    wasi: bool,
+    main: Option<&Type>,
+) {
+    let builder = context.create_builder();
+    let i32_type = context.i32_type();
+    let i64_type = context.i64_type();
+    let void_type = context.void_type();
+    let str_type = context.i8_type().ptr_type(AddressSpace::default());
+
+    let strlen = define_strlen(context, module, &builder);
+    define_strcmp(context, module, &builder);
+    define_memcpy(context, module, &builder);
+    let malloc = define_malloc(context, module, &builder);
+    let alloc = define_memory_functions(context, module, &builder, malloc, None);
+    define_string_copy(context, module, &builder, alloc, strlen);
+    define_concat(context, module, &builder, alloc, strlen);
+    define_format_int(context, module, &builder, alloc);
+    define_byte(context, module, &builder);
+    define_substring(context, module, &builder, alloc);
+
+    let panic_bounds = module.add_function(
+        PANIC_BOUNDS,
+        void_type.fn_type(&[i64_type.into(), i64_type.into()], false),
+        None,
+    );
+    let panic = module.add_function(PANIC, void_type.fn_type(&[str_type.into()], false), None);
+    for function in [panic_bounds, panic] {
+        context.append_basic_block(function, "entry");
+    }
+
+    if !wasi {
+        let trap = module.add_function("llvm.trap", void_type.fn_type(&[], false), None);
+        for function in [panic_bounds, panic] {
+            builder.position_at_end(function.get_first_basic_block().unwrap());
+            builder.build_call(trap, &[], "");
+            builder.build_unreachable();
+        }
+        return;
+    }
+
+    let fd_write = wasi_function(
+        context,
+        module,
+        "fd_write",
+        i32_type.fn_type(&[i32_type.into(); 4], false),
+    );
+    let proc_exit = wasi_function(
+        context,
+        module,
+        "proc_exit",
+        void_type.fn_type(&[i32_type.into()], false),
+    );
+    let write = define_write(context, module, &builder, fd_write);
+    let write_int = define_write_int(context, module, &builder, write);
+
+    builder.position_at_end(panic_bounds.get_first_basic_block().unwrap());
+    let stderr = i32_type.const_int(2, false);
+    let index = panic_bounds.get_nth_param(0).unwrap();
+    let len = panic_bounds.get_nth_param(1).unwrap();
+    let write_text = |text: &str| {
+        let message = builder.build_global_string_ptr(text, "message");
+        builder.build_call(
+            write,
+            &[
+                stderr.into(),
+                message.as_pointer_value().into(),
+                i32_type.const_int(text.len() as u64, false).into(),
+            ],
+            "",
+        );
+    };
+    write_text("panic: index out of bounds: the len is ");
+    builder.build_call(write_int, &[stderr.into(), len.into()], "");
+    write_text(" but the index is ");
+    builder.build_call(write_int, &[stderr.into(), index.into()], "");
+    write_text("\n");
+    builder.build_call(proc_exit, &[i32_type.const_int(101, false).into()], "");
+    builder.build_unreachable();
+
+    builder.position_at_end(panic.get_first_basic_block().unwrap());
+    let message = panic.get_nth_param(0).unwrap().into_pointer_value();
+    let len = builder
+        .build_call(strlen, &[message.into()], "len")
+        .try_as_basic_value()
+        .left()
+        .unwrap()
+        .into_int_value();
+    write_text("panic: ");
+    builder.build_call(
+        write,
+        &[
+            stderr.into(),
+            message.into(),
+            builder.build_int_truncate(len, i32_type, "len").into(),
+        ],
+        "",
+    );
+    write_text("\n");
+    builder.build_call(proc_exit, &[i32_type.const_int(101, false).into()], "");
+    builder.build_unreachable();
+
+    // Like a C program, a WASI command starts at `_start`
+    let main_fn_type = match main {
+        Some(Type::Int32) => i32_type.fn_type(&[], false),
+        Some(Type::Void) => void_type.fn_type(&[], false),
+        _ => return,
+    };
+    let start = module.add_function("_start", void_type.fn_type(&[], false), None);
+    builder.position_at_end(context.append_basic_block(start, "entry"));
+    let main = module.add_function("main", main_fn_type, None);
+    let result = builder.build_call(main, &[], "result");
+    if let Some(result) = result.try_as_basic_value().left() {
+        builder.build_call(proc_exit, &[result.into()], "");
+    }
+    builder.build_return(None);
+}
+
+/// Declares the WASI function `name`, under a name of its own so it can not
+/// clash with Aurora functions.
+fn wasi_function<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    name: &str,
+    fn_type: FunctionType<'ctx>,
+) -> FunctionValue<'ctx> {
+    let function = module.add_function(&format!("aurora_wasi_{}", name), fn_type, None);
+    function.add_attribute(
+        AttributeLoc::Function,
+        context.create_string_attribute("wasm-import-module", WASI_MODULE),
+    );
+    function.add_attribute(
+        AttributeLoc::Function,
+        context.create_string_attribute("wasm-import-name", name),
+    );
+    function
+}
+
+/// Checks that `module`, compiled for WebAssembly, calls only runtime
+/// functions WebAssembly has, so that a missing one is reported by what
+/// calls it instead of by `wasm-ld`.
+pub fn check_wasm_module(module: &Module) -> Result<(), String> {
+    match NOT_ON_WASM
+        .iter()
+        .find(|(name, _)| module.get_function(name).is_some())
+    {
+        Some((_, user)) => Err(format!("{} is not supported on WebAssembly", user)),
+        None => Ok(()),
+    }
+}
+
+/// `strlen(s: i8*) -> i64`, counting the bytes before the terminating 0.
+fn define_strlen<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    builder: &Builder<'ctx>,
+) -> FunctionValue<'ctx> {
+    let i8_type = context.i8_type();
+    let i64_type = context.i64_type();
+    let str_type = i8_type.ptr_type(AddressSpace::default());
+
+    let strlen = module.add_function(STRLEN, i64_type.fn_type(&[str_type.into()], false), None);
+    let entry = context.append_basic_block(strlen, "entry");
+    let check = context.append_basic_block(strlen, "check");
+    let next = context.append_basic_block(strlen, "next");
+    let end = context.append_basic_block(strlen, "end");
+
+    builder.position_at_end(entry);
+    let len_slot = builder.build_alloca(i64_type, "len");
+    builder.build_store(len_slot, i64_type.const_zero());
+    builder.build_unconditional_branch(check);
+
+    builder.position_at_end(check);
+    let len = builder.build_load(i64_type, len_slot, "len").into_int_value();
+    let s = strlen.get_nth_param(0).unwrap().into_pointer_value();
+    let byte_ptr = unsafe { builder.build_in_bounds_gep(i8_type, s, &[len], "byte") };
+    let byte = builder.build_load(i8_type, byte_ptr, "byte").into_int_value();
+    let is_end = builder.build_int_compare(IntPredicate::EQ, byte, i8_type.const_zero(), "is_end");
+    builder.build_conditional_branch(is_end, end, next);
+
+    builder.position_at_end(next);
+    let len = builder.build_int_add(len, i64_type.const_int(1, false), "len");
+    builder.build_store(len_slot, len);
+    builder.build_unconditional_branch(check);
+
+    builder.position_at_end(end);
+    let len = builder.build_load(i64_type, len_slot, "len");
+    builder.build_return(Some(&len));
+
+    strlen
+}
+
+/// `strcmp(lhs: i8*, rhs: i8*) -> i32`, the difference of the first bytes,
+/// as unsigned values, that are not the same.
+fn define_strcmp<'ctx>(context: &'ctx Context, module: &Module<'ctx>, builder: &Builder<'ctx>) {
+    let i8_type = context.i8_type();
+    let i32_type = context.i32_type();
+    let i64_type = context.i64_type();
+    let str_type = i8_type.ptr_type(AddressSpace::default());
+
+    let strcmp = module.add_function(
+        STRCMP,
+        i32_type.fn_type(&[str_type.into(), str_type.into()], false),
+        None,
+    );
+    let entry = context.append_basic_block(strcmp, "entry");
+    let check = context.append_basic_block(strcmp, "check");
+    let same = context.append_basic_block(strcmp, "same");
+    let next = context.append_basic_block(strcmp, "next");
+    let end = context.append_basic_block(strcmp, "end");
+
+    builder.position_at_end(entry);
+    let index_slot = builder.build_alloca(i64_type, "index");
+    builder.build_store(index_slot, i64_type.const_zero());
+    builder.build_unconditional_branch(check);
+
+    builder.position_at_end(check);
+    let index = builder.build_load(i64_type, index_slot, "index").into_int_value();
+    let [lhs, rhs] = [0, 1].map(|i| {
+        let text = strcmp.get_nth_param(i).unwrap().into_pointer_value();
+        let byte_ptr = unsafe { builder.build_in_bounds_gep(i8_type, text, &[index], "byte") };
+        let byte = builder.build_load(i8_type, byte_ptr, "byte").into_int_value();
+        builder.build_int_z_extend(byte, i32_type, "byte")
+    });
+    let is_same = builder.build_int_compare(IntPredicate::EQ, lhs, rhs, "is_same");
+    builder.build_conditional_branch(is_same, same, end);
+
+    // Both strings end here when the byte they have in common is 0
+    builder.position_at_end(same);
+    let is_end = builder.build_int_compare(IntPredicate::EQ, lhs, i32_type.const_zero(), "is_end");
+    builder.build_conditional_branch(is_end, end, next);
+
+    builder.position_at_end(next);
+    let index = builder.build_int_add(index, i64_type.const_int(1, false), "index");
+    builder.build_store(index_slot, index);
+    builder.build_unconditional_branch(check);
+
+    builder.position_at_end(end);
+    let order = builder.build_int_sub(lhs, rhs, "order");
+    builder.build_return(Some(&order));
+}
+
+/// `memcpy(dest: i8*, src: i8*, size: i32) -> i8*`, with the `size_t` of
+/// wasm32, which LLVM calls for the copies of the runtime. LLVM does not turn
+/// the loop of a function named `memcpy` back into a call to it.
+fn define_memcpy<'ctx>(context: &'ctx Context, module: &Module<'ctx>, builder: &Builder<'ctx>) {
+    let i8_type = context.i8_type();
+    let i32_type = context.i32_type();
+    let str_type = i8_type.ptr_type(AddressSpace::default());
+
+    let memcpy = module.add_function(
+        "memcpy",
+        str_type.fn_type(&[str_type.into(), str_type.into(), i32_type.into()], false),
+        None,
+    );
+    let entry = context.append_basic_block(memcpy, "entry");
+    let check = context.append_basic_block(memcpy, "check");
+    let copy = context.append_basic_block(memcpy, "copy");
+    let end = context.append_basic_block(memcpy, "end");
+
+    builder.position_at_end(entry);
+    let dest = memcpy.get_nth_param(0).unwrap().into_pointer_value();
+    let src = memcpy.get_nth_param(1).unwrap().into_pointer_value();
+    let size = memcpy.get_nth_param(2).unwrap().into_int_value();
+    let index_slot = builder.build_alloca(i32_type, "index");
+    builder.build_store(index_slot, i32_type.const_zero());
+    builder.build_unconditional_branch(check);
+
+    builder.position_at_end(check);
+    let index = builder.build_load(i32_type, index_slot, "index").into_int_value();
+    let more = builder.build_int_compare(IntPredicate::ULT, index, size, "more");
+    builder.build_conditional_branch(more, copy, end);
+
+    builder.position_at_end(copy);
+    let from = unsafe { builder.build_in_bounds_gep(i8_type, src, &[index], "from") };
+    let to = unsafe { builder.build_in_bounds_gep(i8_type, dest, &[index], "to") };
+    let byte = builder.build_load(i8_type, from, "byte");
+    builder.build_store(to, byte);
+    let index = builder.build_int_add(index, i32_type.const_int(1, false), "index");
+    builder.build_store(index_slot, index);
+    builder.build_unconditional_branch(check);
+
+    builder.position_at_end(end);
+    builder.build_return(Some(&dest));
+}
+
+/// `malloc(size: i32) -> i8*`, with the `size_t` of wasm32, which
+/// `aurora_alloc` calls. Allocations start at `__heap_base`, which `wasm-ld`
+/// defines, and grow the memory when they do not fit.
+fn define_malloc<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    builder: &Builder<'ctx>,
+) -> FunctionValue<'ctx> {
+    let i8_type = context.i8_type();
+    let i32_type = context.i32_type();
+    let i64_type = context.i64_type();
+    let str_type = i8_type.ptr_type(AddressSpace::default());
+
+    let heap_base = module.add_global(i8_type, None, "__heap_base");
+    let heap_next = module.add_global(i32_type, None, "aurora_heap_next");
+    heap_next.set_linkage(Linkage::Internal);
+    heap_next.set_initializer(&i32_type.const_zero());
+
+    let memory_size = module.add_function(
+        "llvm.wasm.memory.size.i32",
+        i32_type.fn_type(&[i32_type.into()], false),
+        None,
+    );
+    let memory_grow = module.add_function(
+        "llvm.wasm.memory.grow.i32",
+        i32_type.fn_type(&[i32_type.into(), i32_type.into()], false),
+        None,
+    );
+
+    let malloc = module.add_function(MALLOC, str_type.fn_type(&[i32_type.into()], false), None);
+    let entry = context.append_basic_block(malloc, "entry");
+    let grow = context.append_basic_block(malloc, "grow");
+    let out_of_memory = context.append_basic_block(malloc, "out_of_memory");
+    let done = context.append_basic_block(malloc, "done");
+
+    // Computed in 64 bits, since the end of the heap may be 4 GiB
+    builder.position_at_end(entry);
+    let next = builder.build_load(i32_type, heap_next.as_pointer_value(), "next").into_int_value();
+    let base = builder.build_ptr_to_int(heap_base.as_pointer_value(), i32_type, "base");
+    let is_first = builder.build_int_compare(IntPredicate::EQ, next, i32_type.const_zero(), "is_first");
+    let start = builder.build_select(is_first, base, next, "start").into_int_value();
+    let start = builder.build_int_z_extend(start, i64_type, "start");
+    let start = builder.build_int_add(start, i64_type.const_int(7, false), "start");
+    let start = builder.build_and(start, i64_type.const_int(-8_i64 as u64, true), "start");
+    let size = malloc.get_nth_param(0).unwrap().into_int_value();
+    let size = builder.build_int_z_extend(size, i64_type, "size");
+    let end = builder.build_int_add(start, size, "end");
+
+    let pages = builder
+        .build_call(memory_size, &[i32_type.const_zero().into()], "pages")
+        .try_as_basic_value()
+        .left()
+        .unwrap()
+        .into_int_value();
+    let pages = builder.build_int_z_extend(pages, i64_type, "pages");
+    let page_size = i64_type.const_int(WASM_PAGE_SIZE, false);
+    let limit = builder.build_int_mul(pages, page_size, "limit");
+    let fits = builder.build_int_compare(IntPredicate::ULE, end, limit, "fits");
+    builder.build_conditional_branch(fits, done, grow);
+
+    builder.position_at_end(grow);
+    let missing = builder.build_int_sub(end, limit, "missing");
+    let missing = builder.build_int_add(missing, i64_type.const_int(WASM_PAGE_SIZE - 1, false), "missing");
+    let missing = builder.build_int_unsigned_div(missing, page_size, "missing");
+    let missing = builder.build_int_truncate(missing, i32_type, "missing");
+    let previous = builder
+        .build_call(memory_grow, &[i32_type.const_zero().into(), missing.into()], "previous")
+        .try_as_basic_value()
+        .left()
+        .unwrap()
+        .into_int_value();
+    let failed = builder.build_int_compare(IntPredicate::EQ, previous, i32_type.const_all_ones(), "failed");
+    builder.build_conditional_branch(failed, out_of_memory, done);
+
+    builder.position_at_end(out_of_memory);
+    builder.build_unreachable();
+
+    builder.position_at_end(done);
+    let end = builder.build_int_truncate(end, i32_type, "end");
+    builder.build_store(heap_next.as_pointer_value(), end);
+    let start = builder.build_int_truncate(start, i32_type, "start");
+    let ptr = builder.build_int_to_ptr(start, str_type, "ptr");
+    builder.build_return(Some(&ptr));
+
+    malloc
+}
+
+/// `aurora_write(fd: i32, data: i8*, len: i32)`, writing `len` bytes with
+/// the WASI `fd_write`. Errors and short writes are ignored.
+fn define_write<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    builder: &Builder<'ctx>,
+    fd_write: FunctionValue<'ctx>,
+) -> FunctionValue<'ctx> {
+    let i32_type = context.i32_type();
+    let str_type = context.i8_type().ptr_type(AddressSpace::default());
+
+    let write = module.add_function(
+        WRITE,
+        context
+            .void_type()
+            .fn_type(&[i32_type.into(), str_type.into(), i32_type.into()], false),
+        None,
+    );
+    builder.position_at_end(context.append_basic_block(write, "entry"));
+
+    // A single `{ buf: i32, buf_len: i32 }` iovec
+    let iovec_type = i32_type.array_type(2);
+    let iovec = builder.build_alloca(iovec_type, "iovec");
+    let data = write.get_nth_param(1).unwrap().into_pointer_value();
+    let fields = [
+        builder.build_ptr_to_int(data, i32_type, "buf"),
+        write.get_nth_param(2).unwrap().into_int_value(),
+    ];
+    for (i, field) in fields.into_iter().enumerate() {
+        let index = [i32_type.const_zero(), i32_type.const_int(i as u64, false)];
+        let ptr = unsafe { builder.build_in_bounds_gep(iovec_type, iovec, &index, "field") };
+        builder.build_store(ptr, field);
+    }
+
+    let written = builder.build_alloca(i32_type, "written");
+    builder.build_call(
+        fd_write,
+        &[
+            write.get_nth_param(0).unwrap().into(),
+            builder.build_ptr_to_int(iovec, i32_type, "iovs").into(),
+            i32_type.const_int(1, false).into(),
+            builder.build_ptr_to_int(written, i32_type, "nwritten").into(),
+        ],
+        "",
+    );
+    builder.build_return(None);
+
+    write
+}
+
+/// `aurora_write_int(fd: i32, value: i64)`, writing `value` in decimal. The
+/// digits are produced backwards into a buffer large enough for `i64::MIN`.
+fn define_write_int<'ctx>(
+    context: &'ctx Context,
+    module: &Module<'ctx>,
+    builder: &Builder<'ctx>,
+    write: FunctionValue<'ctx>,
+) -> FunctionValue<'ctx> {
+    const BUFFER_SIZE: u64 = 20;
+
+    let i8_type = context.i8_type();
+    let i32_type = context.i32_type();
+    let i64_type = context.i64_type();
+
+    let write_int = module.add_function(
+        WRITE_INT,
+        context
+            .void_type()
+            .fn_type(&[i32_type.into(), i64_type.into()], false),
+        None,
+    );
+    let entry = context.append_basic_block(write_int, "entry");
+    let digit = context.append_basic_block(write_int, "digit");
+    let sign = context.append_basic_block(write_int, "sign");
+    let done = context.append_basic_block(write_int, "done");
+
+    builder.position_at_end(entry);
+    let buffer_type = i8_type.array_type(BUFFER_SIZE as u32);
+    let buffer = builder.build_alloca(buffer_type, "buffer");
+    let pos_slot = builder.build_alloca(i64_type, "pos");
+    builder.build_store(pos_slot, i64_type.const_int(BUFFER_SIZE, false));
+
+    // The magnitude is treated as unsigned, which also holds `-i64::MIN`
+    let value = write_int.get_nth_param(1).unwrap().into_int_value();
+    let is_negative = builder.build_int_compare(IntPredicate::SLT, value, i64_type.const_zero(), "is_negative");
+    let negated = builder.build_int_neg(value, "negated");
+    let magnitude = builder.build_select(is_negative, negated, value, "magnitude");
+    let magnitude_slot = builder.build_alloca(i64_type, "magnitude");
+    builder.build_store(magnitude_slot, magnitude);
+    builder.build_unconditional_branch(digit);
+
+    builder.position_at_end(digit);
+    let ten = i64_type.const_int(10, false);
+    let magnitude = builder.build_load(i64_type, magnitude_slot, "magnitude").into_int_value();
+    let remainder = builder.build_int_unsigned_rem(magnitude, ten, "remainder");
+    let remainder = builder.build_int_truncate(remainder, i8_type, "remainder");
+    let character = builder.build_int_add(remainder, i8_type.const_int(b'0' as u64, false), "character");
+    push_byte(builder, context, buffer_type, buffer, pos_slot, character);
+    let magnitude = builder.build_int_unsigned_div(magnitude, ten, "magnitude");
+    builder.build_store(magnitude_slot, magnitude);
+    let more = builder.build_int_compare(IntPredicate::NE, magnitude, i64_type.const_zero(), "more");
+    builder.build_conditional_branch(more, digit, sign);
+
+    builder.position_at_end(sign);
+    let minus = context.append_basic_block(write_int, "minus");
+    builder.build_conditional_branch(is_negative, minus, done);
+    builder.position_at_end(minus);
+    push_byte(builder, context, buffer_type, buffer, pos_slot, i8_type.const_int(b'-' as u64, false));
+    builder.build_unconditional_branch(done);
+
+    builder.position_at_end(done);
+    let pos = builder.build_load(i64_type, pos_slot, "pos").into_int_value();
+    let start = unsafe {
+        builder.build_in_bounds_gep(buffer_type, buffer, &[i64_type.const_zero(), pos], "start")
+    };
+    let len = builder.build_int_sub(i64_type.const_int(BUFFER_SIZE, false), pos, "len");
+    builder.build_call(
+        write,
+        &[
+            write_int.get_nth_param(0).unwrap().into(),
+            start.into(),
+            builder.build_int_truncate(len, i32_type, "len").into(),
+        ],
+        "",
+    );
+    builder.build_return(None);
+
+    write_int
+}
+
+/// Stores `byte` in front of the bytes written to `buffer` so far, whose
+/// start is kept in `pos_slot`.
+fn push_byte<'ctx>(
+    builder: &Builder<'ctx>,
+    context: &'ctx Context,
+    buffer_type: ArrayType<'ctx>,
+    buffer: PointerValue<'ctx>,
+    pos_slot: PointerValue<'ctx>,
+    byte: IntValue<'ctx>,
+) {
+    let i64_type = context.i64_type();
+    let pos = builder.build_load(i64_type, pos_slot, "pos").into_int_value();
+    let pos = builder.build_int_sub(pos, i64_type.const_int(1, false), "pos");
+    builder.build_store(pos_slot, pos);
+    let ptr = unsafe { builder.build_in_bounds_gep(buffer_type, buffer, &[i64_type.const_zero(), pos], "byte") };
+    builder.build_store(ptr, byte);
+}
@@ -0,0 +1,141 @@
+use std::path::Path;
+
+use inkwell::{
+    context::Context,
+    module::{FlagBehavior, Module},
+    targets::{
+        CodeModel, FileType, InitializationConfig, RelocMode, Target, TargetMachine, TargetTriple,
+    },
+    OptimizationLevel,
+};
+
+/// The WebAssembly targets. Their modules are linked by `wasm-ld` into a
+/// `.wasm` module and get a runtime of their own, see
+/// `runtime::define_wasm_runtime_functions`.
+pub const WASM_TARGETS: [&str; 2] = ["wasm32-unknown-unknown", "wasm32-wasi"];
+
+pub fn is_wasm(triple: &str) -> bool {
+    WASM_TARGETS.contains(&triple)
+}
+
+/// Whether `triple` has the WebAssembly System Interface to talk to the
+/// outside world.
+pub fn is_wasi(triple: &str) -> bool {
+    triple == "wasm32-wasi"
+}
+
+/// Initialises the LLVM target for the architecture of `triple`. Only the
+/// architectures Aurora is tested on are supported.
+fn initialize_target(triple: &str) -> Result<(), String> {
+    let config = InitializationConfig::default();
+    let arch = triple.split('-').next().unwrap_or_default();
+
+    match arch {
+        "x86_64" => Target::initialize_x86(&config),
+        "aarch64" => Target::initialize_aarch64(&config),
+        "riscv64" => Target::initialize_riscv(&config),
+        "wasm32" => Target::initialize_webassembly(&config),
+        arch => {
+            return Err(format!(
+                "Unsupported architecture `{}` in target `{}`, expected x86_64, aarch64, riscv64 or wasm32",
+                arch, triple
+            ))
+        }
+    }
+
+    Ok(())
+}
+
+/// A target machine for `triple`, or for the host if there is none, used to
+/// emit object files. The CPU defaults to the host's when compiling for the
+/// host and to the most generic one of the architecture otherwise, so the
+/// code runs on all of them. `features` are enabled, or disabled, on top of
+/// those of the CPU.
+pub fn target_machine(
+    triple: Option<&str>,
+    cpu: Option<&str>,
+    features: &[String],
+    opt_level: OptimizationLevel,
+) -> Result<TargetMachine, String> {
+    let host_cpu = || TargetMachine::get_host_cpu_name().to_string();
+
+    let (triple, default_cpu, mut all_features, reloc_mode) = match triple {
+        None => {
+            Target::initialize_native(&InitializationConfig::default())?;
+            (
+                TargetMachine::get_default_triple(),
+                host_cpu(),
+                vec![TargetMachine::get_host_cpu_features().to_string()],
+                RelocMode::PIC,
+            )
+        }
+        Some(triple) => {
+            initialize_target(triple)?;
+            let features = match triple.split('-').next() {
+                // RV64GC, the baseline Linux distributions require
+                Some("riscv64") => vec!["+m,+a,+f,+d,+c".to_string()],
+                _ => vec![],
+            };
+            let reloc_mode = if is_wasm(triple) {
+                RelocMode::Default
+            } else {
+                RelocMode::PIC
+            };
+            (TargetTriple::create(triple), String::new(), features, reloc_mode)
+        }
+    };
+
+    let cpu = match cpu {
+        Some("native") if triple.to_string() != TargetMachine::get_default_triple().to_string() => {
+            return Err("`--target-cpu native` can only be used when compiling for the host".to_string())
+        }
+        Some("native") => host_cpu(),
+        Some(cpu) => cpu.to_string(),
+        None => default_cpu,
+    };
+    all_features.extend(features.iter().cloned());
+    let features = all_features
+        .iter()
+        .filter(|f| !f.is_empty())
+        .cloned()
+        .collect::<Vec<String>>()
+        .join(",");
+
+    let target = Target::from_triple(&triple).map_err(|e| e.to_string())?;
+    target
+        .create_target_machine(
+            &triple,
+            &cpu,
+            &features,
+            opt_level,
+            reloc_mode,
+            CodeModel::Default,
+        )
+        .ok_or(format!("Can not create a target machine for {}", triple))
+}
+
+/// Sets the triple and data layout of `module` to those of `machine`, before
+/// any code is generated into it.
+pub fn configure_module<'ctx>(context: &'ctx Context, machine: &TargetMachine, module: &Module<'ctx>) {
+    let triple = machine.get_triple();
+    module.set_triple(&triple);
+    module.set_data_layout(&machine.get_target_data().get_data_layout());
+
+    // Linux on RISC-V passes floats in floating-point registers, which LLVM
+    // only does when asked to
+    if triple.to_string().starts_with("riscv64") {
+        module.add_metadata_flag(
+            "target-abi",
+            FlagBehavior::Error,
+            context.metadata_string("lp64d"),
+        );
+    }
+}
+
+/// Writes `module`, configured by `configure_module`, to `path` as an
+/// object file for `machine`.
+pub fn write_object(machine: &TargetMachine, module: &Module, path: &Path) -> Result<(), String> {
+    machine
+        .write_to_file(module, FileType::Object, path)
+        .map_err(|e| e.to_string())
+}
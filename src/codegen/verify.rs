@@ -0,0 +1,35 @@
+use inkwell::{module::Module, values::FunctionValue};
+
+/// The IR generated for `function`, to show what the verifier rejected.
+fn function_ir(function: FunctionValue) -> String {
+    format!(
+        "IR of `{}`:\n{}",
+        function.get_name().to_string_lossy(),
+        function.print_to_string().to_string().trim_end()
+    )
+}
+
+/// Runs the LLVM verifier over a finished module. Code it rejects is a bug
+/// of the code generator rather than of the program, which would otherwise
+/// only show up as a crash of the JIT or of LLVM's code generation. The
+/// functions it rejects are reported with their IR.
+pub fn verify_module(module: &Module) -> Result<(), String> {
+    let reason = match module.verify() {
+        Ok(()) => return Ok(()),
+        Err(reason) => reason.to_string(),
+    };
+
+    let mut message = format!(
+        "LLVM rejected the code generated for module `{}`:\n{}",
+        module.get_name().to_string_lossy(),
+        reason.trim_end()
+    );
+    for function in module.get_functions() {
+        if !function.verify(false) {
+            message.push_str("\n\n");
+            message.push_str(&function_ir(function));
+        }
+    }
+
+    Err(message)
+}
@@ -0,0 +1,774 @@
+use std::{
+    collections::HashMap,
+    fmt,
+};
+
+use crate::{
+    ast::{
+        expressions::{BinaryOperator, Expression, UnaryOperator},
+        statements::Statements,
+        FuncParam, Program, Type,
+    },
+    error::{CompilerError, CompilerErrorKind},
+    sema::typing::{self, bit_width},
+};
+
+/// Calls to `const fn`s may nest this deep before evaluation is abandoned.
+const MAX_CALL_DEPTH: usize = 256;
+/// Iterations a single `while` loop may run for at compile time.
+const MAX_ITERATIONS: usize = 1_000_000;
+
+/// The result of evaluating a constant expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Int(i128),
+    Float(f64),
+    Bool(bool),
+    String(String),
+}
+
+/// Why an expression could not be evaluated. `context` names the constant
+/// or function the expression is in, and `line` the line of the innermost
+/// statement it is in, or 0 if that is not known.
+#[derive(Debug, Clone)]
+pub struct EvalError {
+    pub code: usize,
+    pub message: String,
+    pub context: Option<String>,
+    pub line: usize,
+}
+
+impl EvalError {
+    fn new(code: usize, message: String) -> Self {
+        Self {
+            code,
+            message,
+            context: None,
+            line: 0,
+        }
+    }
+
+    fn within(mut self, context: impl FnOnce() -> String) -> Self {
+        if self.context.is_none() {
+            self.context = Some(context());
+        }
+        self
+    }
+
+    fn at(mut self, line: usize) -> Self {
+        if self.line == 0 {
+            self.line = line;
+        }
+        self
+    }
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.message)?;
+        if let Some(context) = &self.context {
+            write!(f, ", in {}", context)?;
+        }
+        if self.line != 0 {
+            write!(f, " on line {}", self.line)?;
+        }
+        Ok(())
+    }
+}
+
+impl From<EvalError> for CompilerError {
+    fn from(error: EvalError) -> Self {
+        CompilerError {
+            code: error.code,
+            kind: CompilerErrorKind::ConstEvalError,
+            message: error.to_string(),
+        }
+    }
+}
+
+fn not_constant(expr: &Expression, reason: &str) -> EvalError {
+    EvalError::new(
+        601,
+        format!("`{}` can not be evaluated at compile time{}", expr, reason),
+    )
+}
+
+fn mismatched(expr: &Expression, found: &Type, expected: &Type) -> EvalError {
+    EvalError::new(
+        605,
+        format!("`{}` has type {}, expected {}", expr, found, expected),
+    )
+}
+
+/// What running a statement of a `const fn` did.
+enum Flow {
+    Next,
+    Return(Option<(Value, Type)>),
+}
+
+struct ConstFn {
+    params: Vec<FuncParam>,
+    body: Vec<Statements>,
+    return_type: Type,
+}
+
+/// Evaluates constant expressions at compile time: `const` declarations,
+/// the initialisers of globals and, through them, calls to `const fn`s.
+/// Constants may refer to each other in any order, so they are only
+/// evaluated once their value is first needed.
+///
+/// Expressions are evaluated with the types the compiled code would have,
+/// so a value that would overflow its type at runtime is an error instead.
+#[derive(Default)]
+pub struct ConstEvaluator {
+    declarations: HashMap<String, (Type, Expression, usize)>,
+    functions: HashMap<String, ConstFn>,
+    values: HashMap<String, Value>,
+    /// Constants whose value is being evaluated, outermost first, to detect
+    /// cycles
+    evaluating: Vec<String>,
+    /// The error of each constant that depends on its own value, the same
+    /// for every constant of the cycle so that it is reported once
+    cycles: HashMap<String, EvalError>,
+    /// Scopes of local variables of the `const fn` calls being evaluated
+    frames: Vec<Vec<HashMap<String, (Value, Type)>>>,
+}
+
+/// Checks that the constants and global initialisers of `program` can be
+/// evaluated, and that its `const fn`s only use what can be evaluated at
+/// compile time. `imported` are the declarations of its dependencies.
+pub fn check(imported: &[Statements], program: &Program) -> Result<(), Vec<CompilerError>> {
+    let mut evaluator = ConstEvaluator::default();
+    let mut errors: Vec<CompilerError> = vec![];
+
+    for statement in imported.iter().chain(&program.statements) {
+        let result = match statement {
+            Statements::ConstDeclaration { .. } => evaluator.declare(statement),
+            Statements::FunctionDeclaration { is_const: true, .. } => {
+                evaluator.declare_function(statement)
+            }
+            _ => Ok(()),
+        };
+
+        if let Err(error) = result {
+            errors.push(error.into());
+        }
+    }
+
+    for statement in &program.statements {
+        let result = match statement {
+            Statements::ConstDeclaration { name, .. } => evaluator.value(name).map(|_| ()),
+            Statements::VariableDeclaration {
+                name,
+                r#type,
+                value,
+                line,
+            } => evaluator
+                .evaluate(value, r#type)
+                .map(|_| ())
+                .map_err(|e| e.within(|| format!("global `{}`", name)).at(*line)),
+            Statements::FunctionDeclaration {
+                name,
+                body,
+                is_const: true,
+                ..
+            } => evaluator
+                .check_body(body)
+                .map_err(|e| e.within(|| format!("`const fn` `{}`", name))),
+            _ => Ok(()),
+        };
+
+        // A constant depending on its own value fails every constant and
+        // global using it with the same error
+        match result {
+            Err(error) if error.code == 604 && errors.iter().any(|e| e.message == error.to_string()) => {}
+            Err(error) => errors.push(error.into()),
+            Ok(()) => {}
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors)
+    }
+}
+
+impl ConstEvaluator {
+    pub fn declare(&mut self, constant: &Statements) -> Result<(), EvalError> {
+        match constant {
+            Statements::ConstDeclaration {
+                name,
+                r#type,
+                value,
+                line,
+            } => {
+                if self.declarations.contains_key(name) {
+                    return Err(EvalError::new(
+                        607,
+                        format!("constant `{}` is declared more than once", name),
+                    )
+                    .at(*line));
+                }
+
+                self.declarations
+                    .insert(name.clone(), (r#type.clone(), value.clone(), *line));
+                Ok(())
+            }
+            _ => Err(EvalError::new(607, "Expected ConstDecl as input".to_string())),
+        }
+    }
+
+    pub fn declare_function(&mut self, function: &Statements) -> Result<(), EvalError> {
+        match function {
+            Statements::FunctionDeclaration {
+                name,
+                type_params,
+                params,
+                body,
+                return_type,
+                ..
+            } => {
+                if !type_params.is_empty() {
+                    return Err(EvalError::new(
+                        607,
+                        format!("`const fn` `{}` can not have type parameters", name),
+                    ));
+                }
+
+                self.functions.insert(
+                    name.clone(),
+                    ConstFn {
+                        params: params.clone(),
+                        body: body.clone(),
+                        return_type: return_type.clone(),
+                    },
+                );
+                Ok(())
+            }
+            _ => Err(EvalError::new(607, "Expected FunctionDecl as input".to_string())),
+        }
+    }
+
+    pub fn is_constant(&self, name: &str) -> bool {
+        self.declarations.contains_key(name)
+    }
+
+    pub fn type_of(&self, name: &str) -> Option<&Type> {
+        self.declarations.get(name).map(|(r#type, ..)| r#type)
+    }
+
+    /// Value of the constant `name`.
+    pub fn value(&mut self, name: &str) -> Result<Value, EvalError> {
+        if let Some(value) = self.values.get(name) {
+            return Ok(value.clone());
+        }
+
+        let (r#type, expr, line) = self.declarations.get(name).cloned().ok_or(EvalError::new(
+            601,
+            format!("`{}` is not a constant", name),
+        ))?;
+
+        if let Some(error) = self.cycles.get(name) {
+            return Err(error.clone());
+        }
+        if let Some(start) = self.evaluating.iter().position(|n| n == name) {
+            let cycle = self.evaluating[start..].to_vec();
+            let message = match cycle.len() {
+                1 => format!("constant `{}` depends on its own value", name),
+                _ => format!(
+                    "constant `{}` depends on its own value through `{}`",
+                    name,
+                    cycle[1..].join("`, `")
+                ),
+            };
+            let error = EvalError::new(604, message)
+                .within(|| format!("constant `{}`", name))
+                .at(line);
+            for constant in cycle {
+                self.cycles.insert(constant, error.clone());
+            }
+            return Err(error);
+        }
+        self.evaluating.push(name.to_string());
+        // Constants are evaluated outside of any `const fn` call they are
+        // used in, since they can not see its variables
+        let frames = std::mem::take(&mut self.frames);
+        let value = self.evaluate(&expr, &r#type);
+        self.frames = frames;
+        self.evaluating.pop();
+
+        let value = value.map_err(|e| e.within(|| format!("constant `{}`", name)).at(line))?;
+        self.values.insert(name.to_string(), value.clone());
+        Ok(value)
+    }
+
+    /// Evaluates `expr` as a value of type `r#type`.
+    pub fn evaluate(&mut self, expr: &Expression, r#type: &Type) -> Result<Value, EvalError> {
+        let (value, found) = self.expression(expr, Some(r#type))?;
+        convert(expr, value, &found, r#type)
+    }
+
+    fn expression(&mut self, expr: &Expression, hint: Option<&Type>) -> Result<(Value, Type), EvalError> {
+        match expr {
+            Expression::IntLiteral { val } => match hint {
+                Some(r#type) if r#type.is_float() => Ok((Value::Float(*val as f64), r#type.clone())),
+                Some(r#type) if r#type.is_integer() => {
+                    Ok((fit(expr, *val as i128, r#type)?, r#type.clone()))
+                }
+                _ => Ok((fit(expr, *val as i128, &Type::Int32)?, Type::Int32)),
+            },
+            Expression::FloatLiteral { val } => match hint {
+                Some(r#type) if r#type.is_float() => Ok((Value::Float(*val), r#type.clone())),
+                _ => Ok((Value::Float(*val), Type::Float64)),
+            },
+            Expression::BooleanLiteral { val } => Ok((Value::Bool(*val), Type::Boolean)),
+            Expression::StringLiteral { val } => Ok((Value::String(val.clone()), Type::String)),
+            Expression::Identifier { val } => {
+                if let Some(local) = self.local(val) {
+                    return Ok(local.clone());
+                }
+                if self.is_constant(val) {
+                    let r#type = self.type_of(val).unwrap().clone();
+                    return Ok((self.value(val)?, r#type));
+                }
+                Err(not_constant(expr, &format!(", `{}` is not a constant", val)))
+            }
+            Expression::UnaryOperation { op, expr: operand } => {
+                // `-128` is an i8 even though `128` is not
+                if let (UnaryOperator::Negate, Expression::IntLiteral { val }, Some(r#type)) =
+                    (op, operand.as_ref(), hint)
+                {
+                    if r#type.is_integer() {
+                        return Ok((fit(expr, -(*val as i128), r#type)?, r#type.clone()));
+                    }
+                }
+
+                let (value, r#type) = self.expression(operand, hint)?;
+
+                let value = match (op, value) {
+                    (UnaryOperator::Negate, Value::Int(value)) => match value.checked_neg() {
+                        Some(value) => fit(expr, value, &r#type)?,
+                        None => return Err(overflow(expr, &r#type)),
+                    },
+                    (UnaryOperator::Negate, Value::Float(value)) => Value::Float(-value),
+                    (UnaryOperator::Not, Value::Bool(value)) => Value::Bool(!value),
+                    _ => {
+                        return Err(EvalError::new(
+                            605,
+                            format!("`{}` can not be applied to {}", expr, r#type),
+                        ))
+                    }
+                };
+                Ok((value, r#type))
+            }
+            Expression::BinaryOperation { op, lhs, rhs } => {
+                self.binary_operation(expr, *op, lhs, rhs, hint)
+            }
+            Expression::FunctionCall { name, params } => match self.call(expr, name, params)? {
+                Some(result) => Ok(result),
+                None => Err(EvalError::new(
+                    605,
+                    format!("`{}` does not return a value", expr),
+                )),
+            },
+            _ => Err(not_constant(expr, "")),
+        }
+    }
+
+    fn binary_operation(
+        &mut self,
+        expr: &Expression,
+        op: BinaryOperator,
+        lhs: &Expression,
+        rhs: &Expression,
+        hint: Option<&Type>,
+    ) -> Result<(Value, Type), EvalError> {
+        use BinaryOperator::*;
+
+        let (lhs, lhs_type) = self.expression(lhs, typing::operand_hint(op, hint))?;
+        let (rhs, rhs_type) = self.expression(rhs, Some(&lhs_type))?;
+        let r#type = typing::operand_type(op, &lhs_type, &rhs_type)
+            .map_err(|message| EvalError::new(605, format!("{} in `{}`", message, expr)))?;
+        let result_type = typing::binary_type(op, &r#type)
+            .map_err(|message| EvalError::new(605, format!("{} in `{}`", message, expr)))?;
+
+        let value = match (lhs, rhs) {
+            (Value::Int(lhs), Value::Int(rhs)) => {
+                let result = match op {
+                    Divide | Modulo if rhs == 0 => {
+                        return Err(EvalError::new(603, format!("`{}` divides by zero", expr)))
+                    }
+                    Add => lhs.checked_add(rhs),
+                    Subtract => lhs.checked_sub(rhs),
+                    Multiply => lhs.checked_mul(rhs),
+                    Divide => lhs.checked_div(rhs),
+                    Modulo => lhs.checked_rem(rhs),
+                    op => return Ok((Value::Bool(compare(op, lhs.cmp(&rhs))), result_type)),
+                };
+
+                match result {
+                    Some(value) => fit(expr, value, &r#type)?,
+                    None => return Err(overflow(expr, &r#type)),
+                }
+            }
+            (Value::Float(lhs), Value::Float(rhs)) => match op {
+                Add => Value::Float(lhs + rhs),
+                Subtract => Value::Float(lhs - rhs),
+                Multiply => Value::Float(lhs * rhs),
+                Divide => Value::Float(lhs / rhs),
+                Modulo => Value::Float(lhs % rhs),
+                // Every comparison with NaN is false, except `!=`
+                op => Value::Bool(match lhs.partial_cmp(&rhs) {
+                    Some(ordering) => compare(op, ordering),
+                    None => op == NotEqual,
+                }),
+            },
+            (Value::String(lhs), Value::String(rhs)) if op == Add => Value::String(lhs + &rhs),
+            (Value::String(lhs), Value::String(rhs)) => Value::Bool(compare(op, lhs.cmp(&rhs))),
+            (Value::Bool(lhs), Value::Bool(rhs)) => Value::Bool(compare(op, lhs.cmp(&rhs))),
+            _ => unreachable!("`typing::binary_type` accepted {} for `{}`", r#type, op),
+        };
+
+        Ok((value, result_type))
+    }
+
+    /// `i32(x)` and the like, which wrap around like the compiled code.
+    fn conversion(
+        &mut self,
+        name: &str,
+        r#type: &Type,
+        args: &[Expression],
+    ) -> Result<(Value, Type), EvalError> {
+        if args.len() != 1 {
+            return Err(EvalError::new(
+                605,
+                format!("`{}` takes 1 argument but {} were supplied", name, args.len()),
+            ));
+        }
+
+        match self.expression(&args[0], Some(r#type))? {
+            (Value::Int(value), _) => Ok((Value::Int(typing::wrap(value, r#type)), r#type.clone())),
+            (_, from) => Err(EvalError::new(
+                605,
+                format!("`{}` converts integers, not {}", name, from),
+            )),
+        }
+    }
+
+    /// Calls the `const fn` `name`. Returns `None` for functions without a
+    /// return value.
+    fn call(
+        &mut self,
+        expr: &Expression,
+        name: &str,
+        args: &[Expression],
+    ) -> Result<Option<(Value, Type)>, EvalError> {
+        if let Some(r#type) = typing::conversion_type(name) {
+            return self.conversion(name, &r#type, args).map(Some);
+        }
+
+        let function = match self.functions.get(name) {
+            Some(function) => (function.params.clone(), function.return_type.clone()),
+            None => {
+                return Err(not_constant(
+                    expr,
+                    &format!(", `{}` is not a `const fn`", name),
+                ))
+            }
+        };
+        let (params, return_type) = function;
+
+        if params.len() != args.len() {
+            return Err(EvalError::new(
+                605,
+                format!(
+                    "`{}` takes {} arguments but {} were supplied",
+                    name,
+                    params.len(),
+                    args.len()
+                ),
+            ));
+        }
+        if self.frames.len() >= MAX_CALL_DEPTH {
+            return Err(EvalError::new(
+                606,
+                format!(
+                    "`{}` nests `const fn` calls more than {} deep",
+                    expr, MAX_CALL_DEPTH
+                ),
+            ));
+        }
+
+        let mut locals = HashMap::new();
+        for (arg, param) in args.iter().zip(&params) {
+            let value = self.evaluate(arg, &param.r#type)?;
+            locals.insert(param.name.clone(), (value, param.r#type.clone()));
+        }
+
+        let body = self.functions[name].body.clone();
+        self.frames.push(vec![locals]);
+        let flow = self.block(&body);
+        self.frames.pop();
+
+        let within = || format!("`const fn` `{}`", name);
+        match (flow.map_err(|e| e.within(within))?, return_type) {
+            (Flow::Return(None), Type::Void) | (Flow::Next, Type::Void) => Ok(None),
+            (Flow::Return(Some((value, found))), return_type) => {
+                let value = convert(expr, value, &found, &return_type)?;
+                Ok(Some((value, return_type)))
+            }
+            (_, return_type) => Err(EvalError::new(
+                605,
+                format!("`{}` ended without returning a {}", expr, return_type),
+            )),
+        }
+    }
+
+    fn local(&self, name: &str) -> Option<&(Value, Type)> {
+        self.frames
+            .last()?
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+    }
+
+    fn block(&mut self, block: &[Statements]) -> Result<Flow, EvalError> {
+        self.frames.last_mut().unwrap().push(HashMap::new());
+        let mut flow = Ok(Flow::Next);
+
+        for statement in block {
+            flow = self
+                .statement(statement)
+                .map_err(|e| e.at(statement.line().unwrap_or(0)));
+            if !matches!(flow, Ok(Flow::Next)) {
+                break;
+            }
+        }
+
+        self.frames.last_mut().unwrap().pop();
+        flow
+    }
+
+    fn statement(&mut self, statement: &Statements) -> Result<Flow, EvalError> {
+        match statement {
+            Statements::VariableDeclaration {
+                name,
+                value,
+                r#type,
+                ..
+            } => {
+                let value = self.evaluate(value, r#type)?;
+                let scope = self.frames.last_mut().unwrap().last_mut().unwrap();
+                scope.insert(name.clone(), (value, r#type.clone()));
+            }
+            Statements::Assignment {
+                target: target @ Expression::Identifier { val },
+                value,
+                ..
+            } => {
+                let r#type = match self.local(val) {
+                    Some((_, r#type)) => r#type.clone(),
+                    None => return Err(not_constant(target, ", only local variables can be assigned")),
+                };
+                let value = self.evaluate(value, &r#type)?;
+
+                let local = self
+                    .frames
+                    .last_mut()
+                    .unwrap()
+                    .iter_mut()
+                    .rev()
+                    .find_map(|scope| scope.get_mut(val))
+                    .unwrap();
+                local.0 = value;
+            }
+            Statements::Assignment { target, .. } => {
+                return Err(not_constant(target, ", only local variables can be assigned"))
+            }
+            Statements::Return { value, .. } => {
+                let value = match value {
+                    Some(value) => Some(self.expression(value, None)?),
+                    None => None,
+                };
+                return Ok(Flow::Return(value));
+            }
+            Statements::If {
+                condition,
+                body,
+                else_body,
+                ..
+            } => {
+                return match self.condition(condition)? {
+                    true => self.block(body),
+                    false => self.block(else_body),
+                };
+            }
+            Statements::While { condition, body, .. } => {
+                let mut iterations = 0;
+
+                while self.condition(condition)? {
+                    iterations += 1;
+                    if iterations > MAX_ITERATIONS {
+                        return Err(EvalError::new(
+                            606,
+                            format!(
+                                "`while {}` runs for more than {} iterations",
+                                condition, MAX_ITERATIONS
+                            ),
+                        ));
+                    }
+
+                    if let Flow::Return(value) = self.block(body)? {
+                        return Ok(Flow::Return(value));
+                    }
+                }
+            }
+            Statements::ExpressionStatement {
+                expr: expr @ Expression::FunctionCall { name, params },
+                ..
+            } => {
+                self.call(expr, name, params)?;
+            }
+            Statements::ExpressionStatement { expr, .. } => return Err(not_constant(expr, "")),
+            _ => {
+                return Err(EvalError::new(
+                    601,
+                    "only `let`, assignments, `if`, `while` and `return` can be evaluated at compile time"
+                        .to_string(),
+                ))
+            }
+        }
+
+        Ok(Flow::Next)
+    }
+
+    fn condition(&mut self, condition: &Expression) -> Result<bool, EvalError> {
+        match self.expression(condition, Some(&Type::Boolean))? {
+            (Value::Bool(value), _) => Ok(value),
+            (_, r#type) => Err(mismatched(condition, &r#type, &Type::Boolean)),
+        }
+    }
+
+    /// Checks that a `const fn` body only calls other `const fn`s and
+    /// only uses expressions that can be evaluated, without running it.
+    fn check_body(&self, body: &[Statements]) -> Result<(), EvalError> {
+        for statement in body {
+            self.check_statement(statement)
+                .map_err(|e| e.at(statement.line().unwrap_or(0)))?;
+        }
+
+        Ok(())
+    }
+
+    fn check_statement(&self, statement: &Statements) -> Result<(), EvalError> {
+        match statement {
+            Statements::VariableDeclaration { value, .. } => self.check_expression(value)?,
+            Statements::Assignment { target, value, .. } => {
+                if !matches!(target, Expression::Identifier { .. }) {
+                    return Err(not_constant(target, ", only local variables can be assigned"));
+                }
+                self.check_expression(value)?;
+            }
+            Statements::Return { value, .. } => {
+                if let Some(value) = value {
+                    self.check_expression(value)?;
+                }
+            }
+            Statements::If {
+                condition,
+                body,
+                else_body,
+                ..
+            } => {
+                self.check_expression(condition)?;
+                self.check_body(body)?;
+                self.check_body(else_body)?;
+            }
+            Statements::While { condition, body, .. } => {
+                self.check_expression(condition)?;
+                self.check_body(body)?;
+            }
+            Statements::ExpressionStatement {
+                expr: expr @ Expression::FunctionCall { .. },
+                ..
+            } => self.check_expression(expr)?,
+            Statements::ExpressionStatement { expr, .. } => return Err(not_constant(expr, "")),
+            _ => {
+                return Err(EvalError::new(
+                    601,
+                    "only `let`, assignments, `if`, `while` and `return` can be evaluated at compile time"
+                        .to_string(),
+                ))
+            }
+        }
+
+        Ok(())
+    }
+
+    fn check_expression(&self, expr: &Expression) -> Result<(), EvalError> {
+        match expr {
+            Expression::IntLiteral { .. }
+            | Expression::FloatLiteral { .. }
+            | Expression::BooleanLiteral { .. }
+            | Expression::StringLiteral { .. }
+            | Expression::Identifier { .. } => Ok(()),
+            Expression::UnaryOperation { expr, .. } => self.check_expression(expr),
+            Expression::BinaryOperation { lhs, rhs, .. } => {
+                self.check_expression(lhs)?;
+                self.check_expression(rhs)
+            }
+            Expression::FunctionCall { name, params } => {
+                if !self.functions.contains_key(name) && typing::conversion_type(name).is_none() {
+                    return Err(not_constant(
+                        expr,
+                        &format!(", `{}` is not a `const fn`", name),
+                    ));
+                }
+                params.iter().try_for_each(|p| self.check_expression(p))
+            }
+            _ => Err(not_constant(expr, "")),
+        }
+    }
+}
+
+fn compare(op: BinaryOperator, ordering: std::cmp::Ordering) -> bool {
+    match op {
+        BinaryOperator::Equal => ordering.is_eq(),
+        BinaryOperator::NotEqual => ordering.is_ne(),
+        BinaryOperator::LessThan => ordering.is_lt(),
+        BinaryOperator::LessThanOrEqual => ordering.is_le(),
+        BinaryOperator::GreaterThan => ordering.is_gt(),
+        BinaryOperator::GreaterThanOrEqual => ordering.is_ge(),
+        op => unreachable!("{:?} is not a comparison", op),
+    }
+}
+
+fn overflow(expr: &Expression, r#type: &Type) -> EvalError {
+    EvalError::new(602, format!("`{}` overflows {}", expr, r#type))
+}
+
+/// `value` as a value of the integer type `r#type`, if it is in range.
+fn fit(expr: &Expression, value: i128, r#type: &Type) -> Result<Value, EvalError> {
+    let bits = bit_width(r#type);
+    let (min, max) = if bits == 128 {
+        (i128::MIN, i128::MAX)
+    } else {
+        (-(1 << (bits - 1)), (1 << (bits - 1)) - 1)
+    };
+
+    if value < min || value > max {
+        return Err(overflow(expr, r#type));
+    }
+    Ok(Value::Int(value))
+}
+
+/// Converts `value` of type `from` to `to` where the compiled code would.
+/// Integers are only widened, so they always fit.
+fn convert(expr: &Expression, value: Value, from: &Type, to: &Type) -> Result<Value, EvalError> {
+    match value {
+        _ if from == to => Ok(value),
+        Value::Int(value) if typing::coercible(from, to) && to.is_integer() => Ok(Value::Int(value)),
+        _ => Err(mismatched(expr, from, to)),
+    }
+}
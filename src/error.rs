@@ -1,18 +1,36 @@
+use std::fmt;
+
 use crate::lexer::Lexer;
 
 #[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
 pub enum CompilerErrorKind {
     // ParserError,
     CommandLineError,
+    ModuleError,
+    SemanticError,
+    BuildError,
+    PackageError,
+    ConstEvalError,
+    RuntimeError,
+    InternalCompilerError,
 }
 
 #[derive(Debug)]
 pub struct CompilerError {
     pub code: usize,
+    // Only read through Debug, when `main` returns the error
+    #[allow(dead_code)]
     pub kind: CompilerErrorKind,
     pub message: String,
 }
 
+impl fmt::Display for CompilerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "error[AUR{:04}]: {}", self.code, self.message)
+    }
+}
+
 pub fn print_error(error: &str, lexer: &Lexer) -> String {
     format!("{} {}:{}", error, lexer.line, lexer.read_position)
-}
\ No newline at end of file
+}
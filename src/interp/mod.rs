@@ -0,0 +1,1102 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    io::{self, Write},
+    process,
+    rc::Rc,
+    thread,
+};
+
+use crate::{
+    ast::{
+        expressions::{BinaryOperator, Expression, MatchArm, Pattern, UnaryOperator},
+        impl_functions, mangle_method,
+        statements::Statements,
+        EnumVariant, Program, StructField, Type, TypeParam,
+    },
+    consteval::{self, ConstEvaluator},
+    sema::typing::{self, wrap, Inference},
+    utils::{float_digits, format_float, format_template},
+};
+
+mod value;
+
+use value::{round, Value};
+
+/// Calls may nest this deep before the program is stopped, instead of
+/// overflowing the stack of the compiler.
+const MAX_CALL_DEPTH: usize = 1000;
+
+/// Stack size of the thread programs run on. Every call of the program
+/// takes several frames of the interpreter, more than fit on the main
+/// thread at `MAX_CALL_DEPTH`.
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+/// Runs `main` of `program`, if it has one, and returns its result.
+pub fn run(program: Program) -> Result<Option<String>, String> {
+    let thread = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || {
+            let mut interpreter = Interpreter::new(&program)?;
+            Ok(interpreter.run_main()?.map(|value| value.to_string()))
+        })
+        .map_err(|e| format!("Can not start the interpreter: {}", e))?;
+
+    // A panic of the interpreter itself has already been reported
+    thread.join().unwrap_or_else(|_| process::exit(101))
+}
+
+/// Stops the program the way the runtime of compiled programs does.
+fn panic(message: String) -> ! {
+    eprintln!("panic: {}", message);
+    process::exit(101);
+}
+
+/// What running a statement did.
+enum Flow {
+    Next,
+    Return(Option<Value>),
+}
+
+/// Where an assignable expression is stored: a variable or a temporary,
+/// followed by the fields and elements to go through.
+enum Root {
+    Local(String),
+    Global(String),
+    Temporary(Value),
+}
+
+enum Step {
+    Field(String),
+    Index(i128),
+}
+
+/// Runs a program directly instead of compiling it, so programs can be run
+/// without LLVM. The program has to have passed `sema::check`, which
+/// reports the errors the backends would. Values behave like those of
+/// compiled code: integers wrap around, fixed arrays and structs are copied
+/// and dynamic arrays share their elements, and divisions without a result
+/// panic. Of the C functions, only those the standard library uses can be
+/// called.
+pub struct Interpreter {
+    /// Functions and generic functions by name, and methods by their
+    /// mangled name
+    functions: HashMap<String, Rc<Statements>>,
+    structs: HashMap<String, (Vec<TypeParam>, Vec<StructField>)>,
+    enums: HashMap<String, Vec<EnumVariant>>,
+    constants: ConstEvaluator,
+    globals: HashMap<String, Value>,
+    /// Local variables of the function being run, innermost scope last
+    scopes: Vec<HashMap<String, Value>>,
+    /// Type arguments of the generic function being run
+    type_bindings: HashMap<String, Type>,
+    return_type: Type,
+    depth: usize,
+}
+
+impl Interpreter {
+    pub fn new(program: &Program) -> Result<Self, String> {
+        let mut interpreter = Self {
+            functions: HashMap::new(),
+            structs: HashMap::new(),
+            enums: HashMap::new(),
+            constants: ConstEvaluator::default(),
+            globals: HashMap::new(),
+            scopes: vec![],
+            type_bindings: HashMap::new(),
+            return_type: Type::Void,
+            depth: 0,
+        };
+
+        for statement in &program.statements {
+            match statement {
+                Statements::FunctionDeclaration { name, is_const, .. } => {
+                    if *is_const {
+                        interpreter
+                            .constants
+                            .declare_function(statement)
+                            .map_err(|e| e.to_string())?;
+                    }
+                    interpreter
+                        .functions
+                        .insert(name.clone(), Rc::new(statement.clone()));
+                }
+                Statements::ExternFunctionDeclaration { name, .. } => {
+                    interpreter
+                        .functions
+                        .insert(name.clone(), Rc::new(statement.clone()));
+                }
+                Statements::ImplDeclaration { .. } => {
+                    for method in impl_functions(statement)? {
+                        if let Statements::FunctionDeclaration { name, .. } = &method {
+                            interpreter.functions.insert(name.clone(), Rc::new(method.clone()));
+                        }
+                    }
+                }
+                Statements::StructDeclaration {
+                    name,
+                    type_params,
+                    fields,
+                } => {
+                    interpreter
+                        .structs
+                        .insert(name.clone(), (type_params.clone(), fields.clone()));
+                }
+                Statements::EnumDeclaration { name, variants } => {
+                    interpreter.enums.insert(name.clone(), variants.clone());
+                }
+                Statements::ConstDeclaration { .. } => interpreter
+                    .constants
+                    .declare(statement)
+                    .map_err(|e| e.to_string())?,
+                _ => {}
+            }
+        }
+
+        // Globals are initialised before `main` runs, like those of
+        // compiled programs are by the loader
+        for statement in &program.statements {
+            if let Statements::VariableDeclaration {
+                name,
+                r#type,
+                value,
+                ..
+            } = statement
+            {
+                let value = interpreter
+                    .constants
+                    .evaluate(value, r#type)
+                    .map_err(|e| format!("Initialiser of global `{}` is not a constant: {}", name, e))?;
+                interpreter
+                    .globals
+                    .insert(name.clone(), from_constant(value, r#type));
+            }
+        }
+
+        Ok(interpreter)
+    }
+
+    /// Runs `main`, if the program has one, and returns its result.
+    pub fn run_main(&mut self) -> Result<Option<Value>, String> {
+        if !self.functions.contains_key("main") {
+            return Ok(None);
+        }
+
+        self.call("main", &[], None)
+    }
+
+    fn resolve_type(&self, r#type: &Type) -> Type {
+        r#type.substitute(&self.type_bindings)
+    }
+
+    fn is_constant(&self, name: &str) -> bool {
+        self.constants.is_constant(name) && !self.scopes.iter().any(|scope| scope.contains_key(name))
+    }
+
+    fn variable(&self, name: &str) -> Option<&Value> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .or_else(|| self.globals.get(name))
+    }
+
+    fn lookup_variable(&self, name: &str) -> Result<Value, String> {
+        self.variable(name)
+            .cloned()
+            .ok_or(format!("Unknown variable `{}`", name))
+    }
+
+    /// The function `name` as a value, unless a variable hides it.
+    fn function_value(&self, name: &str) -> Option<Value> {
+        if self.variable(name).is_some() {
+            return None;
+        }
+        match self.functions.get(name)?.as_ref() {
+            Statements::FunctionDeclaration {
+                params, return_type, ..
+            } => Some(Value::Function(
+                name.to_string(),
+                Type::Function {
+                    params: params.iter().map(|p| p.r#type.clone()).collect(),
+                    return_type: Box::new(return_type.clone()),
+                },
+            )),
+            _ => None,
+        }
+    }
+
+    fn block(&mut self, block: &[Statements]) -> Result<Flow, String> {
+        self.scopes.push(HashMap::new());
+        let mut flow = Ok(Flow::Next);
+
+        for statement in block {
+            flow = self.statement(statement);
+            if !matches!(flow, Ok(Flow::Next)) {
+                break;
+            }
+        }
+
+        self.scopes.pop();
+        flow
+    }
+
+    fn statement(&mut self, statement: &Statements) -> Result<Flow, String> {
+        match statement {
+            Statements::VariableDeclaration {
+                name,
+                value,
+                r#type,
+                ..
+            } => {
+                let r#type = self.resolve_type(r#type);
+                let value = self.value_as(value, &r#type)?;
+                self.scopes.last_mut().unwrap().insert(name.clone(), value);
+            }
+            Statements::Assignment { target, value, .. } => {
+                let (mut root, steps) = self.place(target)?;
+                let r#type = self.with_place(&mut root, &steps, |place| place.r#type())?;
+                let value = self.value_as(value, &r#type)?;
+                self.with_place(&mut root, &steps, |place| *place = value)?;
+            }
+            Statements::Return { value, .. } => {
+                let value = match value {
+                    Some(value) => {
+                        let return_type = self.return_type.clone();
+                        Some(self.value_as(value, &return_type)?)
+                    }
+                    None if self.return_type == Type::Void => None,
+                    None => return Err(format!("Expected a return value of type {}", self.return_type)),
+                };
+                return Ok(Flow::Return(value));
+            }
+            Statements::If {
+                condition,
+                body,
+                else_body,
+                ..
+            } => {
+                return match self.condition(condition)? {
+                    true => self.block(body),
+                    false => self.block(else_body),
+                };
+            }
+            Statements::While { condition, body, .. } => {
+                while self.condition(condition)? {
+                    if let Flow::Return(value) = self.block(body)? {
+                        return Ok(Flow::Return(value));
+                    }
+                }
+            }
+            Statements::ExpressionStatement { expr, .. } => {
+                let hint = match expr {
+                    Expression::Match { .. } => Some(&Type::Void),
+                    _ => None,
+                };
+                self.evaluate(expr, hint)?;
+            }
+            Statements::FunctionDeclaration { .. } => {
+                return Err("Nested functions are not supported".to_string())
+            }
+            Statements::ExternFunctionDeclaration { .. } => {
+                return Err("Extern functions must be declared at the top level".to_string())
+            }
+            Statements::StructDeclaration { .. } => {
+                return Err("Structs must be declared at the top level".to_string())
+            }
+            Statements::EnumDeclaration { .. } => {
+                return Err("Enums must be declared at the top level".to_string())
+            }
+            Statements::ImplDeclaration { .. } => {
+                return Err("Impl blocks must be at the top level".to_string())
+            }
+            Statements::TraitDeclaration { .. } => {
+                return Err("Traits must be declared at the top level".to_string())
+            }
+            Statements::ConstDeclaration { .. } => {
+                return Err("Constants must be declared at the top level".to_string())
+            }
+            Statements::ModuleDeclaration { .. } => {
+                return Err("Module declarations must be at the top of the file".to_string())
+            }
+            Statements::Import { .. } => {
+                return Err("Imports must be at the top of the file".to_string())
+            }
+        }
+
+        Ok(Flow::Next)
+    }
+
+    fn condition(&mut self, condition: &Expression) -> Result<bool, String> {
+        match self.value(condition, Some(&Type::Boolean))? {
+            Value::Bool(value) => Ok(value),
+            value => Err(format!(
+                "Expected a condition of type bool, found {}",
+                value.r#type()
+            )),
+        }
+    }
+
+    /// Evaluates `expr` and converts the result to `r#type`.
+    fn value_as(&mut self, expr: &Expression, r#type: &Type) -> Result<Value, String> {
+        let value = self.value(expr, Some(r#type))?;
+        coerce(value, r#type)
+    }
+
+    /// Evaluates an expression that has to produce a value.
+    fn value(&mut self, expr: &Expression, hint: Option<&Type>) -> Result<Value, String> {
+        self.evaluate(expr, hint)?
+            .ok_or(format!("`{}` does not return a value", expr))
+    }
+
+    /// Evaluates an expression, which has no value if it calls a function
+    /// without a return value.
+    fn evaluate(&mut self, expr: &Expression, hint: Option<&Type>) -> Result<Option<Value>, String> {
+        let value = match expr {
+            Expression::StringLiteral { val } => Value::String(val.as_str().into()),
+            Expression::IntLiteral { val } => match hint {
+                Some(r#type) if r#type.is_integer() => Value::Int(wrap(*val as i128, r#type), r#type.clone()),
+                Some(r#type) if r#type.is_float() => Value::Float(round(*val as f64, r#type), r#type.clone()),
+                _ => Value::Int(wrap(*val as i128, &Type::Int32), Type::Int32),
+            },
+            Expression::FloatLiteral { val } => match hint {
+                Some(r#type) if r#type.is_float() => Value::Float(round(*val, r#type), r#type.clone()),
+                _ => Value::Float(*val, Type::Float64),
+            },
+            Expression::BooleanLiteral { val } => Value::Bool(*val),
+            Expression::Identifier { val } if self.is_constant(val) => {
+                let r#type = self.constants.type_of(val).unwrap().clone();
+                let value = self.constants.value(val).map_err(|e| e.to_string())?;
+                from_constant(value, &r#type)
+            }
+            Expression::Identifier { val } => match self.function_value(val) {
+                Some(function) => function,
+                None => self.lookup_variable(val)?,
+            },
+            Expression::FunctionCall { name, params } => return self.call(name, params, hint),
+            Expression::MethodCall {
+                receiver,
+                method,
+                params,
+            } => return self.call_method(receiver, method, params),
+            Expression::ArrayLiteral { elements } => self.array_literal(elements, hint)?,
+            Expression::StructLiteral { name, fields } => self.struct_literal(name, fields, hint)?,
+            Expression::EnumVariant {
+                enum_name,
+                variant,
+                values,
+            } => self.enum_variant(enum_name, variant, values)?,
+            Expression::Match { expr, arms } => return self.r#match(expr, arms, hint),
+            Expression::FieldAccess { .. } | Expression::Index { .. } => {
+                let (mut root, steps) = self.place_or_temporary(expr)?;
+                self.with_place(&mut root, &steps, |place| place.clone())?
+            }
+            Expression::BinaryOperation { op, lhs, rhs } => self.binary_operation(*op, lhs, rhs, hint)?,
+            Expression::UnaryOperation { op, expr } => match (op, self.value(expr, hint)?) {
+                (UnaryOperator::Negate, Value::Int(value, r#type)) => {
+                    Value::Int(wrap(value.wrapping_neg(), &r#type), r#type)
+                }
+                (UnaryOperator::Negate, Value::Float(value, r#type)) => Value::Float(-value, r#type),
+                (UnaryOperator::Not, Value::Bool(value)) => Value::Bool(!value),
+                (op, value) => return Err(format!("Can not apply {:?} to {}", op, value.r#type())),
+            },
+        };
+
+        Ok(Some(value))
+    }
+
+    fn binary_operation(
+        &mut self,
+        op: BinaryOperator,
+        lhs: &Expression,
+        rhs: &Expression,
+        hint: Option<&Type>,
+    ) -> Result<Value, String> {
+        use BinaryOperator::*;
+
+        let is_comparison = typing::is_comparison(op);
+
+        let lhs = self.value(lhs, typing::operand_hint(op, hint))?;
+        let rhs = self.value(rhs, Some(&lhs.r#type()))?;
+
+        let r#type = typing::operand_type(op, &lhs.r#type(), &rhs.r#type())?;
+        let (lhs, rhs) = (coerce(lhs, &r#type)?, coerce(rhs, &r#type)?);
+
+        let value = match (lhs, rhs) {
+            (Value::Int(lhs, r#type), Value::Int(rhs, _)) => {
+                let min = i128::MIN >> (128 - typing::bit_width(&r#type));
+                let value = match op {
+                    Divide | Modulo if rhs == 0 => panic("attempt to divide by zero".to_string()),
+                    Divide | Modulo if lhs == min && rhs == -1 => {
+                        panic("attempt to divide with overflow".to_string())
+                    }
+                    Add => lhs.wrapping_add(rhs),
+                    Subtract => lhs.wrapping_sub(rhs),
+                    Multiply => lhs.wrapping_mul(rhs),
+                    Divide => lhs.wrapping_div(rhs),
+                    Modulo => lhs.wrapping_rem(rhs),
+                    op => return Ok(Value::Bool(compare(op, lhs.partial_cmp(&rhs)))),
+                };
+                Value::Int(wrap(value, &r#type), r#type)
+            }
+            (Value::Float(lhs, r#type), Value::Float(rhs, _)) => {
+                let value = match op {
+                    Add => lhs + rhs,
+                    Subtract => lhs - rhs,
+                    Multiply => lhs * rhs,
+                    Divide => lhs / rhs,
+                    Modulo => lhs % rhs,
+                    op => return Ok(Value::Bool(compare(op, lhs.partial_cmp(&rhs)))),
+                };
+                Value::Float(round(value, &r#type), r#type)
+            }
+            // Like in constant expressions, strings can be concatenated and
+            // compared
+            (Value::String(lhs), Value::String(rhs)) if op == Add => {
+                Value::String(format!("{}{}", lhs, rhs).into())
+            }
+            (Value::String(lhs), Value::String(rhs)) if matches!(op, Equal | NotEqual) => {
+                Value::Bool(compare(op, lhs.partial_cmp(&rhs)))
+            }
+            (Value::Bool(lhs), Value::Bool(rhs)) if is_comparison => {
+                Value::Bool(compare(op, lhs.partial_cmp(&rhs)))
+            }
+            (lhs, _) => return Err(format!("Can not apply {:?} to {}", op, lhs.r#type())),
+        };
+
+        Ok(value)
+    }
+
+    /// Calls the function `name`. The type arguments of generic functions
+    /// are inferred from the arguments in order and then from `hint`, like
+    /// the compiler does.
+    fn call(&mut self, name: &str, params: &[Expression], hint: Option<&Type>) -> Result<Option<Value>, String> {
+        // Variables holding functions hide the functions of the same name
+        let name = match self.variable(name) {
+            Some(Value::Function(function, _)) => function.clone(),
+            _ => name.to_string(),
+        };
+        let name = name.as_str();
+        if name == "len" {
+            return self.len(params).map(Some);
+        }
+        if name == "format" {
+            return self.format(params).map(Some);
+        }
+        if name == "resize" {
+            return self.resize(params).map(Some);
+        }
+        if let Some(r#type) = typing::conversion_type(name) {
+            return self.conversion(name, &r#type, params).map(Some);
+        }
+
+        let function = self
+            .functions
+            .get(name)
+            .cloned()
+            .ok_or(format!("Unknown function `{}`", name))?;
+        let (fn_params, is_extern) = match function.as_ref() {
+            Statements::FunctionDeclaration { params, .. } => (params, false),
+            Statements::ExternFunctionDeclaration { params, .. } => (params, true),
+            _ => return Err("Expected FunctionDecl as input".to_string()),
+        };
+
+        if fn_params.len() != params.len() {
+            return Err(format!(
+                "`{}` takes {} arguments but {} were supplied",
+                name,
+                fn_params.len(),
+                params.len()
+            ));
+        }
+
+        if is_extern {
+            if !RUNTIME_FUNCTIONS.contains(&name) {
+                return Err(cannot_call_c(name));
+            }
+            let args = params
+                .iter()
+                .zip(fn_params)
+                .map(|(param, fn_param)| self.value_as(param, &fn_param.r#type))
+                .collect::<Result<Vec<Value>, String>>()?;
+            return call_runtime(name, &args);
+        }
+
+        let (type_params, return_type) = match function.as_ref() {
+            Statements::FunctionDeclaration {
+                type_params,
+                return_type,
+                ..
+            } => (type_params, return_type),
+            _ => return Err("Expected FunctionDecl as input".to_string()),
+        };
+
+        let mut inference = Inference::new(name, type_params);
+        let mut args: Vec<Value> = vec![];
+        for (param, fn_param) in params.iter().zip(fn_params.iter()) {
+            match inference.expected(&fn_param.r#type) {
+                Some(param_type) => args.push(self.value_as(param, &param_type)?),
+                None => {
+                    let value = self.value(param, None)?;
+                    inference.unify(&fn_param.r#type, &value.r#type())?;
+                    args.push(value);
+                }
+            }
+        }
+        inference.expect_result(return_type, hint);
+
+        self.invoke(&function, args, inference.bindings().clone())
+    }
+
+    /// `receiver.method(params)` calls the method of the receiver's type with
+    /// the receiver as its `self` argument.
+    fn call_method(
+        &mut self,
+        receiver: &Expression,
+        method: &str,
+        params: &[Expression],
+    ) -> Result<Option<Value>, String> {
+        let receiver = self.value(receiver, None)?;
+        let receiver_type = receiver.r#type();
+        let name = mangle_method(&receiver_type, method);
+
+        let function = self
+            .functions
+            .get(&name)
+            .cloned()
+            .ok_or(format!("{} has no method `{}`", receiver_type, method))?;
+        let fn_params = match function.as_ref() {
+            Statements::FunctionDeclaration { params, .. } => params,
+            _ => return Err("Expected FunctionDecl as input".to_string()),
+        };
+
+        if fn_params.first().map(|p| &p.r#type) != Some(&receiver_type) {
+            return Err(format!(
+                "`{}` of {} can not be called as a method, it takes no `self`",
+                method, receiver_type
+            ));
+        }
+        if fn_params.len() != params.len() + 1 {
+            return Err(format!(
+                "`{}` takes {} arguments but {} were supplied",
+                method,
+                fn_params.len() - 1,
+                params.len()
+            ));
+        }
+
+        let mut args = vec![receiver];
+        for (param, fn_param) in params.iter().zip(fn_params[1..].iter()) {
+            args.push(self.value_as(param, &fn_param.r#type)?);
+        }
+
+        self.invoke(&function, args, HashMap::new())
+    }
+
+    /// Runs the body of `function` with `args` bound to its parameters.
+    fn invoke(
+        &mut self,
+        function: &Statements,
+        args: Vec<Value>,
+        bindings: HashMap<String, Type>,
+    ) -> Result<Option<Value>, String> {
+        let (name, params, body, return_type) = match function {
+            Statements::FunctionDeclaration {
+                name,
+                params,
+                body,
+                return_type,
+                ..
+            } => (name, params, body, return_type),
+            _ => return Err("Expected FunctionDecl as input".to_string()),
+        };
+
+        if self.depth >= MAX_CALL_DEPTH {
+            panic(format!(
+                "stack overflow, calls are nested more than {} deep",
+                MAX_CALL_DEPTH
+            ));
+        }
+
+        let mut locals = HashMap::new();
+        for (param, arg) in params.iter().zip(args) {
+            let param_type = param.r#type.substitute(&bindings);
+            locals.insert(param.name.clone(), coerce(arg, &param_type)?);
+        }
+        let return_type = return_type.substitute(&bindings);
+
+        let scopes = std::mem::replace(&mut self.scopes, vec![locals]);
+        let type_bindings = std::mem::replace(&mut self.type_bindings, bindings);
+        let outer_return_type = std::mem::replace(&mut self.return_type, return_type.clone());
+        self.depth += 1;
+
+        let flow = self.block(body);
+
+        self.depth -= 1;
+        self.scopes = scopes;
+        self.type_bindings = type_bindings;
+        self.return_type = outer_return_type;
+
+        match flow? {
+            Flow::Return(value) => Ok(value),
+            Flow::Next if return_type == Type::Void => Ok(None),
+            Flow::Next => Err(format!("`{}` ended without returning a {}", name, return_type)),
+        }
+    }
+
+    /// `len(x)` is the number of elements of an array or bytes of a string.
+    /// `i32(x)` and the like, see [`typing::conversion_type`].
+    fn conversion(&mut self, name: &str, r#type: &Type, params: &[Expression]) -> Result<Value, String> {
+        if params.len() != 1 {
+            return Err(format!("`{}` takes 1 argument but {} were supplied", name, params.len()));
+        }
+
+        match self.value(&params[0], Some(r#type))? {
+            Value::Int(value, _) => Ok(Value::Int(wrap(value, r#type), r#type.clone())),
+            value => Err(format!("`{}` converts integers, not {}", name, value.r#type())),
+        }
+    }
+
+    fn len(&mut self, params: &[Expression]) -> Result<Value, String> {
+        if params.len() != 1 {
+            return Err(format!("`len` takes 1 argument but {} were supplied", params.len()));
+        }
+
+        let len = match self.value(&params[0], None)? {
+            Value::FixedArray(elements, _) => elements.len(),
+            Value::Array(elements, _) => elements.borrow().len(),
+            Value::String(value) => value.len(),
+            value => return Err(format!("`len` is not defined for {}", value.r#type())),
+        };
+
+        Ok(Value::Int(len as i128, Type::Int64))
+    }
+
+    /// `resize(values, len, fill)` is a new array of `len` elements, the
+    /// first of which are copied from `values` and the others set to `fill`.
+    /// A negative `len` gives an empty array.
+    fn resize(&mut self, params: &[Expression]) -> Result<Value, String> {
+        if params.len() != 3 {
+            return Err(format!("`resize` takes 3 arguments but {} were supplied", params.len()));
+        }
+
+        let (elements, element) = match self.value(&params[0], None)? {
+            Value::Array(elements, element) => (elements, element),
+            value => return Err(format!("`resize` takes an array, not {}", value.r#type())),
+        };
+        let len = match self.value_as(&params[1], &Type::Int64)? {
+            Value::Int(len, _) => len.max(0) as usize,
+            value => return Err(format!("Mismatched types: expected i64, found {}", value.r#type())),
+        };
+        let fill = self.value_as(&params[2], &element)?;
+
+        let mut resized: Vec<Value> = elements.borrow().iter().take(len).cloned().collect();
+        resized.resize(len, fill);
+        Ok(Value::Array(Rc::new(RefCell::new(resized)), element))
+    }
+
+    /// `format(template, values...)` replaces the `{}`s of the template
+    /// with the values, written like compiled code writes them.
+    fn format(&mut self, params: &[Expression]) -> Result<Value, String> {
+        let pieces = format_template(params)?;
+        let mut text = pieces[0].clone();
+        for (param, piece) in params[1..].iter().zip(&pieces[1..]) {
+            match self.value(param, None)? {
+                Value::Int(value, _) => text.push_str(&value.to_string()),
+                Value::Float(value, r#type) => text.push_str(&format_float(value, float_digits(&r#type))),
+                Value::Bool(value) => text.push_str(&value.to_string()),
+                Value::String(value) => text.push_str(&value),
+                value => return Err(format!("`format` can not write values of type {}", value.r#type())),
+            }
+            text.push_str(piece);
+        }
+
+        Ok(Value::String(text.into()))
+    }
+
+    fn array_literal(&mut self, elements: &[Expression], hint: Option<&Type>) -> Result<Value, String> {
+        let mut element_type = match hint {
+            Some(Type::Array(r#type)) | Some(Type::FixedArray(r#type, _)) => Some(*r#type.clone()),
+            _ => None,
+        };
+
+        let mut values: Vec<Value> = vec![];
+        for element in elements {
+            let value = match element_type.clone() {
+                Some(r#type) => self.value_as(element, &r#type)?,
+                None => {
+                    let value = self.value(element, None)?;
+                    element_type = Some(value.r#type());
+                    value
+                }
+            };
+            values.push(value);
+        }
+
+        let element_type = match element_type {
+            Some(r#type) => r#type,
+            None => return Err("Can not infer the element type of an empty array".to_string()),
+        };
+
+        match hint {
+            Some(Type::Array(_)) => Ok(Value::Array(Rc::new(RefCell::new(values)), element_type)),
+            _ => Ok(Value::FixedArray(values, element_type)),
+        }
+    }
+
+    /// The type arguments of a generic struct are taken from `hint` or
+    /// inferred from the field values.
+    fn struct_literal(
+        &mut self,
+        name: &str,
+        values: &[(String, Expression)],
+        hint: Option<&Type>,
+    ) -> Result<Value, String> {
+        let (type_params, fields) = self
+            .structs
+            .get(name)
+            .cloned()
+            .ok_or(format!("Unknown struct `{}`", name))?;
+
+        for (field, _) in values {
+            if !fields.iter().any(|f| &f.name == field) {
+                return Err(format!("{} has no field `{}`", name, field));
+            }
+            if values.iter().filter(|(f, _)| f == field).count() > 1 {
+                return Err(format!("Field `{}` is specified more than once", field));
+            }
+        }
+
+        let mut inference = Inference::with_hint(name, &type_params, hint);
+
+        let mut field_values: Vec<(String, Value)> = vec![];
+        for field in &fields {
+            let expr = values
+                .iter()
+                .find(|(f, _)| f == &field.name)
+                .map(|(_, expr)| expr)
+                .ok_or(format!("Missing field `{}` in {} literal", field.name, name))?;
+
+            let value = match inference.expected(&field.r#type) {
+                Some(field_type) => self.value_as(expr, &field_type)?,
+                None => {
+                    let value = self.value(expr, None)?;
+                    inference.unify(&field.r#type, &value.r#type())?;
+                    value
+                }
+            };
+            field_values.push((field.name.clone(), value));
+        }
+
+        let r#type = if type_params.is_empty() {
+            Type::UserDefinedType {
+                name: name.to_string(),
+            }
+        } else {
+            let params = inference.type_arguments()?;
+            Type::Generic {
+                name: name.to_string(),
+                params,
+            }
+        };
+
+        Ok(Value::Struct(r#type, field_values))
+    }
+
+    fn enum_variant(&mut self, enum_name: &str, variant: &str, values: &[Expression]) -> Result<Value, String> {
+        let fields = self
+            .enums
+            .get(enum_name)
+            .ok_or(format!("Unknown enum `{}`", enum_name))?
+            .iter()
+            .find(|v| v.name == variant)
+            .map(|v| v.fields.clone())
+            .ok_or(format!("`{}` has no variant `{}`", enum_name, variant))?;
+
+        let mut field_values = vec![];
+        for (value, field_type) in values.iter().zip(fields.iter()) {
+            field_values.push(self.value_as(value, field_type)?);
+        }
+
+        Ok(Value::Enum(
+            Type::UserDefinedType {
+                name: enum_name.to_string(),
+            },
+            variant.to_string(),
+            field_values,
+        ))
+    }
+
+    /// Runs the first arm whose pattern matches. Arms are statements when
+    /// `hint` is void.
+    fn r#match(
+        &mut self,
+        expr: &Expression,
+        arms: &[MatchArm],
+        hint: Option<&Type>,
+    ) -> Result<Option<Value>, String> {
+        let value = self.value(expr, None)?;
+
+        for arm in arms {
+            let mut bindings = HashMap::new();
+            if !matches_pattern(&arm.pattern, &value, &mut bindings) {
+                continue;
+            }
+
+            self.scopes.push(bindings);
+            let result = match hint {
+                Some(Type::Void) => self.evaluate(&arm.body, None).map(|_| None),
+                hint => self.evaluate(&arm.body, hint),
+            };
+            self.scopes.pop();
+            return result;
+        }
+
+        Err(format!("No arm of `match {}` matches {}", expr, value))
+    }
+
+    /// Where the assignable expression `expr` is stored.
+    fn place(&mut self, expr: &Expression) -> Result<(Root, Vec<Step>), String> {
+        match expr {
+            Expression::Identifier { val } if self.is_constant(val) => {
+                Err(format!("Can not assign to constant `{}`", val))
+            }
+            Expression::Identifier { val } => {
+                if self.scopes.iter().any(|scope| scope.contains_key(val)) {
+                    Ok((Root::Local(val.clone()), vec![]))
+                } else if self.globals.contains_key(val) {
+                    Ok((Root::Global(val.clone()), vec![]))
+                } else {
+                    Err(format!("Unknown variable `{}`", val))
+                }
+            }
+            Expression::FieldAccess { expr, field } => {
+                let (root, mut steps) = self.place_or_temporary(expr)?;
+                steps.push(Step::Field(field.clone()));
+                Ok((root, steps))
+            }
+            Expression::Index { array, index } => {
+                let (root, mut steps) = self.place_or_temporary(array)?;
+                let index = match self.value(index, Some(&Type::Int64))? {
+                    Value::Int(index, _) => index,
+                    value => return Err(format!("Can not index with a value of type {}", value.r#type())),
+                };
+                steps.push(Step::Index(index));
+                Ok((root, steps))
+            }
+            _ => Err("Can not assign to this expression".to_string()),
+        }
+    }
+
+    /// Like `place`, but values that are not stored anywhere are kept in a
+    /// temporary.
+    fn place_or_temporary(&mut self, expr: &Expression) -> Result<(Root, Vec<Step>), String> {
+        match expr {
+            Expression::Identifier { val } if !self.is_constant(val) => self.place(expr),
+            Expression::Index { .. } | Expression::FieldAccess { .. } => self.place(expr),
+            expr => Ok((Root::Temporary(self.value(expr, None)?), vec![])),
+        }
+    }
+
+    /// Calls `f` with the value stored at `root` and `steps`.
+    fn with_place<R>(
+        &mut self,
+        root: &mut Root,
+        steps: &[Step],
+        f: impl FnOnce(&mut Value) -> R,
+    ) -> Result<R, String> {
+        let value = match root {
+            Root::Local(name) => self
+                .scopes
+                .iter_mut()
+                .rev()
+                .find_map(|scope| scope.get_mut(name))
+                .unwrap(),
+            Root::Global(name) => self.globals.get_mut(name).unwrap(),
+            Root::Temporary(value) => value,
+        };
+
+        walk(value, steps, f)
+    }
+}
+
+fn walk<R>(value: &mut Value, steps: &[Step], f: impl FnOnce(&mut Value) -> R) -> Result<R, String> {
+    let (step, rest) = match steps.split_first() {
+        Some(step) => step,
+        None => return Ok(f(value)),
+    };
+
+    let check_bounds = |index: i128, len: usize| {
+        if index < 0 || index >= len as i128 {
+            panic(format!(
+                "index out of bounds: the len is {} but the index is {}",
+                len, index
+            ));
+        }
+        index as usize
+    };
+
+    match (step, value) {
+        (Step::Field(field), Value::Struct(r#type, fields)) => {
+            let r#type = r#type.clone();
+            match fields.iter_mut().find(|(name, _)| name == field) {
+                Some((_, value)) => walk(value, rest, f),
+                None => Err(format!("{} has no field `{}`", r#type, field)),
+            }
+        }
+        (Step::Index(index), Value::FixedArray(elements, _)) => {
+            let index = check_bounds(*index, elements.len());
+            walk(&mut elements[index], rest, f)
+        }
+        (Step::Index(index), Value::Array(elements, _)) => {
+            let elements = elements.clone();
+            let mut elements = elements.borrow_mut();
+            let index = check_bounds(*index, elements.len());
+            walk(&mut elements[index], rest, f)
+        }
+        (Step::Field(field), value) => Err(format!("{} has no field `{}`", value.r#type(), field)),
+        (Step::Index(_), value) => Err(format!("Can not index into a value of type {}", value.r#type())),
+    }
+}
+
+/// The C functions of the runtime of compiled programs that the standard
+/// library uses, which the interpreter provides itself. It can not call any
+/// other C function.
+const RUNTIME_FUNCTIONS: &[&str] = &[
+    "aurora_write",
+    "aurora_panic",
+    "aurora_byte",
+    "aurora_substring",
+    "aurora_sqrt",
+    "aurora_pow",
+    "aurora_floor",
+    "aurora_ceil",
+];
+
+fn cannot_call_c(name: &str) -> String {
+    format!(
+        "`{}` is a C function, which the interp backend can not call, build the program with the llvm or c backend instead",
+        name
+    )
+}
+
+/// Calls `name`, one of the [`RUNTIME_FUNCTIONS`].
+fn call_runtime(name: &str, args: &[Value]) -> Result<Option<Value>, String> {
+    let byte_range = |text: &str, start: i128, end: i128| {
+        if start < 0 || start > end || end > text.len() as i128 {
+            return Err(format!(
+                "`{}` of {}..{} is out of the {} bytes of the string",
+                name,
+                start,
+                end,
+                text.len()
+            ));
+        }
+        Ok(start as usize..end as usize)
+    };
+
+    match (name, args) {
+        ("aurora_write", [Value::Int(fd, _), Value::String(data), Value::Int(len, _)]) => {
+            let len = usize::try_from(*len).unwrap_or(0).min(data.len());
+            // Errors are ignored, like by the runtime
+            let _ = match fd {
+                1 => io::stdout().write_all(&data.as_bytes()[..len]),
+                2 => io::stderr().write_all(&data.as_bytes()[..len]),
+                fd => return Err(format!("Can not write to file descriptor {}, only to 1 and 2", fd)),
+            };
+            Ok(None)
+        }
+        ("aurora_panic", [Value::String(message)]) => panic(message.to_string()),
+        ("aurora_byte", [Value::String(text), Value::Int(index, _)]) => {
+            let range = byte_range(text, *index, index + 1)?;
+            let byte = text.as_bytes()[range.start];
+            Ok(Some(Value::Int(byte as i128, Type::Int32)))
+        }
+        ("aurora_substring", [Value::String(text), Value::Int(start, _), Value::Int(end, _)]) => {
+            let range = byte_range(text, *start, *end)?;
+            let substring = String::from_utf8_lossy(&text.as_bytes()[range]);
+            Ok(Some(Value::String(substring.into())))
+        }
+        ("aurora_sqrt", [Value::Float(x, _)]) => Ok(Some(Value::Float(x.sqrt(), Type::Float64))),
+        ("aurora_pow", [Value::Float(x, _), Value::Float(y, _)]) => {
+            Ok(Some(Value::Float(x.powf(*y), Type::Float64)))
+        }
+        ("aurora_floor", [Value::Float(x, _)]) => Ok(Some(Value::Float(x.floor(), Type::Float64))),
+        ("aurora_ceil", [Value::Float(x, _)]) => Ok(Some(Value::Float(x.ceil(), Type::Float64))),
+        _ => Err(cannot_call_c(name)),
+    }
+}
+
+/// Converts `value` to `r#type` where the compiled code would.
+fn coerce(value: Value, r#type: &Type) -> Result<Value, String> {
+    match (value, r#type) {
+        (value, r#type) if &value.r#type() == r#type => Ok(value),
+        (Value::Int(value, _), r#type) if r#type.is_integer() => {
+            Ok(Value::Int(wrap(value, r#type), r#type.clone()))
+        }
+        (Value::FixedArray(elements, element), Type::Array(expected)) if &element == expected.as_ref() => {
+            Ok(Value::Array(Rc::new(RefCell::new(elements)), element))
+        }
+        (value, r#type) => Err(format!(
+            "Mismatched types: expected {}, found {}",
+            r#type,
+            value.r#type()
+        )),
+    }
+}
+
+fn from_constant(value: consteval::Value, r#type: &Type) -> Value {
+    match value {
+        consteval::Value::Int(value) => Value::Int(value, r#type.clone()),
+        consteval::Value::Float(value) => Value::Float(round(value, r#type), r#type.clone()),
+        consteval::Value::Bool(value) => Value::Bool(value),
+        consteval::Value::String(value) => Value::String(value.into()),
+    }
+}
+
+/// Comparisons involving NaN are false, except `!=`.
+fn compare(op: BinaryOperator, ordering: Option<std::cmp::Ordering>) -> bool {
+    let ordering = match ordering {
+        Some(ordering) => ordering,
+        None => return op == BinaryOperator::NotEqual,
+    };
+
+    match op {
+        BinaryOperator::Equal => ordering.is_eq(),
+        BinaryOperator::NotEqual => ordering.is_ne(),
+        BinaryOperator::LessThan => ordering.is_lt(),
+        BinaryOperator::LessThanOrEqual => ordering.is_le(),
+        BinaryOperator::GreaterThan => ordering.is_gt(),
+        BinaryOperator::GreaterThanOrEqual => ordering.is_ge(),
+        op => unreachable!("{:?} is not a comparison", op),
+    }
+}
+
+/// Whether `pattern` matches `value`, collecting the values it binds.
+fn matches_pattern(pattern: &Pattern, value: &Value, bindings: &mut HashMap<String, Value>) -> bool {
+    match (pattern, value) {
+        (Pattern::Wildcard, _) => true,
+        (Pattern::Binding { name }, value) => {
+            bindings.insert(name.clone(), value.clone());
+            true
+        }
+        (Pattern::IntLiteral { val }, Value::Int(value, r#type)) => wrap(*val as i128, r#type) == *value,
+        (Pattern::BooleanLiteral { val }, Value::Bool(value)) => val == value,
+        (
+            Pattern::Variant {
+                enum_name,
+                variant,
+                fields,
+            },
+            Value::Enum(r#type, value_variant, values),
+        ) => {
+            r#type.to_string() == *enum_name
+                && variant == value_variant
+                && fields
+                    .iter()
+                    .zip(values)
+                    .all(|(pattern, value)| matches_pattern(pattern, value, bindings))
+        }
+        _ => false,
+    }
+}
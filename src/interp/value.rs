@@ -0,0 +1,87 @@
+use std::{cell::RefCell, fmt, rc::Rc};
+
+use crate::ast::Type;
+
+/// A value of the interpreted program. Values carry their type, so that
+/// integers wrap around at the width of their type and methods can be
+/// looked up on them.
+#[derive(Debug, Clone)]
+pub enum Value {
+    Int(i128, Type),
+    Float(f64, Type),
+    Bool(bool),
+    String(Rc<str>),
+    /// `[T; n]`, copied like any other value
+    FixedArray(Vec<Value>, Type),
+    /// `array[T]`. Copies share their elements, like the pointer and length
+    /// pair of compiled code.
+    Array(Rc<RefCell<Vec<Value>>>, Type),
+    /// Fields in declaration order
+    Struct(Type, Vec<(String, Value)>),
+    /// The enum type, variant name and the variant's fields
+    Enum(Type, String, Vec<Value>),
+    /// The name of a function and its `fn` type
+    Function(String, Type),
+}
+
+impl Value {
+    pub fn r#type(&self) -> Type {
+        match self {
+            Value::Int(_, r#type) | Value::Float(_, r#type) => r#type.clone(),
+            Value::Bool(_) => Type::Boolean,
+            Value::String(_) => Type::String,
+            Value::FixedArray(elements, element) => {
+                Type::FixedArray(Box::new(element.clone()), elements.len())
+            }
+            Value::Array(_, element) => Type::Array(Box::new(element.clone())),
+            Value::Struct(r#type, _) | Value::Enum(r#type, ..) | Value::Function(_, r#type) => {
+                r#type.clone()
+            }
+        }
+    }
+}
+
+/// Rounds `value` to the precision of the float type `r#type`. Floats
+/// narrower than 32 bits are approximated with `f32`.
+pub fn round(value: f64, r#type: &Type) -> f64 {
+    match r#type {
+        Type::Float16 | Type::Float32 => value as f32 as f64,
+        _ => value,
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let join = |values: &[Value]| {
+            values
+                .iter()
+                .map(|v| v.to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        };
+
+        match self {
+            Value::Int(value, _) => write!(f, "{}", value),
+            Value::Float(value, _) => write!(f, "{}", value),
+            Value::Bool(value) => write!(f, "{}", value),
+            Value::String(value) => write!(f, "{}", value),
+            Value::FixedArray(elements, _) => write!(f, "[{}]", join(elements)),
+            Value::Array(elements, _) => write!(f, "[{}]", join(&elements.borrow())),
+            Value::Struct(r#type, fields) => {
+                let fields: Vec<String> = fields
+                    .iter()
+                    .map(|(name, value)| format!("{}: {}", name, value))
+                    .collect();
+                write!(f, "{} {{ {} }}", r#type, fields.join(", "))
+            }
+            Value::Enum(r#type, variant, values) => {
+                write!(f, "{}::{}", r#type, variant)?;
+                if !values.is_empty() {
+                    write!(f, "({})", join(values))?;
+                }
+                Ok(())
+            }
+            Value::Function(name, _) => write!(f, "{}", name),
+        }
+    }
+}
@@ -13,7 +13,7 @@ impl Lexer {
         Self {
             input: source_code,
             position: 0,
-            line: 0,
+            line: 1,
             read_position: 0,
             ch: ' ',
         }
@@ -25,7 +25,7 @@ impl Lexer {
         self.read_char();
         loop {
             let token = self.next_token();
-            if token == tokens::Token::EOF {
+            if token == tokens::Token::Eof {
                 break;
             } else {
                 if token != tokens::Token::Whitespace && token != tokens::Token::Unkown {
@@ -43,18 +43,27 @@ impl Lexer {
             self.ch = self.input[self.read_position];
         }
         self.position = self.read_position;
-        self.read_position = self.read_position + 1;
+        self.read_position += 1;
+    }
+    pub fn peek_char(&self) -> char {
+        if self.read_position >= self.input.len() {
+            '\0'
+        } else {
+            self.input[self.read_position]
+        }
     }
     pub fn skip_whitespace(&mut self) {
-        let ch = self.ch;
-        if ch.is_whitespace() {
+        while self.ch.is_whitespace() {
+            if self.ch == '\n' {
+                self.line += 1;
+            }
             self.read_char();
         }
     }
     pub fn token_match(&mut self) -> tokens::Token {
         let read_identifier = |l: &mut Lexer| -> Vec<char> {
             let position = l.position;
-            while l.position < l.input.len() && l.ch.is_alphanumeric() {
+            while l.position < l.input.len() && (l.ch.is_alphanumeric() || l.ch == '_') {
                 l.read_char();
             }
             l.input[position..l.position].to_vec()
@@ -76,24 +85,45 @@ impl Lexer {
         self.skip_whitespace();
         match self.ch {
             '=' => {
-                tok = tokens::Token::Assign { val: self.ch };
+                if self.peek_char() == '=' {
+                    self.read_char();
+                    tok = tokens::Token::Eq {
+                        val: "==".to_string(),
+                    }
+                } else if self.peek_char() == '>' {
+                    self.read_char();
+                    tok = tokens::Token::FatArrow {
+                        val: "=>".to_string(),
+                    }
+                } else {
+                    tok = tokens::Token::Assign { val: self.ch };
+                }
             }
             '+' => {
                 tok = tokens::Token::Plus { val: self.ch };
             }
             '-' => {
-                let cur_ch = self.ch;
-                self.read_char();
-                if self.ch == '>' {
+                if self.peek_char() == '>' {
+                    self.read_char();
                     tok = tokens::Token::Arrow {
-                        val: [cur_ch, self.ch].into_iter().collect(),
+                        val: "->".to_string(),
                     }
                 } else {
                     tok = tokens::Token::Minus { val: self.ch };
                 }
             }
             '!' => {
-                tok = tokens::Token::Bang { val: self.ch };
+                if self.peek_char() == '=' {
+                    self.read_char();
+                    tok = tokens::Token::NotEq {
+                        val: "!=".to_string(),
+                    }
+                } else {
+                    tok = tokens::Token::Bang { val: self.ch };
+                }
+            }
+            '%' => {
+                tok = tokens::Token::Modulo { val: self.ch };
             }
             '/' => {
                 tok = tokens::Token::Division { val: self.ch };
@@ -102,16 +132,37 @@ impl Lexer {
                 tok = tokens::Token::Multiplication { val: self.ch };
             }
             '<' => {
-                tok = tokens::Token::Lt { val: self.ch };
+                if self.peek_char() == '=' {
+                    self.read_char();
+                    tok = tokens::Token::LtEq {
+                        val: "<=".to_string(),
+                    }
+                } else {
+                    tok = tokens::Token::Lt { val: self.ch };
+                }
             }
             '>' => {
-                tok = tokens::Token::Gt { val: self.ch };
+                if self.peek_char() == '=' {
+                    self.read_char();
+                    tok = tokens::Token::GtEq {
+                        val: ">=".to_string(),
+                    }
+                } else {
+                    tok = tokens::Token::Gt { val: self.ch };
+                }
             }
             ';' => {
                 tok = tokens::Token::Semicolon { val: self.ch };
             }
             ':' => {
-                tok = tokens::Token::Colon { val: self.ch };
+                if self.peek_char() == ':' {
+                    self.read_char();
+                    tok = tokens::Token::DoubleColon {
+                        val: "::".to_string(),
+                    }
+                } else {
+                    tok = tokens::Token::Colon { val: self.ch };
+                }
             }
             '(' => {
                 tok = tokens::Token::LParen { val: self.ch };
@@ -122,14 +173,23 @@ impl Lexer {
             ',' => {
                 tok = tokens::Token::Comma { val: self.ch };
             }
+            '.' => {
+                tok = tokens::Token::Dot { val: self.ch };
+            }
             '{' => {
                 tok = tokens::Token::LBrace { val: self.ch };
             }
             '}' => {
                 tok = tokens::Token::RBrace { val: self.ch };
             }
+            '[' => {
+                tok = tokens::Token::LBracket { val: self.ch };
+            }
+            ']' => {
+                tok = tokens::Token::RBracket { val: self.ch };
+            }
             '\0' => {
-                tok = tokens::Token::EOF;
+                tok = tokens::Token::Eof;
             }
             _ if self.ch == '"' => {
                 let mut stri: Vec<char> = vec![];
@@ -137,7 +197,15 @@ impl Lexer {
                 while self.ch != '"' {
                     if self.ch == '\\' {
                         self.read_char();
-                        stri.push(self.ch);
+                        stri.push(match self.ch {
+                            'n' => '\n',
+                            't' => '\t',
+                            'r' => '\r',
+                            // `\\`, `\"` and unknown escapes are the character itself
+                            c => c,
+                        });
+                        self.read_char();
+                        continue;
                     }
                     stri.push(self.ch);
                     self.read_char();
@@ -145,7 +213,7 @@ impl Lexer {
                 self.read_char();
                 return tokens::Token::String { val: stri };
             }
-            _ if self.ch.is_alphabetic() => {
+            _ if self.ch.is_alphabetic() || self.ch == '_' => {
                 let ident: Vec<char> = read_identifier(self);
                 match tokens::get_keyword_token(&ident) {
                     Ok(keywork_token) => {
@@ -165,20 +233,11 @@ impl Lexer {
                 return tokens::Token::Int { val: ident };
             }
             _ => {
-                if self.ch == '\n' || self.ch == '\n' || self.ch == '\r' {
-                    self.line += 1;
-                    self.position = 0;
-                    // self.read_position = 0;
-                }
-
-                if self.ch == '\n' || self.ch == '\n' || self.ch == '\r' || self.ch == ' ' {
-                    return tokens::Token::Whitespace;
-                }
                 eprintln!(
                     "[LEXER] Error: Unknown token found @ position {} '{}'",
                     self.position, self.ch
                 );
-                return tokens::Token::Unkown;
+                tok = tokens::Token::Unkown;
             }
         }
         self.read_char();
@@ -192,16 +251,35 @@ impl Lexer {
         }
         t
     }
+    /// The line the next token starts on.
+    pub fn peek_line(&mut self) -> usize {
+        let old_ch = self.ch;
+        let old_postion = self.position;
+        let old_read_postion = self.read_position;
+        let old_line = self.line;
+
+        self.skip_whitespace();
+        let line = self.line;
+
+        self.ch = old_ch;
+        self.position = old_postion;
+        self.read_position = old_read_postion;
+        self.line = old_line;
+
+        line
+    }
     pub fn peak_next_token(&mut self) -> tokens::Token {
         let old_ch = self.ch;
         let old_postion = self.position;
-        let old_read_postion = self.position;
+        let old_read_postion = self.read_position;
+        let old_line = self.line;
 
         let token = self.token_match();
 
         self.ch = old_ch;
         self.position = old_postion;
         self.read_position = old_read_postion;
+        self.line = old_line;
 
         token
     }
@@ -7,50 +7,80 @@ pub enum Token {
     Minus { val: char },
     Multiplication { val: char },
     Division { val: char },
+    Modulo { val: char },
     Assign { val: char },
     Bang { val: char },
     Lt { val: char },
     Gt { val: char },
+    LtEq { val: String },
+    GtEq { val: String },
+    Eq { val: String },
+    NotEq { val: String },
     Semicolon { val: char },
     Colon { val: char },
+    DoubleColon { val: String },
     LParen { val: char },
     RParen { val: char },
     Comma { val: char },
+    Dot { val: char },
     LBrace { val: char },
     RBrace { val: char },
+    LBracket { val: char },
+    RBracket { val: char },
     Int { val: Vec<char> },
     Float { val: Vec<char> },
     String { val: Vec<char> },
     Arrow { val: String },
+    FatArrow { val: String },
     // KEYWORDS
     Function,
     Let,
+    Const,
     True,
     False,
     If,
     Else,
+    While,
     Return,
     End,
     Module,
+    Struct,
+    Enum,
+    Match,
+    Impl,
+    Import,
+    Trait,
+    For,
     Unkown,
     Public,
+    Extern,
     Whitespace,
-    EOF,
+    Eof,
 }
 
-pub fn get_keyword_token(ident: &Vec<char>) -> Result<Token, String> {
-    let identifier: String = ident.into_iter().collect();
+pub fn get_keyword_token(ident: &[char]) -> Result<Token, String> {
+    let identifier: String = ident.iter().collect();
     match &identifier[..] {
         "fn" => Ok(Token::Function),
         "let" => Ok(Token::Let),
+        "const" => Ok(Token::Const),
         "true" => Ok(Token::True),
         "false" => Ok(Token::False),
         "if" => Ok(Token::If),
         "else" => Ok(Token::Else),
+        "while" => Ok(Token::While),
         "end" => Ok(Token::End),
         "return" => Ok(Token::Return),
         "module" => Ok(Token::Module),
+        "struct" => Ok(Token::Struct),
+        "enum" => Ok(Token::Enum),
+        "match" => Ok(Token::Match),
+        "impl" => Ok(Token::Impl),
+        "import" => Ok(Token::Import),
+        "trait" => Ok(Token::Trait),
+        "for" => Ok(Token::For),
         "public" => Ok(Token::Public),  
+        "extern" => Ok(Token::Extern),
         _ => Err(String::from("Not a keyword")),
     }
 }
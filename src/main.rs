@@ -1,100 +1,1155 @@
+// Without LLVM the build cache and interface files go unused
+#![cfg_attr(not(feature = "llvm"), allow(dead_code))]
+
+#[cfg(feature = "llvm")]
+use ast::{impl_functions, Type};
 use ast::{statements::Statements, Program};
 use error::{CompilerError, CompilerErrorKind};
-use inkwell::{context::Context, execution_engine::JitFunction, OptimizationLevel};
-use lexer::Lexer;
-use parser::parser::Parser;
+#[cfg(feature = "llvm")]
+use inkwell::{context::Context, execution_engine::JitFunction};
+use modules::{Interface, Module};
+#[cfg(feature = "llvm")]
+use std::collections::BTreeMap;
 use std::{
+    collections::{hash_map::Entry, HashMap},
     env::{self},
     fs,
+    path::{Path, PathBuf},
 };
 
-use crate::codegen::CodeGen;
+use crate::package::{Kind, OptLevel, Package};
+#[cfg(feature = "llvm")]
+use crate::{
+    cache::Cache,
+    codegen::{passes, runtime, target, verify, CodeGen},
+};
 
 mod ast;
+mod cache;
+mod cgen;
+#[cfg(feature = "llvm")]
 mod codegen;
+mod consteval;
 mod error;
+mod interp;
 mod lexer;
+mod modules;
+mod package;
 mod parser;
+mod sema;
 mod utils;
 
-fn compile(input: String, output: String) {
-    let code = fs::read_to_string(input).unwrap();
-    let mut m_name: String = String::new();
-    let mut _lexer = Lexer::new(code.chars().collect());
-    let mut _parser = Parser::new(_lexer.clone());
-    let mut _ast = _parser.parse();
-    let mut program = Program::compile((_ast, _parser.exports));
-    let module_statement = program.statements.remove(0);
+fn build_error(message: String) -> CompilerError {
+    CompilerError {
+        code: 402,
+        kind: CompilerErrorKind::BuildError,
+        message,
+    }
+}
+
+/// Errors of the compiler itself rather than of the program, like code the
+/// LLVM verifier rejects.
+#[cfg(feature = "llvm")]
+fn internal_error(message: String) -> CompilerError {
+    CompilerError {
+        code: 403,
+        kind: CompilerErrorKind::InternalCompilerError,
+        message: format!(
+            "internal compiler error: {}\nThis is a bug in aurorac, please report it with the program that caused it",
+            message
+        ),
+    }
+}
 
-    if matches!(module_statement, Statements::ModuleDeclaration { .. }) {
-        match module_statement {
-            Statements::ModuleDeclaration { name } => m_name = name,
-            _ => panic!(""),
+fn exit_with_errors(errors: Vec<CompilerError>) -> ! {
+    for error in errors {
+        eprintln!("{}", error);
+    }
+    std::process::exit(1);
+}
+
+/// Reads the interfaces of `imports` and of the modules they import in turn.
+#[cfg(feature = "llvm")]
+fn read_interfaces(
+    dir: &Path,
+    imports: Vec<String>,
+    interfaces: &mut HashMap<String, Interface>,
+) -> Result<(), CompilerError> {
+    for import in imports {
+        if let Entry::Vacant(entry) = interfaces.entry(import) {
+            let interface = Interface::read(dir, entry.key())?;
+            let imports = interface.imports.clone();
+            entry.insert(interface);
+            read_interfaces(dir, imports, interfaces)?;
         }
     }
 
-    let context = Context::create();
-    let module = context.create_module(&m_name);
-    let code_gen = CodeGen::new();
+    Ok(())
+}
+
+/// Collects the interfaces of `imports` and of the modules they import in
+/// turn from the interfaces of the modules checked so far.
+fn collect_interfaces(
+    checked: &HashMap<String, Interface>,
+    imports: Vec<String>,
+    interfaces: &mut HashMap<String, Interface>,
+) {
+    for import in imports {
+        if let Some(interface) = checked.get(&import) {
+            if let Entry::Vacant(entry) = interfaces.entry(import) {
+                entry.insert(interface.clone());
+                collect_interfaces(checked, interface.imports.clone(), interfaces);
+            }
+        }
+    }
+}
+
+/// Resolves and checks `module` against the interfaces of the modules it
+/// imports. Returns the resolved program and the declarations it imports.
+fn check_module(
+    module: &Module,
+    modules: &[Module],
+    interfaces: &HashMap<String, Interface>,
+) -> Result<(Program, Vec<Statements>), Vec<CompilerError>> {
+    let program = modules::resolve(module, interfaces)?;
+
+    let imported: Vec<Statements> = modules
+        .iter()
+        .filter_map(|module| interfaces.get(&module.name))
+        .flat_map(|interface| interface.declarations())
+        .cloned()
+        .collect();
+
+    sema::check(&imported, &program)?;
+    consteval::check(&imported, &program)?;
+
+    Ok((program, imported))
+}
+
+/// Compiles a module into its own LLVM module. `imported` are the
+/// declarations from the interfaces of its dependencies, which are declared
+/// but compiled as part of their own module. With `-g` the module gets
+/// debug information for its `source`.
+#[cfg(feature = "llvm")]
+fn compile_module<'ctx>(
+    context: &'ctx Context,
+    module: &inkwell::module::Module<'ctx>,
+    imported: &[Statements],
+    program: &Program,
+    source: Option<&Path>,
+    opt_level: OptLevel,
+) -> Result<(), String> {
+    let mut code_gen = CodeGen::new(context, module);
+    if let Some(source) = source {
+        code_gen.enable_debug_info(source, program, opt_level != OptLevel::O0);
+    }
+    let declarations = || imported.iter().chain(program.statements.iter());
+
+    // Declare every type and function up front so they can be used before
+    // the point they are defined in the file.
+    for statement in declarations() {
+        match statement {
+            Statements::StructDeclaration { .. } => code_gen.declare_struct(statement)?,
+            Statements::EnumDeclaration { .. } => code_gen.declare_enum(statement)?,
+            Statements::ConstDeclaration { .. } => code_gen.declare_constant(statement)?,
+            Statements::VariableDeclaration { .. } => code_gen.declare_global(statement)?,
+            _ => {}
+        }
+    }
+    for statement in declarations() {
+        match statement {
+            Statements::StructDeclaration { .. } => code_gen.define_struct(statement)?,
+            Statements::EnumDeclaration { .. } => code_gen.define_enum(statement)?,
+            Statements::FunctionDeclaration { type_params, .. } if !type_params.is_empty() => {
+                code_gen.declare_generic_function(statement)?
+            }
+            Statements::FunctionDeclaration { .. } => {
+                code_gen.declare_function(statement)?;
+            }
+            Statements::ExternFunctionDeclaration { .. } => {
+                code_gen.declare_extern_function(statement)?;
+            }
+            Statements::ImplDeclaration { .. } => {
+                for method in impl_functions(statement)? {
+                    code_gen.declare_function(&method)?;
+                }
+            }
+            _ => {}
+        }
+    }
 
     for statement in &program.statements {
         match statement {
-            // Statements::VariableDeclaration { name, value, r#type } => todo!(),
-            // Statements::ModuleDeclaration { name } => todo!(),
-            Statements::FunctionDeclaration { .. } => code_gen
-                .compile_function_statement(statement.clone(), &module)
-                .unwrap(),
-            _ => todo!(), // Statements::ExpressionStatement { expr } => todo!(),
+            // Generic functions are compiled per instance below
+            Statements::FunctionDeclaration { type_params, .. } if !type_params.is_empty() => {}
+            Statements::FunctionDeclaration { .. } => {
+                code_gen.compile_function_statement(statement.clone())?
+            }
+            Statements::ImplDeclaration { .. } => {
+                for method in impl_functions(statement)? {
+                    code_gen.compile_function_statement(method)?;
+                }
+            }
+            Statements::ConstDeclaration { .. } => code_gen.define_constant(statement)?,
+            Statements::VariableDeclaration { .. } => code_gen.define_global(statement)?,
+            Statements::StructDeclaration { .. }
+            | Statements::EnumDeclaration { .. }
+            | Statements::TraitDeclaration { .. }
+            | Statements::ExternFunctionDeclaration { .. }
+            | Statements::ModuleDeclaration { .. }
+            | Statements::Import { .. } => {}
+            Statements::ExpressionStatement { .. }
+            | Statements::Assignment { .. }
+            | Statements::Return { .. }
+            | Statements::If { .. }
+            | Statements::While { .. } => {
+                return Err("Expressions are not allowed at the top level".to_string())
+            }
+        }
+    }
+
+    code_gen.compile_instances()?;
+    code_gen.finalize_debug_info();
+    Ok(())
+}
+
+#[cfg(feature = "llvm")]
+#[allow(clippy::too_many_arguments)]
+fn compile(
+    input: String,
+    output: String,
+    mut search_paths: Vec<PathBuf>,
+    interface_dir: Option<PathBuf>,
+    opt_level: OptLevel,
+    llvm_passes: Option<&str>,
+    debug_info: bool,
+    verify_ir: bool,
+) {
+    let input = PathBuf::from(input);
+
+    // Imports are looked up next to the entry module first
+    search_paths.insert(
+        0,
+        input.parent().map(|dir| dir.to_path_buf()).unwrap_or_default(),
+    );
+
+    // Interfaces are written next to the output by default
+    let interface_dir = interface_dir.unwrap_or_else(|| {
+        Path::new(&output)
+            .parent()
+            .map(|dir| dir.to_path_buf())
+            .unwrap_or_default()
+    });
+
+    let modules = match modules::load(Some(&input), &[], &search_paths, None) {
+        Ok(modules) => modules,
+        Err(error) => exit_with_errors(vec![error]),
+    };
+
+    let machine = match target::target_machine(None, None, &[], opt_level.optimization_level()) {
+        Ok(machine) => machine,
+        Err(message) => exit_with_errors(vec![build_error(message)]),
+    };
+    let context = Context::create();
+    let mut llvm_modules = vec![];
+    let mut program = None;
+
+    // Modules are compiled in dependency order, each against the interfaces
+    // its imports wrote before it
+    for module in &modules {
+        let mut interfaces: HashMap<String, Interface> = HashMap::new();
+        if let Err(error) = read_interfaces(&interface_dir, module.imports(), &mut interfaces) {
+            exit_with_errors(vec![error]);
+        }
+
+        let (resolved, imported) = match check_module(module, &modules, &interfaces) {
+            Ok(checked) => checked,
+            Err(errors) => exit_with_errors(errors),
+        };
+
+        let llvm_module = context.create_module(module.llvm_name());
+        target::configure_module(&context, &machine, &llvm_module);
+        if let Err(message) = compile_module(
+            &context,
+            &llvm_module,
+            &imported,
+            &resolved,
+            debug_info.then_some(module.path.as_path()),
+            opt_level,
+        ) {
+            exit_with_errors(vec![build_error(message)]);
+        }
+        if verify_ir {
+            if let Err(message) = verify::verify_module(&llvm_module) {
+                exit_with_errors(vec![internal_error(message)]);
+            }
+        }
+        if let Err(message) = passes::optimize(&llvm_module, &machine, opt_level, llvm_passes) {
+            exit_with_errors(vec![build_error(message)]);
         }
+        llvm_modules.push(llvm_module);
+
+        // Only imported modules can be imported, the entry module's items
+        // are not qualified by a module name
+        if !module.name.is_empty() {
+            let interface = Interface::new(module, &module.name, &resolved);
+            if let Err(error) = interface.write(&interface_dir) {
+                exit_with_errors(vec![error]);
+            }
+        }
+
+        program = Some(resolved);
     }
+    let program = program.unwrap();
 
+    // The entry module comes last
+    let (module, dependencies) = llvm_modules.split_last().unwrap();
     let execution_engine = module
-        .create_jit_execution_engine(OptimizationLevel::None)
+        .create_jit_execution_engine(opt_level.optimization_level())
         .unwrap();
+    for dependency in dependencies {
+        execution_engine.add_module(dependency).unwrap();
+    }
+    for llvm_module in &llvm_modules {
+        runtime::map_runtime_functions(llvm_module, &execution_engine);
+    }
+
+    let main_return_type = program.statements.iter().find_map(|s| match s {
+        Statements::FunctionDeclaration {
+            name, return_type, ..
+        } if name == "main" => Some(return_type.clone()),
+        _ => None,
+    });
+
     unsafe {
-        type Addition = unsafe extern "C" fn(i32, i32) -> i32;
-        let add: JitFunction<Addition> = execution_engine.get_function("add").unwrap();
-        let x = 1;
-        let y = 2;
-        println!("{}", add.call(x, y))
+        match main_return_type {
+            Some(Type::Int32) => {
+                type Main = unsafe extern "C" fn() -> i32;
+                let main: JitFunction<Main> = execution_engine.get_function("main").unwrap();
+                println!("{}", main.call())
+            }
+            Some(Type::Void) => {
+                type Main = unsafe extern "C" fn();
+                let main: JitFunction<Main> = execution_engine.get_function("main").unwrap();
+                main.call()
+            }
+            // Sema only accepts the types above
+            _ => {}
+        }
     }
 
     let contents = serde_json::to_string_pretty(&program).unwrap();
     fs::write(output, contents).unwrap();
 }
 
-fn main() -> Result<(), CompilerError> {
-    let args = {
-        let args = env::args().into_iter();
-        let mut arg_str: Vec<String> = vec![];
-        for e in args {
-            arg_str.push(e)
+/// Searches the directory of `input` for imports before `search_paths`.
+fn input_search_paths(input: &Path, mut search_paths: Vec<PathBuf>) -> Vec<PathBuf> {
+    search_paths.insert(
+        0,
+        input.parent().map(|dir| dir.to_path_buf()).unwrap_or_default(),
+    );
+    search_paths
+}
+
+/// Loads and checks a program like `build` does, but without writing
+/// anything to disk. Returns the resolved declarations of every module,
+/// dependencies first, and the exports of the entry module.
+fn check_program(entry: Option<&Path>, package: &[(String, PathBuf)], search_paths: &[PathBuf]) -> Program {
+    let modules = match modules::load(entry, package, search_paths, None) {
+        Ok(modules) => modules,
+        Err(error) => exit_with_errors(vec![error]),
+    };
+
+    // Modules are checked against the interfaces of the modules checked
+    // before them
+    let mut checked: HashMap<String, Interface> = HashMap::new();
+    let mut statements = vec![];
+    let mut exports = vec![];
+
+    for module in &modules {
+        let mut interfaces: HashMap<String, Interface> = HashMap::new();
+        collect_interfaces(&checked, module.imports(), &mut interfaces);
+
+        let (resolved, _) = match check_module(module, &modules, &interfaces) {
+            Ok(checked) => checked,
+            Err(errors) => exit_with_errors(errors),
+        };
+
+        if !module.name.is_empty() {
+            let interface = Interface::new(module, &module.name, &resolved);
+            checked.insert(module.name.clone(), interface);
+        } else {
+            exports = resolved.exports;
+        }
+        statements.extend(resolved.statements);
+    }
+
+    Program { statements, exports }
+}
+
+/// Checks and runs the program `input` with the interpreter, printing the
+/// result of `main` like the JIT does.
+fn interpret(input: String, search_paths: Vec<PathBuf>) {
+    let input = PathBuf::from(input);
+    let search_paths = input_search_paths(&input, search_paths);
+    let program = check_program(Some(&input), &[], &search_paths);
+
+    match interp::run(program) {
+        Ok(Some(value)) => println!("{}", value),
+        Ok(None) => {}
+        Err(message) => exit_with_errors(vec![CompilerError {
+            code: 701,
+            kind: CompilerErrorKind::RuntimeError,
+            message,
+        }]),
+    }
+}
+
+/// Translates `program` to C, writing the source to `output` with a `.c`
+/// extension and the runtime header next to it. Returns the path of the C
+/// file.
+fn emit_c(program: &Program, output: &Path) -> PathBuf {
+    let source = match cgen::translate(program) {
+        Ok(source) => source,
+        Err(message) => exit_with_errors(vec![build_error(message)]),
+    };
+
+    let path = output.with_extension("c");
+    let header = path.with_file_name("aurora.h");
+    if let Some(dir) = path.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        if let Err(e) = fs::create_dir_all(dir) {
+            exit_with_errors(vec![build_error(format!("can not create {}: {}", dir.display(), e))]);
+        }
+    }
+    for (path, contents) in [(&path, source.as_str()), (&header, cgen::RUNTIME_HEADER)] {
+        if let Err(e) = fs::write(path, contents) {
+            exit_with_errors(vec![build_error(format!("can not write {}: {}", path.display(), e))]);
+        }
+    }
+
+    path
+}
+
+/// Builds the program `input` with the C backend. An `output` ending in `.c`
+/// only asks for the C source, anything else is compiled and linked by the
+/// system C compiler.
+fn build_c(
+    input: &Path,
+    search_paths: Vec<PathBuf>,
+    output: &Path,
+    opt_level: OptLevel,
+    debug_info: bool,
+) {
+    let search_paths = input_search_paths(input, search_paths);
+    let program = check_program(Some(input), &[], &search_paths);
+    let source = emit_c(&program, output);
+    if output.extension().is_some_and(|ext| ext == "c") {
+        return;
+    }
+
+    let object = output.with_extension("o");
+    if let Err(error) = package::compile_c(&source, &object, opt_level, debug_info)
+        .and_then(|_| package::link(&[object], Kind::Bin, output))
+    {
+        exit_with_errors(vec![error]);
+    }
+}
+
+/// Writes a C header for the public items of `input` to `output`, or to a
+/// `.h` file named after the input in the current directory. Public items C
+/// can not use are left out with a warning.
+fn emit_header(input: &Path, search_paths: Vec<PathBuf>, output: Option<&str>) {
+    let search_paths = input_search_paths(input, search_paths);
+    let program = check_program(Some(input), &[], &search_paths);
+
+    let output = match output {
+        Some(output) => PathBuf::from(output),
+        None => PathBuf::from(input.file_stem().unwrap_or_default()).with_extension("h"),
+    };
+    let file_name = output.file_name().unwrap_or_default().to_string_lossy();
+    let header = match cgen::header(&program, &file_name) {
+        Ok(header) => header,
+        Err(message) => exit_with_errors(vec![build_error(message)]),
+    };
+
+    for reason in &header.skipped {
+        eprintln!("warning: {}, it is left out of the header", reason);
+    }
+    if let Some(dir) = output.parent().filter(|dir| !dir.as_os_str().is_empty()) {
+        if let Err(e) = fs::create_dir_all(dir) {
+            exit_with_errors(vec![build_error(format!("can not create {}: {}", dir.display(), e))]);
         }
-        arg_str
+    }
+    if let Err(e) = fs::write(&output, header.source) {
+        exit_with_errors(vec![build_error(format!("can not write {}: {}", output.display(), e))]);
+    }
+}
+
+/// What code is generated for, given by `--target`, `--target-cpu` and
+/// `--target-feature`, see `target::target_machine`.
+#[derive(Debug, Clone, Default)]
+struct TargetOptions {
+    /// Triple to compile for, the host's if there is none
+    triple: Option<String>,
+    cpu: Option<String>,
+    /// Features like `+sve` or `-avx2`, in the order they were given
+    features: Vec<String>,
+}
+
+/// What `build` compiles and where to.
+#[cfg(feature = "llvm")]
+struct BuildOptions {
+    entry: Option<PathBuf>,
+    /// Modules of the package being built, see `modules::load`
+    package: Vec<(String, PathBuf)>,
+    search_paths: Vec<PathBuf>,
+    cache_dir: PathBuf,
+    reuse_cache: bool,
+    opt_level: OptLevel,
+    /// Pipeline given by `--llvm-passes`, see `passes::optimize`
+    llvm_passes: Option<String>,
+    /// Whether to emit DWARF debug information, with `-g`
+    debug_info: bool,
+    /// Whether to run the LLVM verifier over every module, see
+    /// `verify::verify_module`
+    verify_ir: bool,
+    target: TargetOptions,
+}
+
+/// What `build` produced, ready to be linked.
+#[cfg(feature = "llvm")]
+struct Built {
+    /// Object files of the modules and of the runtime
+    objects: Vec<PathBuf>,
+    /// Public functions of every module, which WebAssembly modules export
+    exports: Vec<String>,
+    /// Return type of `main`, if the program has one
+    main: Option<Type>,
+}
+
+/// Compiles every module to an object file in the cache. Modules whose
+/// source and the interfaces of whose dependencies are unchanged since the
+/// last build are skipped.
+#[cfg(feature = "llvm")]
+fn build(options: BuildOptions) -> Built {
+    // Objects compiled with other options than these are out of date
+    let codegen = format!(
+        "{:?} {:?} {} {:?}",
+        options.opt_level, options.llvm_passes, options.debug_info, options.target
+    );
+    let mut cache = match Cache::open(options.cache_dir, options.reuse_cache, codegen) {
+        Ok(cache) => cache,
+        Err(error) => exit_with_errors(vec![error]),
+    };
+    let modules = match modules::load(
+        options.entry.as_deref(),
+        &options.package,
+        &options.search_paths,
+        Some(&mut cache),
+    ) {
+        Ok(modules) => modules,
+        Err(error) => exit_with_errors(vec![error]),
+    };
+    cache.stats.modules = modules.len();
+
+    let machine = match target::target_machine(
+        options.target.triple.as_deref(),
+        options.target.cpu.as_deref(),
+        &options.target.features,
+        options.opt_level.optimization_level(),
+    ) {
+        Ok(machine) => machine,
+        Err(message) => exit_with_errors(vec![build_error(message)]),
     };
+    let context = Context::create();
+    let mut objects = vec![];
+
+    for module in &modules {
+        let key = module.cache_key();
+        let has_interface = !module.name.is_empty();
+        objects.push(cache.path(&key, "o"));
+
+        let mut interfaces: HashMap<String, Interface> = HashMap::new();
+        if let Err(error) = read_interfaces(cache.dir(), module.imports(), &mut interfaces) {
+            exit_with_errors(vec![error]);
+        }
+        let dependencies: BTreeMap<String, u64> = interfaces
+            .iter()
+            .map(|(name, interface)| (name.clone(), interface.hash()))
+            .collect();
+
+        if cache.is_compiled(&key, module.source_hash, &dependencies, has_interface) {
+            continue;
+        }
 
-    let mut output: String = "a.out".to_string();
+        let (program, imported) = match check_module(module, &modules, &interfaces) {
+            Ok(checked) => checked,
+            Err(errors) => exit_with_errors(errors),
+        };
+
+        let llvm_module = context.create_module(module.llvm_name());
+        target::configure_module(&context, &machine, &llvm_module);
+        if let Err(message) = compile_module(
+            &context,
+            &llvm_module,
+            &imported,
+            &program,
+            options.debug_info.then_some(module.path.as_path()),
+            options.opt_level,
+        ) {
+            exit_with_errors(vec![build_error(message)]);
+        }
+        if options.target.triple.as_deref().is_some_and(target::is_wasm) {
+            if let Err(message) = runtime::check_wasm_module(&llvm_module) {
+                exit_with_errors(vec![build_error(message)]);
+            }
+        }
+        if options.verify_ir {
+            if let Err(message) = verify::verify_module(&llvm_module) {
+                exit_with_errors(vec![internal_error(message)]);
+            }
+        }
+        if let Err(message) = passes::optimize(
+            &llvm_module,
+            &machine,
+            options.opt_level,
+            options.llvm_passes.as_deref(),
+        ) {
+            exit_with_errors(vec![build_error(message)]);
+        }
+        if let Err(message) = target::write_object(&machine, &llvm_module, &cache.path(&key, "o")) {
+            exit_with_errors(vec![build_error(message)]);
+        }
+
+        if has_interface {
+            let interface = Interface::new(module, &module.name, &program);
+            if let Err(error) = interface.write(cache.dir()) {
+                exit_with_errors(vec![error]);
+            }
+        }
+        if let Err(error) = cache.store_compiled(&key, module.source_hash, dependencies) {
+            exit_with_errors(vec![error]);
+        }
+    }
+
+    // Not a valid module name, so it can not clash with one
+    let runtime_object = cache.path("aurora-runtime", "o");
+    let runtime_module = context.create_module("aurora-runtime");
+    target::configure_module(&context, &machine, &runtime_module);
+    let main = modules
+        .iter()
+        .filter(|module| module.name.is_empty())
+        .flat_map(|module| &module.program.statements)
+        .find_map(|s| match s {
+            Statements::FunctionDeclaration {
+                name, return_type, ..
+            } if name == "main" => Some(return_type.clone()),
+            _ => None,
+        });
+    match options.target.triple.as_deref() {
+        Some(triple) if target::is_wasm(triple) => runtime::define_wasm_runtime_functions(
+            &context,
+            &runtime_module,
+            target::is_wasi(triple),
+            main.as_ref(),
+        ),
+        _ => runtime::define_runtime_functions(&context, &runtime_module),
+    }
+    let optimized = passes::optimize(&runtime_module, &machine, options.opt_level, None);
+    if let Err(message) =
+        optimized.and_then(|_| target::write_object(&machine, &runtime_module, &runtime_object))
+    {
+        exit_with_errors(vec![build_error(message)]);
+    }
+    objects.push(runtime_object);
+
+    println!("{}", cache.stats);
+    Built {
+        objects,
+        exports: modules.iter().flat_map(|module| module.public_functions()).collect(),
+        main,
+    }
+}
+
+/// Links what `build` produced for `triple` into `output`, a `.wasm` module
+/// for WebAssembly, a static library for other targets than the host and as
+/// `kind` says otherwise.
+#[cfg(feature = "llvm")]
+fn link(built: &Built, kind: Kind, triple: Option<&str>, output: &Path) -> Result<(), CompilerError> {
+    match triple {
+        Some(triple) if target::is_wasm(triple) => {
+            let entry = kind == Kind::Bin && target::is_wasi(triple) && built.main.is_some();
+            package::link_wasm(&built.objects, &built.exports, entry, output)
+        }
+        // `ar` does not care what the objects were compiled for
+        Some(_) => package::link(&built.objects, Kind::Lib, output),
+        None => package::link(&built.objects, kind, output),
+    }
+}
+
+/// Builds the package the current directory is in into a binary or static
+/// library under `target/<profile>`.
+#[cfg_attr(not(feature = "llvm"), allow(unused_variables))]
+#[allow(clippy::too_many_arguments)]
+fn build_package(
+    profile_name: &str,
+    opt_level: Option<OptLevel>,
+    llvm_passes: Option<&str>,
+    debug_info: bool,
+    verify_ir: bool,
+    reuse_cache: bool,
+    backend: Backend,
+    target: &TargetOptions,
+) {
+    let dir = env::current_dir().unwrap_or_default();
+    let package = match Package::find(&dir) {
+        Ok(package) => package,
+        Err(error) => exit_with_errors(vec![error]),
+    };
+    let info = &package.manifest.package;
+
+    let (profile, modules, search_paths) = match (
+        package.manifest.profile(profile_name),
+        package.modules(),
+        package.search_paths(),
+    ) {
+        (Ok(profile), Ok(modules), Ok(search_paths)) => (profile, modules, search_paths),
+        (profile, modules, search_paths) => exit_with_errors(
+            [profile.err(), modules.err(), search_paths.err()]
+                .into_iter()
+                .flatten()
+                .collect(),
+        ),
+    };
+
+    // `-O` on the command line wins over the profile
+    let opt_level = opt_level.unwrap_or(profile.opt_level);
+
+    println!("Compiling {} v{} ({})", info.name, info.version, profile_name);
+    let output = package.output(profile_name, target.triple.as_deref());
+
+    let result = match backend {
+        #[cfg(feature = "llvm")]
+        Backend::Llvm => {
+            let cache_dir = match &target.triple {
+                Some(triple) => package.root.join("target/aurora-cache").join(triple),
+                None => package.root.join("target/aurora-cache"),
+            };
+            let built = build(BuildOptions {
+                entry: package.entry(),
+                package: modules,
+                search_paths,
+                cache_dir: cache_dir.join(profile_name),
+                reuse_cache,
+                opt_level,
+                llvm_passes: llvm_passes.map(str::to_string),
+                debug_info,
+                verify_ir,
+                target: target.clone(),
+            });
+            link(&built, info.kind, target.triple.as_deref(), &output)
+        }
+        #[cfg(not(feature = "llvm"))]
+        Backend::Llvm => exit_with_errors(vec![llvm_required("The llvm backend")]),
+        // The whole program is translated to one C file, which is always
+        // compiled from scratch
+        Backend::C => {
+            let program = check_program(package.entry().as_deref(), &modules, &search_paths);
+            let source = emit_c(&program, &output.with_file_name(&info.name));
+            let object = source.with_extension("o");
+            package::compile_c(&source, &object, opt_level, debug_info)
+                .and_then(|_| package::link(&[object], info.kind, &output))
+        }
+        Backend::Interp => unreachable!("the interpreter can not build packages"),
+    };
+
+    if let Err(error) = result {
+        exit_with_errors(vec![error]);
+    }
+    println!("Finished {}", output.display());
+}
+
+/// How a program is run or built.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Backend {
+    /// Compiled to machine code by LLVM, and run by its JIT
+    Llvm,
+    /// Run by the tree-walking interpreter, can not build
+    Interp,
+    /// Translated to C and built by the system C compiler, can not run
+    C,
+}
+
+impl Backend {
+    fn parse(name: &str) -> Result<Self, CompilerError> {
+        match name {
+            "llvm" => Ok(Backend::Llvm),
+            "interp" => Ok(Backend::Interp),
+            "c" => Ok(Backend::C),
+            _ => Err(CompilerError {
+                code: 1,
+                kind: CompilerErrorKind::CommandLineError,
+                message: format!("Unknown backend `{}`, expected `llvm`, `interp` or `c`", name),
+            }),
+        }
+    }
+
+    /// The backend `build` uses unless told otherwise.
+    fn default_build() -> Self {
+        if cfg!(feature = "llvm") {
+            Backend::Llvm
+        } else {
+            Backend::C
+        }
+    }
+
+    /// The backend programs are run with unless told otherwise.
+    fn default_run() -> Self {
+        if cfg!(feature = "llvm") {
+            Backend::Llvm
+        } else {
+            Backend::Interp
+        }
+    }
+}
+
+#[cfg(not(feature = "llvm"))]
+fn llvm_required(what: &str) -> CompilerError {
+    CompilerError {
+        code: 1,
+        kind: CompilerErrorKind::CommandLineError,
+        message: format!(
+            "{} needs LLVM, but aurorac was built without the `llvm` feature",
+            what
+        ),
+    }
+}
+
+// Options of the LLVM backend are still accepted without it
+#[cfg_attr(not(feature = "llvm"), allow(unused_variables, unused_assignments))]
+fn main() -> Result<(), CompilerError> {
+    let args: Vec<String> = env::args().collect();
+
+    let mut output: Option<String> = None;
+    let mut search_paths: Vec<PathBuf> = vec![];
+    let mut interface_dir: Option<PathBuf> = None;
+    let mut cache_dir = PathBuf::from("target/aurora-cache");
+    let mut reuse_cache = true;
+    let mut profile = "debug".to_string();
+    let mut kind = Kind::Bin;
+    let mut backend: Option<Backend> = None;
+    let mut target = TargetOptions::default();
+    let mut opt_level: Option<OptLevel> = None;
+    let mut llvm_passes: Option<String> = None;
+    let mut debug_info = false;
+    let mut verify_ir = false;
+    let mut emit_header_file = false;
 
     if args.len() < 2 {
         return Err(CompilerError {
-            code: 01,
+            code: 1,
             kind: CompilerErrorKind::CommandLineError,
             message: "Not enough arguments provided".to_string(),
         });
     }
 
-    let mut pos: usize = 0;
-    let input = (&args.clone()[pos + 1]).to_owned();
-    for arg in &args {
+    // `build`, `emit`, `new` and `run` are subcommands, anything else is a
+    // file to compile and run
+    let command = match args[1].as_str() {
+        "build" | "emit" | "new" | "run" => args[1].as_str(),
+        _ => "",
+    };
+    // The first argument that is neither an option nor the value of one
+    let options_with_value = [
+        "--output",
+        "-o",
+        "--search-path",
+        "-I",
+        "--interface-dir",
+        "--cache-dir",
+        "--profile",
+        "--backend",
+        "--target",
+        "--target-cpu",
+        "--target-feature",
+        "--llvm-passes",
+    ];
+    let input = args
+        .iter()
+        .enumerate()
+        .skip(1 + !command.is_empty() as usize)
+        .find(|(pos, arg)| !arg.starts_with('-') && !options_with_value.contains(&args[pos - 1].as_str()))
+        .map(|(_, arg)| arg.clone());
+
+    for (pos, arg) in args.iter().enumerate() {
         if arg == "--output" || arg == "-o" {
             let o = &args.clone()[pos + 1];
-            output = o.to_string();
+            output = Some(o.to_string());
+        }
+
+        if arg == "--search-path" || arg == "-I" {
+            search_paths.push(PathBuf::from(&args[pos + 1]));
         }
 
-        pos = pos + 1
+        if arg == "--interface-dir" {
+            interface_dir = Some(PathBuf::from(&args[pos + 1]));
+        }
+
+        if arg == "--cache-dir" {
+            cache_dir = PathBuf::from(&args[pos + 1]);
+        }
+
+        if arg == "--no-cache" {
+            reuse_cache = false;
+        }
+
+        if arg == "--release" {
+            profile = "release".to_string();
+        }
+
+        if arg == "--profile" {
+            profile = args[pos + 1].clone();
+        }
+
+        if arg == "--lib" {
+            kind = Kind::Lib;
+        }
+
+        if arg == "--backend" {
+            backend = Some(Backend::parse(&args[pos + 1])?);
+        } else if let Some(name) = arg.strip_prefix("--backend=") {
+            backend = Some(Backend::parse(name)?);
+        }
+
+        if arg == "--target" {
+            target.triple = Some(args[pos + 1].clone());
+        } else if let Some(triple) = arg.strip_prefix("--target=") {
+            target.triple = Some(triple.to_string());
+        }
+
+        if arg == "--target-cpu" {
+            target.cpu = Some(args[pos + 1].clone());
+        } else if let Some(cpu) = arg.strip_prefix("--target-cpu=") {
+            target.cpu = Some(cpu.to_string());
+        }
+
+        if arg == "--target-feature" {
+            target.features.push(args[pos + 1].clone());
+        } else if let Some(feature) = arg.strip_prefix("--target-feature=") {
+            target.features.push(feature.to_string());
+        }
+
+        if let Some(level) = arg.strip_prefix("-O") {
+            opt_level = Some(OptLevel::parse(level).ok_or(CompilerError {
+                code: 1,
+                kind: CompilerErrorKind::CommandLineError,
+                message: format!("Unknown optimisation level `{}`, expected -O0, -O1, -O2, -O3 or -Os", arg),
+            })?);
+        }
+
+        if arg == "-g" {
+            debug_info = true;
+        }
+
+        if arg == "--verify-ir" {
+            verify_ir = true;
+        }
+
+        if arg == "--header" {
+            emit_header_file = true;
+        }
+
+        if arg == "--llvm-passes" {
+            llvm_passes = Some(args[pos + 1].clone());
+        } else if let Some(passes) = arg.strip_prefix("--llvm-passes=") {
+            llvm_passes = Some(passes.to_string());
+        }
+    }
+
+    // The backend the command will use
+    let chosen_backend = match command {
+        "build" => backend.unwrap_or(Backend::default_build()),
+        _ => backend.unwrap_or(Backend::default_run()),
+    };
+
+    if target.triple.is_some() || target.cpu.is_some() || !target.features.is_empty() {
+        let message = match (command, chosen_backend, &target.triple) {
+            (_, Backend::Interp, _) | (_, Backend::C, _) => Some(
+                "`--target`, `--target-cpu` and `--target-feature` are only supported by the llvm backend"
+                    .to_string(),
+            ),
+            ("build", _, _) => None,
+            (_, _, Some(triple)) => Some(format!(
+                "Code for `{}` can not be run by the JIT, build it with `aurorac build --target {}`",
+                triple, triple
+            )),
+            (_, _, None) => Some(
+                "The JIT always compiles for the host, `--target-cpu` and `--target-feature` only apply to `aurorac build`"
+                    .to_string(),
+            ),
+        };
+        if let Some(message) = message {
+            return Err(CompilerError {
+                code: 1,
+                kind: CompilerErrorKind::CommandLineError,
+                message,
+            });
+        }
+    }
+
+    if llvm_passes.is_some() && chosen_backend != Backend::Llvm {
+        return Err(CompilerError {
+            code: 1,
+            kind: CompilerErrorKind::CommandLineError,
+            message: "`--llvm-passes` is only supported by the llvm backend".to_string(),
+        });
+    }
+
+    if verify_ir && chosen_backend != Backend::Llvm {
+        return Err(CompilerError {
+            code: 1,
+            kind: CompilerErrorKind::CommandLineError,
+            message: "`--verify-ir` is only supported by the llvm backend".to_string(),
+        });
+    }
+    // Debug builds of aurorac always check what they generate
+    let verify_ir = verify_ir || cfg!(debug_assertions);
+
+    if debug_info && chosen_backend == Backend::Interp {
+        return Err(CompilerError {
+            code: 1,
+            kind: CompilerErrorKind::CommandLineError,
+            message: "The interp backend has no debug information, `-g` needs the llvm or c backend"
+                .to_string(),
+        });
     }
 
-    compile(input, output);
+    match (command, input) {
+        ("new", Some(name)) => {
+            if let Err(error) = package::new(Path::new(&name), kind) {
+                exit_with_errors(vec![error]);
+            }
+        }
+        ("new", None) => {
+            return Err(CompilerError {
+                code: 1,
+                kind: CompilerErrorKind::CommandLineError,
+                message: "No package name provided".to_string(),
+            })
+        }
+        ("build", _) if backend == Some(Backend::Interp) => {
+            return Err(CompilerError {
+                code: 1,
+                kind: CompilerErrorKind::CommandLineError,
+                message: "The interp backend can only run programs, not build them".to_string(),
+            })
+        }
+        ("build", Some(input)) => match backend.unwrap_or(Backend::default_build()) {
+            // `aurorac build <file>` only compiles the object files of the
+            // program unless `-o` names an executable to link them into,
+            // packages are linked as their manifest says. Builds for other
+            // targets are linked right away, since their objects are of no
+            // use on the host: WebAssembly into a module and the rest into a
+            // static library for the cross toolchain.
+            #[cfg(feature = "llvm")]
+            Backend::Llvm => {
+                let input = PathBuf::from(input);
+                search_paths.insert(
+                    0,
+                    input.parent().map(|dir| dir.to_path_buf()).unwrap_or_default(),
+                );
+                let stem = input.file_stem().unwrap_or_default().to_string_lossy().to_string();
+                let linked_output = match (target.triple.as_deref(), &output) {
+                    (_, Some(output)) => Some(PathBuf::from(output)),
+                    (None, None) => None,
+                    (Some(triple), None) if target::is_wasm(triple) => {
+                        Some(PathBuf::from(format!("{}.wasm", stem)))
+                    }
+                    (Some(_), None) => Some(PathBuf::from(format!("lib{}.a", stem))),
+                };
+
+                let built = build(BuildOptions {
+                    entry: Some(input),
+                    package: vec![],
+                    search_paths,
+                    cache_dir: match &target.triple {
+                        Some(triple) => cache_dir.join(triple),
+                        None => cache_dir,
+                    },
+                    reuse_cache,
+                    opt_level: opt_level.unwrap_or_default(),
+                    llvm_passes,
+                    debug_info,
+                    verify_ir,
+                    target: target.clone(),
+                });
+                if let Some(output) = linked_output {
+                    if let Err(error) = link(&built, Kind::Bin, target.triple.as_deref(), &output) {
+                        exit_with_errors(vec![error]);
+                    }
+                }
+            }
+            #[cfg(not(feature = "llvm"))]
+            Backend::Llvm => return Err(llvm_required("The llvm backend")),
+            Backend::C => build_c(
+                Path::new(&input),
+                search_paths,
+                Path::new(output.as_deref().unwrap_or("a.out")),
+                opt_level.unwrap_or_default(),
+                debug_info,
+            ),
+            Backend::Interp => unreachable!(),
+        },
+        ("emit", _) if !emit_header_file => {
+            return Err(CompilerError {
+                code: 1,
+                kind: CompilerErrorKind::CommandLineError,
+                message: "Nothing to emit, `aurorac emit` needs `--header`".to_string(),
+            })
+        }
+        ("emit", Some(input)) => emit_header(Path::new(&input), search_paths, output.as_deref()),
+        ("build", None) => build_package(
+            &profile,
+            opt_level,
+            llvm_passes.as_deref(),
+            debug_info,
+            verify_ir,
+            reuse_cache,
+            backend.unwrap_or(Backend::default_build()),
+            &target,
+        ),
+        (_, Some(input)) => match backend.unwrap_or(Backend::default_run()) {
+            #[cfg(feature = "llvm")]
+            Backend::Llvm => compile(
+                input,
+                output.unwrap_or("a.out".to_string()),
+                search_paths,
+                interface_dir,
+                opt_level.unwrap_or_default(),
+                llvm_passes.as_deref(),
+                debug_info,
+                verify_ir,
+            ),
+            #[cfg(not(feature = "llvm"))]
+            Backend::Llvm => return Err(llvm_required("The llvm backend")),
+            Backend::Interp => interpret(input, search_paths),
+            Backend::C => {
+                return Err(CompilerError {
+                    code: 1,
+                    kind: CompilerErrorKind::CommandLineError,
+                    message: "The c backend can only build programs, use `aurorac build --backend=c`"
+                        .to_string(),
+                })
+            }
+        },
+        (_, None) => {
+            return Err(CompilerError {
+                code: 1,
+                kind: CompilerErrorKind::CommandLineError,
+                message: "No file to compile provided".to_string(),
+            })
+        }
+    }
 
     Ok(())
 }
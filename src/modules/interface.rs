@@ -0,0 +1,175 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    ast::{statements::Statements, Program},
+    error::CompilerError,
+};
+
+use super::{module_error, resolve::declared_name, Module};
+
+/// Everything importers need to know about a compiled module, written as a
+/// `.auri` file next to the compiler output. Importers are compiled against
+/// the interfaces of their dependencies instead of their source, so a module
+/// only has to be recompiled when the interface of one of its imports
+/// changes.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Interface {
+    /// Dotted import path of the module
+    pub name: String,
+    /// Hash of the module's source code
+    pub source_hash: u64,
+    /// Modules imported by the module, whose declarations the items below
+    /// may refer to
+    pub imports: Vec<String>,
+    pub items: Vec<InterfaceItem>,
+    /// Impl blocks of the module. Methods are reachable through their type,
+    /// so they are part of the interface whether or not they are public.
+    pub impls: Vec<Statements>,
+}
+
+/// A top-level item of a module. Private items are included when importers
+/// may still need them, e.g. the layout of a private struct returned by a
+/// public function, but can not be named outside the module.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InterfaceItem {
+    /// Name the item is declared as in the module
+    pub name: String,
+    pub public: bool,
+    /// The resolved declaration. Function bodies are removed, except those of
+    /// generic functions, which are instantiated by the importer.
+    pub declaration: Statements,
+}
+
+/// 64 bit FNV-1a. Unlike `DefaultHasher` the result does not depend on the
+/// version of the compiler, so hashes written to disk stay comparable.
+pub fn hash(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf29ce484222325, |hash, byte| {
+        (hash ^ *byte as u64).wrapping_mul(0x100000001b3)
+    })
+}
+
+/// Drops the bodies of the functions in `statement` that importers only
+/// need to call.
+fn signature_only(statement: &Statements) -> Statements {
+    match statement {
+        Statements::FunctionDeclaration {
+            name,
+            type_params,
+            params,
+            return_type,
+            is_const: false,
+            is_extern,
+            ..
+        } if type_params.is_empty() => Statements::FunctionDeclaration {
+            name: name.clone(),
+            type_params: vec![],
+            params: params.clone(),
+            body: vec![],
+            return_type: return_type.clone(),
+            is_const: false,
+            is_extern: *is_extern,
+            // Keeps the interface the same when only lines move
+            line: 0,
+        },
+        Statements::ImplDeclaration {
+            r#trait,
+            r#type,
+            methods,
+        } => Statements::ImplDeclaration {
+            r#trait: r#trait.clone(),
+            r#type: r#type.clone(),
+            methods: methods.iter().map(signature_only).collect(),
+        },
+        statement => statement.clone(),
+    }
+}
+
+impl Interface {
+    /// Builds the interface of `module` from its resolved `program`.
+    pub fn new(module: &Module, name: &str, program: &Program) -> Self {
+        let exported: HashSet<&String> = module
+            .program
+            .exports
+            .iter()
+            .filter_map(|export| declared_name(&export.statement))
+            .collect();
+        let prefix = format!("{}.", module.name);
+
+        let items = program
+            .statements
+            .iter()
+            .filter_map(|statement| {
+                let qualified = declared_name(statement)?;
+                let name = qualified.strip_prefix(&prefix).unwrap_or(qualified);
+
+                Some(InterfaceItem {
+                    name: name.to_string(),
+                    public: exported.contains(&name.to_string()),
+                    declaration: signature_only(statement),
+                })
+            })
+            .collect();
+
+        let impls = program
+            .statements
+            .iter()
+            .filter(|s| matches!(s, Statements::ImplDeclaration { .. }))
+            .map(signature_only)
+            .collect();
+
+        Self {
+            name: name.to_string(),
+            source_hash: module.source_hash,
+            imports: module.imports(),
+            items,
+            impls,
+        }
+    }
+
+    /// Hash of the parts of the interface importers depend on. Edits that
+    /// leave it unchanged, like those to the bodies of non-generic functions,
+    /// do not require the importers to be compiled again.
+    pub fn hash(&self) -> u64 {
+        let contents = serde_json::to_string(&(&self.imports, &self.items, &self.impls)).unwrap();
+        hash(contents.as_bytes())
+    }
+
+    /// The declarations importers compile against.
+    pub fn declarations(&self) -> impl Iterator<Item = &Statements> {
+        self.items
+            .iter()
+            .map(|item| &item.declaration)
+            .chain(self.impls.iter())
+    }
+
+    pub fn path(dir: &Path, name: &str) -> PathBuf {
+        dir.join(format!("{}.auri", name))
+    }
+
+    pub fn write(&self, dir: &Path) -> Result<(), CompilerError> {
+        let path = Self::path(dir, &self.name);
+        let contents = serde_json::to_string(self).unwrap();
+
+        fs::write(&path, contents)
+            .map_err(|e| module_error(106, format!("can not write {}: {}", path.display(), e)))
+    }
+
+    pub fn read(dir: &Path, name: &str) -> Result<Self, CompilerError> {
+        let path = Self::path(dir, name);
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| module_error(106, format!("can not read {}: {}", path.display(), e)))?;
+
+        serde_json::from_str(&contents).map_err(|e| {
+            module_error(
+                106,
+                format!("{} is not a valid interface file: {}", path.display(), e),
+            )
+        })
+    }
+}
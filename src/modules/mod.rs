@@ -0,0 +1,247 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{
+    ast::{statements::Statements, Program},
+    cache::Cache,
+    error::{CompilerError, CompilerErrorKind},
+    lexer::Lexer,
+    parser::parser::Parser,
+};
+
+mod interface;
+mod resolve;
+mod stdlib;
+
+pub use interface::Interface;
+pub use resolve::resolve;
+
+/// A source file and the name it is imported by.
+pub struct Module {
+    /// Dotted import path, e.g. `std.io`. Empty for the entry module, whose
+    /// items keep their names unqualified.
+    pub name: String,
+    pub path: PathBuf,
+    /// Hash of the source code, see `interface::hash`
+    pub source_hash: u64,
+    pub program: Program,
+}
+
+impl Module {
+    /// The name given by the `module` declaration at the top of the file.
+    pub fn declared_name(&self) -> Option<&str> {
+        self.program.statements.iter().find_map(|s| match s {
+            Statements::ModuleDeclaration { name } => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Name of the LLVM module the module is compiled to.
+    pub fn llvm_name(&self) -> &str {
+        if self.name.is_empty() {
+            self.declared_name().unwrap_or_default()
+        } else {
+            &self.name
+        }
+    }
+
+    /// Name the module's files are cached under, see `cache_key`.
+    pub fn cache_key(&self) -> String {
+        cache_key(&self.name, &self.path)
+    }
+
+    /// Names of the public functions of the module that are compiled as
+    /// they are, that is all but generic ones.
+    pub fn public_functions(&self) -> Vec<String> {
+        self.program
+            .exports
+            .iter()
+            .filter_map(|export| match &export.statement {
+                statement @ Statements::FunctionDeclaration {
+                    name, type_params, ..
+                } if type_params.is_empty() => {
+                    Some(resolve::qualified_name(&self.name, name, statement))
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Dotted paths of the modules imported by this module.
+    pub fn imports(&self) -> Vec<String> {
+        self.program
+            .statements
+            .iter()
+            .filter_map(|s| match s {
+                Statements::Import { path, .. } => Some(path.join(".")),
+                _ => None,
+            })
+            .collect()
+    }
+}
+
+fn module_error(code: usize, message: String) -> CompilerError {
+    CompilerError {
+        code,
+        kind: CompilerErrorKind::ModuleError,
+        message,
+    }
+}
+
+fn read_file(path: &Path) -> Result<String, CompilerError> {
+    fs::read_to_string(path)
+        .map_err(|e| module_error(101, format!("can not read {}: {}", path.display(), e)))
+}
+
+fn parse_source(code: &str) -> Program {
+    let lexer = Lexer::new(code.chars().collect());
+    let mut parser = Parser::new(lexer);
+    let statements = parser.parse();
+
+    Program::compile((statements, parser.exports))
+}
+
+/// Loads `entry` and every module it imports, directly or indirectly, along
+/// with the modules of a `package` that may not be imported by anything.
+/// Modules are returned in dependency order, so the entry module is last.
+/// With a `cache`, modules whose source is unchanged are not parsed again.
+pub fn load(
+    entry: Option<&Path>,
+    package: &[(String, PathBuf)],
+    search_paths: &[PathBuf],
+    cache: Option<&mut Cache>,
+) -> Result<Vec<Module>, CompilerError> {
+    let mut loader = Loader {
+        search_paths: search_paths.to_vec(),
+        cache,
+        modules: vec![],
+        loaded: HashSet::new(),
+        stack: vec![],
+    };
+
+    for (name, path) in package {
+        let path = path.canonicalize().unwrap_or(path.clone());
+        if !loader.loaded.contains(&path) {
+            loader.load(name.clone(), path)?;
+        }
+    }
+    if let Some(entry) = entry {
+        loader.load(String::new(), entry.to_path_buf())?;
+    }
+
+    Ok(loader.modules)
+}
+
+struct Loader<'a> {
+    search_paths: Vec<PathBuf>,
+    cache: Option<&'a mut Cache>,
+    modules: Vec<Module>,
+    loaded: HashSet<PathBuf>,
+    /// Modules being loaded, to detect import cycles
+    stack: Vec<(String, PathBuf)>,
+}
+
+impl<'a> Loader<'a> {
+    fn load(&mut self, name: String, path: PathBuf) -> Result<(), CompilerError> {
+        let path = path.canonicalize().unwrap_or(path);
+        let code = match stdlib::source(&path) {
+            Some(source) => source.to_string(),
+            None => read_file(&path)?,
+        };
+        let source_hash = interface::hash(code.as_bytes());
+        let program = self.parse(&cache_key(&name, &path), &code, source_hash)?;
+        self.stack.push((name.clone(), path.clone()));
+
+        for statement in &program.statements {
+            if let Statements::Import { path: import, .. } = statement {
+                let import = import.join(".");
+                let file = self.find(&import)?;
+
+                if let Some(start) = self.stack.iter().position(|(_, p)| p == &file) {
+                    let mut cycle: Vec<String> = self.stack[start..]
+                        .iter()
+                        .map(|(name, path)| display_name(name, path))
+                        .collect();
+                    cycle.push(import);
+                    return Err(module_error(
+                        102,
+                        format!("import cycle: {}", cycle.join(" -> ")),
+                    ));
+                }
+
+                if !self.loaded.contains(&file) {
+                    self.load(import, file)?;
+                }
+            }
+        }
+
+        self.stack.pop();
+        self.loaded.insert(path.clone());
+        self.modules.push(Module {
+            name,
+            path,
+            source_hash,
+            program,
+        });
+
+        Ok(())
+    }
+
+    fn parse(&mut self, key: &str, code: &str, source_hash: u64) -> Result<Program, CompilerError> {
+        let cache = match &mut self.cache {
+            Some(cache) => cache,
+            None => return Ok(parse_source(code)),
+        };
+
+        if let Some(program) = cache.parsed(key, source_hash) {
+            return Ok(program);
+        }
+
+        let program = parse_source(code);
+        cache.store_parsed(key, source_hash, &program)?;
+        Ok(program)
+    }
+
+    /// Finds the file of module `a.b` as `a/b.aur` in the search paths. The
+    /// modules of the standard library are always found.
+    fn find(&self, name: &str) -> Result<PathBuf, CompilerError> {
+        if let Some(path) = stdlib::path(name) {
+            return Ok(path);
+        }
+
+        let relative: PathBuf = name.split('.').collect::<PathBuf>().with_extension("aur");
+
+        self.search_paths
+            .iter()
+            .map(|dir| dir.join(&relative))
+            .find(|path| path.is_file())
+            .map(|path| path.canonicalize().unwrap_or(path))
+            .ok_or(module_error(
+                101,
+                format!("module `{}` not found, looked for {}", name, relative.display()),
+            ))
+    }
+}
+
+fn display_name(name: &str, path: &Path) -> String {
+    if name.is_empty() {
+        path.file_stem()
+            .map(|stem| stem.to_string_lossy().to_string())
+            .unwrap_or_default()
+    } else {
+        name.to_string()
+    }
+}
+
+/// Imported modules are cached under their name. The entry module is named
+/// after its file, with a suffix that can not clash with a module name.
+fn cache_key(name: &str, path: &Path) -> String {
+    if name.is_empty() {
+        format!("{}-entry", display_name(name, path))
+    } else {
+        name.to_string()
+    }
+}
@@ -0,0 +1,507 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ast::{
+        expressions::{Expression, Pattern},
+        statements::Statements,
+        Program, Type,
+    },
+    error::CompilerError,
+};
+
+use super::{module_error, Interface, Module};
+
+/// A top-level item of a module.
+struct Item {
+    /// Name the item is compiled under
+    qualified: String,
+    public: bool,
+}
+
+/// Items of the entry module keep their names, the items of imported
+/// modules are prefixed with the module path so that modules may reuse
+/// names.
+fn qualify(module: &str, name: &str) -> String {
+    if module.is_empty() {
+        name.to_string()
+    } else {
+        format!("{}.{}", module, name)
+    }
+}
+
+/// Functions from and for C are known to it by their plain name, whichever
+/// module they are in, so they are not qualified.
+pub(super) fn is_extern(statement: &Statements) -> bool {
+    matches!(
+        statement,
+        Statements::ExternFunctionDeclaration { .. }
+            | Statements::FunctionDeclaration { is_extern: true, .. }
+    )
+}
+
+/// The name `statement`, a top-level item of `module`, is compiled under.
+pub(super) fn qualified_name(module: &str, name: &str, statement: &Statements) -> String {
+    if is_extern(statement) {
+        name.to_string()
+    } else {
+        qualify(module, name)
+    }
+}
+
+pub(super) fn declared_name(statement: &Statements) -> Option<&String> {
+    match statement {
+        Statements::FunctionDeclaration { name, .. }
+        | Statements::ExternFunctionDeclaration { name, .. }
+        | Statements::StructDeclaration { name, .. }
+        | Statements::EnumDeclaration { name, .. }
+        | Statements::TraitDeclaration { name, .. }
+        | Statements::ConstDeclaration { name, .. }
+        | Statements::VariableDeclaration { name, .. } => Some(name),
+        _ => None,
+    }
+}
+
+fn module_items(module: &Module) -> HashMap<String, Item> {
+    let exported: HashSet<&String> = module
+        .program
+        .exports
+        .iter()
+        .filter_map(|export| declared_name(&export.statement))
+        .collect();
+
+    module
+        .program
+        .statements
+        .iter()
+        .filter_map(|statement| Some((declared_name(statement)?, statement)))
+        .map(|(name, statement)| {
+            (
+                name.clone(),
+                Item {
+                    qualified: qualified_name(&module.name, name, statement),
+                    public: exported.contains(name),
+                },
+            )
+        })
+        .collect()
+}
+
+fn interface_items(interface: &Interface) -> HashMap<String, Item> {
+    interface
+        .items
+        .iter()
+        .map(|item| {
+            (
+                item.name.clone(),
+                Item {
+                    qualified: qualified_name(&interface.name, &item.name, &item.declaration),
+                    public: item.public,
+                },
+            )
+        })
+        .collect()
+}
+
+/// Resolves the names used in `module` to the items they refer to, using
+/// the interfaces of the modules it imports. Items of other modules are only
+/// accessible if they are `public`, either imported by name with
+/// `import a.{x}` or through the module as `a.x(...)` after `import a`.
+pub fn resolve(module: &Module, interfaces: &HashMap<String, Interface>) -> Result<Program, Vec<CompilerError>> {
+    let mut items: HashMap<String, HashMap<String, Item>> = interfaces
+        .iter()
+        .map(|(name, interface)| (name.clone(), interface_items(interface)))
+        .collect();
+    items.insert(module.name.clone(), module_items(module));
+
+    let mut resolver = Resolver::new(module, &items);
+    let mut program = module.program.clone();
+
+    for statement in program.statements.iter_mut() {
+        resolver.statement(statement);
+    }
+    program.statements.retain(|s| {
+        !matches!(
+            s,
+            Statements::ModuleDeclaration { .. } | Statements::Import { .. }
+        )
+    });
+
+    // Exports refer to the resolved declarations
+    for export in program.exports.iter_mut() {
+        let qualified = declared_name(&export.statement)
+            .map(|name| qualified_name(&module.name, name, &export.statement));
+        if let Some(resolved) = program
+            .statements
+            .iter()
+            .find(|s| declared_name(s) == qualified.as_ref())
+        {
+            export.statement = resolved.clone();
+        }
+    }
+
+    if resolver.errors.is_empty() {
+        Ok(program)
+    } else {
+        Err(resolver.errors)
+    }
+}
+
+struct Resolver<'a> {
+    items: &'a HashMap<String, HashMap<String, Item>>,
+    /// Qualified names of the items in scope, by the name they are used as
+    scope: HashMap<String, String>,
+    /// Modules imported as a whole, by the last segment of their path
+    aliases: HashMap<String, String>,
+    locals: Vec<HashSet<String>>,
+    errors: Vec<CompilerError>,
+}
+
+impl<'a> Resolver<'a> {
+    fn new(module: &Module, items: &'a HashMap<String, HashMap<String, Item>>) -> Self {
+        let mut resolver = Self {
+            items,
+            scope: items[&module.name]
+                .iter()
+                .map(|(name, item)| (name.clone(), item.qualified.clone()))
+                .collect(),
+            aliases: HashMap::new(),
+            locals: vec![],
+            errors: vec![],
+        };
+
+        for statement in &module.program.statements {
+            if let Statements::Import { path, items } = statement {
+                resolver.import(&path.join("."), items.as_deref());
+            }
+        }
+
+        resolver
+    }
+
+    fn import(&mut self, module: &str, names: Option<&[String]>) {
+        let names = match names {
+            Some(names) => names,
+            None => {
+                let alias = module.rsplit('.').next().unwrap().to_string();
+                if self.scope.contains_key(&alias) || self.aliases.contains_key(&alias) {
+                    self.errors.push(module_error(
+                        105,
+                        format!("`{}` is imported or defined more than once", alias),
+                    ));
+                }
+                self.aliases.insert(alias, module.to_string());
+                return;
+            }
+        };
+
+        for name in names {
+            let qualified = match self.lookup(module, name) {
+                Some(qualified) => qualified,
+                None => continue,
+            };
+
+            if self.scope.contains_key(name) || self.aliases.contains_key(name) {
+                self.errors.push(module_error(
+                    105,
+                    format!("`{}` is imported or defined more than once", name),
+                ));
+            }
+            self.scope.insert(name.clone(), qualified);
+        }
+    }
+
+    /// Qualified name of the public item `name` of `module`.
+    fn lookup(&mut self, module: &str, name: &str) -> Option<String> {
+        match self.items[module].get(name) {
+            Some(item) if item.public => Some(item.qualified.clone()),
+            Some(_) => {
+                self.errors.push(module_error(
+                    104,
+                    format!("`{}` is private to module `{}`", name, module),
+                ));
+                None
+            }
+            None => {
+                self.errors.push(module_error(
+                    103,
+                    format!("module `{}` has no item `{}`", module, name),
+                ));
+                None
+            }
+        }
+    }
+
+    fn resolve_name(&self, name: &mut String) {
+        if let Some(qualified) = self.scope.get(name) {
+            *name = qualified.clone();
+        }
+    }
+
+    fn is_local(&self, name: &str) -> bool {
+        self.locals.iter().any(|scope| scope.contains(name))
+    }
+
+    fn declare_local(&mut self, name: &str) {
+        if let Some(scope) = self.locals.last_mut() {
+            scope.insert(name.to_string());
+        }
+    }
+
+    fn r#type(&self, r#type: &mut Type) {
+        match r#type {
+            Type::Array(r#type) | Type::FixedArray(r#type, _) | Type::Pointer(r#type) => {
+                self.r#type(r#type)
+            }
+            Type::Tuple(types) => types.iter_mut().for_each(|t| self.r#type(t)),
+            Type::Function {
+                params,
+                return_type,
+            } => {
+                params.iter_mut().for_each(|t| self.r#type(t));
+                self.r#type(return_type);
+            }
+            Type::Generic { name, params } => {
+                self.resolve_name(name);
+                params.iter_mut().for_each(|t| self.r#type(t));
+            }
+            Type::UserDefinedType { name } => self.resolve_name(name),
+            _ => {}
+        }
+    }
+
+    fn statement(&mut self, statement: &mut Statements) {
+        match statement {
+            Statements::VariableDeclaration {
+                name,
+                value,
+                r#type,
+                ..
+            }
+            | Statements::ConstDeclaration {
+                name,
+                value,
+                r#type,
+                ..
+            } => {
+                self.r#type(r#type);
+                self.expression(value);
+
+                // Module-level variables and constants are globals
+                if self.locals.is_empty() {
+                    self.resolve_name(name);
+                } else {
+                    self.declare_local(name);
+                }
+            }
+            Statements::ModuleDeclaration { .. } | Statements::Import { .. } => {}
+            Statements::FunctionDeclaration {
+                name,
+                type_params,
+                params,
+                body,
+                return_type,
+                ..
+            } => {
+                // Methods are named after their type instead
+                if self.locals.is_empty() {
+                    self.resolve_name(name);
+                }
+                for param in type_params.iter_mut() {
+                    param.bounds.iter_mut().for_each(|b| self.resolve_name(b));
+                }
+                params.iter_mut().for_each(|p| self.r#type(&mut p.r#type));
+                self.r#type(return_type);
+
+                self.locals.push(params.iter().map(|p| p.name.clone()).collect());
+                self.block(body);
+                self.locals.pop();
+            }
+            Statements::ExternFunctionDeclaration {
+                params,
+                return_type,
+                ..
+            } => {
+                params.iter_mut().for_each(|p| self.r#type(&mut p.r#type));
+                self.r#type(return_type);
+            }
+            Statements::StructDeclaration {
+                name,
+                type_params,
+                fields,
+            } => {
+                self.resolve_name(name);
+                for param in type_params.iter_mut() {
+                    param.bounds.iter_mut().for_each(|b| self.resolve_name(b));
+                }
+                fields.iter_mut().for_each(|f| self.r#type(&mut f.r#type));
+            }
+            Statements::EnumDeclaration { name, variants } => {
+                self.resolve_name(name);
+                for variant in variants.iter_mut() {
+                    variant.fields.iter_mut().for_each(|t| self.r#type(t));
+                }
+            }
+            Statements::TraitDeclaration { name, methods } => {
+                self.resolve_name(name);
+                for method in methods.iter_mut() {
+                    method.params.iter_mut().for_each(|p| self.r#type(&mut p.r#type));
+                    self.r#type(&mut method.return_type);
+                }
+            }
+            Statements::ImplDeclaration {
+                r#trait,
+                r#type,
+                methods,
+            } => {
+                if let Some(r#trait) = r#trait {
+                    self.resolve_name(r#trait);
+                }
+                self.r#type(r#type);
+
+                // Marks the methods as nested, see `FunctionDeclaration`
+                self.locals.push(HashSet::new());
+                methods.iter_mut().for_each(|m| self.statement(m));
+                self.locals.pop();
+            }
+            Statements::ExpressionStatement { expr, .. } => self.expression(expr),
+            Statements::Assignment { target, value, .. } => {
+                self.expression(target);
+                self.expression(value);
+            }
+            Statements::Return { value, .. } => {
+                if let Some(value) = value {
+                    self.expression(value);
+                }
+            }
+            Statements::If {
+                condition,
+                body,
+                else_body,
+                ..
+            } => {
+                self.expression(condition);
+                self.block(body);
+                self.block(else_body);
+            }
+            Statements::While { condition, body, .. } => {
+                self.expression(condition);
+                self.block(body);
+            }
+        }
+    }
+
+    fn block(&mut self, block: &mut [Statements]) {
+        self.locals.push(HashSet::new());
+        block.iter_mut().for_each(|s| self.statement(s));
+        self.locals.pop();
+    }
+
+    fn expression(&mut self, expr: &mut Expression) {
+        match expr {
+            Expression::Identifier { val } => {
+                if !self.is_local(val) {
+                    self.resolve_name(val);
+                }
+            }
+            Expression::StringLiteral { .. }
+            | Expression::IntLiteral { .. }
+            | Expression::BooleanLiteral { .. }
+            | Expression::FloatLiteral { .. } => {}
+            Expression::FunctionCall { name, params } => {
+                // Calls through a local holding a function
+                if !self.is_local(name) {
+                    self.resolve_name(name);
+                }
+                params.iter_mut().for_each(|p| self.expression(p));
+            }
+            Expression::MethodCall {
+                receiver,
+                method,
+                params,
+            } => {
+                params.iter_mut().for_each(|p| self.expression(p));
+
+                // `module.function(...)` unless `module` is a variable
+                let module = match receiver.as_ref() {
+                    Expression::Identifier { val } if !self.is_local(val) => {
+                        self.aliases.get(val).cloned()
+                    }
+                    _ => None,
+                };
+
+                match module {
+                    Some(module) => {
+                        let name = self.lookup(&module, method).unwrap_or(method.clone());
+                        *expr = Expression::FunctionCall {
+                            name,
+                            params: std::mem::take(params),
+                        };
+                    }
+                    None => self.expression(receiver),
+                }
+            }
+            Expression::ArrayLiteral { elements } => {
+                elements.iter_mut().for_each(|e| self.expression(e))
+            }
+            Expression::Index { array, index } => {
+                self.expression(array);
+                self.expression(index);
+            }
+            Expression::StructLiteral { name, fields } => {
+                self.resolve_name(name);
+                fields.iter_mut().for_each(|(_, value)| self.expression(value));
+            }
+            Expression::FieldAccess { expr: inner, field } => {
+                // `module.constant` unless `module` is a variable
+                let module = match inner.as_ref() {
+                    Expression::Identifier { val } if !self.is_local(val) => {
+                        self.aliases.get(val).cloned()
+                    }
+                    _ => None,
+                };
+
+                match module {
+                    Some(module) => {
+                        let val = self.lookup(&module, field).unwrap_or(field.clone());
+                        *expr = Expression::Identifier { val };
+                    }
+                    None => self.expression(inner),
+                }
+            }
+            Expression::EnumVariant {
+                enum_name, values, ..
+            } => {
+                self.resolve_name(enum_name);
+                values.iter_mut().for_each(|v| self.expression(v));
+            }
+            Expression::Match { expr, arms } => {
+                self.expression(expr);
+
+                for arm in arms.iter_mut() {
+                    self.locals.push(HashSet::new());
+                    self.pattern(&mut arm.pattern);
+                    self.expression(&mut arm.body);
+                    self.locals.pop();
+                }
+            }
+            Expression::BinaryOperation { lhs, rhs, .. } => {
+                self.expression(lhs);
+                self.expression(rhs);
+            }
+            Expression::UnaryOperation { expr, .. } => self.expression(expr),
+        }
+    }
+
+    fn pattern(&mut self, pattern: &mut Pattern) {
+        match pattern {
+            Pattern::Binding { name } => self.declare_local(&name.clone()),
+            Pattern::Variant {
+                enum_name, fields, ..
+            } => {
+                self.resolve_name(enum_name);
+                fields.iter_mut().for_each(|f| self.pattern(f));
+            }
+            Pattern::Wildcard | Pattern::IntLiteral { .. } | Pattern::BooleanLiteral { .. } => {}
+        }
+    }
+}
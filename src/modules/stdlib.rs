@@ -0,0 +1,32 @@
+use std::path::{Path, PathBuf};
+
+/// Directory the modules of the standard library appear to be in, in
+/// messages and debug information. It can not be a real directory.
+const STD_DIR: &str = "<std>";
+
+/// Source of the modules of the standard library, which is part of the
+/// compiler so that `import std.io` works without a search path. None of
+/// them is imported implicitly, there is no prelude.
+const MODULES: &[(&str, &str)] = &[
+    ("std.collections", include_str!("../../std/collections.aur")),
+    ("std.io", include_str!("../../std/io.aur")),
+    ("std.math", include_str!("../../std/math.aur")),
+    ("std.string", include_str!("../../std/string.aur")),
+];
+
+/// Where the standard library module `name` appears to be, if there is one.
+pub fn path(name: &str) -> Option<PathBuf> {
+    MODULES.iter().find(|(module, _)| *module == name).map(|_| {
+        Path::new(STD_DIR)
+            .join(name.split('.').collect::<PathBuf>())
+            .with_extension("aur")
+    })
+}
+
+/// Source of the standard library module at `path`.
+pub fn source(path: &Path) -> Option<&'static str> {
+    MODULES
+        .iter()
+        .find(|(module, _)| self::path(module).as_deref() == Some(path))
+        .map(|(_, source)| *source)
+}
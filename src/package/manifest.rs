@@ -0,0 +1,168 @@
+use std::{
+    collections::BTreeMap,
+    fmt, fs,
+    path::{Path, PathBuf},
+};
+
+#[cfg(feature = "llvm")]
+use inkwell::OptimizationLevel;
+use serde::Deserialize;
+
+use crate::error::CompilerError;
+
+use super::package_error;
+
+pub const MANIFEST: &str = "aurora.toml";
+
+/// The `aurora.toml` at the root of a package.
+///
+/// ```toml
+/// [package]
+/// name = "shapes"
+/// version = "0.1.0"
+/// kind = "bin"              # or "lib" for a static library
+/// entry = "src/main.aur"    # the default for binaries
+/// source-dirs = ["src"]
+///
+/// [dependencies]
+/// geometry = { path = "../geometry" }
+///
+/// [profile.release]
+/// opt-level = 3             # 0 to 3, or "s" to optimise for size
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub package: PackageInfo,
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, Dependency>,
+    #[serde(default)]
+    pub profile: BTreeMap<String, Profile>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct PackageInfo {
+    pub name: String,
+    pub version: String,
+    #[serde(default)]
+    pub kind: Kind,
+    /// Module containing `main`, relative to the package root
+    pub entry: Option<PathBuf>,
+    /// Directories the modules of the package are in. A module is imported
+    /// by its path relative to its source directory.
+    #[serde(default = "default_source_dirs")]
+    pub source_dirs: Vec<PathBuf>,
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Kind {
+    #[default]
+    Bin,
+    Lib,
+}
+
+/// A package on the local file system whose modules may be imported.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Dependency {
+    /// Root of the package, relative to the depending package
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+pub struct Profile {
+    #[serde(default)]
+    pub opt_level: OptLevel,
+}
+
+/// How much the code is optimised, `opt-level` in a profile and `-O0` to
+/// `-O3` or `-Os` on the command line.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "toml::Value")]
+pub enum OptLevel {
+    #[default]
+    O0,
+    O1,
+    O2,
+    O3,
+    /// Like `O2`, but without optimisations that make the code larger
+    Os,
+}
+
+fn default_source_dirs() -> Vec<PathBuf> {
+    vec![PathBuf::from("src")]
+}
+
+impl OptLevel {
+    /// The level of `-O<level>`.
+    pub fn parse(level: &str) -> Option<Self> {
+        match level {
+            "0" => Some(OptLevel::O0),
+            "1" => Some(OptLevel::O1),
+            "2" => Some(OptLevel::O2),
+            "3" => Some(OptLevel::O3),
+            "s" => Some(OptLevel::Os),
+            _ => None,
+        }
+    }
+
+    /// The level of the LLVM target machine, which has none for size.
+    #[cfg(feature = "llvm")]
+    pub fn optimization_level(self) -> OptimizationLevel {
+        match self {
+            OptLevel::O0 => OptimizationLevel::None,
+            OptLevel::O1 => OptimizationLevel::Less,
+            OptLevel::O2 | OptLevel::Os => OptimizationLevel::Default,
+            OptLevel::O3 => OptimizationLevel::Aggressive,
+        }
+    }
+}
+
+/// The flag C compilers know the level by, like `O2`.
+impl fmt::Display for OptLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self)
+    }
+}
+
+impl TryFrom<toml::Value> for OptLevel {
+    type Error = String;
+
+    fn try_from(value: toml::Value) -> Result<Self, String> {
+        let level = match &value {
+            toml::Value::Integer(level) => OptLevel::parse(&level.to_string()),
+            toml::Value::String(level) => OptLevel::parse(level).filter(|_| level == "s"),
+            _ => None,
+        };
+        level.ok_or(format!("`opt-level` must be 0, 1, 2, 3 or \"s\", not {}", value))
+    }
+}
+
+impl Manifest {
+    pub fn read(root: &Path) -> Result<Self, CompilerError> {
+        let path = root.join(MANIFEST);
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| package_error(501, format!("can not read {}: {}", path.display(), e)))?;
+
+        toml::from_str(&contents)
+            .map_err(|e| package_error(501, format!("invalid manifest {}: {}", path.display(), e)))
+    }
+
+    /// The profile called `name`. `debug` and `release` always exist, with
+    /// `opt-level` 0 and 3 unless the manifest says otherwise.
+    pub fn profile(&self, name: &str) -> Result<Profile, CompilerError> {
+        match (self.profile.get(name), name) {
+            (Some(profile), _) => Ok(profile.clone()),
+            (None, "debug") => Ok(Profile {
+                opt_level: OptLevel::O0,
+            }),
+            (None, "release") => Ok(Profile {
+                opt_level: OptLevel::O3,
+            }),
+            (None, _) => Err(package_error(502, format!("no profile `{}` in {}", name, MANIFEST))),
+        }
+    }
+}
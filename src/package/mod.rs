@@ -0,0 +1,322 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use crate::error::{CompilerError, CompilerErrorKind};
+
+mod manifest;
+
+pub use manifest::{Kind, Manifest, OptLevel, MANIFEST};
+
+fn package_error(code: usize, message: String) -> CompilerError {
+    CompilerError {
+        code,
+        kind: CompilerErrorKind::PackageError,
+        message,
+    }
+}
+
+/// A directory with an `aurora.toml`.
+pub struct Package {
+    pub root: PathBuf,
+    pub manifest: Manifest,
+}
+
+impl Package {
+    /// The package `dir` is in, found by looking for a manifest in `dir`
+    /// and its parents.
+    pub fn find(dir: &Path) -> Result<Self, CompilerError> {
+        let root = dir
+            .ancestors()
+            .find(|dir| dir.join(MANIFEST).is_file())
+            .ok_or(package_error(
+                501,
+                format!("could not find {} in {} or any parent directory", MANIFEST, dir.display()),
+            ))?;
+
+        Self::open(root)
+    }
+
+    fn open(root: &Path) -> Result<Self, CompilerError> {
+        Ok(Self {
+            root: root.to_path_buf(),
+            manifest: Manifest::read(root)?,
+        })
+    }
+
+    pub fn entry(&self) -> Option<PathBuf> {
+        match (&self.manifest.package.entry, self.manifest.package.kind) {
+            (Some(entry), _) => Some(self.root.join(entry)),
+            (None, Kind::Bin) => Some(self.root.join("src/main.aur")),
+            (None, Kind::Lib) => None,
+        }
+    }
+
+    fn source_dirs(&self) -> Vec<PathBuf> {
+        self.manifest
+            .package
+            .source_dirs
+            .iter()
+            .map(|dir| self.root.join(dir))
+            .collect()
+    }
+
+    /// The source directories of the package and of the packages it
+    /// depends on, directly or indirectly.
+    pub fn search_paths(&self) -> Result<Vec<PathBuf>, CompilerError> {
+        let mut search_paths = vec![];
+        self.collect_search_paths(&mut search_paths, &mut HashSet::new())?;
+        Ok(search_paths)
+    }
+
+    fn collect_search_paths(
+        &self,
+        search_paths: &mut Vec<PathBuf>,
+        visited: &mut HashSet<PathBuf>,
+    ) -> Result<(), CompilerError> {
+        let root = self.root.canonicalize().unwrap_or(self.root.clone());
+        if !visited.insert(root) {
+            return Ok(());
+        }
+
+        search_paths.extend(self.source_dirs());
+        for (name, dependency) in &self.manifest.dependencies {
+            let package = Self::open(&self.root.join(&dependency.path)).map_err(|e| {
+                package_error(
+                    503,
+                    format!("can not load dependency `{}`: {}", name, e.message),
+                )
+            })?;
+            package.collect_search_paths(search_paths, visited)?;
+        }
+
+        Ok(())
+    }
+
+    /// Every module in the source directories of the package except the
+    /// entry module, with the name it is imported by.
+    pub fn modules(&self) -> Result<Vec<(String, PathBuf)>, CompilerError> {
+        let entry = self.entry().and_then(|entry| entry.canonicalize().ok());
+        let mut modules = vec![];
+
+        for dir in self.source_dirs() {
+            let mut files = vec![];
+            find_sources(&dir, &mut files)
+                .map_err(|e| package_error(504, format!("can not read {}: {}", dir.display(), e)))?;
+
+            for file in files {
+                if file.canonicalize().ok() == entry {
+                    continue;
+                }
+
+                let name: Vec<String> = file
+                    .strip_prefix(&dir)
+                    .unwrap()
+                    .with_extension("")
+                    .iter()
+                    .map(|part| part.to_string_lossy().to_string())
+                    .collect();
+                modules.push((name.join("."), file));
+            }
+        }
+
+        Ok(modules)
+    }
+
+    /// Where the build of the package with `profile` is written to. Builds
+    /// for another target than the host go to `target/<triple>/<profile>`.
+    /// WebAssembly builds are a `.wasm` module whatever the kind, other
+    /// cross builds a static library.
+    pub fn output(&self, profile: &str, target: Option<&str>) -> PathBuf {
+        let name = &self.manifest.package.name;
+        let dir = match target {
+            Some(triple) => self.root.join("target").join(triple).join(profile),
+            None => self.root.join("target").join(profile),
+        };
+
+        match (self.manifest.package.kind, target) {
+            (_, Some(triple)) if triple.starts_with("wasm32-") => dir.join(format!("{}.wasm", name)),
+            // Executables for another target need its libc and linker, so
+            // linking them is left to the cross toolchain
+            (_, Some(_)) => dir.join(format!("lib{}.a", name)),
+            (Kind::Bin, _) => dir.join(name),
+            (Kind::Lib, _) => dir.join(format!("lib{}.a", name)),
+        }
+    }
+}
+
+/// Collects the `.aur` files in `dir` and its subdirectories, sorted so that
+/// builds do not depend on the order the file system lists them in.
+fn find_sources(dir: &Path, files: &mut Vec<PathBuf>) -> std::io::Result<()> {
+    let mut entries: Vec<PathBuf> = fs::read_dir(dir)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<std::io::Result<_>>()?;
+    entries.sort();
+
+    for path in entries {
+        if path.is_dir() {
+            find_sources(&path, files)?;
+        } else if path.extension().is_some_and(|ext| ext == "aur") {
+            files.push(path);
+        }
+    }
+
+    Ok(())
+}
+
+/// Links `objects` into a binary, or archives them into a static library.
+/// The system C compiler and `ar` do the work.
+pub fn link(objects: &[PathBuf], kind: Kind, output: &Path) -> Result<(), CompilerError> {
+    if let Some(dir) = output.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| package_error(505, format!("can not create {}: {}", dir.display(), e)))?;
+    }
+
+    let mut command = match kind {
+        Kind::Bin => {
+            let mut command = Command::new("cc");
+            command.arg("-o").arg(output);
+            command
+        }
+        Kind::Lib => {
+            // `ar` adds to existing archives, so start from an empty one
+            let _ = fs::remove_file(output);
+            let mut command = Command::new("ar");
+            command.arg("rcs").arg(output);
+            command
+        }
+    };
+    command.args(objects);
+    if kind == Kind::Bin {
+        // For the C backend, which may call the C math library
+        command.arg("-lm");
+    }
+
+    run(command)
+}
+
+/// Links `objects` compiled for a WebAssembly target into a `.wasm` module
+/// with `wasm-ld`, exporting the functions `exports` by name. Modules without
+/// an `entry` are libraries, the others start at `_start`.
+pub fn link_wasm(objects: &[PathBuf], exports: &[String], entry: bool, output: &Path) -> Result<(), CompilerError> {
+    if let Some(dir) = output.parent() {
+        fs::create_dir_all(dir)
+            .map_err(|e| package_error(505, format!("can not create {}: {}", dir.display(), e)))?;
+    }
+
+    let mut command = Command::new("wasm-ld");
+    command.arg("-o").arg(output);
+    if !entry {
+        command.arg("--no-entry");
+    }
+    for export in exports {
+        command.arg(format!("--export={}", export));
+    }
+    command.args(objects);
+
+    run(command)
+}
+
+/// Compiles the C file `source`, generated by the C backend, into the
+/// object file `object` with the system C compiler, with debug information
+/// for the C source if `debug_info` is set.
+pub fn compile_c(
+    source: &Path,
+    object: &Path,
+    opt_level: OptLevel,
+    debug_info: bool,
+) -> Result<(), CompilerError> {
+    let mut command = Command::new("cc");
+    if debug_info {
+        command.arg("-g");
+    }
+    command
+        .arg("-std=c99")
+        .arg(format!("-{}", opt_level))
+        .arg("-c")
+        .arg(source)
+        .arg("-o")
+        .arg(object);
+
+    run(command)
+}
+
+fn run(mut command: Command) -> Result<(), CompilerError> {
+    let program = command.get_program().to_string_lossy().to_string();
+    let result = command
+        .output()
+        .map_err(|e| package_error(505, format!("can not run `{}`: {}", program, e)))?;
+
+    if !result.status.success() {
+        return Err(package_error(
+            505,
+            format!(
+                "`{}` failed with {}:\n{}",
+                program,
+                result.status,
+                String::from_utf8_lossy(&result.stderr)
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+/// Creates a new package in the directory `path`, named after it.
+pub fn new(path: &Path, kind: Kind) -> Result<(), CompilerError> {
+    let name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().to_string())
+        .unwrap_or_default();
+
+    let is_identifier = name
+        .chars()
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !is_identifier {
+        return Err(package_error(
+            506,
+            format!("`{}` is not a valid package name, it has to be an identifier", name),
+        ));
+    }
+    if path.exists() {
+        return Err(package_error(506, format!("{} already exists", path.display())));
+    }
+
+    let (kind, file, source) = match kind {
+        Kind::Bin => (
+            "bin",
+            "main.aur".to_string(),
+            "module main\n\nfn main() -> i32\n    return 0\nend\n".to_string(),
+        ),
+        Kind::Lib => (
+            "lib",
+            format!("{}.aur", name),
+            format!(
+                "module {}\n\npublic fn add(x: i32, y: i32) -> i32\n    return x + y\nend\n",
+                name
+            ),
+        ),
+    };
+    let manifest = format!(
+        "[package]\nname = \"{}\"\nversion = \"0.1.0\"\nkind = \"{}\"\n\n[dependencies]\n",
+        name, kind
+    );
+
+    let write = |path: PathBuf, contents: &str| {
+        fs::write(&path, contents)
+            .map_err(|e| package_error(506, format!("can not write {}: {}", path.display(), e)))
+    };
+
+    fs::create_dir_all(path.join("src"))
+        .map_err(|e| package_error(506, format!("can not create {}: {}", path.display(), e)))?;
+    write(path.join(MANIFEST), &manifest)?;
+    write(path.join("src").join(file), &source)?;
+    write(path.join(".gitignore"), "/target\n")?;
+
+    Ok(())
+}
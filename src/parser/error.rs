@@ -3,6 +3,8 @@ pub enum ErrorKind {
     ExpressionError,
 }
 
+// Only the message is reported, the rest shows up when an error is unwrapped
+#[allow(dead_code)]
 #[derive(Debug)]
 pub struct Error {
     pub code: String,
@@ -1,5 +1,11 @@
 use crate::{
-    ast::{expressions::Expression, statements::Statements, Export, ExportType, FuncParam, Type},
+    ast::{
+        expressions::{BinaryOperator, Expression, MatchArm, Pattern, UnaryOperator},
+        statements::Statements,
+        EnumVariant, Export, ExportType, FuncParam, FunctionSignature, StructField, Type,
+        TypeParam,
+    },
+    consteval::{ConstEvaluator, Value},
     error::print_error,
     lexer::{tokens::Token, Lexer},
 };
@@ -8,6 +14,11 @@ use super::error;
 pub struct Parser {
     lexer: Lexer,
     pub exports: Vec<Export>,
+    /// Type parameters in scope, innermost last
+    type_params: Vec<String>,
+    /// Constants and `const fn`s declared so far, which the sizes of
+    /// fixed arrays can use
+    constants: ConstEvaluator,
 }
 
 impl Parser {
@@ -15,39 +26,30 @@ impl Parser {
         Self {
             lexer,
             exports: vec![],
+            type_params: vec![],
+            constants: ConstEvaluator::default(),
         }
     }
 
     pub fn parse(&mut self) -> Vec<Statements> {
         let mut program: Vec<Statements> = vec![];
-        loop {
-            let statement = match self.parse_statement() {
-                Ok(statement) => statement,
-                Err(_) => break,
-            };
-
+        while let Ok(statement) = self.parse_statement() {
             program.push(statement)
         }
 
         program
     }
 
+    /// Parses statements up to (but not including) the `end` or `else`
+    /// keyword closing the block.
     pub fn parse_block(&mut self) -> Vec<Statements> {
         let mut block: Vec<Statements> = vec![];
 
         loop {
-            let mut curr_token = self.lexer.next_token();
-
-            if matches!(curr_token, Token::Whitespace) {
-                curr_token = self.lexer.next_token();
-            }
-
-            if matches!(curr_token, Token::End) {
-                break;
-            }
-
-            if matches!(self.lexer.peak_next_token(), Token::End) {
-                self.lexer.next_token();
+            if matches!(
+                self.lexer.peak_next_token(),
+                Token::End | Token::Else | Token::Eof
+            ) {
                 break;
             }
 
@@ -56,197 +58,1036 @@ impl Parser {
                 Err(e) => {
                     println!("{}", print_error(&e, &self.lexer));
                     return block;
-                },
+                }
             }
         }
 
         block
     }
 
-    pub fn parse_statement(&mut self) -> Result<Statements, String> {
-        let mut curr_token = self.lexer.next_token();
-        let mut public = false;
+    fn expect_end(&mut self) {
+        if !matches!(self.lexer.next_token(), Token::End) {
+            panic!("{}", print_error("Expected 'end'", &self.lexer));
+        }
+    }
+
+    fn parse_identifier(&mut self, message: &str) -> String {
+        match self.lexer.next_token() {
+            Token::Identifier { val } => String::from_iter(val),
+            _ => panic!("{}", print_error(message, &self.lexer)),
+        }
+    }
+
+    /// Parses a type:
+    ///
+    /// ```text
+    /// type := name | name '[' types ']' | '[' type ';' expr ']'
+    ///       | '(' types ')' | 'fn' '(' types ')' [ '->' type ] | '*' type
+    /// ```
+    ///
+    /// The size of a fixed array is a constant expression, which can use the
+    /// constants declared before it in the module. `*u8` is the same type as
+    /// `*i8`, a pointer to C's `char`.
+    pub fn parse_type(&mut self) -> Type {
+        match self.lexer.next_token() {
+            Token::Multiplication { .. } => match self.parse_type() {
+                // C declarations take bytes as `*u8`, which are `i8` here
+                Type::UserDefinedType { name } if name == "u8" => Type::Pointer(Box::new(Type::Int8)),
+                pointee => Type::Pointer(Box::new(pointee)),
+            },
+            Token::LBracket { .. } => {
+                let r#type = self.parse_type();
+
+                if !matches!(self.lexer.next_token(), Token::Semicolon { .. }) {
+                    panic!("{}", print_error("Expected ';' in array type", &self.lexer));
+                }
+
+                let size = self.parse_array_size();
+
+                if !matches!(self.lexer.next_token(), Token::RBracket { .. }) {
+                    panic!("{}", print_error("Expected ']'", &self.lexer));
+                }
+
+                Type::FixedArray(Box::new(r#type), size)
+            }
+            Token::LParen { .. } => {
+                let mut types = self.parse_type_list(|t| matches!(t, Token::RParen { .. }));
+
+                // `(T)` is just a parenthesised `T`
+                if types.len() == 1 {
+                    types.remove(0)
+                } else {
+                    Type::Tuple(types)
+                }
+            }
+            Token::Function => {
+                if !matches!(self.lexer.next_token(), Token::LParen { .. }) {
+                    panic!("{}", print_error("expected '(' in function type", &self.lexer));
+                }
+
+                let params = self.parse_type_list(|t| matches!(t, Token::RParen { .. }));
+
+                let return_type = if matches!(self.lexer.peak_next_token(), Token::Arrow { .. }) {
+                    self.lexer.next_token();
+                    self.parse_type()
+                } else {
+                    Type::Void
+                };
+
+                Type::Function {
+                    params,
+                    return_type: Box::new(return_type),
+                }
+            }
+            Token::Identifier { val } => {
+                let name = String::from_iter(val);
+
+                if self.type_params.contains(&name) {
+                    return Type::TypeParameter { name };
+                }
+
+                if !matches!(self.lexer.peak_next_token(), Token::LBracket { .. }) {
+                    return Type::parse_type(name);
+                }
 
-        if matches!(curr_token, Token::Whitespace) {
-            curr_token = self.lexer.next_token();
+                self.lexer.next_token();
+                let mut params = self.parse_type_list(|t| matches!(t, Token::RBracket { .. }));
+
+                match name.as_str() {
+                    "array" if params.len() == 1 => Type::Array(Box::new(params.remove(0))),
+                    "array" => panic!(
+                        "{}",
+                        print_error("array takes exactly one type parameter", &self.lexer)
+                    ),
+                    _ => Type::Generic { name, params },
+                }
+            }
+            _ => panic!("{}", print_error("expected type", &self.lexer)),
+        }
+    }
+
+    /// Parses the optional `[T, U: Trait + Other]` type parameter list of a
+    /// generic declaration and brings the parameters into scope. The caller
+    /// removes them again once the declaration is parsed.
+    fn parse_type_params(&mut self) -> Vec<TypeParam> {
+        let mut type_params: Vec<TypeParam> = vec![];
+
+        if !matches!(self.lexer.peak_next_token(), Token::LBracket { .. }) {
+            return type_params;
         }
+        self.lexer.next_token();
 
-        if matches!(curr_token, Token::EOF) {
+        loop {
+            if matches!(self.lexer.peak_next_token(), Token::Comma { .. }) {
+                self.lexer.next_token();
+            }
+
+            if matches!(self.lexer.peak_next_token(), Token::RBracket { .. }) {
+                self.lexer.next_token();
+                break;
+            }
+
+            let name = self.parse_identifier("Expected type parameter name");
+            let mut bounds: Vec<String> = vec![];
+
+            if matches!(self.lexer.peak_next_token(), Token::Colon { .. }) {
+                self.lexer.next_token();
+                bounds.push(self.parse_identifier("Expected trait name"));
+
+                while matches!(self.lexer.peak_next_token(), Token::Plus { .. }) {
+                    self.lexer.next_token();
+                    bounds.push(self.parse_identifier("Expected trait name"));
+                }
+            }
+
+            self.type_params.push(name.clone());
+            type_params.push(TypeParam { name, bounds });
+        }
+
+        type_params
+    }
+
+    fn parse_array_size(&mut self) -> usize {
+        let size = match self.parse_expr() {
+            Ok(size) => size,
+            Err(_) => panic!("{}", print_error("Expected array size", &self.lexer)),
+        };
+
+        match self.constants.evaluate(&size, &Type::Int64) {
+            Ok(Value::Int(size)) if size >= 0 => size as usize,
+            Ok(_) => panic!("{}", print_error("Array size can not be negative", &self.lexer)),
+            Err(error) => panic!(
+                "{}",
+                print_error(&format!("Invalid array size: {}", error), &self.lexer)
+            ),
+        }
+    }
+
+    /// Parses a comma separated list of types up to `close`, which is
+    /// consumed.
+    fn parse_type_list(&mut self, close: fn(&Token) -> bool) -> Vec<Type> {
+        let mut types: Vec<Type> = vec![];
+
+        loop {
+            if matches!(self.lexer.peak_next_token(), Token::Comma { .. }) {
+                self.lexer.next_token();
+            }
+
+            if close(&self.lexer.peak_next_token()) {
+                self.lexer.next_token();
+                break;
+            }
+
+            types.push(self.parse_type());
+        }
+
+        types
+    }
+
+    pub fn parse_statement(&mut self) -> Result<Statements, String> {
+        let mut curr_token = self.lexer.peak_next_token();
+        let mut public = false;
+
+        if matches!(curr_token, Token::Eof) {
             return Err("Reached end of file".to_string());
         }
 
         if matches!(curr_token, Token::Public) {
             public = true;
-            curr_token = self.lexer.next_token();
+            self.lexer.next_token();
+            curr_token = self.lexer.peak_next_token();
         }
+        let line = self.lexer.peek_line();
 
         if matches!(curr_token, Token::Let) {
-            let id = if matches!(self.lexer.peak_next_token(), Token::Identifier { .. }) {
-                match self.lexer.next_token() {
-                    Token::Identifier { val } => String::from_iter(val),
-                    _ => panic!("unexpected identifier"),
-                }
-            } else {
-                panic!("{}", print_error("Expected an identifier", &self.lexer))
+            self.lexer.next_token();
+            let (name, r#type, value) = self.parse_binding();
+            let variable = Statements::VariableDeclaration {
+                name,
+                value,
+                r#type,
+                line,
             };
 
-            if !matches!(self.lexer.peak_next_token(), Token::Colon { .. }) {
-                panic!("{}", print_error("Expected ':'", &self.lexer))
-            } else {
-                self.lexer.next_token();
-                self.lexer.next_token();
+            // Only meaningful for module-level globals
+            if public {
+                self.exports.push(Export {
+                    r#type: ExportType::Global,
+                    statement: variable.clone(),
+                })
             }
 
-            let _type = if matches!(self.lexer.peak_next_token(), Token::Identifier { .. }) {
-                let raw_type = match self.lexer.next_token() {
-                    Token::Identifier { val } => String::from_iter(val),
-                    x => panic!("{:?}", x),
-                };
-                Type::parse_type(raw_type)
-            } else {
-                panic!("{}", print_error("expected type", &self.lexer))
-            };
+            return Ok(variable);
+        } else
+        // Constant Declaration
+        if matches!(curr_token, Token::Const) {
+            self.lexer.next_token();
 
-            if !matches!(self.lexer.peak_next_token(), Token::Assign { .. }) {
-                panic!(
-                    "{}",
-                    print_error("Expected assignment operoator '='", &self.lexer)
-                )
-            } else {
+            // `const fn`
+            if matches!(self.lexer.peak_next_token(), Token::Function) {
                 self.lexer.next_token();
+                let func = self.parse_function(None, true, false);
+                // Errors are reported when the program is checked
+                let _ = self.constants.declare_function(&func);
+
+                if public {
+                    self.exports.push(Export {
+                        r#type: ExportType::Function,
+                        statement: func.clone(),
+                    })
+                }
+
+                return Ok(func);
+            }
+
+            let (name, r#type, value) = self.parse_binding();
+            let constant = Statements::ConstDeclaration {
+                name,
+                r#type,
+                value,
+                line,
             };
 
-            let expr = self.parse_expr().unwrap();
-            return Ok(Statements::VariableDeclaration {
-                name: id,
-                value: expr,
-                r#type: _type,
-            });
+            if public {
+                self.exports.push(Export {
+                    r#type: ExportType::Const,
+                    statement: constant.clone(),
+                })
+            }
+
+            let _ = self.constants.declare(&constant);
+            return Ok(constant);
         } else
         // Module Declaration
         if matches!(curr_token, Token::Module) {
-            let id = if matches!(self.lexer.peak_next_token(), Token::Identifier { .. }) {
-                match self.lexer.next_token() {
-                    Token::Identifier { val } => String::from_iter(val),
-                    _ => panic!("unexpected identifier"),
-                }
+            self.lexer.next_token();
+            let id = self.parse_identifier("Expected module name");
+
+            return Ok(Statements::ModuleDeclaration { name: id });
+        } else
+        // Import
+        if matches!(curr_token, Token::Import) {
+            self.lexer.next_token();
+            return Ok(self.parse_import());
+        } else
+        // Return Statement
+        if matches!(curr_token, Token::Return) {
+            self.lexer.next_token();
+            let value = if matches!(
+                self.lexer.peak_next_token(),
+                Token::End | Token::Else | Token::Eof
+            ) {
+                None
             } else {
-                panic!("Expected module name")
+                Some(self.parse_expr().map_err(|e| e.message)?)
             };
 
-            return Ok(Statements::ModuleDeclaration { name: id });
+            return Ok(Statements::Return { value, line });
+        } else
+        // If Statement
+        if matches!(curr_token, Token::If) {
+            self.lexer.next_token();
+            return self.parse_if();
+        } else
+        // While Loop
+        if matches!(curr_token, Token::While) {
+            self.lexer.next_token();
+            let condition = self.parse_expr().map_err(|e| e.message)?;
+            let body = self.parse_block();
+            self.expect_end();
+
+            return Ok(Statements::While {
+                condition,
+                body,
+                line,
+            });
+        } else
+        // Struct Declaration
+        if matches!(curr_token, Token::Struct) {
+            self.lexer.next_token();
+            let r#struct = self.parse_struct();
+
+            if public {
+                self.exports.push(Export {
+                    r#type: ExportType::Struct,
+                    statement: r#struct.clone(),
+                })
+            }
+
+            return Ok(r#struct);
+        } else
+        // Enum Declaration
+        if matches!(curr_token, Token::Enum) {
+            self.lexer.next_token();
+            let r#enum = self.parse_enum();
+
+            if public {
+                self.exports.push(Export {
+                    r#type: ExportType::Enum,
+                    statement: r#enum.clone(),
+                })
+            }
+
+            return Ok(r#enum);
+        } else
+        // Trait Declaration
+        if matches!(curr_token, Token::Trait) {
+            self.lexer.next_token();
+            let r#trait = self.parse_trait();
+
+            if public {
+                self.exports.push(Export {
+                    r#type: ExportType::Trait,
+                    statement: r#trait.clone(),
+                })
+            }
+
+            return Ok(r#trait);
+        } else
+        // Impl Block
+        if matches!(curr_token, Token::Impl) {
+            self.lexer.next_token();
+            return Ok(self.parse_impl());
         } else
         // Function Declaration
         if matches!(curr_token, Token::Function) {
-            let id = if matches!(self.lexer.peak_next_token(), Token::Identifier { .. }) {
-                match self.lexer.next_token() {
-                    Token::Identifier { val } => String::from_iter(val),
-                    _ => panic!("unexpected identifier"),
+            self.lexer.next_token();
+            let func = self.parse_function(None, false, false);
+
+            if public {
+                self.exports.push(Export {
+                    r#type: ExportType::Function,
+                    statement: func.clone(),
+                })
+            }
+
+            return Ok(func);
+        } else
+        // C Function
+        if matches!(curr_token, Token::Extern) {
+            self.lexer.next_token();
+            let func = self.parse_extern(public);
+
+            if public {
+                self.exports.push(Export {
+                    r#type: ExportType::Function,
+                    statement: func.clone(),
+                })
+            }
+
+            return Ok(func);
+        }
+
+        match self.parse_expr() {
+            Ok(expr) => {
+                if matches!(self.lexer.peak_next_token(), Token::Assign { .. }) {
+                    self.lexer.next_token();
+                    let value = self.parse_expr().map_err(|e| e.message)?;
+                    return Ok(Statements::Assignment {
+                        target: expr,
+                        value,
+                        line,
+                    });
                 }
-            } else {
-                panic!("{}", print_error("Expected an identifier", &self.lexer))
-            };
 
-            if !matches!(self.lexer.next_token(), Token::LParen { .. }) {
-                panic!("{}", print_error("expected '('", &self.lexer));
+                Ok(Statements::ExpressionStatement { expr, line })
             }
+            Err(_) => Err(format!("Unknown keyword found: {:#?}", curr_token)),
+        }
+    }
 
-            let mut parameters: Vec<FuncParam> = vec![];
+    /// Parses the `name: type = value` following `let` or `const`.
+    fn parse_binding(&mut self) -> (String, Type, Expression) {
+        let id = self.parse_identifier("Expected an identifier");
 
-            // handle function patams
-            loop {
-                let mut curr_tk = self.lexer.next_token();
+        if !matches!(self.lexer.next_token(), Token::Colon { .. }) {
+            panic!("{}", print_error("Expected ':'", &self.lexer))
+        }
 
-                if matches!(curr_tk, Token::RParen { .. }) {
-                    break;
+        let _type = self.parse_type();
+
+        if !matches!(self.lexer.next_token(), Token::Assign { .. }) {
+            panic!(
+                "{}",
+                print_error("Expected assignment operoator '='", &self.lexer)
+            )
+        };
+
+        let expr = self.parse_expr().unwrap();
+        (id, _type, expr)
+    }
+
+    /// Parses the remainder of a function declaration after the `fn`
+    /// keyword. Inside an `impl` block `self_type` is the type being
+    /// implemented, which a leading `self` parameter takes implicitly.
+    fn parse_function(&mut self, self_type: Option<&Type>, is_const: bool, is_extern: bool) -> Statements {
+        // `fn` was just read
+        let line = self.lexer.line;
+        let id = self.parse_identifier("Expected an identifier");
+        let scope = self.type_params.len();
+        let type_params = self.parse_type_params();
+        let (params, return_type) = self.parse_signature(self_type);
+
+        let body = self.parse_block();
+        self.expect_end();
+        self.type_params.truncate(scope);
+
+        Statements::FunctionDeclaration {
+            name: id,
+            type_params,
+            params,
+            body,
+            return_type,
+            is_const,
+            is_extern,
+            line,
+        }
+    }
+
+    /// Parses the remainder of an `extern "C" fn` after the `extern`
+    /// keyword. A `public` one is an Aurora function for C to call, the
+    /// others are declarations of C functions without a body.
+    fn parse_extern(&mut self, public: bool) -> Statements {
+        match self.lexer.next_token() {
+            Token::String { val } if val == ['C'] => {}
+            _ => panic!("{}", print_error("Expected \"C\" after 'extern'", &self.lexer)),
+        }
+
+        if !matches!(self.lexer.next_token(), Token::Function) {
+            panic!("{}", print_error("Expected 'fn'", &self.lexer));
+        }
+
+        if public {
+            return self.parse_function(None, false, true);
+        }
+
+        let name = self.parse_identifier("Expected an identifier");
+        let (params, return_type) = self.parse_signature(None);
+
+        Statements::ExternFunctionDeclaration {
+            name,
+            params,
+            return_type,
+        }
+    }
+
+    /// Parses the `(params) -> type` part of a function declaration.
+    fn parse_signature(&mut self, self_type: Option<&Type>) -> (Vec<FuncParam>, Type) {
+        if !matches!(self.lexer.next_token(), Token::LParen { .. }) {
+            panic!("{}", print_error("expected '('", &self.lexer));
+        }
+
+        let mut parameters: Vec<FuncParam> = vec![];
+
+        // handle function patams
+        loop {
+            if matches!(self.lexer.peak_next_token(), Token::RParen { .. }) {
+                self.lexer.next_token();
+                break;
+            }
+
+            if matches!(self.lexer.peak_next_token(), Token::Comma { .. }) {
+                self.lexer.next_token();
+            }
+
+            let id = self.parse_identifier("unexpected identifier");
+
+            // The type of `self` is implied by the enclosing `impl`
+            let implicit = parameters.is_empty()
+                && id == "self"
+                && !matches!(self.lexer.peak_next_token(), Token::Colon { .. });
+            let _type = match self_type {
+                Some(r#type) if implicit => r#type.clone(),
+                _ => {
+                    if !matches!(self.lexer.next_token(), Token::Colon { .. }) {
+                        panic!("{}", print_error("Expected ':'", &self.lexer))
+                    }
+
+                    self.parse_type()
                 }
+            };
+
+            parameters.push(FuncParam {
+                name: id,
+                r#type: _type,
+            })
+        }
+
+        let ret_type = if matches!(self.lexer.peak_next_token(), Token::Arrow { .. }) {
+            self.lexer.next_token();
+            self.parse_type()
+        } else {
+            Type::Void
+        };
+
+        (parameters, ret_type)
+    }
+
+    /// Parses the remainder of an `import` after the `import` keyword: a
+    /// dotted module path, optionally followed by `.{items}`.
+    fn parse_import(&mut self) -> Statements {
+        let mut path = vec![self.parse_identifier("Expected module name")];
+        let mut items: Option<Vec<String>> = None;
 
-                if matches!(curr_tk, Token::Comma { .. }) {
-                    curr_tk = self.lexer.next_token();
+        while matches!(self.lexer.peak_next_token(), Token::Dot { .. }) {
+            self.lexer.next_token();
+
+            if matches!(self.lexer.peak_next_token(), Token::LBrace { .. }) {
+                self.lexer.next_token();
+                let mut names: Vec<String> = vec![];
+
+                loop {
+                    if matches!(self.lexer.peak_next_token(), Token::Comma { .. }) {
+                        self.lexer.next_token();
+                    }
+
+                    if matches!(self.lexer.peak_next_token(), Token::RBrace { .. }) {
+                        self.lexer.next_token();
+                        break;
+                    }
+
+                    names.push(self.parse_identifier("Expected name to import"));
                 }
 
-                let id = match curr_tk {
+                items = Some(names);
+                break;
+            }
+
+            path.push(self.parse_identifier("Expected module name"));
+        }
+
+        Statements::Import { path, items }
+    }
+
+    /// Parses the remainder of a `trait` declaration after the `trait`
+    /// keyword: method signatures without bodies up to `end`. `Self` stands
+    /// for the implementing type.
+    fn parse_trait(&mut self) -> Statements {
+        let name = self.parse_identifier("Expected trait name");
+        let self_type = Type::TypeParameter {
+            name: "Self".to_string(),
+        };
+        let mut methods: Vec<FunctionSignature> = vec![];
+
+        self.type_params.push("Self".to_string());
+        loop {
+            match self.lexer.next_token() {
+                Token::End => break,
+                Token::Function => {
+                    let method = self.parse_identifier("Expected method name");
+                    let (params, return_type) = self.parse_signature(Some(&self_type));
+                    methods.push(FunctionSignature {
+                        name: method,
+                        params,
+                        return_type,
+                    });
+                }
+                _ => panic!("{}", print_error("Expected a method or 'end' in trait", &self.lexer)),
+            }
+        }
+        self.type_params.pop();
+
+        Statements::TraitDeclaration { name, methods }
+    }
+
+    /// Parses the remainder of an `impl` or `impl Trait for` block after the
+    /// `impl` keyword: the type followed by its methods up to `end`.
+    fn parse_impl(&mut self) -> Statements {
+        let mut r#type = self.parse_type();
+        let mut r#trait: Option<String> = None;
+
+        if matches!(self.lexer.peak_next_token(), Token::For) {
+            self.lexer.next_token();
+            r#trait = match r#type {
+                Type::UserDefinedType { name } => Some(name),
+                _ => panic!("{}", print_error("Expected trait name", &self.lexer)),
+            };
+            r#type = self.parse_type();
+        }
+
+        let mut methods: Vec<Statements> = vec![];
+
+        loop {
+            match self.lexer.next_token() {
+                Token::End => break,
+                Token::Function => methods.push(self.parse_function(Some(&r#type), false, false)),
+                _ => panic!("{}", print_error("Expected a method or 'end' in impl block", &self.lexer)),
+            }
+        }
+
+        Statements::ImplDeclaration {
+            r#trait,
+            r#type,
+            methods,
+        }
+    }
+
+    /// Parses the remainder of a `struct` declaration after the `struct`
+    /// keyword, one `name: type` field per line up to `end`.
+    fn parse_struct(&mut self) -> Statements {
+        let name = self.parse_identifier("Expected struct name");
+        let scope = self.type_params.len();
+        let type_params = self.parse_type_params();
+        let mut fields: Vec<StructField> = vec![];
+
+        loop {
+            if matches!(self.lexer.peak_next_token(), Token::Comma { .. }) {
+                self.lexer.next_token();
+            }
+
+            if matches!(self.lexer.peak_next_token(), Token::End) {
+                self.lexer.next_token();
+                break;
+            }
+
+            let field = self.parse_identifier("Expected field name");
+
+            if !matches!(self.lexer.next_token(), Token::Colon { .. }) {
+                panic!("{}", print_error("Expected ':'", &self.lexer))
+            }
+
+            fields.push(StructField {
+                name: field,
+                r#type: self.parse_type(),
+            });
+        }
+
+        self.type_params.truncate(scope);
+
+        Statements::StructDeclaration {
+            name,
+            type_params,
+            fields,
+        }
+    }
+
+    /// Parses the remainder of an `enum` declaration after the `enum`
+    /// keyword, one `Variant` or `Variant(types)` per line up to `end`.
+    fn parse_enum(&mut self) -> Statements {
+        let name = self.parse_identifier("Expected enum name");
+        let mut variants: Vec<EnumVariant> = vec![];
+
+        loop {
+            if matches!(self.lexer.peak_next_token(), Token::Comma { .. }) {
+                self.lexer.next_token();
+            }
+
+            if matches!(self.lexer.peak_next_token(), Token::End) {
+                self.lexer.next_token();
+                break;
+            }
+
+            let variant = self.parse_identifier("Expected variant name");
+            let fields = if matches!(self.lexer.peak_next_token(), Token::LParen { .. }) {
+                self.lexer.next_token();
+                self.parse_type_list(|t| matches!(t, Token::RParen { .. }))
+            } else {
+                vec![]
+            };
+
+            variants.push(EnumVariant {
+                name: variant,
+                fields,
+            });
+        }
+
+        Statements::EnumDeclaration { name, variants }
+    }
+
+    /// Parses the remainder of an `if` statement after the `if` keyword.
+    /// `else if` chains share the `end` of the outermost `if`.
+    fn parse_if(&mut self) -> Result<Statements, String> {
+        // `if` was just read
+        let line = self.lexer.line;
+        let condition = self.parse_expr().map_err(|e| e.message)?;
+        let body = self.parse_block();
+
+        let else_body = if matches!(self.lexer.peak_next_token(), Token::Else) {
+            self.lexer.next_token();
+            if matches!(self.lexer.peak_next_token(), Token::If) {
+                self.lexer.next_token();
+                return Ok(Statements::If {
+                    condition,
+                    body,
+                    else_body: vec![self.parse_if()?],
+                    line,
+                });
+            }
+            self.parse_block()
+        } else {
+            vec![]
+        };
+
+        self.expect_end();
+
+        Ok(Statements::If {
+            condition,
+            body,
+            else_body,
+            line,
+        })
+    }
+
+    pub fn parse_expr(&mut self) -> Result<Expression, error::Error> {
+        self.parse_binary_expr(0)
+    }
+
+    fn binary_operator(token: &Token) -> Option<(BinaryOperator, u8)> {
+        match token {
+            Token::Eq { .. } => Some((BinaryOperator::Equal, 1)),
+            Token::NotEq { .. } => Some((BinaryOperator::NotEqual, 1)),
+            Token::Lt { .. } => Some((BinaryOperator::LessThan, 1)),
+            Token::LtEq { .. } => Some((BinaryOperator::LessThanOrEqual, 1)),
+            Token::Gt { .. } => Some((BinaryOperator::GreaterThan, 1)),
+            Token::GtEq { .. } => Some((BinaryOperator::GreaterThanOrEqual, 1)),
+            Token::Plus { .. } => Some((BinaryOperator::Add, 2)),
+            Token::Minus { .. } => Some((BinaryOperator::Subtract, 2)),
+            Token::Multiplication { .. } => Some((BinaryOperator::Multiply, 3)),
+            Token::Division { .. } => Some((BinaryOperator::Divide, 3)),
+            Token::Modulo { .. } => Some((BinaryOperator::Modulo, 3)),
+            _ => None,
+        }
+    }
+
+    fn parse_binary_expr(&mut self, min_precedence: u8) -> Result<Expression, error::Error> {
+        let mut lhs = self.parse_unary_expr()?;
+
+        loop {
+            let (op, precedence) = match Self::binary_operator(&self.lexer.peak_next_token()) {
+                Some((op, precedence)) if precedence > min_precedence => (op, precedence),
+                _ => break,
+            };
+            self.lexer.next_token();
+
+            let rhs = self.parse_binary_expr(precedence)?;
+            lhs = Expression::BinaryOperation {
+                op,
+                lhs: Box::new(lhs),
+                rhs: Box::new(rhs),
+            };
+        }
+
+        Ok(lhs)
+    }
+
+    fn parse_unary_expr(&mut self) -> Result<Expression, error::Error> {
+        let op = match self.lexer.peak_next_token() {
+            Token::Minus { .. } => UnaryOperator::Negate,
+            Token::Bang { .. } => UnaryOperator::Not,
+            _ => return self.parse_postfix_expr(),
+        };
+        self.lexer.next_token();
+
+        Ok(Expression::UnaryOperation {
+            op,
+            expr: Box::new(self.parse_unary_expr()?),
+        })
+    }
+
+    fn parse_postfix_expr(&mut self) -> Result<Expression, error::Error> {
+        let mut expr = self.parse_primary_expr()?;
+
+        loop {
+            if matches!(self.lexer.peak_next_token(), Token::Dot { .. }) {
+                self.lexer.next_token();
+                let field = match self.lexer.next_token() {
                     Token::Identifier { val } => String::from_iter(val),
-                    _ => panic!("unexpected identifier"),
+                    _ => {
+                        return Err(error::Error {
+                            code: "AUR3003".to_string(),
+                            kind: error::ErrorKind::ExpressionError,
+                            message: print_error("expected field name after '.'", &self.lexer),
+                        })
+                    }
                 };
 
-                if !matches!(self.lexer.peak_next_token(), Token::Colon { .. }) {
-                    panic!("Expected ':'")
-                } else {
-                    self.lexer.next_token();
+                if matches!(self.lexer.peak_next_token(), Token::LParen { .. }) {
                     self.lexer.next_token();
+                    expr = Expression::MethodCall {
+                        receiver: Box::new(expr),
+                        method: field,
+                        params: self.parse_expr_list(|t| matches!(t, Token::RParen { .. }))?,
+                    };
+                    continue;
                 }
 
-                let _type = if matches!(self.lexer.peak_next_token(), Token::Identifier { .. }) {
-                    let raw_type = match self.lexer.next_token() {
-                        Token::Identifier { val } => String::from_iter(val),
-                        x => panic!("{:?}", x),
-                    };
-                    Type::parse_type(raw_type)
-                } else {
-                    panic!("expected type")
+                expr = Expression::FieldAccess {
+                    expr: Box::new(expr),
+                    field,
                 };
+                continue;
+            }
 
-                parameters.push(FuncParam {
-                    name: id,
-                    r#type: _type,
-                })
+            if !matches!(self.lexer.peak_next_token(), Token::LBracket { .. }) {
+                break;
+            }
+
+            self.lexer.next_token();
+            let index = self.parse_expr()?;
+
+            if !matches!(self.lexer.next_token(), Token::RBracket { .. }) {
+                return Err(error::Error {
+                    code: "AUR3001".to_string(),
+                    kind: error::ErrorKind::ExpressionError,
+                    message: print_error("expected ']' after index", &self.lexer),
+                });
+            }
+
+            expr = Expression::Index {
+                array: Box::new(expr),
+                index: Box::new(index),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    /// Parses a comma separated list of expressions up to `close`, which is
+    /// consumed.
+    fn parse_expr_list(
+        &mut self,
+        close: fn(&Token) -> bool,
+    ) -> Result<Vec<Expression>, error::Error> {
+        let mut exprs: Vec<Expression> = vec![];
+
+        loop {
+            if matches!(self.lexer.peak_next_token(), Token::Eof) {
+                return Err(error::Error {
+                    code: "AUR3000".to_string(),
+                    kind: error::ErrorKind::ExpressionError,
+                    message: "unexpected end of file".to_string(),
+                });
             }
 
-            if matches!(self.lexer.peak_next_token(), Token::Arrow { .. }) {
+            if matches!(self.lexer.peak_next_token(), Token::Comma { .. }) {
                 self.lexer.next_token();
             }
 
-            let ret_type = if matches!(self.lexer.peak_next_token(), Token::Identifier { .. }) {
-                let raw_type = match self.lexer.next_token() {
+            if close(&self.lexer.peak_next_token()) {
+                self.lexer.next_token();
+                break;
+            }
+
+            exprs.push(self.parse_expr()?);
+        }
+
+        Ok(exprs)
+    }
+
+    /// Parses the remainder of a `match` expression after the `match`
+    /// keyword: the scrutinee followed by `pattern => expression` arms up to
+    /// `end`.
+    fn parse_match(&mut self) -> Result<Expression, error::Error> {
+        let expr = self.parse_expr()?;
+        let mut arms: Vec<MatchArm> = vec![];
+
+        loop {
+            if matches!(self.lexer.peak_next_token(), Token::End) {
+                self.lexer.next_token();
+                break;
+            }
+
+            let pattern = self.parse_pattern()?;
+
+            if !matches!(self.lexer.next_token(), Token::FatArrow { .. }) {
+                return Err(error::Error {
+                    code: "AUR3007".to_string(),
+                    kind: error::ErrorKind::ExpressionError,
+                    message: print_error("expected '=>' after pattern", &self.lexer),
+                });
+            }
+
+            arms.push(MatchArm {
+                pattern,
+                body: self.parse_expr()?,
+            });
+        }
+
+        Ok(Expression::Match {
+            expr: Box::new(expr),
+            arms,
+        })
+    }
+
+    fn parse_pattern(&mut self) -> Result<Pattern, error::Error> {
+        match self.lexer.next_token() {
+            Token::Int { val } => Ok(Pattern::IntLiteral {
+                val: String::from_iter(val).parse().unwrap(),
+            }),
+            Token::Minus { .. } => match self.lexer.next_token() {
+                Token::Int { val } => Ok(Pattern::IntLiteral {
+                    val: -String::from_iter(val).parse::<i64>().unwrap(),
+                }),
+                _ => Err(error::Error {
+                    code: "AUR3008".to_string(),
+                    kind: error::ErrorKind::ExpressionError,
+                    message: print_error("expected integer after '-' in pattern", &self.lexer),
+                }),
+            },
+            Token::True => Ok(Pattern::BooleanLiteral { val: true }),
+            Token::False => Ok(Pattern::BooleanLiteral { val: false }),
+            Token::Identifier { val } => {
+                let name = String::from_iter(val);
+
+                if !matches!(self.lexer.peak_next_token(), Token::DoubleColon { .. }) {
+                    if name == "_" {
+                        return Ok(Pattern::Wildcard);
+                    }
+                    return Ok(Pattern::Binding { name });
+                }
+
+                self.lexer.next_token();
+                let variant = match self.lexer.next_token() {
                     Token::Identifier { val } => String::from_iter(val),
-                    x => panic!("{:?}", x),
+                    _ => {
+                        return Err(error::Error {
+                            code: "AUR3006".to_string(),
+                            kind: error::ErrorKind::ExpressionError,
+                            message: print_error("expected variant name after '::'", &self.lexer),
+                        })
+                    }
                 };
-                Type::parse_type(raw_type)
-            } else {
-                Type::Void
-            };
 
-            let body = self.parse_block();
+                let mut fields: Vec<Pattern> = vec![];
+                if matches!(self.lexer.peak_next_token(), Token::LParen { .. }) {
+                    self.lexer.next_token();
+                    loop {
+                        if matches!(self.lexer.peak_next_token(), Token::Comma { .. }) {
+                            self.lexer.next_token();
+                        }
 
-            // if !matches!(self.lexer.next_token(), Token::End) {
-            //     return Err(print_error("error", &self.lexer));
-            // }
+                        if matches!(self.lexer.peak_next_token(), Token::RParen { .. }) {
+                            self.lexer.next_token();
+                            break;
+                        }
 
-            self.lexer.next_token();
+                        fields.push(self.parse_pattern()?);
+                    }
+                }
 
-            let func = Statements::FunctionDeclaration {
-                name: id,
-                params: parameters,
-                body,
-                return_type: ret_type,
+                Ok(Pattern::Variant {
+                    enum_name: name,
+                    variant,
+                    fields,
+                })
+            }
+            x => Err(error::Error {
+                code: "AUR3009".to_string(),
+                kind: error::ErrorKind::ExpressionError,
+                message: print_error(&format!("can not parse this pattern {:#?}", x), &self.lexer),
+            }),
+        }
+    }
+
+    /// Parses the `{ field: value, ... }` part of a struct literal.
+    fn parse_struct_literal(&mut self, name: String) -> Result<Expression, error::Error> {
+        let mut fields: Vec<(String, Expression)> = vec![];
+
+        loop {
+            if matches!(self.lexer.peak_next_token(), Token::Comma { .. }) {
+                self.lexer.next_token();
+            }
+
+            if matches!(self.lexer.peak_next_token(), Token::RBrace { .. }) {
+                self.lexer.next_token();
+                break;
+            }
+
+            let field = match self.lexer.next_token() {
+                Token::Identifier { val } => String::from_iter(val),
+                _ => {
+                    return Err(error::Error {
+                        code: "AUR3004".to_string(),
+                        kind: error::ErrorKind::ExpressionError,
+                        message: print_error("expected field name in struct literal", &self.lexer),
+                    })
+                }
             };
 
-            if public {
-                self.exports.push(Export {
-                    r#type: ExportType::Function,
-                    statement: func.clone(),
-                })
+            if !matches!(self.lexer.next_token(), Token::Colon { .. }) {
+                return Err(error::Error {
+                    code: "AUR3005".to_string(),
+                    kind: error::ErrorKind::ExpressionError,
+                    message: print_error("expected ':' after field name", &self.lexer),
+                });
             }
 
-            return Ok(func);
-        } else if let Ok(expr) = self.parse_expr() {
-            Ok(Statements::ExpressionStatement { expr })
-        } else {
-            return Err(format!("Unknown keyword found: {:#?}", curr_token));
+            fields.push((field, self.parse_expr()?));
         }
+
+        Ok(Expression::StructLiteral { name, fields })
     }
 
-    pub fn parse_expr(&mut self) -> Result<Expression, error::Error> {
+    fn parse_primary_expr(&mut self) -> Result<Expression, error::Error> {
         match self.lexer.next_token() {
             Token::String { val } => Ok(Expression::StringLiteral {
                 val: String::from_iter(val),
             }),
             Token::Int { val } => {
                 let val = String::from_iter(val);
-                return Ok(Expression::IntLiteral {
+                Ok(Expression::IntLiteral {
                     val: val.trim().parse().unwrap(),
-                });
+                })
             }
             Token::Float { val } => {
                 let val = String::from_iter(val);
@@ -256,38 +1097,65 @@ impl Parser {
             }
             Token::False => Ok(Expression::BooleanLiteral { val: false }),
             Token::True => Ok(Expression::BooleanLiteral { val: true }),
+            Token::Match => self.parse_match(),
+            Token::LParen { .. } => {
+                let expr = self.parse_expr()?;
+
+                if !matches!(self.lexer.next_token(), Token::RParen { .. }) {
+                    return Err(error::Error {
+                        code: "AUR3002".to_string(),
+                        kind: error::ErrorKind::ExpressionError,
+                        message: print_error("expected ')'", &self.lexer),
+                    });
+                }
+
+                Ok(expr)
+            }
+            Token::LBracket { .. } => {
+                let elements = self.parse_expr_list(|t| matches!(t, Token::RBracket { .. }))?;
+
+                Ok(Expression::ArrayLiteral { elements })
+            }
             Token::Identifier { val } => {
                 let val = String::from_iter(val);
 
                 if matches!(self.lexer.peak_next_token(), Token::LParen { .. }) {
                     self.lexer.next_token();
-                    self.lexer.next_token();
+                    let params = self.parse_expr_list(|t| matches!(t, Token::RParen { .. }))?;
 
-                    let mut params: Vec<Expression> = vec![];
+                    return Ok(Expression::FunctionCall { name: val, params });
+                }
 
-                    loop {
-                        if matches!(self.lexer.peak_next_token(), Token::EOF) {
+                if matches!(self.lexer.peak_next_token(), Token::LBrace { .. }) {
+                    self.lexer.next_token();
+                    return self.parse_struct_literal(val);
+                }
+
+                if matches!(self.lexer.peak_next_token(), Token::DoubleColon { .. }) {
+                    self.lexer.next_token();
+                    let variant = match self.lexer.next_token() {
+                        Token::Identifier { val } => String::from_iter(val),
+                        _ => {
                             return Err(error::Error {
-                                code: "AUR3000".to_string(),
+                                code: "AUR3006".to_string(),
                                 kind: error::ErrorKind::ExpressionError,
-                                message: "unexpected end of file".to_string(),
-                            });
-                        }
-
-                        if matches!(self.lexer.peak_next_token(), Token::RParen { .. }) {
-                            self.lexer.next_token();
-                            break;
-                        }
-
-                        if matches!(self.lexer.peak_next_token(), Token::Comma { .. }) {
-                            self.lexer.next_token();
-                            self.lexer.next_token();
+                                message: print_error("expected variant name after '::'", &self.lexer),
+                            })
                         }
+                    };
 
-                        params.push(self.parse_expr().unwrap());
-                    }
+                    let values = if matches!(self.lexer.peak_next_token(), Token::LParen { .. }) {
+                        self.lexer.next_token();
+                        self.parse_expr_list(|t| matches!(t, Token::RParen { .. }))?
+                    } else {
+                        vec![]
+                    };
 
-                    return Ok(Expression::FunctionCall { name: val, params });
+                    return Ok(Expression::EnumVariant {
+                        enum_name: val,
+                        variant,
+                        values,
+                    });
                 }
 
                 Ok(Expression::Identifier { val })
@@ -0,0 +1,207 @@
+//! Pattern usefulness, following Maranget's "Warnings for pattern matching".
+//!
+//! A pattern is useful with respect to a list of rows if some value matches
+//! it but none of the rows. An arm is unreachable when its pattern is not
+//! useful against the arms before it, and a `match` is exhaustive when `_`
+//! is not useful against all of its arms.
+
+use std::collections::HashMap;
+
+use crate::ast::{expressions::Pattern, EnumVariant};
+
+#[derive(Debug, Clone, PartialEq)]
+enum Constructor {
+    Variant { enum_name: String, index: usize },
+    Boolean(bool),
+    Int(i64),
+}
+
+#[derive(Debug, Clone)]
+enum Pat {
+    Wild,
+    Ctor(Constructor, Vec<Pat>),
+}
+
+pub struct Matrix<'a> {
+    enums: &'a HashMap<String, Vec<EnumVariant>>,
+    rows: Vec<Vec<Pat>>,
+}
+
+impl<'a> Matrix<'a> {
+    pub fn new(enums: &'a HashMap<String, Vec<EnumVariant>>) -> Self {
+        Self {
+            enums,
+            rows: vec![],
+        }
+    }
+
+    pub fn push(&mut self, pattern: &Pattern) {
+        let row = vec![self.lower(pattern)];
+        self.rows.push(row);
+    }
+
+    /// Returns `true` if some value matches `pattern` but no row so far.
+    pub fn is_useful(&self, pattern: &Pattern) -> bool {
+        self.useful(&self.rows, &[self.lower(pattern)]).is_some()
+    }
+
+    /// Returns a pattern for a value no row matches, if there is one.
+    pub fn missing_pattern(&self) -> Option<Pattern> {
+        self.useful(&self.rows, &[Pat::Wild])
+            .map(|mut witness| self.raise(witness.remove(0)))
+    }
+
+    /// Patterns are expected to have been validated against the enum
+    /// declarations already.
+    fn lower(&self, pattern: &Pattern) -> Pat {
+        match pattern {
+            Pattern::Wildcard | Pattern::Binding { .. } => Pat::Wild,
+            Pattern::IntLiteral { val } => Pat::Ctor(Constructor::Int(*val), vec![]),
+            Pattern::BooleanLiteral { val } => Pat::Ctor(Constructor::Boolean(*val), vec![]),
+            Pattern::Variant {
+                enum_name,
+                variant,
+                fields,
+            } => {
+                let index = self.enums[enum_name]
+                    .iter()
+                    .position(|v| &v.name == variant)
+                    .unwrap();
+                Pat::Ctor(
+                    Constructor::Variant {
+                        enum_name: enum_name.clone(),
+                        index,
+                    },
+                    fields.iter().map(|f| self.lower(f)).collect(),
+                )
+            }
+        }
+    }
+
+    fn raise(&self, pat: Pat) -> Pattern {
+        match pat {
+            Pat::Wild => Pattern::Wildcard,
+            Pat::Ctor(Constructor::Int(val), _) => Pattern::IntLiteral { val },
+            Pat::Ctor(Constructor::Boolean(val), _) => Pattern::BooleanLiteral { val },
+            Pat::Ctor(Constructor::Variant { enum_name, index }, fields) => Pattern::Variant {
+                variant: self.enums[&enum_name][index].name.clone(),
+                enum_name,
+                fields: fields.into_iter().map(|f| self.raise(f)).collect(),
+            },
+        }
+    }
+
+    fn arity(&self, constructor: &Constructor) -> usize {
+        match constructor {
+            Constructor::Variant { enum_name, index } => self.enums[enum_name][*index].fields.len(),
+            Constructor::Boolean(_) | Constructor::Int(_) => 0,
+        }
+    }
+
+    /// Every constructor of the type `heads` belong to, or `None` when the
+    /// type has too many values to list.
+    fn all_constructors(&self, heads: &[Constructor]) -> Option<Vec<Constructor>> {
+        match heads.first()? {
+            Constructor::Variant { enum_name, .. } => Some(
+                (0..self.enums[enum_name].len())
+                    .map(|index| Constructor::Variant {
+                        enum_name: enum_name.clone(),
+                        index,
+                    })
+                    .collect(),
+            ),
+            Constructor::Boolean(_) => Some(vec![
+                Constructor::Boolean(true),
+                Constructor::Boolean(false),
+            ]),
+            Constructor::Int(_) => None,
+        }
+    }
+
+    fn useful(&self, rows: &[Vec<Pat>], vector: &[Pat]) -> Option<Vec<Pat>> {
+        if vector.is_empty() {
+            return if rows.is_empty() { Some(vec![]) } else { None };
+        }
+
+        match &vector[0] {
+            Pat::Ctor(constructor, fields) => {
+                let rows = specialize(rows, constructor, fields.len());
+                let mut vector_tail = fields.clone();
+                vector_tail.extend_from_slice(&vector[1..]);
+
+                self.useful(&rows, &vector_tail)
+                    .map(|witness| rebuild(constructor.clone(), fields.len(), witness))
+            }
+            Pat::Wild => {
+                let heads: Vec<Constructor> = rows
+                    .iter()
+                    .filter_map(|row| match &row[0] {
+                        Pat::Ctor(constructor, _) => Some(constructor.clone()),
+                        Pat::Wild => None,
+                    })
+                    .collect();
+                let all = self.all_constructors(&heads);
+
+                if let Some(all) = &all {
+                    if all.iter().all(|c| heads.contains(c)) {
+                        // Every constructor appears, so try each of them
+                        for constructor in all {
+                            let arity = self.arity(constructor);
+                            let rows = specialize(rows, constructor, arity);
+                            let mut vector_tail = vec![Pat::Wild; arity];
+                            vector_tail.extend_from_slice(&vector[1..]);
+
+                            if let Some(witness) = self.useful(&rows, &vector_tail) {
+                                return Some(rebuild(constructor.clone(), arity, witness));
+                            }
+                        }
+                        return None;
+                    }
+                }
+
+                let default: Vec<Vec<Pat>> = rows
+                    .iter()
+                    .filter(|row| matches!(row[0], Pat::Wild))
+                    .map(|row| row[1..].to_vec())
+                    .collect();
+
+                self.useful(&default, &vector[1..]).map(|mut witness| {
+                    let head = match all {
+                        Some(all) => {
+                            let missing = all.into_iter().find(|c| !heads.contains(c)).unwrap();
+                            let arity = self.arity(&missing);
+                            Pat::Ctor(missing, vec![Pat::Wild; arity])
+                        }
+                        None => Pat::Wild,
+                    };
+                    witness.insert(0, head);
+                    witness
+                })
+            }
+        }
+    }
+}
+
+/// Keeps the rows that match `constructor`, replacing their first column by
+/// the constructor's fields.
+fn specialize(rows: &[Vec<Pat>], constructor: &Constructor, arity: usize) -> Vec<Vec<Pat>> {
+    rows.iter()
+        .filter_map(|row| {
+            let mut specialized = match &row[0] {
+                Pat::Wild => vec![Pat::Wild; arity],
+                Pat::Ctor(c, fields) if c == constructor => fields.clone(),
+                Pat::Ctor(..) => return None,
+            };
+            specialized.extend_from_slice(&row[1..]);
+            Some(specialized)
+        })
+        .collect()
+}
+
+/// Folds the first `arity` patterns of a witness back under `constructor`.
+fn rebuild(constructor: Constructor, arity: usize, mut witness: Vec<Pat>) -> Vec<Pat> {
+    let rest = witness.split_off(arity);
+    let mut rebuilt = vec![Pat::Ctor(constructor, witness)];
+    rebuilt.extend(rest);
+    rebuilt
+}
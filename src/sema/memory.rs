@@ -0,0 +1,57 @@
+//! Which values are reference counted, shared by the backends so that they
+//! manage the same values.
+//!
+//! Strings and dynamic arrays are reference counted, and so are the
+//! aggregates containing them through their fields. Each variable and each
+//! value stored in another one holds a reference, which is released when
+//! the variable goes out of scope or the value is overwritten.
+//!
+//! The value of an expression is borrowed, except where it is new, like the
+//! result of a call. New values are temporaries that the end of the
+//! statement releases, unless they are moved somewhere first. Arguments are
+//! borrowed from the caller, so a function only retains the parameters it
+//! assigns to.
+
+use std::collections::HashSet;
+
+use crate::ast::{EnumVariant, StructField, Type};
+
+/// The structs and enums of the program being compiled.
+pub trait Declarations {
+    /// The fields of `r#type`, with its type arguments substituted, if it is
+    /// a struct.
+    fn struct_fields(&self, r#type: &Type) -> Option<Vec<StructField>>;
+
+    fn enum_variants(&self, name: &str) -> Option<&[EnumVariant]>;
+}
+
+/// Whether values of `r#type` hold references.
+pub fn is_managed(declarations: &impl Declarations, r#type: &Type) -> bool {
+    holds_references(declarations, r#type, &mut HashSet::new())
+}
+
+fn holds_references(declarations: &impl Declarations, r#type: &Type, seen: &mut HashSet<Type>) -> bool {
+    // Sema rejects types containing themselves, a type seen again can only
+    // be another field of the same type, which is answered already
+    if !seen.insert(r#type.clone()) {
+        return false;
+    }
+
+    match r#type {
+        Type::String | Type::Array(_) => true,
+        Type::FixedArray(element, _) => holds_references(declarations, element, seen),
+        Type::Tuple(types) => types.iter().any(|t| holds_references(declarations, t, seen)),
+        r#type => match declarations.struct_fields(r#type) {
+            Some(fields) => fields
+                .iter()
+                .any(|f| holds_references(declarations, &f.r#type, seen)),
+            None => match declarations.enum_variants(&r#type.to_string()) {
+                Some(variants) => variants
+                    .iter()
+                    .flat_map(|v| &v.fields)
+                    .any(|t| holds_references(declarations, t, seen)),
+                None => false,
+            },
+        },
+    }
+}
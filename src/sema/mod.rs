@@ -0,0 +1,577 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{
+    ast::{
+        expressions::{Expression, MatchArm, Pattern},
+        statements::Statements,
+        EnumVariant, FuncParam, FunctionSignature, Program, StructField, Type, TypeParam,
+    },
+    error::{CompilerError, CompilerErrorKind},
+    utils::format_template,
+};
+
+mod exhaustiveness;
+pub mod memory;
+mod types;
+pub mod typing;
+
+use exhaustiveness::Matrix;
+
+/// Checks run on the parsed program before code generation.
+pub struct Analyzer {
+    enums: HashMap<String, Vec<EnumVariant>>,
+    traits: HashMap<String, Vec<FunctionSignature>>,
+    structs: HashMap<String, (Vec<TypeParam>, Vec<StructField>)>,
+    /// Parameter and return types of the non-generic functions, including
+    /// extern functions and methods under their mangled names
+    functions: HashMap<String, (Vec<Type>, Type)>,
+    /// Functions defined in C
+    c_functions: HashSet<String>,
+    generic_functions: HashMap<String, Statements>,
+    constants: HashMap<String, Type>,
+    globals: HashMap<String, Type>,
+    trait_impls: HashSet<(String, Type)>,
+    /// Instances of generic functions, checked with their type arguments
+    /// bound like they are compiled
+    instances: HashSet<(String, Vec<Type>)>,
+    pending_instances: Vec<(Statements, HashMap<String, Type>)>,
+    /// State of the function being type checked
+    scopes: Vec<HashMap<String, Type>>,
+    return_type: Type,
+    type_bindings: HashMap<String, Type>,
+    /// Type parameters of the generic struct whose fields are being
+    /// checked, which stand for any type implementing their bounds
+    type_params: Vec<TypeParam>,
+    /// Types reported for containing themselves, with the types they
+    /// contain themselves through
+    recursive_types: HashSet<String>,
+    /// What is being checked, for error messages
+    context: Option<String>,
+    line: usize,
+    errors: Vec<CompilerError>,
+}
+
+/// Whether values of `r#type` can be passed to and from C as they are.
+/// Strings are `const char *` to C.
+fn is_c_type(r#type: &Type) -> bool {
+    match r#type {
+        Type::Int8
+        | Type::Int16
+        | Type::Int32
+        | Type::Int64
+        | Type::Float32
+        | Type::Float64
+        | Type::Boolean
+        | Type::String
+        | Type::Pointer(_) => true,
+        Type::Function {
+            params,
+            return_type,
+        } => params.iter().all(is_c_type) && is_c_return_type(return_type),
+        _ => false,
+    }
+}
+
+fn is_c_return_type(r#type: &Type) -> bool {
+    *r#type == Type::Void || is_c_type(r#type)
+}
+
+/// Checks `program` against the declarations it imports. Only the
+/// program's own functions are type checked, the imported ones were when
+/// their module was compiled.
+pub fn check(imported: &[Statements], program: &Program) -> Result<(), Vec<CompilerError>> {
+    let mut analyzer = Analyzer::new();
+    analyzer.check_program(imported, program);
+
+    if analyzer.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(analyzer.errors)
+    }
+}
+
+impl Analyzer {
+    fn new() -> Self {
+        Self {
+            enums: HashMap::new(),
+            traits: HashMap::new(),
+            structs: HashMap::new(),
+            functions: HashMap::new(),
+            c_functions: HashSet::new(),
+            generic_functions: HashMap::new(),
+            constants: HashMap::new(),
+            globals: HashMap::new(),
+            trait_impls: HashSet::new(),
+            instances: HashSet::new(),
+            pending_instances: vec![],
+            scopes: vec![],
+            return_type: Type::Void,
+            type_bindings: HashMap::new(),
+            type_params: vec![],
+            recursive_types: HashSet::new(),
+            context: None,
+            line: 0,
+            errors: vec![],
+        }
+    }
+
+    fn error(&mut self, code: usize, message: String) {
+        let mut message = message;
+        if let Some(context) = &self.context {
+            message = format!("{}, in {}", message, context);
+        }
+        if self.line != 0 {
+            message = format!("{} on line {}", message, self.line);
+        }
+
+        self.errors.push(CompilerError {
+            code,
+            kind: CompilerErrorKind::SemanticError,
+            message,
+        })
+    }
+
+    pub fn check_program(&mut self, imported: &[Statements], program: &Program) {
+        let statements: Vec<&Statements> = imported.iter().chain(&program.statements).collect();
+
+        for statement in &statements {
+            match statement {
+                Statements::EnumDeclaration { name, variants } => self.declare_enum(name, variants),
+                Statements::TraitDeclaration { name, methods } => {
+                    let previous = self.traits.insert(name.clone(), methods.clone());
+                    if previous.is_some() {
+                        self.error(207, format!("trait `{}` is declared more than once", name));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        // Impls may not overlap, so that every call of a trait method
+        // resolves to exactly one implementation
+        let mut impls: HashSet<(String, Type)> = HashSet::new();
+        for statement in &statements {
+            if let Statements::ImplDeclaration {
+                r#trait: Some(r#trait),
+                r#type,
+                methods,
+            } = statement
+            {
+                if !impls.insert((r#trait.clone(), r#type.clone())) {
+                    self.error(
+                        210,
+                        format!("conflicting implementations of `{}` for {}", r#trait, r#type),
+                    );
+                }
+                self.check_trait_impl(r#trait, r#type, methods);
+            }
+        }
+
+        let mut methods: HashSet<(String, String)> = HashSet::new();
+        for statement in &statements {
+            if let Statements::ImplDeclaration {
+                r#type,
+                methods: functions,
+                ..
+            } = statement
+            {
+                for function in functions {
+                    if let Statements::FunctionDeclaration { name, .. } = function {
+                        if !methods.insert((r#type.to_string(), name.clone())) {
+                            self.error(
+                                206,
+                                format!("method `{}` is defined more than once for {}", name, r#type),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+
+        for statement in &statements {
+            self.declare(statement);
+        }
+
+        for statement in &program.statements {
+            if matches!(
+                statement,
+                Statements::ExpressionStatement { .. }
+                    | Statements::Assignment { .. }
+                    | Statements::Return { .. }
+                    | Statements::If { .. }
+                    | Statements::While { .. }
+            ) {
+                self.error(213, "expressions are not allowed at the top level".to_string());
+            }
+            self.check_statement(statement);
+        }
+
+        // Types are only checked in programs that are otherwise valid, so
+        // that one mistake is not reported again by every use
+        if !self.errors.is_empty() {
+            return;
+        }
+        for statement in &program.statements {
+            self.check_types(statement);
+        }
+        self.check_instances();
+    }
+
+    fn declare_enum(&mut self, name: &str, variants: &[EnumVariant]) {
+        if self.enums.contains_key(name) {
+            self.error(203, format!("enum `{}` is declared more than once", name));
+            return;
+        }
+
+        for (i, variant) in variants.iter().enumerate() {
+            if variants[..i].iter().any(|v| v.name == variant.name) {
+                self.error(
+                    203,
+                    format!("variant `{}::{}` is declared more than once", name, variant.name),
+                );
+            }
+        }
+
+        self.enums.insert(name.to_string(), variants.to_vec());
+    }
+
+    fn check_statement(&mut self, statement: &Statements) {
+        match statement {
+            Statements::VariableDeclaration { value, .. }
+            | Statements::ConstDeclaration { value, .. } => self.check_expression(value),
+            Statements::FunctionDeclaration {
+                name,
+                type_params,
+                params,
+                body,
+                return_type,
+                is_extern,
+                ..
+            } => {
+                if *is_extern {
+                    if !type_params.is_empty() {
+                        self.error(
+                            211,
+                            format!("extern function `{}` can not have type parameters", name),
+                        );
+                    }
+                    self.check_extern_signature(name, params, return_type);
+                }
+                self.check_bounds(type_params);
+                self.check_block(body);
+            }
+            Statements::ExternFunctionDeclaration {
+                name,
+                params,
+                return_type,
+            } => self.check_extern_signature(name, params, return_type),
+            Statements::StructDeclaration { type_params, .. } => self.check_bounds(type_params),
+            Statements::ImplDeclaration { methods, .. } => self.check_block(methods),
+            Statements::ExpressionStatement { expr, .. } => self.check_expression(expr),
+            Statements::Assignment { target, value, .. } => {
+                self.check_expression(target);
+                self.check_expression(value);
+            }
+            Statements::Return { value, .. } => {
+                if let Some(value) = value {
+                    self.check_expression(value);
+                }
+            }
+            Statements::If {
+                condition,
+                body,
+                else_body,
+                ..
+            } => {
+                self.check_expression(condition);
+                self.check_block(body);
+                self.check_block(else_body);
+            }
+            Statements::While { condition, body, .. } => {
+                self.check_expression(condition);
+                self.check_block(body);
+            }
+            Statements::ModuleDeclaration { .. }
+            | Statements::Import { .. }
+            | Statements::EnumDeclaration { .. }
+            | Statements::TraitDeclaration { .. } => {}
+        }
+    }
+
+    /// Checks that an `extern "C"` function only takes and returns values
+    /// that C has a type for.
+    fn check_extern_signature(&mut self, name: &str, params: &[FuncParam], return_type: &Type) {
+        for param in params {
+            if !is_c_type(&param.r#type) {
+                self.error(
+                    211,
+                    format!(
+                        "parameter `{}` of extern function `{}` has type {}, which can not be passed to C",
+                        param.name, name, param.r#type
+                    ),
+                );
+            }
+        }
+
+        if !is_c_return_type(return_type) {
+            self.error(
+                211,
+                format!(
+                    "extern function `{}` returns {}, which can not be returned by C",
+                    name, return_type
+                ),
+            );
+        }
+    }
+
+    /// Checks the template of a `format` call. The values are checked by
+    /// the backends, which know their types.
+    fn check_format(&mut self, params: &[Expression]) {
+        if let Err(message) = format_template(params) {
+            self.error(212, message);
+        }
+    }
+
+    fn check_bounds(&mut self, type_params: &[TypeParam]) {
+        for param in type_params {
+            for bound in &param.bounds {
+                if !self.traits.contains_key(bound) {
+                    self.error(208, format!("unknown trait `{}`", bound));
+                }
+            }
+        }
+    }
+
+    /// Checks that an `impl Trait for Type` block defines exactly the methods
+    /// of the trait, with `Self` replaced by `Type` in their signatures.
+    fn check_trait_impl(&mut self, r#trait: &str, r#type: &Type, methods: &[Statements]) {
+        let signatures = match self.traits.get(r#trait) {
+            Some(signatures) => signatures.clone(),
+            None => {
+                self.error(208, format!("unknown trait `{}`", r#trait));
+                return;
+            }
+        };
+        let bindings = HashMap::from([("Self".to_string(), r#type.clone())]);
+
+        for signature in &signatures {
+            let method = methods.iter().find_map(|m| match m {
+                Statements::FunctionDeclaration {
+                    name,
+                    params,
+                    return_type,
+                    ..
+                } if name == &signature.name => Some((params, return_type)),
+                _ => None,
+            });
+
+            let (params, return_type) = match method {
+                Some(method) => method,
+                None => {
+                    self.error(
+                        209,
+                        format!("missing method `{}` in impl of `{}` for {}", signature.name, r#trait, r#type),
+                    );
+                    continue;
+                }
+            };
+
+            let expected: Vec<Type> = signature
+                .params
+                .iter()
+                .map(|p| p.r#type.substitute(&bindings))
+                .collect();
+            let found: Vec<Type> = params.iter().map(|p| p.r#type.clone()).collect();
+
+            if expected != found || &signature.return_type.substitute(&bindings) != return_type {
+                self.error(
+                    209,
+                    format!(
+                        "method `{}` of {} does not match its signature in trait `{}`",
+                        signature.name, r#type, r#trait
+                    ),
+                );
+            }
+        }
+
+        for method in methods {
+            if let Statements::FunctionDeclaration { name, .. } = method {
+                if !signatures.iter().any(|s| &s.name == name) {
+                    self.error(209, format!("method `{}` is not a member of trait `{}`", name, r#trait));
+                }
+            }
+        }
+    }
+
+    fn check_block(&mut self, block: &[Statements]) {
+        for statement in block {
+            self.check_statement(statement);
+        }
+    }
+
+    fn check_expression(&mut self, expr: &Expression) {
+        match expr {
+            Expression::StringLiteral { .. }
+            | Expression::Identifier { .. }
+            | Expression::IntLiteral { .. }
+            | Expression::BooleanLiteral { .. }
+            | Expression::FloatLiteral { .. } => {}
+            Expression::FunctionCall { name, params } => {
+                if name == "format" {
+                    self.check_format(params);
+                }
+                for param in params {
+                    self.check_expression(param);
+                }
+            }
+            Expression::MethodCall {
+                receiver, params, ..
+            } => {
+                self.check_expression(receiver);
+                for param in params {
+                    self.check_expression(param);
+                }
+            }
+            Expression::ArrayLiteral { elements } => {
+                for element in elements {
+                    self.check_expression(element);
+                }
+            }
+            Expression::Index { array, index } => {
+                self.check_expression(array);
+                self.check_expression(index);
+            }
+            Expression::StructLiteral { fields, .. } => {
+                for (_, value) in fields {
+                    self.check_expression(value);
+                }
+            }
+            Expression::FieldAccess { expr, .. } => self.check_expression(expr),
+            Expression::EnumVariant {
+                enum_name,
+                variant,
+                values,
+            } => {
+                match self.lookup_variant(enum_name, variant) {
+                    Ok(fields) if fields != values.len() => self.error(
+                        204,
+                        format!(
+                            "`{}::{}` takes {} values but {} were supplied",
+                            enum_name,
+                            variant,
+                            fields,
+                            values.len()
+                        ),
+                    ),
+                    Ok(_) => {}
+                    Err(message) => self.error(205, message),
+                }
+
+                for value in values {
+                    self.check_expression(value);
+                }
+            }
+            Expression::BinaryOperation { lhs, rhs, .. } => {
+                self.check_expression(lhs);
+                self.check_expression(rhs);
+            }
+            Expression::UnaryOperation { expr, .. } => self.check_expression(expr),
+            Expression::Match { expr, arms } => {
+                self.check_expression(expr);
+                self.check_match(arms);
+            }
+        }
+    }
+
+    /// Returns the number of fields of `enum_name::variant`.
+    fn lookup_variant(&self, enum_name: &str, variant: &str) -> Result<usize, String> {
+        let variants = self
+            .enums
+            .get(enum_name)
+            .ok_or(format!("unknown enum `{}`", enum_name))?;
+
+        variants
+            .iter()
+            .find(|v| v.name == variant)
+            .map(|v| v.fields.len())
+            .ok_or(format!("`{}` has no variant `{}`", enum_name, variant))
+    }
+
+    /// Checks that a pattern only names existing variants with the right
+    /// number of fields.
+    fn check_pattern(&mut self, pattern: &Pattern) -> bool {
+        match pattern {
+            Pattern::Wildcard
+            | Pattern::Binding { .. }
+            | Pattern::IntLiteral { .. }
+            | Pattern::BooleanLiteral { .. } => true,
+            Pattern::Variant {
+                enum_name,
+                variant,
+                fields,
+            } => {
+                let valid = match self.lookup_variant(enum_name, variant) {
+                    Ok(arity) if arity == fields.len() => true,
+                    Ok(arity) => {
+                        self.error(
+                            204,
+                            format!(
+                                "pattern `{}` has {} fields but `{}::{}` has {}",
+                                pattern,
+                                fields.len(),
+                                enum_name,
+                                variant,
+                                arity
+                            ),
+                        );
+                        false
+                    }
+                    Err(message) => {
+                        self.error(205, message);
+                        false
+                    }
+                };
+
+                fields.iter().fold(valid, |valid, f| self.check_pattern(f) && valid)
+            }
+        }
+    }
+
+    fn check_match(&mut self, arms: &[MatchArm]) {
+        for arm in arms {
+            self.check_expression(&arm.body);
+        }
+
+        let mut valid = true;
+        for arm in arms {
+            valid = self.check_pattern(&arm.pattern) && valid;
+        }
+        if !valid {
+            return;
+        }
+
+        let mut errors: Vec<(usize, String)> = vec![];
+        let mut matrix = Matrix::new(&self.enums);
+
+        for arm in arms {
+            if !matrix.is_useful(&arm.pattern) {
+                errors.push((
+                    202,
+                    format!("unreachable match arm: `{}` is already covered", arm.pattern),
+                ));
+            }
+            matrix.push(&arm.pattern);
+        }
+
+        if let Some(missing) = matrix.missing_pattern() {
+            errors.push((
+                201,
+                format!("non-exhaustive match: `{}` is not covered", missing),
+            ));
+        }
+
+        for (code, message) in errors {
+            self.error(code, message);
+        }
+    }
+}
@@ -0,0 +1,1121 @@
+//! Type checking of function bodies.
+//!
+//! Expressions are typed the way the backends compile them: integer and
+//! float literals take the type they are used as, integers of different
+//! widths are widened to the larger one and the type arguments of generic
+//! calls and struct literals are inferred from the arguments in order and
+//! then from the type expected of the result. Generic functions are
+//! checked per instance, with their type arguments bound, like they are
+//! compiled.
+
+use std::collections::{HashMap, HashSet};
+
+use crate::ast::{
+    expressions::{BinaryOperator, Expression, MatchArm, Pattern},
+    impl_functions, mangle_method,
+    statements::Statements,
+    StructField, Type, TypeParam,
+};
+
+use super::{
+    typing::{self, Inference},
+    Analyzer,
+};
+
+impl Analyzer {
+    /// Collects the declarations function bodies are checked against.
+    pub(super) fn declare(&mut self, statement: &Statements) {
+        match statement {
+            Statements::FunctionDeclaration {
+                name,
+                type_params,
+                params,
+                return_type,
+                ..
+            } => {
+                let declared =
+                    self.functions.contains_key(name) || self.generic_functions.contains_key(name);
+                if declared {
+                    self.error(214, format!("function `{}` is declared more than once", name));
+                } else if type_params.is_empty() {
+                    let params = params.iter().map(|p| p.r#type.clone()).collect();
+                    self.functions.insert(name.clone(), (params, return_type.clone()));
+                } else {
+                    self.generic_functions.insert(name.clone(), statement.clone());
+                }
+            }
+            Statements::ExternFunctionDeclaration {
+                name,
+                params,
+                return_type,
+            } => {
+                let signature = (params.iter().map(|p| p.r#type.clone()).collect(), return_type.clone());
+                // Every module using a C function declares it
+                match self.functions.get(name) {
+                    Some(declared) if *declared == signature => {}
+                    Some(_) => self.error(214, format!("function `{}` is declared more than once", name)),
+                    None => {
+                        self.functions.insert(name.clone(), signature);
+                        self.c_functions.insert(name.clone());
+                    }
+                }
+            }
+            Statements::ImplDeclaration {
+                r#trait, r#type, ..
+            } => {
+                if let Some(r#trait) = r#trait {
+                    self.trait_impls.insert((r#trait.clone(), r#type.clone()));
+                }
+                match impl_functions(statement) {
+                    Ok(methods) => {
+                        for method in &methods {
+                            if let Statements::FunctionDeclaration {
+                                name,
+                                params,
+                                return_type,
+                                ..
+                            } = method
+                            {
+                                let params = params.iter().map(|p| p.r#type.clone()).collect();
+                                self.functions.insert(name.clone(), (params, return_type.clone()));
+                            }
+                        }
+                    }
+                    Err(message) => self.error(220, message),
+                }
+            }
+            Statements::StructDeclaration {
+                name,
+                type_params,
+                fields,
+            } => {
+                if self.structs.contains_key(name) || self.enums.contains_key(name) {
+                    self.error(203, format!("type `{}` is declared more than once", name));
+                }
+                self.structs
+                    .insert(name.clone(), (type_params.clone(), fields.clone()));
+            }
+            Statements::ConstDeclaration { name, r#type, .. } => {
+                self.constants.insert(name.clone(), r#type.clone());
+            }
+            Statements::VariableDeclaration { name, r#type, .. } => {
+                self.globals.insert(name.clone(), r#type.clone());
+            }
+            _ => {}
+        }
+    }
+
+    /// Type checks the functions and methods declared by `statement`.
+    pub(super) fn check_types(&mut self, statement: &Statements) {
+        self.check_main(statement);
+        match statement {
+            // Generic functions are checked per instance
+            Statements::FunctionDeclaration { type_params, .. } if !type_params.is_empty() => {}
+            Statements::FunctionDeclaration { .. } => self.check_function(statement, HashMap::new()),
+            Statements::ExternFunctionDeclaration {
+                name,
+                params,
+                return_type,
+            } => {
+                self.context = Some(format!("extern function `{}`", name));
+                for param in params {
+                    self.check_type(&param.r#type);
+                }
+                self.check_type(return_type);
+                self.context = None;
+            }
+            Statements::ImplDeclaration { .. } => {
+                for method in impl_functions(statement).unwrap_or_default() {
+                    self.check_function(&method, HashMap::new());
+                }
+            }
+            Statements::StructDeclaration {
+                name,
+                type_params,
+                fields,
+            } => {
+                self.context = Some(format!("struct `{}`", name));
+                self.type_params = type_params.clone();
+                for field in fields {
+                    self.check_type(&field.r#type);
+                }
+                let fields: Vec<Type> = fields.iter().map(|f| f.r#type.clone()).collect();
+                self.check_not_recursive(name, &fields);
+                self.type_params.clear();
+                self.context = None;
+            }
+            Statements::EnumDeclaration { name, variants } => {
+                self.context = Some(format!("enum `{}`", name));
+                let fields: Vec<Type> = variants.iter().flat_map(|v| v.fields.clone()).collect();
+                for field in &fields {
+                    self.check_type(field);
+                }
+                self.check_not_recursive(name, &fields);
+                self.context = None;
+            }
+            Statements::VariableDeclaration {
+                value,
+                r#type,
+                line,
+                ..
+            } => {
+                self.line = *line;
+                if self.check_type(r#type).is_some() {
+                    self.expression_as(value, r#type);
+                }
+                self.line = 0;
+            }
+            _ => {}
+        }
+    }
+
+    /// Checks that `main`, which only the entry module has under that name,
+    /// can be called by every backend: its result becomes the exit status.
+    fn check_main(&mut self, statement: &Statements) {
+        let (type_params, params, return_type, line) = match statement {
+            Statements::FunctionDeclaration {
+                name,
+                type_params,
+                params,
+                return_type,
+                line,
+                ..
+            } if name == "main" => (type_params, params, return_type, *line),
+            _ => return,
+        };
+
+        self.line = line;
+        if !type_params.is_empty() || !params.is_empty() {
+            self.error(221, "`main` can not take parameters".to_string());
+        }
+        if !matches!(return_type, Type::Int32 | Type::Void) {
+            self.error(221, format!("`main` must return i32 or void, not {}", return_type));
+        }
+        self.line = 0;
+    }
+
+    /// Checks the instances of generic functions called so far, including
+    /// any instances those call in turn.
+    pub(super) fn check_instances(&mut self) {
+        while let Some((instance, bindings)) = self.pending_instances.pop() {
+            self.check_function(&instance, bindings);
+        }
+    }
+
+    fn check_function(&mut self, function: &Statements, bindings: HashMap<String, Type>) {
+        let (name, params, body, return_type, line) = match function {
+            Statements::FunctionDeclaration {
+                name,
+                params,
+                body,
+                return_type,
+                line,
+                ..
+            } => (name, params, body, return_type, *line),
+            _ => return,
+        };
+
+        self.context = Some(format!("function `{}`", name));
+        self.line = line;
+        self.type_bindings = bindings;
+        self.return_type = self.resolve_type(return_type);
+        self.check_type(&self.return_type.clone());
+
+        let mut locals = HashMap::new();
+        for param in params {
+            let r#type = self.resolve_type(&param.r#type);
+            self.check_type(&r#type);
+            locals.insert(param.name.clone(), r#type);
+        }
+        self.scopes = vec![locals];
+
+        self.check_body(body);
+
+        self.scopes.clear();
+        self.type_bindings.clear();
+        self.context = None;
+        self.line = 0;
+    }
+
+    /// Reports an error and gives up on the expression being checked.
+    fn fail<T>(&mut self, code: usize, message: String) -> Option<T> {
+        self.error(code, message);
+        None
+    }
+
+    fn resolve_type(&self, r#type: &Type) -> Type {
+        r#type.substitute(&self.type_bindings)
+    }
+
+    /// Checks that the types `r#type` is made of exist, and that the type
+    /// arguments of generic structs implement the bounds of their
+    /// parameters.
+    fn check_type(&mut self, r#type: &Type) -> Option<()> {
+        match r#type {
+            Type::UserDefinedType { name } => match self.structs.get(name) {
+                Some((type_params, _)) if !type_params.is_empty() => self.fail(
+                    217,
+                    format!("`{}` takes {} type arguments but 0 were supplied", name, type_params.len()),
+                ),
+                Some(_) => Some(()),
+                None if self.enums.contains_key(name) => Some(()),
+                None => self.fail(216, format!("unknown type `{}`", name)),
+            },
+            Type::Generic { name, params } => {
+                let type_params = match self.structs.get(name) {
+                    Some((type_params, _)) => type_params.clone(),
+                    None => return self.fail(216, format!("unknown type `{}`", name)),
+                };
+                if type_params.len() != params.len() {
+                    return self.fail(
+                        217,
+                        format!(
+                            "`{}` takes {} type arguments but {} were supplied",
+                            name,
+                            type_params.len(),
+                            params.len()
+                        ),
+                    );
+                }
+                for param in params {
+                    self.check_type(param)?;
+                }
+                self.check_type_arguments(&type_params, params)
+            }
+            Type::Array(r#type) | Type::FixedArray(r#type, _) | Type::Pointer(r#type) => {
+                self.check_type(r#type)
+            }
+            Type::Tuple(types) => types.iter().try_for_each(|t| self.check_type(t)),
+            Type::Function {
+                params,
+                return_type,
+            } => {
+                params.iter().try_for_each(|t| self.check_type(t))?;
+                self.check_type(return_type)
+            }
+            _ => Some(()),
+        }
+    }
+
+    /// Checks that every type argument implements the traits its parameter
+    /// is bounded by. Since generic code is only compiled per instance, this
+    /// is what keeps errors about missing methods out of the instances.
+    fn check_type_arguments(&mut self, type_params: &[TypeParam], type_args: &[Type]) -> Option<()> {
+        for (param, arg) in type_params.iter().zip(type_args) {
+            for bound in &param.bounds {
+                let implemented = match arg {
+                    Type::TypeParameter { name } => self
+                        .type_params
+                        .iter()
+                        .any(|p| p.name == *name && p.bounds.contains(bound)),
+                    arg => self.trait_impls.contains(&(bound.clone(), arg.clone())),
+                };
+                if !implemented {
+                    return self.fail(
+                        219,
+                        format!(
+                            "{} does not implement `{}`, required by `{}`",
+                            arg, bound, param.name
+                        ),
+                    );
+                }
+            }
+        }
+
+        Some(())
+    }
+
+    /// Reports the struct or enum `name` if `fields`, the types its values
+    /// are made of, contain a value of its own type, which would make them
+    /// infinitely large. Arrays and pointers hold their elements elsewhere.
+    /// A cycle of several types is only reported for the first of them.
+    fn check_not_recursive(&mut self, name: &str, fields: &[Type]) {
+        if self.recursive_types.contains(name) {
+            return;
+        }
+
+        let mut path = vec![];
+        let mut visited = HashSet::new();
+        if fields
+            .iter()
+            .any(|field| self.embeds(name, field, &mut path, &mut visited))
+        {
+            let message = match path.is_empty() {
+                true => format!("`{}` contains itself", name),
+                false => format!("`{}` contains itself through `{}`", name, path.join("`, `")),
+            };
+            self.error(220, message);
+            self.recursive_types.insert(name.to_string());
+            self.recursive_types.extend(path);
+        }
+    }
+
+    /// Whether values of `r#type` contain a value of the struct or enum
+    /// `name`. `path` is left with the types in between.
+    fn embeds(
+        &self,
+        name: &str,
+        r#type: &Type,
+        path: &mut Vec<String>,
+        visited: &mut HashSet<String>,
+    ) -> bool {
+        let embedded: Vec<Type> = match r#type {
+            Type::FixedArray(element, _) => vec![*element.clone()],
+            Type::Tuple(types) => types.clone(),
+            Type::UserDefinedType { name: found } | Type::Generic { name: found, .. } => {
+                if found == name {
+                    return true;
+                }
+                if !visited.insert(found.clone()) {
+                    return false;
+                }
+                let fields: Vec<Type> = match self.struct_fields(r#type) {
+                    Some(fields) => fields.into_iter().map(|f| f.r#type).collect(),
+                    None => match self.enums.get(found) {
+                        Some(variants) => variants.iter().flat_map(|v| v.fields.clone()).collect(),
+                        None => vec![],
+                    },
+                };
+                path.push(found.clone());
+                if fields.iter().any(|t| self.embeds(name, t, path, visited)) {
+                    return true;
+                }
+                path.pop();
+                return false;
+            }
+            _ => vec![],
+        };
+
+        embedded.iter().any(|t| self.embeds(name, t, path, visited))
+    }
+
+    fn struct_fields(&self, r#type: &Type) -> Option<Vec<StructField>> {
+        let (name, type_args) = match r#type {
+            Type::UserDefinedType { name } => (name, &[][..]),
+            Type::Generic { name, params } => (name, &params[..]),
+            _ => return None,
+        };
+        let (type_params, fields) = self.structs.get(name)?;
+        let bindings: HashMap<String, Type> = type_params
+            .iter()
+            .map(|p| p.name.clone())
+            .zip(type_args.iter().cloned())
+            .collect();
+
+        Some(
+            fields
+                .iter()
+                .map(|f| StructField {
+                    name: f.name.clone(),
+                    r#type: f.r#type.substitute(&bindings),
+                })
+                .collect(),
+        )
+    }
+
+    fn variable(&self, name: &str) -> Option<&Type> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .or_else(|| self.globals.get(name))
+    }
+
+    fn lookup_variable(&mut self, name: &str) -> Option<Type> {
+        match self.variable(name).cloned() {
+            Some(r#type) => Some(r#type),
+            None => self.fail(216, format!("unknown variable `{}`", name)),
+        }
+    }
+
+    /// Whether `name` is a function used as a value, which variables of the
+    /// same name hide.
+    fn is_function_value(&self, name: &str) -> bool {
+        self.variable(name).is_none()
+            && (self.functions.contains_key(name) || self.generic_functions.contains_key(name))
+    }
+
+    /// The function `name` as a value, a pointer to it in compiled code.
+    /// Generic functions have no single instance to point to, and C
+    /// functions returning strings would have to have their result copied.
+    fn function_value(&mut self, name: &str) -> Option<Type> {
+        if self.generic_functions.contains_key(name) {
+            return self.fail(218, format!("generic function `{}` can not be used as a value", name));
+        }
+        if self.c_functions.contains(name) {
+            return self.fail(220, format!("C function `{}` can not be used as a value", name));
+        }
+
+        let (params, return_type) = self.functions[name].clone();
+        Some(Type::Function {
+            params,
+            return_type: Box::new(return_type),
+        })
+    }
+
+    fn is_constant(&self, name: &str) -> bool {
+        self.constants.contains_key(name) && !self.scopes.iter().any(|scope| scope.contains_key(name))
+    }
+
+    fn check_body(&mut self, block: &[Statements]) {
+        self.scopes.push(HashMap::new());
+        for statement in block {
+            self.check_statement_types(statement);
+        }
+        self.scopes.pop();
+    }
+
+    fn check_statement_types(&mut self, statement: &Statements) {
+        if let Some(line) = statement.line() {
+            self.line = line;
+        }
+
+        match statement {
+            Statements::VariableDeclaration {
+                name,
+                value,
+                r#type,
+                ..
+            } => {
+                let r#type = self.resolve_type(r#type);
+                if self.check_type(&r#type).is_some() {
+                    self.expression_as(value, &r#type);
+                }
+                // Later uses are checked against the declared type even if
+                // the value is wrong
+                self.scopes.last_mut().unwrap().insert(name.clone(), r#type);
+            }
+            Statements::Assignment { target, value, .. } => {
+                if let Some(r#type) = self.place(target) {
+                    self.expression_as(value, &r#type);
+                }
+            }
+            Statements::Return { value, .. } => {
+                let return_type = self.return_type.clone();
+                match value {
+                    Some(value) => {
+                        self.expression_as(value, &return_type);
+                    }
+                    None if return_type == Type::Void => {}
+                    None => self.error(215, format!("expected a return value of type {}", return_type)),
+                }
+            }
+            Statements::If {
+                condition,
+                body,
+                else_body,
+                ..
+            } => {
+                self.condition(condition);
+                self.check_body(body);
+                self.check_body(else_body);
+            }
+            Statements::While { condition, body, .. } => {
+                self.condition(condition);
+                self.check_body(body);
+            }
+            Statements::ExpressionStatement { expr, .. } => {
+                match expr {
+                    Expression::Match { expr, arms } => self.r#match(expr, arms, Some(&Type::Void)),
+                    Expression::FunctionCall { .. } | Expression::MethodCall { .. } => {
+                        self.value_or_void(expr, None)
+                    }
+                    expr => self.expression(expr, None),
+                };
+            }
+            Statements::FunctionDeclaration { .. } => {
+                self.error(220, "nested functions are not supported".to_string())
+            }
+            Statements::ExternFunctionDeclaration { .. } => {
+                self.error(220, "extern functions must be declared at the top level".to_string())
+            }
+            Statements::StructDeclaration { .. } => {
+                self.error(220, "structs must be declared at the top level".to_string())
+            }
+            Statements::EnumDeclaration { .. } => {
+                self.error(220, "enums must be declared at the top level".to_string())
+            }
+            Statements::ImplDeclaration { .. } => {
+                self.error(220, "impl blocks must be at the top level".to_string())
+            }
+            Statements::TraitDeclaration { .. } => {
+                self.error(220, "traits must be declared at the top level".to_string())
+            }
+            Statements::ConstDeclaration { .. } => {
+                self.error(220, "constants must be declared at the top level".to_string())
+            }
+            Statements::ModuleDeclaration { .. } => {
+                self.error(220, "module declarations must be at the top of the file".to_string())
+            }
+            Statements::Import { .. } => {
+                self.error(220, "imports must be at the top of the file".to_string())
+            }
+        }
+    }
+
+    fn condition(&mut self, condition: &Expression) {
+        match self.expression(condition, Some(&Type::Boolean)) {
+            Some(Type::Boolean) | None => {}
+            Some(r#type) => self.error(
+                215,
+                format!("expected a condition of type bool, found {}", r#type),
+            ),
+        }
+    }
+
+    /// The type of `expr` converted to `r#type`.
+    fn expression_as(&mut self, expr: &Expression, r#type: &Type) -> Option<Type> {
+        let found = self.expression(expr, Some(r#type))?;
+        self.coerce(&found, r#type)
+    }
+
+    /// Checks that values of type `from` can be used where a `to` is
+    /// expected.
+    fn coerce(&mut self, from: &Type, to: &Type) -> Option<Type> {
+        if typing::coercible(from, to) {
+            Some(to.clone())
+        } else {
+            self.fail(215, format!("mismatched types: expected {}, found {}", to, from))
+        }
+    }
+
+    /// The type of an expression that has to produce a value.
+    fn expression(&mut self, expr: &Expression, hint: Option<&Type>) -> Option<Type> {
+        match expr {
+            Expression::StringLiteral { .. } => Some(Type::String),
+            Expression::IntLiteral { .. } => Some(typing::int_literal_type(hint)),
+            Expression::FloatLiteral { .. } => Some(typing::float_literal_type(hint)),
+            Expression::BooleanLiteral { .. } => Some(Type::Boolean),
+            Expression::Identifier { val } if self.is_constant(val) => self.constants.get(val).cloned(),
+            Expression::Identifier { val } if self.is_function_value(val) => self.function_value(val),
+            Expression::Identifier { .. } | Expression::FieldAccess { .. } | Expression::Index { .. } => {
+                self.place(expr)
+            }
+            Expression::FunctionCall { name, .. } | Expression::MethodCall { method: name, .. } => {
+                match self.value_or_void(expr, hint)? {
+                    Type::Void => self.fail(220, format!("`{}` does not return a value", name)),
+                    r#type => Some(r#type),
+                }
+            }
+            Expression::ArrayLiteral { elements } => self.array_literal(elements, hint),
+            Expression::StructLiteral { name, fields } => self.struct_literal(name, fields, hint),
+            Expression::EnumVariant {
+                enum_name,
+                variant,
+                values,
+            } => {
+                let fields = self.enums[enum_name]
+                    .iter()
+                    .find(|v| &v.name == variant)
+                    .map(|v| v.fields.clone())
+                    .unwrap_or_default();
+                for (value, field_type) in values.iter().zip(&fields) {
+                    self.expression_as(value, field_type)?;
+                }
+                Some(Type::UserDefinedType {
+                    name: enum_name.clone(),
+                })
+            }
+            Expression::Match { expr, arms } => match self.r#match(expr, arms, hint)? {
+                Type::Void => self.fail(220, "`match` does not produce a value".to_string()),
+                r#type => Some(r#type),
+            },
+            Expression::BinaryOperation { op, lhs, rhs } => self.binary_operation(*op, lhs, rhs, hint),
+            Expression::UnaryOperation { op, expr } => {
+                let r#type = self.expression(expr, hint)?;
+                self.check(typing::unary_type(*op, &r#type))
+            }
+        }
+    }
+
+    /// The type of an expression that is allowed to have no value, such as a
+    /// call to a function without a return value, which is void.
+    fn value_or_void(&mut self, expr: &Expression, hint: Option<&Type>) -> Option<Type> {
+        match expr {
+            Expression::FunctionCall { name, params } => self.call(name, params, hint),
+            Expression::MethodCall {
+                receiver,
+                method,
+                params,
+            } => self.method_call(receiver, method, params),
+            Expression::Match { expr, arms } => self.r#match(expr, arms, hint),
+            expr => self.expression(expr, hint),
+        }
+    }
+
+    /// The type of an assignable expression, or of a field or element of
+    /// any value.
+    fn place(&mut self, expr: &Expression) -> Option<Type> {
+        match expr {
+            Expression::Identifier { val } if self.is_constant(val) => {
+                self.fail(220, format!("can not assign to constant `{}`", val))
+            }
+            Expression::Identifier { val } => self.lookup_variable(val),
+            Expression::FieldAccess { expr, field } => {
+                let struct_type = self.place_or_value(expr)?;
+                let field_type = self
+                    .struct_fields(&struct_type)
+                    .and_then(|fields| fields.into_iter().find(|f| &f.name == field))
+                    .map(|f| f.r#type);
+
+                match field_type {
+                    Some(r#type) => Some(r#type),
+                    None => self.fail(216, format!("{} has no field `{}`", struct_type, field)),
+                }
+            }
+            Expression::Index { array, index } => {
+                let array_type = self.place_or_value(array)?;
+                let index_type = self.expression(index, Some(&Type::Int64))?;
+                if !index_type.is_integer() {
+                    return self.fail(
+                        215,
+                        format!("can not index with a value of type {}", index_type),
+                    );
+                }
+
+                match array_type {
+                    Type::FixedArray(element, _) | Type::Array(element) => Some(*element),
+                    r#type => self.fail(215, format!("can not index into a value of type {}", r#type)),
+                }
+            }
+            _ => self.fail(220, "can not assign to this expression".to_string()),
+        }
+    }
+
+    fn place_or_value(&mut self, expr: &Expression) -> Option<Type> {
+        match expr {
+            Expression::Identifier { val } if !self.is_constant(val) => self.place(expr),
+            Expression::Index { .. } | Expression::FieldAccess { .. } => self.place(expr),
+            expr => self.expression(expr, None),
+        }
+    }
+
+    /// An array literal is a fixed array unless a dynamic one is expected.
+    /// Without a hint the first element decides the element type.
+    fn array_literal(&mut self, elements: &[Expression], hint: Option<&Type>) -> Option<Type> {
+        let mut element_type = match hint {
+            Some(Type::Array(r#type)) | Some(Type::FixedArray(r#type, _)) => Some(*r#type.clone()),
+            _ => None,
+        };
+
+        for element in elements {
+            match element_type.clone() {
+                Some(r#type) => self.expression_as(element, &r#type)?,
+                None => {
+                    let r#type = self.expression(element, None)?;
+                    element_type = Some(r#type.clone());
+                    r#type
+                }
+            };
+        }
+
+        let element_type = match element_type {
+            Some(r#type) => Box::new(r#type),
+            None => return self.fail(218, "can not infer the element type of an empty array".to_string()),
+        };
+        match hint {
+            Some(Type::Array(_)) => Some(Type::Array(element_type)),
+            _ => Some(Type::FixedArray(element_type, elements.len())),
+        }
+    }
+
+    /// The type arguments of a generic struct are taken from `hint` or
+    /// inferred from the field values, in the order the fields are declared.
+    fn struct_literal(
+        &mut self,
+        name: &str,
+        values: &[(String, Expression)],
+        hint: Option<&Type>,
+    ) -> Option<Type> {
+        let (type_params, fields) = match self.structs.get(name) {
+            Some(declaration) => declaration.clone(),
+            None => return self.fail(216, format!("unknown struct `{}`", name)),
+        };
+
+        for (field, _) in values {
+            if !fields.iter().any(|f| &f.name == field) {
+                return self.fail(216, format!("{} has no field `{}`", name, field));
+            }
+            if values.iter().filter(|(f, _)| f == field).count() > 1 {
+                return self.fail(220, format!("field `{}` is specified more than once", field));
+            }
+        }
+
+        let mut inference = Inference::with_hint(name, &type_params, hint);
+        for field in &fields {
+            let expr = match values.iter().find(|(f, _)| f == &field.name) {
+                Some((_, expr)) => expr,
+                None => {
+                    return self.fail(
+                        220,
+                        format!("missing field `{}` in {} literal", field.name, name),
+                    )
+                }
+            };
+
+            match inference.expected(&field.r#type) {
+                Some(field_type) => {
+                    self.expression_as(expr, &field_type)?;
+                }
+                None => {
+                    let found = self.expression(expr, None)?;
+                    self.check(inference.unify(&field.r#type, &found))?;
+                }
+            }
+        }
+
+        if type_params.is_empty() {
+            return Some(Type::UserDefinedType {
+                name: name.to_string(),
+            });
+        }
+
+        let r#type = match inference.type_arguments() {
+            Ok(params) => Type::Generic {
+                name: name.to_string(),
+                params,
+            },
+            Err(message) => return self.fail(218, message),
+        };
+        self.check_type(&r#type)?;
+        Some(r#type)
+    }
+
+    fn binary_operation(
+        &mut self,
+        op: BinaryOperator,
+        lhs: &Expression,
+        rhs: &Expression,
+        hint: Option<&Type>,
+    ) -> Option<Type> {
+        let lhs_type = self.expression(lhs, typing::operand_hint(op, hint))?;
+        let rhs_type = self.expression(rhs, Some(&lhs_type))?;
+
+        let r#type = self.check(typing::operand_type(op, &lhs_type, &rhs_type))?;
+        self.check(typing::binary_type(op, &r#type))
+    }
+
+    /// Reports the error of a typing rule as mismatched types.
+    fn check<T>(&mut self, result: Result<T, String>) -> Option<T> {
+        match result {
+            Ok(value) => Some(value),
+            Err(message) => self.fail(215, message),
+        }
+    }
+
+    fn call(&mut self, name: &str, params: &[Expression], hint: Option<&Type>) -> Option<Type> {
+        // Variables holding functions hide the functions of the same name
+        if let Some(Type::Function {
+            params: param_types,
+            return_type,
+        }) = self.variable(name).cloned()
+        {
+            self.check_arguments(name, param_types.len(), params.len())?;
+            for (param, r#type) in params.iter().zip(&param_types) {
+                self.expression_as(param, r#type)?;
+            }
+            return Some(*return_type);
+        }
+
+        match name {
+            "len" => return self.len(params),
+            "format" => return self.format(params),
+            "resize" => return self.resize(params),
+            _ => {}
+        }
+        if let Some(r#type) = typing::conversion_type(name) {
+            return self.conversion(name, &r#type, params);
+        }
+
+        if let Some(function) = self.generic_functions.get(name).cloned() {
+            return self.generic_call(&function, params, hint);
+        }
+
+        let (param_types, return_type) = match self.functions.get(name) {
+            Some(signature) => signature.clone(),
+            None => return self.fail(216, format!("unknown function `{}`", name)),
+        };
+        self.check_arguments(name, param_types.len(), params.len())?;
+
+        for (param, r#type) in params.iter().zip(&param_types) {
+            self.expression_as(param, r#type)?;
+        }
+        Some(return_type)
+    }
+
+    fn check_arguments(&mut self, name: &str, expected: usize, supplied: usize) -> Option<()> {
+        if expected != supplied {
+            return self.fail(
+                217,
+                format!(
+                    "`{}` takes {} arguments but {} were supplied",
+                    name, expected, supplied
+                ),
+            );
+        }
+        Some(())
+    }
+
+    /// `receiver.method(params)` calls the method of the receiver's type with
+    /// the receiver as its `self` argument.
+    fn method_call(&mut self, receiver: &Expression, method: &str, params: &[Expression]) -> Option<Type> {
+        let receiver_type = self.expression(receiver, None)?;
+        let name = mangle_method(&receiver_type, method);
+
+        let (param_types, return_type) = match self.functions.get(&name) {
+            Some(signature) => signature.clone(),
+            None => return self.fail(216, format!("{} has no method `{}`", receiver_type, method)),
+        };
+        if param_types.first() != Some(&receiver_type) {
+            return self.fail(
+                220,
+                format!(
+                    "`{}` of {} can not be called as a method, it takes no `self`",
+                    method, receiver_type
+                ),
+            );
+        }
+        self.check_arguments(method, param_types.len() - 1, params.len())?;
+
+        for (param, r#type) in params.iter().zip(&param_types[1..]) {
+            self.expression_as(param, r#type)?;
+        }
+        Some(return_type)
+    }
+
+    /// Infers the type arguments of a call to a generic function from the
+    /// arguments in order and then from `hint`, checks them against the
+    /// bounds of the type parameters and queues the instance for checking.
+    fn generic_call(&mut self, function: &Statements, params: &[Expression], hint: Option<&Type>) -> Option<Type> {
+        let (name, type_params, fn_params, return_type) = match function {
+            Statements::FunctionDeclaration {
+                name,
+                type_params,
+                params,
+                return_type,
+                ..
+            } => (name, type_params, params, return_type),
+            _ => return None,
+        };
+        self.check_arguments(name, fn_params.len(), params.len())?;
+
+        let mut inference = Inference::new(name, type_params);
+        let mut found_types = vec![];
+        for (param, fn_param) in params.iter().zip(fn_params) {
+            let found = match inference.expected(&fn_param.r#type) {
+                Some(param_type) => self.expression_as(param, &param_type)?,
+                None => {
+                    let found = self.expression(param, None)?;
+                    self.check(inference.unify(&fn_param.r#type, &found))?;
+                    found
+                }
+            };
+            found_types.push(found);
+        }
+        inference.expect_result(return_type, hint);
+
+        let type_args = match inference.type_arguments() {
+            Ok(type_args) => type_args,
+            Err(message) => return self.fail(218, message),
+        };
+        self.check_type_arguments(type_params, &type_args)?;
+
+        for (found, fn_param) in found_types.iter().zip(fn_params) {
+            self.coerce(found, &inference.substitute(&fn_param.r#type))?;
+        }
+        self.instantiate(function, type_args, inference.bindings().clone());
+
+        Some(inference.substitute(return_type))
+    }
+
+    /// Queues the instance of a generic function for `type_args` for
+    /// checking, unless it was already.
+    fn instantiate(&mut self, function: &Statements, type_args: Vec<Type>, bindings: HashMap<String, Type>) {
+        if let Statements::FunctionDeclaration {
+            name,
+            params,
+            body,
+            return_type,
+            line,
+            ..
+        } = function
+        {
+            if !self.instances.insert((name.clone(), type_args.clone())) {
+                return;
+            }
+
+            let instance = Statements::FunctionDeclaration {
+                name: Type::Generic {
+                    name: name.clone(),
+                    params: type_args,
+                }
+                .to_string(),
+                type_params: vec![],
+                params: params.clone(),
+                body: body.clone(),
+                return_type: return_type.clone(),
+                is_const: false,
+                is_extern: false,
+                line: *line,
+            };
+            self.pending_instances.push((instance, bindings));
+        }
+    }
+
+    /// `len(x)` is the number of elements of an array or bytes of a string.
+    fn len(&mut self, params: &[Expression]) -> Option<Type> {
+        self.check_arguments("len", 1, params.len())?;
+
+        match self.expression(&params[0], None)? {
+            Type::FixedArray(..) | Type::Array(_) | Type::String => Some(Type::Int64),
+            r#type => self.fail(215, format!("`len` is not defined for {}", r#type)),
+        }
+    }
+
+    /// `resize(values, len, fill)` is a new array like `values`.
+    fn resize(&mut self, params: &[Expression]) -> Option<Type> {
+        self.check_arguments("resize", 3, params.len())?;
+
+        let array_type = self.expression(&params[0], None)?;
+        let element = match &array_type {
+            Type::Array(element) => *element.clone(),
+            r#type => return self.fail(215, format!("`resize` takes an array, not {}", r#type)),
+        };
+        self.expression_as(&params[1], &Type::Int64)?;
+        self.expression_as(&params[2], &element)?;
+        Some(array_type)
+    }
+
+    /// `i32(x)` and the like convert an integer to the type they are named
+    /// after.
+    fn conversion(&mut self, name: &str, r#type: &Type, params: &[Expression]) -> Option<Type> {
+        self.check_arguments(name, 1, params.len())?;
+
+        match self.expression(&params[0], Some(r#type))? {
+            from if from.is_integer() => Some(r#type.clone()),
+            from => self.fail(215, format!("`{}` converts integers, not {}", name, from)),
+        }
+    }
+
+    /// `format(template, values...)` writes numbers, bools and strings. The
+    /// template itself is checked with the rest of the program.
+    fn format(&mut self, params: &[Expression]) -> Option<Type> {
+        for param in params.iter().skip(1) {
+            match self.expression(param, None)? {
+                r#type if r#type.is_integer() || r#type.is_float() => {}
+                Type::Boolean | Type::String => {}
+                r#type => {
+                    return self.fail(215, format!("`format` can not write values of type {}", r#type))
+                }
+            }
+        }
+        Some(Type::String)
+    }
+
+    /// The type of a `match`. The first arm with a value decides its type,
+    /// which the others are converted to. A void hint makes the match a
+    /// statement, whose arms need not have values.
+    fn r#match(&mut self, expr: &Expression, arms: &[MatchArm], hint: Option<&Type>) -> Option<Type> {
+        let r#type = self.expression(expr, None)?;
+
+        let mut result_type: Option<Type> = None;
+        for arm in arms {
+            self.check_pattern_type(&arm.pattern, &r#type)?;
+
+            let mut bindings = HashMap::new();
+            self.bind_pattern(&arm.pattern, &r#type, &mut bindings);
+            self.scopes.push(bindings);
+
+            let arm_type = match (&result_type, hint) {
+                (_, Some(Type::Void)) | (Some(Type::Void), _) => {
+                    let statement = Statements::ExpressionStatement {
+                        expr: arm.body.clone(),
+                        line: 0,
+                    };
+                    self.check_statement_types(&statement);
+                    Some(Type::Void)
+                }
+                (Some(expected), _) => {
+                    let expected = expected.clone();
+                    self.expression_as(&arm.body, &expected)
+                }
+                (None, hint) => self.value_or_void(&arm.body, hint),
+            };
+
+            self.scopes.pop();
+            let arm_type = arm_type?;
+            if result_type.is_none() {
+                result_type = Some(arm_type);
+            }
+        }
+
+        match (hint, result_type) {
+            (Some(Type::Void), _) | (_, None) => Some(Type::Void),
+            (_, Some(r#type)) => Some(r#type),
+        }
+    }
+
+    fn check_pattern_type(&mut self, pattern: &Pattern, r#type: &Type) -> Option<()> {
+        let matches = match pattern {
+            Pattern::Wildcard | Pattern::Binding { .. } => true,
+            Pattern::IntLiteral { .. } => r#type.is_integer(),
+            Pattern::BooleanLiteral { .. } => *r#type == Type::Boolean,
+            Pattern::Variant {
+                enum_name,
+                variant,
+                fields,
+            } => {
+                if *r#type
+                    != (Type::UserDefinedType {
+                        name: enum_name.clone(),
+                    })
+                {
+                    false
+                } else {
+                    let field_types = self.variant_fields(enum_name, variant);
+                    for (field, field_type) in fields.iter().zip(&field_types) {
+                        self.check_pattern_type(field, field_type)?;
+                    }
+                    true
+                }
+            }
+        };
+
+        if matches {
+            Some(())
+        } else {
+            self.fail(
+                215,
+                format!("pattern `{}` can not match a value of type {}", pattern, r#type),
+            )
+        }
+    }
+
+    fn variant_fields(&self, enum_name: &str, variant: &str) -> Vec<Type> {
+        self.enums
+            .get(enum_name)
+            .and_then(|variants| variants.iter().find(|v| v.name == variant))
+            .map(|v| v.fields.clone())
+            .unwrap_or_default()
+    }
+
+    /// Collects the types of the variables `pattern` binds.
+    fn bind_pattern(&self, pattern: &Pattern, r#type: &Type, bindings: &mut HashMap<String, Type>) {
+        match pattern {
+            Pattern::Binding { name } => {
+                bindings.insert(name.clone(), r#type.clone());
+            }
+            Pattern::Variant {
+                enum_name,
+                variant,
+                fields,
+            } => {
+                let field_types = self.variant_fields(enum_name, variant);
+                for (field, field_type) in fields.iter().zip(&field_types) {
+                    self.bind_pattern(field, field_type, bindings);
+                }
+            }
+            Pattern::Wildcard | Pattern::IntLiteral { .. } | Pattern::BooleanLiteral { .. } => {}
+        }
+    }
+}
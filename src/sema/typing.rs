@@ -0,0 +1,218 @@
+//! Typing rules shared by the type checker and the backends, so that code
+//! is generated for the types sema checked.
+
+use std::collections::HashMap;
+
+use crate::ast::{
+    expressions::{BinaryOperator, UnaryOperator},
+    unify, Type, TypeParam,
+};
+
+/// Width of the integer type `r#type` in bits.
+pub fn bit_width(r#type: &Type) -> u32 {
+    match r#type {
+        Type::Int8 => 8,
+        Type::Int16 => 16,
+        Type::Int32 => 32,
+        Type::Int64 => 64,
+        _ => 128,
+    }
+}
+
+/// Wraps `value` around to the range of the integer type `r#type`, like
+/// two's complement arithmetic and integer conversions do.
+pub fn wrap(value: i128, r#type: &Type) -> i128 {
+    let shift = 128 - bit_width(r#type);
+    (value << shift) >> shift
+}
+
+/// The type of an integer literal where a value of type `hint` is expected.
+pub fn int_literal_type(hint: Option<&Type>) -> Type {
+    match hint {
+        Some(r#type) if r#type.is_integer() || r#type.is_float() => r#type.clone(),
+        _ => Type::Int32,
+    }
+}
+
+pub fn float_literal_type(hint: Option<&Type>) -> Type {
+    match hint {
+        Some(r#type) if r#type.is_float() => r#type.clone(),
+        _ => Type::Float64,
+    }
+}
+
+/// Whether values of type `from` are converted where a `to` is expected.
+/// Integers are only widened, narrowing them takes a conversion like
+/// `i32(x)`, see [`conversion_type`].
+pub fn coercible(from: &Type, to: &Type) -> bool {
+    match (from, to) {
+        (from, to) if from == to => true,
+        (from, to) if from.is_integer() && to.is_integer() => bit_width(from) <= bit_width(to),
+        // C takes strings as `char *`
+        (Type::String, Type::Pointer(pointee)) => **pointee == Type::Int8,
+        (Type::Pointer(_), Type::Pointer(pointee)) => **pointee == Type::Void,
+        (Type::FixedArray(element, _), Type::Array(expected)) => element == expected,
+        _ => false,
+    }
+}
+
+/// The integer type a call of `name` converts its argument to, if `name`
+/// is the name of one, as in `i8(x)`. Integers converted to a narrower type
+/// wrap around.
+pub fn conversion_type(name: &str) -> Option<Type> {
+    match Type::parse_type(name.to_string()) {
+        r#type if r#type.is_integer() => Some(r#type),
+        _ => None,
+    }
+}
+
+pub fn is_comparison(op: BinaryOperator) -> bool {
+    use BinaryOperator::*;
+    !matches!(op, Add | Subtract | Multiply | Divide | Modulo)
+}
+
+/// The hint the left operand of `op` is typed with. The operands of a
+/// comparison do not have to be of the type of its result.
+pub fn operand_hint(op: BinaryOperator, hint: Option<&Type>) -> Option<&Type> {
+    if is_comparison(op) {
+        None
+    } else {
+        hint
+    }
+}
+
+/// The type both operands of a binary operation are converted to. Integers
+/// of different widths are widened to the larger one.
+pub fn operand_type(op: BinaryOperator, lhs: &Type, rhs: &Type) -> Result<Type, String> {
+    match (lhs, rhs) {
+        (l, r) if l == r => Ok(l.clone()),
+        (l, r) if l.is_integer() && r.is_integer() => {
+            if bit_width(l) >= bit_width(r) {
+                Ok(l.clone())
+            } else {
+                Ok(r.clone())
+            }
+        }
+        (l, r) => Err(format!("mismatched types for `{}`: {} and {}", op, l, r)),
+    }
+}
+
+/// The type of a binary operation on operands of type `operand`. Strings can
+/// be concatenated and compared for equality.
+pub fn binary_type(op: BinaryOperator, operand: &Type) -> Result<Type, String> {
+    use BinaryOperator::*;
+
+    match operand {
+        Type::String => match op {
+            Add => Ok(Type::String),
+            Equal | NotEqual => Ok(Type::Boolean),
+            op => Err(format!("`{}` can not be applied to str", op)),
+        },
+        r#type if is_comparison(op) && (r#type.is_integer() || r#type.is_float() || *r#type == Type::Boolean) => {
+            Ok(Type::Boolean)
+        }
+        r#type if !is_comparison(op) && (r#type.is_integer() || r#type.is_float()) => Ok(r#type.clone()),
+        r#type => Err(format!("`{}` can not be applied to {}", op, r#type)),
+    }
+}
+
+pub fn unary_type(op: UnaryOperator, operand: &Type) -> Result<Type, String> {
+    match op {
+        UnaryOperator::Negate if operand.is_integer() || operand.is_float() => Ok(operand.clone()),
+        UnaryOperator::Not if *operand == Type::Boolean => Ok(Type::Boolean),
+        UnaryOperator::Negate => Err(format!("`-` can not be applied to {}", operand)),
+        UnaryOperator::Not => Err(format!("`!` can not be applied to {}", operand)),
+    }
+}
+
+/// Infers the type arguments of a generic function or struct from the
+/// values of its parameters or fields, in order, and then from the type
+/// expected of the result.
+///
+/// A value whose declared type still has unbound type parameters is typed
+/// on its own and its type binds them, see [`Inference::unify`]. The others
+/// are converted to [`Inference::expected`].
+pub struct Inference<'a> {
+    name: &'a str,
+    type_params: &'a [TypeParam],
+    bindings: HashMap<String, Type>,
+}
+
+impl<'a> Inference<'a> {
+    pub fn new(name: &'a str, type_params: &'a [TypeParam]) -> Self {
+        Self {
+            name,
+            type_params,
+            bindings: HashMap::new(),
+        }
+    }
+
+    /// Starts from the type arguments of `hint`, for a struct literal where
+    /// a value of the generic struct `name` is expected.
+    pub fn with_hint(name: &'a str, type_params: &'a [TypeParam], hint: Option<&Type>) -> Self {
+        let mut inference = Self::new(name, type_params);
+        if let Some(Type::Generic {
+            name: hint_name,
+            params,
+        }) = hint
+        {
+            if hint_name == name {
+                inference.bindings = type_params
+                    .iter()
+                    .map(|p| p.name.clone())
+                    .zip(params.iter().cloned())
+                    .collect();
+            }
+        }
+        inference
+    }
+
+    /// The type a value declared as `r#type` is converted to, or `None` if
+    /// its type is still to be inferred from the value.
+    pub fn expected(&self, r#type: &Type) -> Option<Type> {
+        let r#type = self.substitute(r#type);
+        if r#type.has_type_params() {
+            None
+        } else {
+            Some(r#type)
+        }
+    }
+
+    /// Binds the type parameters in `declared` to the types in `found`.
+    pub fn unify(&mut self, declared: &Type, found: &Type) -> Result<(), String> {
+        unify(&self.substitute(declared), found, &mut self.bindings)
+    }
+
+    /// Binds the type parameters left in `result` to the types in `hint`,
+    /// unless they conflict with what the values bound.
+    pub fn expect_result(&mut self, result: &Type, hint: Option<&Type>) {
+        if let Some(hint) = hint {
+            let mut expected = self.bindings.clone();
+            if unify(&self.substitute(result), hint, &mut expected).is_ok() {
+                self.bindings = expected;
+            }
+        }
+    }
+
+    pub fn substitute(&self, r#type: &Type) -> Type {
+        r#type.substitute(&self.bindings)
+    }
+
+    pub fn bindings(&self) -> &HashMap<String, Type> {
+        &self.bindings
+    }
+
+    /// The inferred type arguments, in the order the parameters are
+    /// declared.
+    pub fn type_arguments(&self) -> Result<Vec<Type>, String> {
+        self.type_params
+            .iter()
+            .map(|p| {
+                self.bindings.get(&p.name).cloned().ok_or(format!(
+                    "can not infer type parameter `{}` of `{}`",
+                    p.name, self.name
+                ))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,110 @@
+use crate::ast::{expressions::Expression, Type};
+
+/// Whether the C function `name` is part of the runtime, which returns
+/// strings allocated like those of compiled code rather than ones owned by C.
+pub fn is_runtime_function(name: &str) -> bool {
+    name.starts_with("aurora_")
+}
+
+/// Splits the template of a `format` call into the text around its `{}`
+/// placeholders, so a template with `n` placeholders has `n + 1` pieces.
+/// `{{` and `}}` stand for literal braces.
+fn format_pieces(template: &str) -> Result<Vec<String>, String> {
+    let mut pieces = vec![String::new()];
+    let mut chars = template.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match (c, chars.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                chars.next();
+                pieces.last_mut().unwrap().push(c);
+            }
+            ('{', Some('}')) => {
+                chars.next();
+                pieces.push(String::new());
+            }
+            ('{', _) => {
+                return Err(
+                    "`format` string has a `{` without a `}`, write `{{` for a literal brace"
+                        .to_string(),
+                )
+            }
+            ('}', _) => {
+                return Err(
+                    "`format` string has a `}` without a `{`, write `}}` for a literal brace"
+                        .to_string(),
+                )
+            }
+            (c, _) => pieces.last_mut().unwrap().push(c),
+        }
+    }
+
+    Ok(pieces)
+}
+
+/// The pieces of the template of `format(template, values...)`, a string
+/// literal with a placeholder for each value. `format` is built into the
+/// compiler, since Aurora functions take a fixed number of arguments.
+pub fn format_template(params: &[Expression]) -> Result<Vec<String>, String> {
+    let pieces = match params.first() {
+        Some(Expression::StringLiteral { val }) => format_pieces(val)?,
+        _ => return Err("`format` takes a string literal as its first argument".to_string()),
+    };
+
+    if pieces.len() != params.len() {
+        return Err(format!(
+            "`format` string has {} placeholders but {} values were supplied",
+            pieces.len() - 1,
+            params.len() - 1
+        ));
+    }
+
+    Ok(pieces)
+}
+
+/// Significant digits `format` writes floats of `r#type` with, as many as
+/// survive a round trip through the type.
+pub fn float_digits(r#type: &Type) -> usize {
+    match r#type {
+        Type::Float16 | Type::Float32 => 6,
+        _ => 15,
+    }
+}
+
+/// Writes `value` like C's `%.{digits}g`, which compiled code formats floats
+/// with: in the shortest of fixed or scientific notation, without trailing
+/// zeros.
+pub fn format_float(value: f64, digits: usize) -> String {
+    if value.is_nan() {
+        return if value.is_sign_negative() {
+            "-nan"
+        } else {
+            "nan"
+        }
+        .to_string();
+    }
+    if value.is_infinite() {
+        return if value < 0.0 { "-inf" } else { "inf" }.to_string();
+    }
+
+    let strip_zeros = |number: &str| match number.contains('.') {
+        true => number
+            .trim_end_matches('0')
+            .trim_end_matches('.')
+            .to_string(),
+        false => number.to_string(),
+    };
+
+    let digits = digits.max(1);
+    let scientific = format!("{:.*e}", digits - 1, value);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+
+    if exponent < -4 || exponent >= digits as i32 {
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{}{:02}", strip_zeros(mantissa), sign, exponent.abs())
+    } else {
+        let decimals = (digits as i32 - 1 - exponent) as usize;
+        strip_zeros(&format!("{:.*}", decimals, value))
+    }
+}
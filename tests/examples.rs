@@ -0,0 +1,99 @@
+//! Builds the programs in `examples/` with every backend and checks that
+//! they agree: the built programs have to exit with the same code and print
+//! the same output, and the interpreter has to print the same output followed
+//! by the value `main` returns.
+
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    process::{Command, Output},
+};
+
+const COMPILER: &str = env!("CARGO_BIN_EXE_aurorac");
+
+#[derive(Debug, PartialEq)]
+struct Run {
+    code: i32,
+    stdout: String,
+    stderr: String,
+}
+
+impl From<Output> for Run {
+    fn from(output: Output) -> Self {
+        Self {
+            code: output.status.code().expect("killed by a signal"),
+            stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+            stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        }
+    }
+}
+
+fn examples() -> Vec<PathBuf> {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("examples");
+    let mut examples: Vec<PathBuf> = fs::read_dir(&dir)
+        .expect("examples/ is missing")
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "aur"))
+        .collect();
+    examples.push(dir.join("modules/main.aur"));
+    examples.sort();
+    examples
+}
+
+fn compiler(args: &[&str]) -> Run {
+    Command::new(COMPILER)
+        .args(args)
+        .output()
+        .expect("can not run aurorac")
+        .into()
+}
+
+/// Builds `example` with `backend` into `dir` and runs the result.
+fn build_and_run(example: &Path, backend: &str, dir: &Path) -> Run {
+    let output = dir.join(backend);
+    let built = compiler(&[
+        "build",
+        &format!("--backend={}", backend),
+        example.to_str().unwrap(),
+        "-o",
+        output.to_str().unwrap(),
+    ]);
+    assert_eq!(built.code, 0, "{} failed to build with {}: {:?}", example.display(), backend, built);
+
+    Command::new(&output).output().expect("can not run the built program").into()
+}
+
+#[test]
+fn backends_agree_on_examples() {
+    let scratch = std::env::temp_dir().join(format!("aurora-examples-{}", std::process::id()));
+
+    for example in examples() {
+        let name = example.with_extension("");
+        let name = name.strip_prefix(env!("CARGO_MANIFEST_DIR")).unwrap();
+        let dir = scratch.join(name);
+        fs::create_dir_all(&dir).unwrap();
+
+        let c = build_and_run(&example, "c", &dir);
+        if cfg!(feature = "llvm") {
+            let llvm = build_and_run(&example, "llvm", &dir);
+            assert_eq!(llvm, c, "{}: the llvm and c backends disagree", example.display());
+        }
+
+        // The interpreter prints the value of `main` instead of exiting
+        // with it
+        let source = fs::read_to_string(&example).unwrap();
+        let interpreted = compiler(&["run", "--backend=interp", example.to_str().unwrap()]);
+        let expected = if source.contains("fn main() -> i32") {
+            Run {
+                code: 0,
+                stdout: format!("{}{}\n", c.stdout, c.code),
+                stderr: c.stderr,
+            }
+        } else {
+            c
+        };
+        assert_eq!(interpreted, expected, "{}: the interpreter and c backend disagree", example.display());
+    }
+
+    fs::remove_dir_all(&scratch).unwrap();
+}